@@ -0,0 +1,36 @@
+//! Feeds arbitrary page bytes into the header and table page parsers, which must return errors
+//! rather than panic on corrupt pages. Run with `cargo fuzz run page fuzz/corpus/page`.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use toydb::storage::relational::page::{HeaderPage, Page, TablePage, PAGE_SIZE};
+
+fuzz_target!(|input: &[u8]| {
+    let mut data = [0u8; PAGE_SIZE];
+    let len = input.len().min(PAGE_SIZE);
+    data[..len].copy_from_slice(&input[..len]);
+
+    let _ = Page::verify_checksum(0, &data);
+    let _ = Page::verify_checksum(1, &data);
+
+    if let Ok(header_page) = HeaderPage::from_data(data) {
+        let _ = header_page.get_record_count();
+        let _ = header_page.get_unloaded_page_id();
+        for name in &["", "a", "b", "movies", "\0"] {
+            let _ = header_page.get_root_id(name);
+            let _ = header_page.get_fill_factor(name);
+        }
+    }
+
+    if let Ok(mut table_page) = TablePage::from_data(1, data) {
+        let _ = table_page.get_lsn();
+        let _ = table_page.get_table_page_id();
+        let _ = table_page.get_prev_page_id();
+        let _ = table_page.get_next_page_id();
+        let _ = table_page.page_is_deleted();
+        let mut next = table_page.get_first_tuple_rid();
+        while let Ok(Some(rid)) = next {
+            let _ = table_page.get_tuple(&rid);
+            next = table_page.get_next_tuple_rid(&rid);
+        }
+    }
+});
@@ -0,0 +1,47 @@
+//! An audit log of the SQL statements executed by clients, written as one line per statement to
+//! an append-only file. Each line holds tab-separated fields: the time in milliseconds since the
+//! Unix epoch, the client's address, the outcome (ok, or error followed by the quoted error), and
+//! the quoted statement text.
+
+use crate::error::Result;
+use crate::sql::execution::ResultSet;
+
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An audit log, shared by all client sessions.
+pub struct AuditLog {
+    file: Mutex<File>,
+    include_reads: bool,
+}
+
+impl AuditLog {
+    /// Opens an audit log file, creating it if it doesn't exist and appending to it otherwise.
+    /// If include_reads is false, successful queries and EXPLAINs aren't recorded.
+    pub fn new(path: &Path, include_reads: bool) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file), include_reads })
+    }
+
+    /// Records an executed statement with its outcome. Failed statements are always recorded,
+    /// since a failure doesn't tell whether the statement would have read or written. Query
+    /// rows are streamed after this, so errors while reading rows aren't part of the outcome.
+    pub fn record(&self, peer: &SocketAddr, sql: &str, result: &Result<ResultSet>) -> Result<()> {
+        let outcome = match result {
+            Ok(ResultSet::Query { .. }) | Ok(ResultSet::Explain(_)) if !self.include_reads => {
+                return Ok(())
+            }
+            Ok(_) => "ok".to_string(),
+            Err(err) => format!("error {:?}", err.to_string()),
+        };
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        // A single write per entry, so entries from concurrent sessions don't interleave.
+        let entry = format!("{}\t{}\t{}\t{:?}\n", time, peer, outcome, sql);
+        self.file.lock()?.write_all(entry.as_bytes())?;
+        Ok(())
+    }
+}
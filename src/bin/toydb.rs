@@ -9,9 +9,11 @@
 use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version};
 use serde_derive::Deserialize;
 use std::collections::HashMap;
+use toydb::audit::AuditLog;
 use toydb::error::{Error, Result};
+use toydb::raft;
 use toydb::storage;
-use toydb::Server;
+use toydb::{DurabilityPreset, Server};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -35,8 +37,17 @@ async fn main() -> Result<()> {
     simplelog::SimpleLogger::init(loglevel, logconfig.build())?;
 
     let path = std::path::Path::new(&cfg.data_dir);
+    let durability = match cfg.durability.as_str() {
+        "" if cfg.sync => DurabilityPreset::Safe,
+        "" => DurabilityPreset::Fast,
+        "safe" => DurabilityPreset::Safe,
+        "balanced" => DurabilityPreset::Balanced,
+        "fast" => DurabilityPreset::Fast,
+        name => return Err(Error::Config(format!("Unknown durability preset {}", name))),
+    };
     let raft_store: Box<dyn storage::log::Store> = match cfg.storage_raft.as_str() {
-        "hybrid" | "" => Box::new(storage::log::Hybrid::new(path, cfg.sync)?),
+        // The sync policy is set by the durability preset in Server::new().
+        "hybrid" | "" => Box::new(storage::log::Hybrid::new(path, false)?),
         "memory" => Box::new(storage::log::Memory::new()),
         name => return Err(Error::Config(format!("Unknown Raft storage engine {}", name))),
     };
@@ -46,12 +57,61 @@ async fn main() -> Result<()> {
         name => return Err(Error::Config(format!("Unknown SQL storage engine {}", name))),
     };
 
-    Server::new(&cfg.id, cfg.peers, raft_store, sql_store)
-        .await?
-        .listen(&cfg.listen_sql, &cfg.listen_raft)
-        .await?
-        .serve()
-        .await
+    let raft_config = raft::RaftConfig {
+        tick: std::time::Duration::from_millis(cfg.raft_tick),
+        heartbeat_interval: cfg.raft_heartbeat_interval,
+        election_timeout_range: cfg.raft_election_timeout_min..=cfg.raft_election_timeout_max,
+        quorum_loss_timeout: cfg.raft_quorum_loss_timeout,
+        query_timeout: cfg.raft_query_timeout,
+        max_batch_entries: cfg.raft_max_batch_entries,
+        max_batch_bytes: cfg.raft_max_batch_bytes,
+        max_inflight_entries: cfg.raft_max_inflight_entries,
+        max_inflight_bytes: cfg.raft_max_inflight_bytes,
+        witness: cfg.raft_witness,
+        compression_threshold: match cfg.raft_compression_threshold {
+            0 => None,
+            threshold => Some(threshold),
+        },
+        snapshot_interval: match cfg.raft_snapshot_interval {
+            0 => None,
+            interval => Some(interval),
+        },
+    };
+
+    let server = Server::new(
+        &cfg.id,
+        cfg.peers,
+        raft_store,
+        durability,
+        sql_store,
+        raft_config,
+        cfg.max_frame_size,
+    )
+    .await?
+    .listen(&cfg.listen_sql, &cfg.listen_raft)
+    .await?;
+    let server = match cfg.listen_metrics.as_str() {
+        "" => server,
+        #[cfg(feature = "metrics")]
+        addr => server.listen_metrics(addr).await?,
+        #[cfg(not(feature = "metrics"))]
+        _ => return Err(Error::Config("listen_metrics requires the metrics feature".into())),
+    };
+    let server = match cfg.audit_log.as_str() {
+        "" => server,
+        path => server.audit(AuditLog::new(std::path::Path::new(path), cfg.audit_log_reads)?),
+    };
+    let server = server.monitor_transactions(
+        match cfg.transaction_warn_after {
+            0 => None,
+            secs => Some(std::time::Duration::from_secs(secs)),
+        },
+        match cfg.transaction_abort_after {
+            0 => None,
+            secs => Some(std::time::Duration::from_secs(secs)),
+        },
+    );
+    server.allow_admin_requests(cfg.allow_admin_requests).serve().await
 }
 
 #[derive(Debug, Deserialize)]
@@ -60,11 +120,32 @@ struct Config {
     peers: HashMap<String, String>,
     listen_sql: String,
     listen_raft: String,
+    listen_metrics: String,
+    max_frame_size: usize,
+    audit_log: String,
+    audit_log_reads: bool,
+    transaction_warn_after: u64,
+    transaction_abort_after: u64,
+    allow_admin_requests: bool,
     log_level: String,
     data_dir: String,
     sync: bool,
+    durability: String,
     storage_raft: String,
     storage_sql: String,
+    raft_tick: u64,
+    raft_heartbeat_interval: u64,
+    raft_election_timeout_min: u64,
+    raft_election_timeout_max: u64,
+    raft_quorum_loss_timeout: u64,
+    raft_query_timeout: u64,
+    raft_max_batch_entries: usize,
+    raft_max_batch_bytes: u64,
+    raft_max_inflight_entries: u64,
+    raft_max_inflight_bytes: u64,
+    raft_witness: bool,
+    raft_compression_threshold: u64,
+    raft_snapshot_interval: u64,
 }
 
 impl Config {
@@ -74,11 +155,32 @@ impl Config {
         c.set_default("peers", HashMap::<String, String>::new())?;
         c.set_default("listen_sql", "0.0.0.0:9605")?;
         c.set_default("listen_raft", "0.0.0.0:9705")?;
+        c.set_default("listen_metrics", "")?;
+        c.set_default("max_frame_size", 8388608)?;
+        c.set_default("audit_log", "")?;
+        c.set_default("audit_log_reads", true)?;
+        c.set_default("transaction_warn_after", 60)?;
+        c.set_default("transaction_abort_after", 0)?;
+        c.set_default("allow_admin_requests", false)?;
         c.set_default("log_level", "info")?;
         c.set_default("data_dir", "/var/lib/toydb")?;
         c.set_default("sync", true)?;
+        c.set_default("durability", "")?;
         c.set_default("storage_raft", "hybrid")?;
         c.set_default("storage_sql", "memory")?;
+        c.set_default("raft_tick", 100)?;
+        c.set_default("raft_heartbeat_interval", 1)?;
+        c.set_default("raft_election_timeout_min", 8)?;
+        c.set_default("raft_election_timeout_max", 15)?;
+        c.set_default("raft_quorum_loss_timeout", 15)?;
+        c.set_default("raft_query_timeout", 60)?;
+        c.set_default("raft_max_batch_entries", 256)?;
+        c.set_default("raft_max_batch_bytes", 1048576)?;
+        c.set_default("raft_max_inflight_entries", 1024)?;
+        c.set_default("raft_max_inflight_bytes", 4194304)?;
+        c.set_default("raft_witness", false)?;
+        c.set_default("raft_compression_threshold", 0)?;
+        c.set_default("raft_snapshot_interval", 0)?;
 
         c.merge(config::File::with_name(file))?;
         c.merge(config::Environment::with_prefix("TOYDB"))?;
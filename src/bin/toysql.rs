@@ -13,12 +13,22 @@ use toydb::error::{Error, Result};
 use toydb::sql::engine::Mode;
 use toydb::sql::execution::ResultSet;
 use toydb::sql::parser::{Lexer, Token};
+use toydb::sql::schema::Column;
+use toydb::sql::types::{DataType, Value};
 use toydb::Client;
 
+use std::io::Write as _;
+
+/// The number of rows inserted per statement by !copy.
+const COPY_BATCH_SIZE: usize = 100;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let opts = app_from_crate!()
         .arg(clap::Arg::with_name("command"))
+        .arg(
+            clap::Arg::with_name("align").short("a").long("align").help("Align columns in a table"),
+        )
         .arg(clap::Arg::with_name("headers").short("H").long("headers").help("Show column headers"))
         .arg(
             clap::Arg::with_name("host")
@@ -46,6 +56,9 @@ async fn main() -> Result<()> {
     if opts.is_present("headers") {
         toysql.show_headers = true
     }
+    if opts.is_present("align") {
+        toysql.align = true
+    }
 
     if let Some(command) = opts.value_of("command") {
         toysql.execute(command).await
@@ -60,6 +73,7 @@ struct ToySQL {
     editor: Editor<InputValidator>,
     history_path: Option<std::path::PathBuf>,
     show_headers: bool,
+    align: bool,
 }
 
 impl ToySQL {
@@ -71,6 +85,7 @@ impl ToySQL {
             history_path: std::env::var_os("HOME")
                 .map(|home| std::path::Path::new(&home).join(".toysql.history")),
             show_headers: false,
+            align: false,
         })
     }
 
@@ -100,6 +115,42 @@ impl ToySQL {
         };
 
         match command {
+            "!add" => {
+                let args = getargs(2)?;
+                self.client.add_server(args[0], args[1]).await?;
+                println!("Added node {} at {}", args[0], args[1])
+            }
+            "!align" => match getargs(1)?[0] {
+                "on" => {
+                    self.align = true;
+                    println!("Alignment enabled");
+                }
+                "off" => {
+                    self.align = false;
+                    println!("Alignment disabled");
+                }
+                v => return Err(Error::Parse(format!("Invalid value {}, expected on or off", v))),
+            },
+            "!copy" => {
+                let args = getargs(3)?;
+                let path = args[2].trim_matches('\'');
+                match args[1].to_uppercase().as_str() {
+                    "FROM" => {
+                        let count = self.copy_from(args[0], path).await?;
+                        println!("Copied {} rows from {}", count, path)
+                    }
+                    "TO" => {
+                        let count = self.copy_to(args[0], path).await?;
+                        println!("Copied {} rows to {}", count, path)
+                    }
+                    v => {
+                        return Err(Error::Parse(format!(
+                            "Invalid value {}, expected FROM or TO",
+                            v
+                        )))
+                    }
+                }
+            }
             "!headers" => match getargs(1)?[0] {
                 "on" => {
                     self.show_headers = true;
@@ -116,11 +167,21 @@ impl ToySQL {
 Enter a SQL statement terminated by a semicolon (;) to execute it and display the result.
 The following commands are also available:
 
+    !add <node> <addr> Add a node with the given Raft address to the cluster
+    !align <on|off>    Enable or disable aligning query results in a table
+    !copy <table> <from|to> <file>
+                       Import CSV rows from a file into a table, or export a table to a file
     !headers <on|off>  Enable or disable column headers
     !help              This help message
+    !learner <node> <addr>
+                       Add a non-voting learner with the given Raft address to the cluster
+    !promote <node>    Promote a learner to a voting member
+    !remove <node>     Remove a node from the cluster
+    !reset-stats       Clear the server's statement statistics (toydb_stat_statements)
     !status            Display server status
     !table [table]     Display table schema, if it exists
     !tables            List tables
+    !transfer <node>   Transfer Raft leadership to the given node
 "#
             ),
             "!status" => {
@@ -129,13 +190,17 @@ The following commands are also available:
                     .raft
                     .node_last_index
                     .iter()
-                    .map(|(id, index)| format!("{}:{}", id, index))
+                    .map(|(id, index)| match status.raft.node_last_contact.get(id) {
+                        Some(ticks) => format!("{}:{} ({} ticks ago)", id, index, ticks),
+                        None => format!("{}:{}", id, index),
+                    })
                     .collect::<Vec<_>>();
                 node_logs.sort();
                 println!(
                     r#"
 Server:    {server} (leader {leader} in term {term} with {nodes} nodes)
-Raft log:  {committed} committed, {applied} applied, {raft_size} MB ({raft_storage} storage)
+Raft log:  {last} entries, {committed} committed, {applied} applied, {raft_size} MB ({raft_storage} storage)
+Pending:   {notifications} notifications, {queries} queries
 Node logs: {logs}
 SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
 "#,
@@ -143,8 +208,11 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
                     leader = status.raft.leader,
                     term = status.raft.term,
                     nodes = status.raft.node_last_index.len(),
+                    last = status.raft.last_index,
                     committed = status.raft.commit_index,
                     applied = status.raft.apply_index,
+                    notifications = status.raft.pending_notifications,
+                    queries = status.raft.pending_queries,
                     raft_storage = status.raft.storage,
                     raft_size = format!("{:.3}", status.raft.storage_size as f64 / 1000.0 / 1000.0),
                     logs = node_logs.join(" "),
@@ -163,6 +231,31 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
                     println!("{}", table)
                 }
             }
+            "!learner" => {
+                let args = getargs(2)?;
+                self.client.add_learner(args[0], args[1]).await?;
+                println!("Added learner {} at {}", args[0], args[1])
+            }
+            "!promote" => {
+                let args = getargs(1)?;
+                self.client.promote_learner(args[0]).await?;
+                println!("Promoted learner {}", args[0])
+            }
+            "!remove" => {
+                let args = getargs(1)?;
+                self.client.remove_server(args[0]).await?;
+                println!("Removed node {}", args[0])
+            }
+            "!reset-stats" => {
+                getargs(0)?;
+                self.client.reset_statement_stats().await?;
+                println!("Reset statement statistics")
+            }
+            "!transfer" => {
+                let args = getargs(1)?;
+                self.client.transfer_leadership(args[0]).await?;
+                println!("Transferred leadership to {}", args[0])
+            }
             c => return Err(Error::Parse(format!("Unknown command {}", c))),
         }
         Ok(())
@@ -186,7 +279,20 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
             ResultSet::Update { count } => println!("Updated {} rows", count),
             ResultSet::CreateTable { name } => println!("Created table {}", name),
             ResultSet::DropTable { name } => println!("Dropped table {}", name),
+            ResultSet::CreateIndex { name } => println!("Created index {}", name),
+            ResultSet::DropIndex { name } => println!("Dropped index {}", name),
+            ResultSet::Analyze { name, rows } => {
+                println!("Analyzed table {} ({} rows)", name, rows)
+            }
             ResultSet::Explain(plan) => println!("{}", plan.to_string()),
+            ResultSet::Query { columns, rows } if self.align => {
+                let headers: Vec<String> =
+                    columns.iter().map(|c| c.name.as_deref().unwrap_or("?").to_string()).collect();
+                let rows = rows
+                    .map(|r| r.map(|row| row.iter().map(|v| v.to_string()).collect()))
+                    .collect::<Result<Vec<Vec<String>>>>()?;
+                self.print_table(headers, rows);
+            }
             ResultSet::Query { columns, mut rows } => {
                 if self.show_headers {
                     println!(
@@ -209,6 +315,104 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
         Ok(())
     }
 
+    /// Imports a CSV file into a table, returning the number of rows inserted. The header
+    /// names the columns of the fields, and rows are inserted in a single transaction, unless
+    /// one is already open.
+    async fn copy_from(&self, table: &str, path: &str) -> Result<u64> {
+        let table = self.client.get_table(table).await?;
+        let mut records = parse_csv(&std::fs::read_to_string(path)?)?;
+        if records.is_empty() {
+            return Err(Error::Value(format!("{} has no CSV header", path)));
+        }
+        let columns = records
+            .remove(0)
+            .into_iter()
+            .map(|name| table.get_column(name.as_deref().unwrap_or_default()))
+            .collect::<Result<Vec<_>>>()?;
+        let insert = format!(
+            "INSERT INTO {} ({}) VALUES ",
+            quote_ident(&table.name),
+            columns.iter().map(|c| quote_ident(&c.name)).collect::<Vec<_>>().join(", ")
+        );
+        let placeholders = format!("({})", vec!["?"; columns.len()].join(", "));
+
+        let begin = self.client.txn().is_none();
+        if begin {
+            self.client.execute("BEGIN").await?;
+        }
+        let insert_all = async {
+            let mut count = 0;
+            for (i, batch) in records.chunks(COPY_BATCH_SIZE).enumerate() {
+                let mut params = Vec::with_capacity(batch.len() * columns.len());
+                for (j, record) in batch.iter().enumerate() {
+                    if record.len() != columns.len() {
+                        return Err(Error::Value(format!(
+                            "CSV record {} has {} fields, expected {}",
+                            i * COPY_BATCH_SIZE + j + 1,
+                            record.len(),
+                            columns.len()
+                        )));
+                    }
+                    for (field, column) in record.iter().zip(&columns) {
+                        params.push(csv_value(field.clone(), column)?);
+                    }
+                }
+                let query = insert.clone() + &vec![placeholders.as_str(); batch.len()].join(", ");
+                match self.client.execute_with_params(&query, params).await? {
+                    ResultSet::Create { count: created } => count += created,
+                    r => return Err(Error::Internal(format!("Unexpected result {:?}", r))),
+                }
+            }
+            Ok(count)
+        };
+        match insert_all.await {
+            Ok(count) if begin => {
+                self.client.execute("COMMIT").await?;
+                Ok(count)
+            }
+            Err(err) if begin => {
+                self.client.execute("ROLLBACK").await?;
+                Err(err)
+            }
+            result => result,
+        }
+    }
+
+    /// Exports a table to a CSV file with a header, returning the number of rows written.
+    async fn copy_to(&self, table: &str, path: &str) -> Result<u64> {
+        let resultset =
+            self.client.execute(&format!("SELECT * FROM {}", quote_ident(table))).await?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let count = resultset.to_csv(&mut file)?;
+        file.flush()?;
+        Ok(count)
+    }
+
+    /// Prints query results as a table with aligned columns, and the row count
+    fn print_table(&self, headers: Vec<String>, rows: Vec<Vec<String>>) {
+        let mut widths: Vec<usize> =
+            headers.iter().map(|h| if self.show_headers { h.chars().count() } else { 0 }).collect();
+        for row in &rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
+            }
+        }
+        let format_row = |row: &[String]| {
+            let fields: Vec<_> =
+                row.iter().zip(&widths).map(|(v, w)| format!("{:<w$}", v, w = *w)).collect();
+            fields.join(" | ").trim_end().to_string()
+        };
+
+        if self.show_headers {
+            println!("{}", format_row(&headers));
+            println!("{}", widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-"));
+        }
+        for row in &rows {
+            println!("{}", format_row(row));
+        }
+        println!("({} {})", rows.len(), if rows.len() == 1 { "row" } else { "rows" });
+    }
+
     /// Prompts the user for input
     fn prompt(&mut self) -> Result<Option<String>> {
         let prompt = match self.client.txn() {
@@ -249,7 +453,15 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
             status.raft.server
         );
 
-        while let Some(input) = self.prompt()? {
+        while let Some(mut input) = self.prompt()? {
+            // Without a terminal, e.g. for a piped script, the editor doesn't validate input, so
+            // read on until the statement is complete.
+            while !is_complete(&input) {
+                match self.prompt()? {
+                    Some(more) => input = format!("{}\n{}", input, more),
+                    None => break,
+                }
+            }
             match self.execute(&input).await {
                 Ok(()) => {}
                 error @ Err(Error::Internal(_)) => return error,
@@ -271,27 +483,126 @@ struct InputValidator;
 
 impl Validator for InputValidator {
     fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        let input = ctx.input();
+        if is_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+
+    fn validate_while_typing(&self) -> bool {
+        false
+    }
+}
 
-        // Empty lines and ! commands are fine.
-        if input.is_empty() || input.starts_with('!') || input == ";" {
-            return Ok(ValidationResult::Valid(None));
+/// Checks whether the input makes up a complete command.
+fn is_complete(input: &str) -> bool {
+    // Empty lines and ! commands are fine.
+    if input.is_empty() || input.starts_with('!') || input == ";" {
+        return true;
+    }
+
+    // For SQL statements, just look for any semicolon or lexer error and if found accept the
+    // input and rely on the server to do further validation and error handling. Otherwise,
+    // wait for more input.
+    for result in Lexer::new(input) {
+        match result {
+            Ok(Token::Semicolon) => return true,
+            Err(_) => return true,
+            _ => {}
         }
+    }
+    false
+}
+
+/// Quotes an identifier, so that names are taken as is.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
 
-        // For SQL statements, just look for any semicolon or lexer error and if found accept the
-        // input and rely on the server to do further validation and error handling. Otherwise,
-        // wait for more input.
-        for result in Lexer::new(ctx.input()) {
-            match result {
-                Ok(Token::Semicolon) => return Ok(ValidationResult::Valid(None)),
-                Err(_) => return Ok(ValidationResult::Valid(None)),
-                _ => {}
+/// Parses CSV records, as written by ResultSet::to_csv. Quoted fields may contain commas, line
+/// breaks and doubled quotes. Empty unquoted fields are returned as None, i.e. NULL, while
+/// quoted ones are empty strings.
+fn parse_csv(input: &str) -> Result<Vec<Vec<Option<String>>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        let mut field = String::new();
+        let quoted = chars.peek() == Some(&'"');
+        if quoted {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"')
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(Error::Parse("Unterminated quoted CSV field".into())),
+                }
             }
         }
-        Ok(ValidationResult::Incomplete)
-    }
+        while let Some(c) = chars.peek().filter(|c| !matches!(c, ',' | '\r' | '\n')) {
+            if quoted {
+                return Err(Error::Parse(format!("Unexpected {} after quoted CSV field", c)));
+            }
+            field.push(*c);
+            chars.next();
+        }
+        record.push(if quoted || !field.is_empty() { Some(field) } else { None });
 
-    fn validate_while_typing(&self) -> bool {
-        false
+        match chars.next() {
+            Some(',') => {}
+            Some(c) => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                records.push(std::mem::take(&mut record));
+            }
+            // A final line break doesn't start another record.
+            None if record == vec![None] => break,
+            None => {
+                records.push(record);
+                break;
+            }
+        }
     }
+    Ok(records)
+}
+
+/// Converts a CSV field to a value of the column's datatype.
+fn csv_value(field: Option<String>, column: &Column) -> Result<Value> {
+    let field = match field {
+        Some(field) => field,
+        None => return Ok(Value::Null),
+    };
+    let invalid = || {
+        Error::Value(format!("Invalid {} {} for column {}", column.datatype, field, column.name))
+    };
+    Ok(match column.datatype {
+        DataType::Boolean => match field.to_uppercase().as_str() {
+            "TRUE" => Value::Boolean(true),
+            "FALSE" => Value::Boolean(false),
+            _ => return Err(invalid()),
+        },
+        DataType::Integer => Value::Integer(field.parse().map_err(|_| invalid())?),
+        DataType::Float => Value::Float(field.parse().map_err(|_| invalid())?),
+        DataType::String => Value::String(field),
+        // As written by Value's Display, e.g. x'00ff'.
+        DataType::Bytes => {
+            let hex = field
+                .strip_prefix("x'")
+                .and_then(|hex| hex.strip_suffix('\''))
+                .filter(|hex| hex.len() % 2 == 0 && hex.is_ascii())
+                .ok_or_else(invalid)?;
+            Value::Bytes(
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+                    .collect::<Result<_>>()?,
+            )
+        }
+    })
 }
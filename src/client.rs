@@ -3,16 +3,18 @@ use crate::server::{Request, Response};
 use crate::sql::engine::{Mode, Status};
 use crate::sql::execution::ResultSet;
 use crate::sql::schema::Table;
+use crate::sql::types::Value;
 
 use futures::future::FutureExt as _;
 use futures::sink::SinkExt as _;
-use futures::stream::TryStreamExt as _;
+use futures::stream::{SplitSink, SplitStream, StreamExt as _, TryStream, TryStreamExt as _};
 use rand::Rng as _;
 use std::cell::Cell;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::ops::{Deref, Drop};
 use std::sync::Arc;
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
 use tokio::sync::{Mutex, MutexGuard};
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
@@ -23,13 +25,37 @@ type Connection = tokio_serde::Framed<
     tokio_serde::formats::Bincode<Result<Response>, Request>,
 >;
 
+/// A client connection, split into halves that are locked separately. A request keeps the stream
+/// half locked until its response has been read, but only locks the sink half to send itself, so
+/// Client::cancel() can send a request while a response is being read. Requests lock the stream
+/// half first, so responses are read in the order the requests were sent.
+struct SplitConnection {
+    sink: Mutex<SplitSink<Connection, Request>>,
+    stream: Mutex<SplitStream<Connection>>,
+}
+
+impl SplitConnection {
+    /// Splits a connection
+    fn new(conn: Connection) -> Self {
+        let (sink, stream) = conn.split();
+        Self { sink: Mutex::new(sink), stream: Mutex::new(stream) }
+    }
+
+    /// Sends a request, returning the locked stream half to read its response from
+    async fn send(&self, request: Request) -> Result<MutexGuard<'_, SplitStream<Connection>>> {
+        let stream = self.stream.lock().await;
+        self.sink.lock().await.send(request).await?;
+        Ok(stream)
+    }
+}
+
 /// Number of serialization retries in with_txn()
 const WITH_TXN_RETRIES: u8 = 8;
 
 /// A toyDB client
 #[derive(Clone)]
 pub struct Client {
-    conn: Arc<Mutex<Connection>>,
+    conn: Arc<SplitConnection>,
     txn: Cell<Option<(u64, Mode)>>,
 }
 
@@ -37,7 +63,7 @@ impl Client {
     /// Creates a new client
     pub async fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
         Ok(Self {
-            conn: Arc::new(Mutex::new(tokio_serde::Framed::new(
+            conn: Arc::new(SplitConnection::new(tokio_serde::Framed::new(
                 Framed::new(TcpStream::connect(addr).await?, LengthDelimitedCodec::new()),
                 tokio_serde::formats::Bincode::default(),
             ))),
@@ -47,18 +73,8 @@ impl Client {
 
     /// Call a server method
     async fn call(&self, request: Request) -> Result<Response> {
-        let mut conn = self.conn.lock().await;
-        self.call_locked(&mut conn, request).await
-    }
-
-    /// Call a server method while holding the mutex lock
-    async fn call_locked(
-        &self,
-        conn: &mut MutexGuard<'_, Connection>,
-        request: Request,
-    ) -> Result<Response> {
-        conn.send(request).await?;
-        match conn.try_next().await? {
+        let mut stream = self.conn.send(request).await?;
+        match stream.try_next().await? {
             Some(result) => result,
             None => Err(Error::Internal("Server disconnected".into())),
         }
@@ -66,20 +82,93 @@ impl Client {
 
     /// Executes a query
     pub async fn execute(&self, query: &str) -> Result<ResultSet> {
-        let mut conn = self.conn.lock().await;
-        let mut resultset =
-            match self.call_locked(&mut conn, Request::Execute(query.into())).await? {
-                Response::Execute(rs) => rs,
-                resp => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
-            };
+        self.execute_with_params(query, Vec::new()).await
+    }
+
+    /// Executes a query with values bound to its ? parameter placeholders
+    pub async fn execute_with_params(&self, query: &str, params: Vec<Value>) -> Result<ResultSet> {
+        let mut stream = self.conn.send(Request::Execute { sql: query.into(), params }).await?;
+        self.receive_execute(&mut *stream).await?
+    }
+
+    /// Prepares a query for repeated execution with execute_prepared(), returning its handle.
+    /// Prepared statements belong to the connection, and are removed when it's closed or they're
+    /// deallocated.
+    pub async fn prepare(&self, query: &str) -> Result<u64> {
+        match self.call(Request::Prepare(query.into())).await? {
+            Response::Prepare(handle) => Ok(handle),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Executes a prepared statement with values bound to its ? parameter placeholders
+    pub async fn execute_prepared(&self, handle: u64, params: Vec<Value>) -> Result<ResultSet> {
+        let mut stream = self.conn.send(Request::ExecutePrepared { handle, params }).await?;
+        self.receive_execute(&mut *stream).await?
+    }
+
+    /// Deallocates a prepared statement, freeing its handle
+    pub async fn deallocate(&self, handle: u64) -> Result<()> {
+        match self.call(Request::Deallocate(handle)).await? {
+            Response::Deallocate => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Executes several queries, sending them all without waiting for each result. The
+    /// results are returned in query order. If the connection fails, the queries without a
+    /// result yet fail with the connection error.
+    pub async fn pipeline(&self, statements: Vec<String>) -> Vec<Result<ResultSet>> {
+        let count = statements.len();
+        // Results are read while queries are sent, so neither side stalls on a full buffer.
+        let mut stream = self.conn.stream.lock().await;
+        let mut sink = self.conn.sink.lock().await;
+        let send = async {
+            for sql in statements {
+                sink.feed(Request::Execute { sql, params: Vec::new() }).await?;
+            }
+            sink.flush().await?;
+            Ok::<_, Error>(())
+        };
+        let mut results = Vec::with_capacity(count);
+        let receive = async {
+            while results.len() < count {
+                results.push(self.receive_execute(&mut *stream).await?);
+            }
+            Ok::<_, Error>(())
+        };
+        if let Err(error) = futures::future::try_join(send, receive).await {
+            while results.len() < count {
+                results.push(Err(error.clone()));
+            }
+        }
+        results
+    }
+
+    /// Receives the result of an Execute request, buffering any rows. Returns an outer error
+    /// if the connection failed, and an inner error if the query failed.
+    async fn receive_execute<S>(&self, stream: &mut S) -> Result<Result<ResultSet>>
+    where
+        S: TryStream<Ok = Result<Response>> + Unpin,
+        Error: From<S::Error>,
+    {
+        let mut resultset = match stream.try_next().await? {
+            Some(Ok(Response::Execute(rs))) => rs,
+            Some(Err(err)) => return Ok(Err(err)),
+            Some(Ok(resp)) => {
+                return Err(Error::Internal(format!("Unexpected response {:?}", resp)))
+            }
+            None => return Err(Error::Internal("Server disconnected".into())),
+        };
         if let ResultSet::Query { columns, .. } = resultset {
             // FIXME We buffer rows for now to avoid lifetime hassles
             let mut rows = Vec::new();
-            while let Some(result) = conn.try_next().await? {
-                match result? {
-                    Response::Row(Some(row)) => rows.push(row),
-                    Response::Row(None) => break,
-                    response => {
+            while let Some(result) = stream.try_next().await? {
+                match result {
+                    Ok(Response::Row(Some(row))) => rows.push(row),
+                    Ok(Response::Row(None)) => break,
+                    Err(err) => return Ok(Err(err)),
+                    Ok(response) => {
                         return Err(Error::Internal(format!("Unexpected response {:?}", response)))
                     }
                 }
@@ -92,7 +181,7 @@ impl Client {
             ResultSet::Rollback { .. } => self.txn.set(None),
             _ => {}
         }
-        Ok(resultset)
+        Ok(Ok(resultset))
     }
 
     /// Fetches the table schema as SQL
@@ -105,8 +194,19 @@ impl Client {
 
     /// Lists database tables
     pub async fn list_tables(&self) -> Result<Vec<String>> {
-        match self.call(Request::ListTables).await? {
-            Response::ListTables(t) => Ok(t),
+        Ok(self.list_tables_with(None, None).await?.0)
+    }
+
+    /// Lists database tables whose names start with the prefix, if any, returning at most limit
+    /// names, if given, and whether more tables match
+    pub async fn list_tables_with(
+        &self,
+        prefix: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<String>, bool)> {
+        let prefix = prefix.map(|p| p.to_string());
+        match self.call(Request::ListTables { prefix, limit }).await? {
+            Response::ListTables { tables, more } => Ok((tables, more)),
             resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
         }
     }
@@ -119,6 +219,91 @@ impl Client {
         }
     }
 
+    /// Checks that the connection to the server works
+    pub async fn ping(&self) -> Result<()> {
+        Self::ping_connection(&self.conn).await
+    }
+
+    /// Pings the server over a client's connection. Unlike ping(), the future doesn't borrow the
+    /// client, which isn't Sync, so Send futures like Pool::get() can await it.
+    async fn ping_connection(conn: &SplitConnection) -> Result<()> {
+        let mut stream = conn.send(Request::Ping).await?;
+        match stream.try_next().await? {
+            Some(Ok(Response::Ping)) => Ok(()),
+            Some(Ok(resp)) => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+            Some(Err(err)) => Err(err),
+            None => Err(Error::Internal("Server disconnected".into())),
+        }
+    }
+
+    /// Cancels the statement being executed over the client's connection, and any sent before it.
+    /// The statement fails with a cancellation error, or if its rows are being received, they
+    /// end early. Statements sent later are unaffected. The future doesn't borrow the client, so
+    /// it can be awaited alongside the statement, or spawned.
+    pub fn cancel(&self) -> impl Future<Output = Result<()>> + Send {
+        let conn = self.conn.clone();
+        async move {
+            conn.sink.lock().await.send(Request::Cancel).await?;
+            Ok(())
+        }
+    }
+
+    /// Checks that a query parses and plans, without executing it or touching the transaction
+    pub async fn validate(&self, query: &str) -> Result<()> {
+        match self.call(Request::Validate(query.into())).await? {
+            Response::Validate => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Clears the statement statistics of the connected server's toydb_stat_statements table
+    pub async fn reset_statement_stats(&self) -> Result<()> {
+        match self.call(Request::ResetStatementStats).await? {
+            Response::ResetStatementStats => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Transfers Raft leadership to the given node, e.g. before restarting the current leader
+    pub async fn transfer_leadership(&self, id: &str) -> Result<()> {
+        match self.call(Request::TransferLeadership(id.into())).await? {
+            Response::TransferLeadership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Adds a node to the cluster, given its ID and Raft address
+    pub async fn add_server(&self, id: &str, addr: &str) -> Result<()> {
+        match self.call(Request::AddServer { id: id.into(), addr: addr.into() }).await? {
+            Response::ChangeMembership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Adds a non-voting learner to the cluster, given its ID and Raft address
+    pub async fn add_learner(&self, id: &str, addr: &str) -> Result<()> {
+        match self.call(Request::AddLearner { id: id.into(), addr: addr.into() }).await? {
+            Response::ChangeMembership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Promotes a learner to a voting member, once it has caught up with the leader
+    pub async fn promote_learner(&self, id: &str) -> Result<()> {
+        match self.call(Request::PromoteLearner(id.into())).await? {
+            Response::ChangeMembership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Removes a node from the cluster
+    pub async fn remove_server(&self, id: &str) -> Result<()> {
+        match self.call(Request::RemoveServer(id.into())).await? {
+            Response::ChangeMembership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
     /// Returns the transaction status of the client
     pub fn txn(&self) -> Option<(u64, Mode)> {
         self.txn.get()
@@ -160,27 +345,40 @@ impl Client {
 /// A toyDB client pool
 pub struct Pool {
     clients: Vec<Mutex<Client>>,
+    addrs: Vec<SocketAddr>,
 }
 
 impl Pool {
     /// Creates a new connection pool for the given servers, eagerly connecting clients.
     pub async fn new<A: ToSocketAddrs + Clone>(addrs: Vec<A>, size: u64) -> Result<Self> {
-        let mut addrs = addrs.into_iter().cycle();
+        let mut resolved = Vec::new();
+        for addr in addrs {
+            match lookup_host(addr).await?.next() {
+                Some(addr) => resolved.push(addr),
+                None => return Err(Error::Config("Server address did not resolve".into())),
+            }
+        }
+        let addrs: Vec<_> = resolved.into_iter().cycle().take(size as usize).collect();
         let clients = futures::future::try_join_all(
-            std::iter::from_fn(|| {
-                Some(Client::new(addrs.next().unwrap()).map(|r| r.map(Mutex::new)))
-            })
-            .take(size as usize),
+            addrs.iter().map(|addr| Client::new(*addr).map(|r| r.map(Mutex::new))),
         )
         .await?;
-        Ok(Self { clients })
+        Ok(Self { clients, addrs })
     }
 
     /// Fetches a client from the pool. It is reset (i.e. any open txns are rolled back) and
-    /// returned when it goes out of scope.
+    /// returned when it goes out of scope. The client is pinged first, and reconnected if the
+    /// ping fails, e.g. because the server restarted. If reconnecting fails too, the broken
+    /// client is returned, and its calls will error.
     pub async fn get(&self) -> PoolClient<'_> {
-        let (client, index, _) =
+        let (mut client, index, _) =
             futures::future::select_all(self.clients.iter().map(|m| m.lock().boxed())).await;
+        let conn = client.conn.clone();
+        if Client::ping_connection(&conn).await.is_err() {
+            if let Ok(reconnected) = Client::new(self.addrs[index]).await {
+                *client = reconnected;
+            }
+        }
         PoolClient::new(index, client)
     }
 
@@ -11,6 +11,9 @@ pub enum Error {
     Config(String),
     Past(String),
     Internal(String),
+    /// A write was rejected because the Raft leader lost contact with a quorum.
+    NoQuorum,
+    PageCorrupt(String),
     Parse(String),
     ReadOnly,
     Serialization,
@@ -24,12 +27,14 @@ impl Display for Error {
         match self {
             Error::Config(s)
             | Error::Internal(s)
+            | Error::PageCorrupt(s)
             | Error::Parse(s)
             | Error::Value(s)
             | Error::Past(s) => {
                 write!(f, "{}", s)
             }
             Error::Abort => write!(f, "Operation aborted"),
+            Error::NoQuorum => write!(f, "No quorum, writes are rejected until it's regained"),
             Error::Serialization => write!(f, "Serialization failure, retry transaction"),
             Error::ReadOnly => write!(f, "Read-only transaction"),
         }
@@ -72,6 +77,12 @@ impl From<rustyline::error::ReadlineError> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Internal(err.to_string())
+    }
+}
+
 impl From<std::array::TryFromSliceError> for Error {
     fn from(err: std::array::TryFromSliceError) -> Self {
         Error::Internal(err.to_string())
@@ -2,12 +2,15 @@
 #![allow(clippy::new_without_default)]
 #![allow(clippy::unneeded_field_pattern)]
 
+pub mod audit;
 pub mod client;
 pub mod error;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod raft;
 pub mod server;
 pub mod sql;
 pub mod storage;
 
 pub use client::Client;
-pub use server::Server;
+pub use server::{DurabilityPreset, Server};
@@ -0,0 +1,232 @@
+//! A Prometheus metrics endpoint, exporting node status in the Prometheus text exposition format
+//! over a minimal HTTP server. Only GET /metrics is served, one request per connection.
+
+use crate::error::{Error, Result};
+use crate::sql;
+
+use ::log::error;
+use std::fmt::Write as _;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::StreamExt as _;
+
+/// The maximum size of an HTTP request head. Scrapers send small requests without bodies.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Serves metrics requests until the returned future is dropped.
+pub async fn serve(listener: TcpListener, engine: sql::engine::Raft) -> Result<()> {
+    let mut listener = TcpListenerStream::new(listener);
+    while let Some(socket) = listener.try_next().await? {
+        let peer = socket.peer_addr()?;
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(socket, engine).await {
+                error!("Metrics client {} error: {}", peer, err);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Handles a single HTTP request.
+async fn handle(mut socket: TcpStream, engine: sql::engine::Raft) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(Error::Value("HTTP request too large".into()));
+        }
+        match socket.read(&mut buf).await? {
+            0 => return Err(Error::Value("Connection closed during HTTP request".into())),
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default().split('?').next().unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => match tokio::task::block_in_place(|| engine.status()) {
+            Ok(status) => ("200 OK", render(&status)),
+            Err(err) => ("503 Service Unavailable", format!("{}\n", err)),
+        },
+        ("GET", _) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method not allowed\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+/// Renders node status as Prometheus metrics.
+pub fn render(status: &sql::engine::Status) -> String {
+    let raft = &status.raft;
+    let mut out = String::new();
+    let is_leader = u64::from(raft.leader == raft.server);
+    metric(
+        &mut out,
+        "toydb_raft_leader",
+        "gauge",
+        "Whether this node is the Raft leader.",
+        is_leader,
+    );
+    metric(&mut out, "toydb_raft_term", "gauge", "The current Raft term.", raft.term);
+    metric(&mut out, "toydb_raft_last_index", "gauge", "The last Raft log index.", raft.last_index);
+    metric(
+        &mut out,
+        "toydb_raft_commit_index",
+        "gauge",
+        "The last committed Raft log index.",
+        raft.commit_index,
+    );
+    metric(
+        &mut out,
+        "toydb_raft_apply_index",
+        "gauge",
+        "The last Raft log index applied to the state machine.",
+        raft.apply_index,
+    );
+    metric(
+        &mut out,
+        "toydb_raft_pending_notifications",
+        "gauge",
+        "The number of applied entries the state machine has yet to notify clients of.",
+        raft.pending_notifications as u64,
+    );
+    metric(
+        &mut out,
+        "toydb_raft_pending_queries",
+        "gauge",
+        "The number of read queries the state machine has yet to execute.",
+        raft.pending_queries as u64,
+    );
+    metric(
+        &mut out,
+        "toydb_raft_storage_bytes",
+        "gauge",
+        "The size of the Raft log storage in bytes.",
+        raft.storage_size,
+    );
+
+    // Peer progress is only known on the leader.
+    let peers = [
+        ("toydb_raft_node_last_index", "The last log index of each node.", &raft.node_last_index),
+        (
+            "toydb_raft_node_next_index",
+            "The next log index the leader will replicate to each peer.",
+            &raft.node_next_index,
+        ),
+        (
+            "toydb_raft_node_last_contact_ticks",
+            "The number of ticks since the leader last heard from each peer.",
+            &raft.node_last_contact,
+        ),
+    ];
+    for (name, help, values) in peers.iter() {
+        if values.is_empty() {
+            continue;
+        }
+        let mut nodes: Vec<_> = values.iter().collect();
+        nodes.sort();
+        header(&mut out, name, "gauge", help);
+        for (node, value) in nodes {
+            writeln!(out, "{}{{node=\"{}\"}} {}", name, escape(node), value).unwrap();
+        }
+    }
+
+    metric(
+        &mut out,
+        "toydb_mvcc_txns_total",
+        "counter",
+        "The number of MVCC transactions started.",
+        status.mvcc.txns,
+    );
+    metric(
+        &mut out,
+        "toydb_mvcc_txns_active",
+        "gauge",
+        "The number of active MVCC transactions.",
+        status.mvcc.txns_active,
+    );
+    out
+}
+
+/// Writes the HELP and TYPE lines of a metric.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Writes an unlabeled metric.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    header(out, name, kind, help);
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raft;
+    use crate::storage::kv;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn render() {
+        let status = sql::engine::Status {
+            raft: raft::Status {
+                server: "a".into(),
+                leader: "a".into(),
+                term: 3,
+                node_last_index: vec![("b".into(), 6), ("a".into(), 7)].into_iter().collect(),
+                node_next_index: vec![("b".into(), 7)].into_iter().collect(),
+                node_last_contact: vec![("b\"".into(), 1)].into_iter().collect(),
+                last_index: 7,
+                commit_index: 6,
+                apply_index: 5,
+                pending_notifications: 2,
+                pending_queries: 1,
+                storage: "hybrid".into(),
+                storage_size: 1024,
+            },
+            mvcc: kv::mvcc::Status { txns: 4, txns_active: 1, storage: "memory".into() },
+        };
+        let output = super::render(&status);
+        let samples: Vec<&str> = output.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            samples,
+            vec![
+                "toydb_raft_leader 1",
+                "toydb_raft_term 3",
+                "toydb_raft_last_index 7",
+                "toydb_raft_commit_index 6",
+                "toydb_raft_apply_index 5",
+                "toydb_raft_pending_notifications 2",
+                "toydb_raft_pending_queries 1",
+                "toydb_raft_storage_bytes 1024",
+                "toydb_raft_node_last_index{node=\"a\"} 7",
+                "toydb_raft_node_last_index{node=\"b\"} 6",
+                "toydb_raft_node_next_index{node=\"b\"} 7",
+                "toydb_raft_node_last_contact_ticks{node=\"b\\\"\"} 1",
+                "toydb_mvcc_txns_total 4",
+                "toydb_mvcc_txns_active 1",
+            ]
+        );
+        assert!(output.contains("# TYPE toydb_mvcc_txns_total counter\n"));
+        assert!(output.contains("# HELP toydb_raft_term The current Raft term.\n"));
+    }
+}
@@ -1,27 +1,53 @@
-use super::{Request, Response, Status};
+use super::{ConfigChange, RaftConfig, Request, Response, Status};
 use crate::error::{Error, Result};
 
+use std::future::Future;
+use std::time::Duration;
 use tokio::sync::{mpsc, oneshot};
 
 /// A client for a local Raft server.
 #[derive(Clone)]
 pub struct Client {
     request_tx: mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response>>)>,
+    /// How long to wait for a response before aborting a request, if at all.
+    timeout: Option<Duration>,
+    /// The number of times to retry aborted queries.
+    query_retries: u32,
 }
 
 impl Client {
-    /// Creates a new Raft client.
+    /// Creates a new Raft client, which waits indefinitely for responses.
     pub fn new(
         request_tx: mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response>>)>,
     ) -> Self {
-        Self { request_tx }
+        Self { request_tx, timeout: None, query_retries: 0 }
+    }
+
+    /// Aborts requests that don't get a response within the given timeout with Error::Abort.
+    /// An aborted request may still be executed by the server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retries aborted queries up to the given number of times, e.g. after a timeout or leader
+    /// change. Queries don't modify the state machine, so retrying them is safe. Mutations are
+    /// never retried, since an aborted mutation may have been applied anyway.
+    pub fn with_query_retries(mut self, retries: u32) -> Self {
+        self.query_retries = retries;
+        self
     }
 
     /// Executes a request against the Raft cluster.
     async fn request(&self, request: Request) -> Result<Response> {
         let (response_tx, response_rx) = oneshot::channel();
         self.request_tx.send((request, response_tx))?;
-        response_rx.await?
+        match self.timeout {
+            Some(timeout) => {
+                tokio::time::timeout(timeout, response_rx).await.map_err(|_| Error::Abort)??
+            }
+            None => response_rx.await?,
+        }
     }
 
     /// Mutates the Raft state machine.
@@ -32,19 +58,237 @@ impl Client {
         }
     }
 
-    /// Queries the Raft state machine.
+    /// Mutates the Raft state machine with several commands in a single request, which are
+    /// appended as consecutive log entries. Returns their responses in order. If a command fails,
+    /// its error is returned, but the other commands are still applied.
+    pub async fn mutate_batch(&self, commands: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        match self.request(Request::MutateBatch(commands)).await? {
+            Response::StateBatch(responses) => Ok(responses),
+            resp => Err(Error::Internal(format!("Unexpected Raft mutate response {:?}", resp))),
+        }
+    }
+
+    /// Queries the Raft state machine, retrying aborted queries if configured.
     pub async fn query(&self, command: Vec<u8>) -> Result<Vec<u8>> {
-        match self.request(Request::Query(command)).await? {
-            Response::State(response) => Ok(response),
-            resp => Err(Error::Internal(format!("Unexpected Raft query response {:?}", resp))),
+        let mut retries = self.query_retries;
+        loop {
+            match self.request(Request::Query(command.clone())).await {
+                Ok(Response::State(response)) => return Ok(response),
+                Ok(resp) => {
+                    return Err(Error::Internal(format!(
+                        "Unexpected Raft query response {:?}",
+                        resp
+                    )))
+                }
+                Err(Error::Abort) if retries > 0 => retries -= 1,
+                Err(err) => return Err(err),
+            }
         }
     }
 
     /// Fetches Raft node status.
     pub async fn status(&self) -> Result<Status> {
         match self.request(Request::Status).await? {
-            Response::Status(status) => Ok(status),
+            Response::Status(status) => Ok(*status),
             resp => Err(Error::Internal(format!("Unexpected Raft status response {:?}", resp))),
         }
     }
+
+    /// Transfers leadership to the given node, once it has caught up with the leader's log.
+    pub async fn transfer_leadership(&self, target: &str) -> Result<()> {
+        match self.request(Request::TransferLeadership(target.to_string())).await? {
+            Response::TransferLeadership => Ok(()),
+            resp => Err(Error::Internal(format!("Unexpected Raft transfer response {:?}", resp))),
+        }
+    }
+
+    /// Changes the cluster membership, returning once the change has been committed.
+    pub async fn change_config(&self, config: ConfigChange) -> Result<()> {
+        match self.request(Request::ConfigChange(config)).await? {
+            Response::ConfigChange => Ok(()),
+            resp => Err(Error::Internal(format!("Unexpected Raft config response {:?}", resp))),
+        }
+    }
+
+    /// Updates the local node's timing configuration, e.g. heartbeat interval and election
+    /// timeouts. The tick duration can't be changed at runtime.
+    pub async fn update_config(&self, config: RaftConfig) -> Result<()> {
+        match self.request(Request::UpdateConfig(config)).await? {
+            Response::UpdateConfig => Ok(()),
+            resp => Err(Error::Internal(format!("Unexpected Raft config response {:?}", resp))),
+        }
+    }
+}
+
+/// A blocking client for a local Raft server, for callers outside of an async runtime, e.g.
+/// CLI tools. It owns a current-thread Tokio runtime which drives the async client. It must not
+/// be used or dropped from within an async runtime.
+pub struct SyncClient {
+    client: Client,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl SyncClient {
+    /// Creates a new blocking Raft client, wrapping an async client.
+    pub fn new(client: Client) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { client, runtime })
+    }
+
+    /// Runs a request to completion. Blocking a runtime thread would panic or stall the
+    /// runtime, so this fails when called from within one.
+    fn block_on<T>(&self, request: impl Future<Output = Result<T>>) -> Result<T> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(Error::Internal("SyncClient can't be used within an async runtime".into()));
+        }
+        self.runtime.block_on(request)
+    }
+
+    /// Mutates the Raft state machine.
+    pub fn mutate(&self, command: Vec<u8>) -> Result<Vec<u8>> {
+        self.block_on(self.client.mutate(command))
+    }
+
+    /// Queries the Raft state machine.
+    pub fn query(&self, command: Vec<u8>) -> Result<Vec<u8>> {
+        self.block_on(self.client.query(command))
+    }
+
+    /// Fetches Raft node status.
+    pub fn status(&self) -> Result<Status> {
+        self.block_on(self.client.status())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Starts a fake Raft server thread, which echoes mutations and queries with a prefix and
+    /// fails everything else.
+    fn setup() -> Client {
+        let (request_tx, mut request_rx) =
+            mpsc::unbounded_channel::<(Request, oneshot::Sender<Result<Response>>)>();
+        std::thread::spawn(move || {
+            while let Some((request, response_tx)) = futures::executor::block_on(request_rx.recv())
+            {
+                let response = match request {
+                    Request::Mutate(command) => {
+                        Ok(Response::State([&b"mutate "[..], &command[..]].concat()))
+                    }
+                    Request::Query(command) => {
+                        Ok(Response::State([&b"query "[..], &command[..]].concat()))
+                    }
+                    Request::MutateBatch(commands) => Ok(Response::StateBatch(
+                        commands
+                            .into_iter()
+                            .map(|command| [&b"mutate "[..], &command[..]].concat())
+                            .collect(),
+                    )),
+                    _ => Err(Error::Internal("unsupported".into())),
+                };
+                response_tx.send(response).ok();
+            }
+        });
+        Client::new(request_tx)
+    }
+
+    #[test]
+    fn sync_client() -> Result<()> {
+        let client = SyncClient::new(setup())?;
+        assert_eq!(client.mutate(b"a".to_vec())?, b"mutate a".to_vec());
+        assert_eq!(client.query(b"b".to_vec())?, b"query b".to_vec());
+        assert_eq!(client.status(), Err(Error::Internal("unsupported".into())));
+
+        // The client can be shared by plain threads.
+        let client = std::sync::Arc::new(client);
+        let threads: Vec<_> = (0..4u8)
+            .map(|i| {
+                let client = client.clone();
+                std::thread::spawn(move || client.query(vec![b'0' + i]))
+            })
+            .collect();
+        for (i, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap()?, format!("query {}", i).into_bytes());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mutate_batch() -> Result<()> {
+        let client = setup();
+        assert_eq!(
+            client.mutate_batch(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]).await?,
+            vec![b"mutate a".to_vec(), b"mutate b".to_vec(), b"mutate c".to_vec()]
+        );
+        assert_eq!(client.mutate_batch(vec![]).await?, Vec::<Vec<u8>>::new());
+        Ok(())
+    }
+
+    /// Starts a fake Raft server task, which never responds to the first `stall` requests and
+    /// echoes the rest. Returns the client and a receiver of all received requests.
+    fn setup_stalled(stall: usize) -> (Client, mpsc::UnboundedReceiver<Request>) {
+        let (request_tx, mut request_rx) =
+            mpsc::unbounded_channel::<(Request, oneshot::Sender<Result<Response>>)>();
+        let (seen_tx, seen_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut stalled = Vec::new();
+            while let Some((request, response_tx)) = request_rx.recv().await {
+                seen_tx.send(request.clone()).ok();
+                if stalled.len() < stall {
+                    stalled.push(response_tx);
+                    continue;
+                }
+                if let Request::Query(command) | Request::Mutate(command) = request {
+                    response_tx.send(Ok(Response::State(command))).ok();
+                }
+            }
+        });
+        (Client::new(request_tx), seen_rx)
+    }
+
+    #[tokio::test]
+    async fn timeout() -> Result<()> {
+        let (client, mut seen_rx) = setup_stalled(usize::MAX);
+        let client = client.with_timeout(Duration::from_millis(10));
+        assert_eq!(client.query(b"a".to_vec()).await, Err(Error::Abort));
+        assert_eq!(client.mutate(b"b".to_vec()).await, Err(Error::Abort));
+        assert_eq!(seen_rx.recv().await, Some(Request::Query(b"a".to_vec())));
+        assert_eq!(seen_rx.recv().await, Some(Request::Mutate(b"b".to_vec())));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_retries() -> Result<()> {
+        // An aborted query is retried, up to the retry limit.
+        let (client, _) = setup_stalled(2);
+        let client = client.with_timeout(Duration::from_millis(10)).with_query_retries(2);
+        assert_eq!(client.query(b"a".to_vec()).await?, b"a".to_vec());
+
+        let (client, _) = setup_stalled(3);
+        let client = client.with_timeout(Duration::from_millis(10)).with_query_retries(2);
+        assert_eq!(client.query(b"a".to_vec()).await, Err(Error::Abort));
+
+        // Mutations aren't retried.
+        let (client, mut seen_rx) = setup_stalled(1);
+        let client = client.with_timeout(Duration::from_millis(10)).with_query_retries(2);
+        assert_eq!(client.mutate(b"b".to_vec()).await, Err(Error::Abort));
+        drop(client);
+        assert_eq!(seen_rx.recv().await, Some(Request::Mutate(b"b".to_vec())));
+        assert_eq!(seen_rx.recv().await, None);
+        Ok(())
+    }
+
+    #[test]
+    fn sync_client_in_runtime() -> Result<()> {
+        let client = SyncClient::new(setup())?;
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        let result = runtime.block_on(async { client.query(b"a".to_vec()) });
+        assert_eq!(
+            result,
+            Err(Error::Internal("SyncClient can't be used within an async runtime".into()))
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,182 @@
+use futures::future::{BoxFuture, FutureExt as _};
+use futures::stream::{BoxStream, StreamExt as _};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// A source of time for Raft servers. This allows tests to run Raft against a virtual clock
+/// that they advance explicitly, rather than wall-clock time.
+pub trait Clock: Send + Sync {
+    /// Returns the time elapsed since the clock was created.
+    fn now(&self) -> Duration;
+
+    /// Returns a stream that yields once per interval, with the first tick yielded immediately.
+    fn ticker(&self, interval: Duration) -> BoxStream<'static, ()>;
+
+    /// Returns a future that completes after the given duration.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// A wall clock, using the Tokio timer.
+pub struct TokioClock {
+    start: Instant,
+}
+
+impl TokioClock {
+    /// Creates a new Tokio clock.
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn ticker(&self, interval: Duration) -> BoxStream<'static, ()> {
+        futures::stream::unfold(tokio::time::interval(interval), |mut interval| async move {
+            interval.tick().await;
+            Some(((), interval))
+        })
+        .boxed()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        tokio::time::sleep(duration).boxed()
+    }
+}
+
+/// A virtual clock, which only moves forward when advanced. Timers fire during the advance
+/// call, in deadline order, so simulations are deterministic. Clones share the same time.
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    inner: Arc<Mutex<VirtualTime>>,
+}
+
+#[derive(Default)]
+struct VirtualTime {
+    now: Duration,
+    timers: Vec<Timer>,
+}
+
+/// A pending virtual timer.
+struct Timer {
+    deadline: Duration,
+    /// The interval for recurring timers, or None for one-off timers.
+    interval: Option<Duration>,
+    tx: mpsc::UnboundedSender<()>,
+}
+
+impl VirtualClock {
+    /// Creates a new virtual clock, starting at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the clock by the given duration, firing any timers that come due.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.inner.lock().unwrap();
+        let until = time.now + duration;
+        // Fire timers one at a time in deadline order, such that recurring timers interleave.
+        loop {
+            let next = time
+                .timers
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, t)| t.deadline <= until)
+                .min_by_key(|(i, t)| (t.deadline, *i));
+            let (index, deadline, interval) = match next {
+                Some((index, timer)) => (index, timer.deadline, timer.interval),
+                None => break,
+            };
+            let timer = &mut time.timers[index];
+            let fired = timer.tx.send(()).is_ok();
+            match interval {
+                Some(interval) if fired => timer.deadline += interval,
+                _ => {
+                    time.timers.remove(index);
+                }
+            }
+            time.now = deadline;
+        }
+        time.now = until;
+    }
+
+    /// Registers a timer, returning its receiver.
+    fn timer(&self, delay: Duration, interval: Option<Duration>) -> mpsc::UnboundedReceiver<()> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut time = self.inner.lock().unwrap();
+        let deadline = time.now + delay;
+        time.timers.push(Timer { deadline, interval, tx });
+        rx
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.inner.lock().unwrap().now
+    }
+
+    fn ticker(&self, interval: Duration) -> BoxStream<'static, ()> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tx.send(()).ok();
+        let mut time = self.inner.lock().unwrap();
+        let deadline = time.now + interval;
+        time.timers.push(Timer { deadline, interval: Some(interval), tx });
+        tokio_stream::wrappers::UnboundedReceiverStream::new(rx).boxed()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut rx = self.timer(duration, None);
+        async move {
+            rx.recv().await;
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{FutureExt, StreamExt};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn virtual_clock() {
+        let clock = VirtualClock::new();
+        let mut fast = clock.ticker(Duration::from_millis(10));
+        let mut slow = clock.ticker(Duration::from_millis(25));
+        let mut sleep = clock.sleep(Duration::from_millis(30));
+
+        let ticks = |stream: &mut BoxStream<'static, ()>| {
+            let mut count = 0;
+            while let Some(Some(())) = stream.next().now_or_never() {
+                count += 1;
+            }
+            count
+        };
+
+        // The first tick is immediate.
+        assert_eq!(ticks(&mut fast), 1);
+        assert_eq!(ticks(&mut slow), 1);
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_millis(29));
+        assert_eq!(clock.now(), Duration::from_millis(29));
+        assert_eq!(ticks(&mut fast), 2);
+        assert_eq!(ticks(&mut slow), 1);
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(ticks(&mut fast), 1);
+        assert_eq!(ticks(&mut slow), 0);
+        assert!(sleep.now_or_never().is_some());
+    }
+}
@@ -3,6 +3,7 @@ use crate::storage::log;
 use crate::storage::log::Range;
 
 use ::log::debug;
+use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use serde_derive::{Deserialize, Serialize};
 use std::ops::RangeBounds;
@@ -16,18 +17,53 @@ pub struct Entry {
     pub term: u64,
     /// The state machine command. None is used to commit noops during leader election.
     pub command: Option<Vec<u8>>,
+    /// A cluster membership change, if any. These are applied by the Raft nodes themselves
+    /// rather than the state machine.
+    pub config: Option<ConfigChange>,
+}
+
+/// A cluster membership change, adding or removing a single server at a time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConfigChange {
+    /// Adds a server with the given ID and Raft address.
+    AddServer { id: String, addr: String },
+    /// Adds a non-voting learner with the given ID and Raft address, which receives the log but
+    /// doesn't count towards quorum until promoted.
+    AddLearner { id: String, addr: String },
+    /// Promotes a learner to a voting member.
+    PromoteLearner { id: String },
+    /// Removes the server with the given ID, either a voter or a learner.
+    RemoveServer { id: String },
+}
+
+/// A snapshot of the state machine, covering all log entries up to and including its index.
+#[derive(Clone, Derivative, PartialEq, Serialize, Deserialize)]
+#[derivative(Debug)]
+pub struct Snapshot {
+    /// The index of the last entry applied to the snapshot.
+    pub index: u64,
+    /// The term of the last entry applied to the snapshot.
+    pub term: u64,
+    /// The cluster membership changes up to and including the snapshot index, since the log
+    /// entries recording them may be compacted.
+    pub configs: Vec<ConfigChange>,
+    /// The serialized state machine, as given by State::snapshot().
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
 }
 
 /// A metadata key
 #[derive(Clone, Debug, PartialEq)]
 pub enum Key {
     TermVote,
+    Snapshot,
 }
 
 impl Key {
     fn encode(&self) -> Vec<u8> {
         match self {
             Self::TermVote => vec![0x00],
+            Self::Snapshot => vec![0x01],
         }
     }
 }
@@ -47,13 +83,30 @@ pub struct Log {
     pub(super) commit_index: u64,
     /// The term of the last committed entry.
     pub(super) commit_term: u64,
+    /// The index of the last saved snapshot, if any.
+    pub(super) snapshot_index: u64,
+    /// The term of the last saved snapshot, if any.
+    pub(super) snapshot_term: u64,
 }
 
 impl Log {
     /// Creates a new log, using a log::Store for storage.
-    pub fn new(store: Box<dyn log::Store>) -> Result<Self> {
+    pub fn new(mut store: Box<dyn log::Store>) -> Result<Self> {
+        // Entries covered by the snapshot may have been compacted.
+        let (snapshot_index, snapshot_term) = store
+            .get_metadata(&Key::Snapshot.encode())?
+            .map(|v| Self::deserialize::<Snapshot>(&v))
+            .transpose()?
+            .map(|s| (s.index, s.term))
+            .unwrap_or((0, 0));
+        // A snapshot beyond the committed index can only come from an interrupted install, so
+        // finish replacing the log with it.
+        if snapshot_index > store.committed() {
+            store.reset(snapshot_index)?;
+        }
         let (commit_index, commit_term) = match store.committed() {
             0 => (0, 0),
+            index if index == snapshot_index => (snapshot_index, snapshot_term),
             index => store
                 .get(index)?
                 .map(|v| Self::deserialize::<Entry>(&v))
@@ -63,6 +116,7 @@ impl Log {
         };
         let (last_index, last_term) = match store.len() {
             0 => (0, 0),
+            index if index == snapshot_index => (snapshot_index, snapshot_term),
             index => store
                 .get(index)?
                 .map(|v| Self::deserialize::<Entry>(&v))
@@ -70,12 +124,35 @@ impl Log {
                 .map(|e| (e.index, e.term))
                 .ok_or_else(|| Error::Internal("Last entry not found".into()))?,
         };
-        Ok(Self { store, last_index, last_term, commit_index, commit_term })
+        Ok(Self {
+            store,
+            last_index,
+            last_term,
+            commit_index,
+            commit_term,
+            snapshot_index,
+            snapshot_term,
+        })
     }
 
     /// Appends a command to the log, returning the entry.
     pub fn append(&mut self, term: u64, command: Option<Vec<u8>>) -> Result<Entry> {
-        let entry = Entry { index: self.last_index + 1, term, command };
+        self.append_entry(term, command, None)
+    }
+
+    /// Appends a cluster membership change to the log, returning the entry.
+    pub fn append_config(&mut self, term: u64, config: ConfigChange) -> Result<Entry> {
+        self.append_entry(term, None, Some(config))
+    }
+
+    /// Appends an entry to the log.
+    fn append_entry(
+        &mut self,
+        term: u64,
+        command: Option<Vec<u8>>,
+        config: Option<ConfigChange>,
+    ) -> Result<Entry> {
+        let entry = Entry { index: self.last_index + 1, term, command, config };
         debug!("Appending log entry {}: {:?}", entry.index, entry);
         self.store.append(Self::serialize(&entry)?)?;
         self.last_index = entry.index;
@@ -94,16 +171,22 @@ impl Log {
         Ok(index)
     }
 
+    /// Fsyncs committed entries whose fsync was deferred by the store's sync policy, once due.
+    pub fn sync_deferred(&mut self) -> Result<()> {
+        self.store.sync_deferred()
+    }
+
     /// Fetches an entry at an index
     pub fn get(&self, index: u64) -> Result<Option<Entry>> {
         self.store.get(index)?.map(|v| Self::deserialize(&v)).transpose()
     }
 
-    /// Checks if the log contains an entry
+    /// Checks if the log contains an entry, including the last entry covered by the snapshot
     pub fn has(&self, index: u64, term: u64) -> Result<bool> {
         match self.get(index)? {
             Some(entry) => Ok(entry.term == term),
             None if index == 0 && term == 0 => Ok(true),
+            None if index == self.snapshot_index && term == self.snapshot_term => Ok(true),
             None => Ok(false),
         }
     }
@@ -132,7 +215,7 @@ impl Log {
                 }
                 self.truncate(entry.index - 1)?;
             }
-            self.append(entry.term, entry.command)?;
+            self.append_entry(entry.term, entry.command, entry.config)?;
         }
         Ok(self.last_index)
     }
@@ -143,6 +226,7 @@ impl Log {
         debug!("Truncating log from entry {}", index);
         let (index, term) = match self.store.truncate(index)? {
             0 => (0, 0),
+            i if i == self.snapshot_index => (self.snapshot_index, self.snapshot_term),
             i => self
                 .store
                 .get(i)?
@@ -169,9 +253,91 @@ impl Log {
         Ok((term, voted_for))
     }
 
-    /// Saves information about the most recent term.
+    /// Saves information about the most recent term, and flushes it to durable storage. This must
+    /// complete before responding to a vote request, otherwise a node could vote twice in the same
+    /// term after a crash.
     pub fn save_term(&mut self, term: u64, voted_for: Option<&str>) -> Result<()> {
-        self.store.set_metadata(&Key::TermVote.encode(), Self::serialize(&(term, voted_for))?)
+        self.store.set_metadata(&Key::TermVote.encode(), Self::serialize(&(term, voted_for))?)?;
+        self.store.flush()
+    }
+
+    /// Loads the last saved state machine snapshot, if any.
+    pub fn load_snapshot(&self) -> Result<Option<Snapshot>> {
+        self.store.get_metadata(&Key::Snapshot.encode())?.map(|v| Self::deserialize(&v)).transpose()
+    }
+
+    /// Saves a state machine snapshot in place of the previous one, and flushes it to durable
+    /// storage. The snapshot can't be past the commit index, and snapshots that aren't newer than
+    /// the saved one are ignored. The snapshot's membership changes are taken from the previous
+    /// snapshot and the log.
+    pub fn save_snapshot(&mut self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.index > self.commit_index {
+            return Err(Error::Internal(format!(
+                "Snapshot index {} is beyond commit index {}",
+                snapshot.index, self.commit_index
+            )));
+        }
+        if snapshot.index <= self.snapshot_index {
+            return Ok(());
+        }
+        debug!("Saving snapshot at index {}", snapshot.index);
+        let mut configs = self.load_snapshot()?.map(|s| s.configs).unwrap_or_default();
+        for entry in self.scan((self.snapshot_index + 1)..=snapshot.index) {
+            configs.extend(entry?.config);
+        }
+        let snapshot = Snapshot {
+            index: snapshot.index,
+            term: snapshot.term,
+            configs,
+            data: snapshot.data.clone(),
+        };
+        self.store.set_metadata(&Key::Snapshot.encode(), Self::serialize(&snapshot)?)?;
+        self.store.flush()?;
+        self.snapshot_index = snapshot.index;
+        self.snapshot_term = snapshot.term;
+        Ok(())
+    }
+
+    /// Installs a snapshot received from the leader, replacing the saved snapshot and all log
+    /// entries covered by it, and commits up to its index. If the log contains the snapshot's last
+    /// entry the following entries are kept, otherwise the log is discarded and restarts after the
+    /// snapshot. Snapshots that aren't past the commit index are ignored.
+    pub fn install_snapshot(&mut self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.index <= self.commit_index {
+            return Ok(());
+        }
+        debug!("Installing snapshot at index {}", snapshot.index);
+        // The snapshot is saved before changing the log, so an interrupted install is completed
+        // when the log is reopened.
+        self.store.set_metadata(&Key::Snapshot.encode(), Self::serialize(snapshot)?)?;
+        self.store.flush()?;
+        let keep = self.has(snapshot.index, snapshot.term)?;
+        self.snapshot_index = snapshot.index;
+        self.snapshot_term = snapshot.term;
+        if keep {
+            self.commit(snapshot.index)?;
+            self.store.compact(snapshot.index)?;
+        } else {
+            self.store.reset(snapshot.index)?;
+            self.last_index = snapshot.index;
+            self.last_term = snapshot.term;
+            self.commit_index = snapshot.index;
+            self.commit_term = snapshot.term;
+        }
+        Ok(())
+    }
+
+    /// Compacts the log by removing entries up to and including an index, returning the compacted
+    /// index. Refuses to remove entries that aren't covered by the saved snapshot.
+    pub fn compact(&mut self, index: u64) -> Result<u64> {
+        if index > self.snapshot_index {
+            return Err(Error::Internal(format!(
+                "Cannot compact beyond snapshot index {}",
+                self.snapshot_index
+            )));
+        }
+        debug!("Compacting log up to entry {}", index);
+        self.store.compact(index)
     }
 
     /// Serializes a value for the log store.
@@ -213,10 +379,13 @@ mod tests {
         assert_eq!(Ok(None), l.get(1));
 
         assert_eq!(
-            Entry { index: 1, term: 3, command: Some(vec![0x01]) },
+            Entry { index: 1, term: 3, command: Some(vec![0x01]), config: None },
             l.append(3, Some(vec![0x01]))?
         );
-        assert_eq!(Some(Entry { index: 1, term: 3, command: Some(vec![0x01]) }), l.get(1)?);
+        assert_eq!(
+            Some(Entry { index: 1, term: 3, command: Some(vec![0x01]), config: None }),
+            l.get(1)?
+        );
         assert_eq!(None, l.get(2)?);
 
         assert_eq!(1, l.last_index);
@@ -229,8 +398,19 @@ mod tests {
     #[test]
     fn append_none() -> Result<()> {
         let (mut l, _) = setup()?;
-        assert_eq!(Entry { index: 1, term: 3, command: None }, l.append(3, None)?);
-        assert_eq!(Some(Entry { index: 1, term: 3, command: None }), l.get(1)?);
+        assert_eq!(Entry { index: 1, term: 3, command: None, config: None }, l.append(3, None)?);
+        assert_eq!(Some(Entry { index: 1, term: 3, command: None, config: None }), l.get(1)?);
+        Ok(())
+    }
+
+    #[test]
+    fn append_config() -> Result<()> {
+        let (mut l, _) = setup()?;
+        let config = ConfigChange::AddServer { id: "d".into(), addr: "localhost:9704".into() };
+        let entry = Entry { index: 1, term: 3, command: None, config: Some(config.clone()) };
+        assert_eq!(entry, l.append_config(3, config)?);
+        assert_eq!(Some(entry), l.get(1)?);
+        assert_eq!(1, l.last_index);
         Ok(())
     }
 
@@ -242,9 +422,15 @@ mod tests {
         l.append(2, Some(vec![0x03]))?;
 
         let l = Log::new(store)?;
-        assert_eq!(Some(Entry { index: 1, term: 1, command: Some(vec![0x01]) }), l.get(1)?);
-        assert_eq!(Some(Entry { index: 2, term: 2, command: None }), l.get(2)?);
-        assert_eq!(Some(Entry { index: 3, term: 2, command: Some(vec![0x03]) }), l.get(3)?);
+        assert_eq!(
+            Some(Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None }),
+            l.get(1)?
+        );
+        assert_eq!(Some(Entry { index: 2, term: 2, command: None, config: None }), l.get(2)?);
+        assert_eq!(
+            Some(Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None }),
+            l.get(3)?
+        );
         Ok(())
     }
 
@@ -294,7 +480,10 @@ mod tests {
         assert_eq!(None, l.get(1)?);
 
         l.append(3, Some(vec![0x01]))?;
-        assert_eq!(Some(Entry { index: 1, term: 3, command: Some(vec![0x01]) }), l.get(1)?);
+        assert_eq!(
+            Some(Entry { index: 1, term: 3, command: Some(vec![0x01]), config: None }),
+            l.get(1)?
+        );
         assert_eq!(None, l.get(2)?);
         Ok(())
     }
@@ -323,16 +512,16 @@ mod tests {
 
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), config: None },
             ],
             l.scan(0..).collect::<Result<Vec<_>>>()?
         );
         assert_eq!(
             vec![
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), config: None },
             ],
             l.scan(2..).collect::<Result<Vec<_>>>()?
         );
@@ -371,16 +560,16 @@ mod tests {
         assert_eq!(
             4,
             l.splice(vec![
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 4, command: Some(vec![0x04]) },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
+                Entry { index: 4, term: 4, command: Some(vec![0x04]), config: None },
             ])?
         );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 4, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
+                Entry { index: 4, term: 4, command: Some(vec![0x04]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -399,14 +588,14 @@ mod tests {
         assert_eq!(
             2,
             l.splice(vec![
-                Entry { index: 1, term: 4, command: Some(vec![0x0a]) },
-                Entry { index: 2, term: 4, command: Some(vec![0x0b]) },
+                Entry { index: 1, term: 4, command: Some(vec![0x0a]), config: None },
+                Entry { index: 2, term: 4, command: Some(vec![0x0b]), config: None },
             ])?
         );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 4, command: Some(vec![0x0a]) },
-                Entry { index: 2, term: 4, command: Some(vec![0x0b]) },
+                Entry { index: 1, term: 4, command: Some(vec![0x0a]), config: None },
+                Entry { index: 2, term: 4, command: Some(vec![0x0b]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -424,16 +613,16 @@ mod tests {
         assert_eq!(
             4,
             l.splice(vec![
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 4, command: Some(vec![0x04]) },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
+                Entry { index: 4, term: 4, command: Some(vec![0x04]), config: None },
             ])?
         );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 4, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
+                Entry { index: 4, term: 4, command: Some(vec![0x04]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -453,15 +642,15 @@ mod tests {
         assert_eq!(
             3,
             l.splice(vec![
-                Entry { index: 2, term: 3, command: Some(vec![0x0b]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x0c]) }
+                Entry { index: 2, term: 3, command: Some(vec![0x0b]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x0c]), config: None }
             ])?
         );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 3, command: Some(vec![0x0b]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x0c]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 3, command: Some(vec![0x0b]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x0c]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -480,15 +669,15 @@ mod tests {
         assert_eq!(
             Err(Error::Internal("Spliced entries must be contiguous".into())),
             l.splice(vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
             ])
         );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -505,15 +694,15 @@ mod tests {
         assert_eq!(
             Err(Error::Internal("Spliced entries cannot begin past last index".into())),
             l.splice(vec![
-                Entry { index: 5, term: 3, command: Some(vec![0x05]) },
-                Entry { index: 6, term: 3, command: Some(vec![0x06]) },
+                Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
+                Entry { index: 6, term: 3, command: Some(vec![0x06]), config: None },
             ])
         );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -527,12 +716,15 @@ mod tests {
         l.append(2, Some(vec![0x02]))?;
         l.append(3, Some(vec![0x03]))?;
 
-        assert_eq!(3, l.splice(vec![Entry { index: 2, term: 2, command: Some(vec![0x02]) },])?);
+        assert_eq!(
+            3,
+            l.splice(vec![Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },])?
+        );
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -549,8 +741,8 @@ mod tests {
         assert_eq!(2, l.truncate(2)?);
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -567,9 +759,9 @@ mod tests {
         assert_eq!(3, l.truncate(4)?);
         assert_eq!(
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 2, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 3, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), config: None },
+                Entry { index: 3, term: 3, command: Some(vec![0x03]), config: None },
             ],
             l.scan(..).collect::<Result<Vec<_>>>()?
         );
@@ -603,4 +795,178 @@ mod tests {
         assert!(l.scan(..).collect::<Result<Vec<_>>>()?.is_empty());
         Ok(())
     }
+
+    #[test]
+    fn load_save_snapshot() -> Result<()> {
+        let (mut l, store) = setup()?;
+        assert_eq!(None, l.load_snapshot()?);
+
+        l.append(1, Some(vec![0x01]))?;
+        l.append(2, Some(vec![0x02]))?;
+        l.append(2, Some(vec![0x03]))?;
+        l.commit(2)?;
+
+        // Snapshots can't be taken of uncommitted entries.
+        let snapshot = Snapshot { index: 3, term: 2, configs: vec![], data: vec![0x03] };
+        assert_eq!(
+            Err(Error::Internal("Snapshot index 3 is beyond commit index 2".into())),
+            l.save_snapshot(&snapshot)
+        );
+
+        let snapshot = Snapshot { index: 2, term: 2, configs: vec![], data: vec![0x02] };
+        l.save_snapshot(&snapshot)?;
+        assert_eq!(Some(snapshot.clone()), l.load_snapshot()?);
+        assert_eq!(2, l.snapshot_index);
+        assert_eq!(2, l.snapshot_term);
+
+        // Older snapshots are ignored.
+        l.save_snapshot(&Snapshot { index: 1, term: 1, configs: vec![], data: vec![0x01] })?;
+        assert_eq!(Some(snapshot.clone()), l.load_snapshot()?);
+
+        // The snapshot is persisted.
+        let l = Log::new(store)?;
+        assert_eq!(Some(snapshot), l.load_snapshot()?);
+        assert_eq!(2, l.snapshot_index);
+        assert_eq!(2, l.snapshot_term);
+        Ok(())
+    }
+
+    #[test]
+    fn compact() -> Result<()> {
+        let (mut l, store) = setup()?;
+        l.append(1, Some(vec![0x01]))?;
+        l.append(2, Some(vec![0x02]))?;
+        l.append(2, Some(vec![0x03]))?;
+        l.commit(3)?;
+
+        // Entries not covered by a snapshot can't be compacted.
+        assert_eq!(
+            Err(Error::Internal("Cannot compact beyond snapshot index 0".into())),
+            l.compact(1)
+        );
+
+        l.save_snapshot(&Snapshot { index: 2, term: 2, configs: vec![], data: vec![0x02] })?;
+        assert_eq!(
+            Err(Error::Internal("Cannot compact beyond snapshot index 2".into())),
+            l.compact(3)
+        );
+        assert_eq!(2, l.compact(2)?);
+        assert_eq!(None, l.get(2)?);
+        assert_eq!(
+            vec![Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None }],
+            l.scan(..).collect::<Result<Vec<_>>>()?
+        );
+
+        // The last compacted entry is still known via the snapshot.
+        assert_eq!(true, l.has(2, 2)?);
+        assert_eq!(false, l.has(2, 1)?);
+        assert_eq!(false, l.has(1, 1)?);
+
+        // A snapshot covering the whole log allows compacting all entries.
+        l.save_snapshot(&Snapshot { index: 3, term: 2, configs: vec![], data: vec![0x03] })?;
+        assert_eq!(3, l.compact(3)?);
+        assert!(l.scan(..).collect::<Result<Vec<_>>>()?.is_empty());
+
+        // The commit and last index are recovered from the snapshot when compacted.
+        let mut l = Log::new(store)?;
+        assert_eq!(3, l.commit_index);
+        assert_eq!(2, l.commit_term);
+        assert_eq!(3, l.last_index);
+        assert_eq!(2, l.last_term);
+        assert_eq!(
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+            l.append(3, Some(vec![0x04]))?
+        );
+        assert_eq!(3, l.truncate(3)?);
+        assert_eq!(2, l.last_term);
+        Ok(())
+    }
+
+    #[test]
+    fn save_snapshot_configs() -> Result<()> {
+        let (mut l, store) = setup()?;
+        let add_a = ConfigChange::AddServer { id: "a".into(), addr: "a:9705".into() };
+        let add_b = ConfigChange::AddLearner { id: "b".into(), addr: "b:9705".into() };
+        l.append_config(1, add_a.clone())?;
+        l.append(1, Some(vec![0x02]))?;
+        l.append_config(1, add_b.clone())?;
+        l.append(1, Some(vec![0x04]))?;
+        l.commit(4)?;
+
+        // Membership changes are carried over from the log and the previous snapshot, so they
+        // survive compaction.
+        l.save_snapshot(&Snapshot { index: 2, term: 1, configs: vec![], data: vec![0x02] })?;
+        assert_eq!(Some(vec![add_a.clone()]), l.load_snapshot()?.map(|s| s.configs));
+        l.compact(2)?;
+
+        l.save_snapshot(&Snapshot { index: 4, term: 1, configs: vec![], data: vec![0x04] })?;
+        assert_eq!(Some(vec![add_a, add_b]), l.load_snapshot()?.map(|s| s.configs));
+        l.compact(4)?;
+
+        let l = Log::new(store)?;
+        assert_eq!(2, l.load_snapshot()?.map(|s| s.configs.len()).unwrap_or_default());
+        Ok(())
+    }
+
+    #[test]
+    fn install_snapshot() -> Result<()> {
+        let (mut l, store) = setup()?;
+        l.append(1, Some(vec![0x01]))?;
+        l.append(1, Some(vec![0x02]))?;
+        l.append(2, Some(vec![0x03]))?;
+        l.commit(1)?;
+
+        // Snapshots that aren't past the commit index are ignored.
+        l.install_snapshot(&Snapshot { index: 1, term: 1, configs: vec![], data: vec![0x01] })?;
+        assert_eq!(None, l.load_snapshot()?);
+
+        // If the log contains the snapshot's last entry, later entries are kept.
+        let snapshot = Snapshot { index: 2, term: 1, configs: vec![], data: vec![0x02] };
+        l.install_snapshot(&snapshot)?;
+        assert_eq!(Some(snapshot), l.load_snapshot()?);
+        assert_eq!((2, 1), (l.commit_index, l.commit_term));
+        assert_eq!((3, 2), (l.last_index, l.last_term));
+        assert_eq!(None, l.get(2)?);
+        assert_eq!(
+            vec![Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None }],
+            l.scan(..).collect::<Result<Vec<_>>>()?
+        );
+
+        // Otherwise, the log is discarded and restarts after the snapshot.
+        let snapshot = Snapshot { index: 5, term: 3, configs: vec![], data: vec![0x05] };
+        l.install_snapshot(&snapshot)?;
+        assert_eq!((5, 3), (l.commit_index, l.commit_term));
+        assert_eq!((5, 3), (l.last_index, l.last_term));
+        assert!(l.scan(..).collect::<Result<Vec<_>>>()?.is_empty());
+        assert_eq!(true, l.has(5, 3)?);
+        assert_eq!(
+            Entry { index: 6, term: 3, command: Some(vec![0x06]), config: None },
+            l.append(3, Some(vec![0x06]))?
+        );
+
+        let l = Log::new(store)?;
+        assert_eq!(Some(snapshot), l.load_snapshot()?);
+        assert_eq!((5, 3), (l.commit_index, l.commit_term));
+        assert_eq!((6, 3), (l.last_index, l.last_term));
+        Ok(())
+    }
+
+    #[test]
+    fn install_snapshot_interrupted() -> Result<()> {
+        let (mut l, store) = setup()?;
+        l.append(1, Some(vec![0x01]))?;
+        l.append(1, Some(vec![0x02]))?;
+        l.commit(1)?;
+
+        // A snapshot saved without replacing the log, as when crashing during an install, is
+        // completed when the log is reopened.
+        let snapshot = Snapshot { index: 4, term: 2, configs: vec![], data: vec![0x04] };
+        l.store.set_metadata(&Key::Snapshot.encode(), Log::serialize(&snapshot)?)?;
+
+        let l = Log::new(store)?;
+        assert_eq!((4, 2), (l.commit_index, l.commit_term));
+        assert_eq!((4, 2), (l.last_index, l.last_term));
+        assert!(l.scan(..).collect::<Result<Vec<_>>>()?.is_empty());
+        Ok(())
+    }
 }
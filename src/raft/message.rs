@@ -1,4 +1,4 @@
-use super::{Entry, Status};
+use super::{ConfigChange, Entry, RaftConfig, Snapshot, Status};
 use crate::error::Result;
 
 use serde_derive::{Deserialize, Serialize};
@@ -57,6 +57,21 @@ pub enum Event {
     },
     /// Followers may grant votes to candidates.
     GrantVote,
+    /// Candidates solicit pre-votes from all peers before starting an election,
+    /// using the term they would campaign in. Pre-votes do not change the term
+    /// of either the candidate or its peers.
+    PreVote {
+        // The index of the candidate's last stored log entry
+        last_index: u64,
+        // The term of the candidate's last stored log entry
+        last_term: u64,
+    },
+    /// Followers may grant pre-votes to candidates, unless they have recently
+    /// heard from a leader.
+    GrantPreVote,
+    /// Leaders transferring leadership tell the caught-up target to start an
+    /// election immediately, without waiting for an election timeout.
+    TimeoutNow,
     /// Leaders replicate a set of log entries to followers.
     ReplicateEntries {
         /// The index of the log entry immediately preceding the submitted commands.
@@ -66,6 +81,13 @@ pub enum Event {
         /// Commands to replicate.
         entries: Vec<Entry>,
     },
+    /// Leaders send their saved snapshot to followers that need log entries which have been
+    /// compacted. The follower replaces its state machine with it, and accepts it like a set of
+    /// log entries up to the snapshot index.
+    InstallSnapshot {
+        /// The snapshot.
+        snapshot: Box<Snapshot>,
+    },
     /// Followers may accept a set of log entries from a leader.
     AcceptEntries {
         /// The index of the last log entry.
@@ -87,6 +109,12 @@ pub enum Event {
         /// The response.
         response: Result<Response>,
     },
+    /// Nodes notify the local server about applied membership changes, such that it can
+    /// connect to added peers and disconnect from removed ones. Never sent to peers.
+    ConfigChange(ConfigChange),
+    /// The state machine driver hands periodic state machine snapshots to the local node, which
+    /// saves them in its log. Never sent to peers.
+    Snapshot(Box<Snapshot>),
 }
 
 /// A client request.
@@ -94,12 +122,25 @@ pub enum Event {
 pub enum Request {
     Query(Vec<u8>),
     Mutate(Vec<u8>),
+    /// Mutates the state machine with several commands, appended as consecutive log entries.
+    MutateBatch(Vec<Vec<u8>>),
     Status,
+    /// Transfers leadership to the given node.
+    TransferLeadership(String),
+    /// Adds or removes a server from the cluster.
+    ConfigChange(ConfigChange),
+    /// Updates the local node's Raft configuration at runtime. Not proxied to the leader.
+    UpdateConfig(RaftConfig),
 }
 
 /// A client response.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Response {
     State(Vec<u8>),
-    Status(Status),
+    /// The state machine responses to a MutateBatch request, in order.
+    StateBatch(Vec<Vec<u8>>),
+    Status(Box<Status>),
+    TransferLeadership,
+    ConfigChange,
+    UpdateConfig,
 }
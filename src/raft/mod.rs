@@ -1,13 +1,19 @@
 mod client;
+mod clock;
 mod log;
 mod message;
 mod node;
 mod server;
+mod shard;
+#[cfg(test)]
+mod sim;
 mod state;
 
-pub use self::log::{Entry, Log, Scan};
-pub use client::Client;
+pub use self::log::{ConfigChange, Entry, Log, Scan, Snapshot};
+pub use client::{Client, SyncClient};
+pub use clock::{Clock, TokioClock, VirtualClock};
 pub use message::{Address, Event, Message, Request, Response};
-pub use node::{Node, Status};
+pub use node::{Node, RaftConfig, Status};
 pub use server::Server;
+pub use shard::{GroupId, ShardRouter};
 pub use state::{Driver, Instruction, State};
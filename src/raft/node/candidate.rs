@@ -1,9 +1,8 @@
-use super::super::{Address, Event, Message, Response};
-use super::{Follower, Leader, Node, RoleNode, ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN};
+use super::super::{Address, Event, Message, Request, Response};
+use super::{Follower, Leader, Node, RoleNode};
 use crate::error::Result;
 
 use ::log::{debug, info, warn};
-use rand::Rng as _;
 
 /// A candidate is campaigning to become a leader.
 #[derive(Debug)]
@@ -14,27 +13,58 @@ pub struct Candidate {
     election_timeout: u64,
     /// Votes received (including ourself).
     votes: u64,
+    /// Whether we're soliciting pre-votes for the next term, rather than votes.
+    pre_vote: bool,
 }
 
 impl Candidate {
-    /// Creates a new candidate role.
-    pub fn new() -> Self {
+    /// Creates a new candidate role, either for a pre-vote or an election, with the given
+    /// election timeout in ticks.
+    pub fn new(pre_vote: bool, election_timeout: u64) -> Self {
         Self {
             votes: 1, // We always start with a vote for ourselves.
+            pre_vote,
             election_ticks: 0,
-            election_timeout: rand::thread_rng()
-                .gen_range(ELECTION_TIMEOUT_MIN..=ELECTION_TIMEOUT_MAX),
+            election_timeout,
         }
     }
 }
 
 impl RoleNode<Candidate> {
+    /// Starts a pre-vote for the next term. The term is only incremented once a quorum of peers
+    /// grant their pre-vote, such that a node rejoining after a partition can't disrupt an
+    /// established leader by forcing a new election.
+    pub(super) fn pre_vote(mut self) -> Result<Self> {
+        info!("Starting pre-vote for term {}", self.term + 1);
+        self.role = Candidate::new(true, self.config.election_timeout());
+        self.send_term(
+            Address::Peers,
+            self.term + 1,
+            Event::PreVote { last_index: self.log.last_index, last_term: self.log.last_term },
+        )?;
+        Ok(self)
+    }
+
+    /// Starts an election for the next term.
+    pub(super) fn campaign(mut self) -> Result<Self> {
+        info!("Starting election for term {}", self.term + 1);
+        self.term += 1;
+        self.log.save_term(self.term, None)?;
+        self.role = Candidate::new(false, self.config.election_timeout());
+        self.send(
+            Address::Peers,
+            Event::SolicitVote { last_index: self.log.last_index, last_term: self.log.last_term },
+        )?;
+        Ok(self)
+    }
+
     /// Transition to follower role.
     fn become_follower(mut self, term: u64, leader: &str) -> Result<RoleNode<Follower>> {
         info!("Discovered leader {} for term {}, following", leader, term);
         self.term = term;
         self.log.save_term(term, None)?;
-        let mut node = self.become_role(Follower::new(Some(leader), None))?;
+        let election_timeout = self.config.election_timeout();
+        let mut node = self.become_role(Follower::new(Some(leader), None, election_timeout))?;
         node.abort_proxied()?;
         node.forward_queued(Address::Peer(leader.to_string()))?;
         Ok(node)
@@ -43,7 +73,7 @@ impl RoleNode<Candidate> {
     /// Transition to leader role.
     fn become_leader(self) -> Result<RoleNode<Leader>> {
         info!("Won election for term {}, becoming leader", self.term);
-        let peers = self.peers.clone();
+        let peers = self.peers.iter().chain(&self.learners).cloned().collect();
         let last_index = self.log.last_index;
         let mut node = self.become_role(Leader::new(peers, last_index))?;
         node.send(
@@ -64,7 +94,9 @@ impl RoleNode<Candidate> {
             warn!("Ignoring invalid message: {}", err);
             return Ok(self.into());
         }
-        if msg.term > self.term {
+        // Pre-votes are sent for the next term, but don't change it
+        let pre_vote = matches!(msg.event, Event::PreVote { .. } | Event::GrantPreVote);
+        if msg.term > self.term && !pre_vote {
             if let Address::Peer(from) = &msg.from {
                 return self.become_follower(msg.term, from)?.step(msg);
             }
@@ -77,6 +109,11 @@ impl RoleNode<Candidate> {
                 }
             }
 
+            // Ignore stray votes from a previous election while soliciting pre-votes, and votes
+            // from learners which may not yet know they're learners.
+            Event::GrantVote | Event::GrantPreVote if !self.is_voter(&msg.from) => {}
+            Event::GrantVote if self.role.pre_vote => {}
+
             Event::GrantVote => {
                 debug!("Received term {} vote from {:?}", self.term, msg.from);
                 self.role.votes += 1;
@@ -90,6 +127,21 @@ impl RoleNode<Candidate> {
                 }
             }
 
+            Event::GrantPreVote => {
+                if self.role.pre_vote && msg.term == self.term + 1 {
+                    debug!("Received term {} pre-vote from {:?}", msg.term, msg.from);
+                    self.role.votes += 1;
+                    if self.role.votes >= self.quorum() {
+                        return Ok(self.campaign()?.into());
+                    }
+                }
+            }
+
+            Event::ClientRequest { id, request: Request::UpdateConfig(config) } => {
+                self.update_config(msg.from, id, config)?;
+                self.role.election_timeout = self.config.election_timeout();
+            }
+
             Event::ClientRequest { .. } => self.queued_reqs.push((msg.from, msg.event)),
 
             Event::ClientResponse { id, mut response } => {
@@ -101,32 +153,27 @@ impl RoleNode<Candidate> {
             }
 
             // Ignore other candidates when we're also campaigning
-            Event::SolicitVote { .. } => {}
+            Event::SolicitVote { .. } | Event::PreVote { .. } => {}
 
             Event::ConfirmLeader { .. }
+            | Event::TimeoutNow
             | Event::ReplicateEntries { .. }
+            | Event::InstallSnapshot { .. }
             | Event::AcceptEntries { .. }
-            | Event::RejectEntries { .. } => warn!("Received unexpected message {:?}", msg),
+            | Event::RejectEntries
+            | Event::ConfigChange(_)
+            | Event::Snapshot(_) => warn!("Received unexpected message {:?}", msg),
         }
         Ok(self.into())
     }
 
     /// Processes a logical clock tick.
     pub fn tick(mut self) -> Result<Node> {
-        // If the election times out, start a new one for the next term.
+        // If the election times out, start a new pre-vote for the next term.
         self.role.election_ticks += 1;
         if self.role.election_ticks >= self.role.election_timeout {
-            info!("Election timed out, starting new election for term {}", self.term + 1);
-            self.term += 1;
-            self.log.save_term(self.term, None)?;
-            self.role = Candidate::new();
-            self.send(
-                Address::Peers,
-                Event::SolicitVote {
-                    last_index: self.log.last_index,
-                    last_term: self.log.last_term,
-                },
-            )?;
+            info!("Election timed out for term {}", self.term);
+            return Ok(self.pre_vote()?.into());
         }
         Ok(self.into())
     }
@@ -136,6 +183,7 @@ impl RoleNode<Candidate> {
 mod tests {
     use super::super::super::{Entry, Instruction, Log, Request};
     use super::super::tests::{assert_messages, assert_node};
+    use super::super::{RaftConfig, ELECTION_TIMEOUT_MAX};
     use super::*;
     use crate::storage::log;
     use futures::FutureExt;
@@ -166,7 +214,9 @@ mod tests {
             state_tx,
             queued_reqs: Vec::new(),
             proxied_reqs: HashMap::new(),
-            role: Candidate::new(),
+            learners: Vec::new(),
+            config: RaftConfig::default(),
+            role: Candidate::new(false, ELECTION_TIMEOUT_MAX),
         };
         node = match node.step(Message {
             from: Address::Client,
@@ -315,36 +365,80 @@ mod tests {
                     event: Event::ReplicateEntries {
                         base_index: 3,
                         base_term: 2,
-                        entries: vec![Entry { index: 4, term: 3, command: None }],
+                        entries: vec![Entry { index: 4, term: 3, command: None, config: None }],
                     },
                 }))
             )
         }
 
-        // Now that we're leader, we process the queued request
+        // The queued query stays queued until we've committed an entry in our term
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // A quorum of pre-votes starts an election for the next term.
+    fn step_grantprevote() -> Result<()> {
+        let (mut candidate, mut node_rx, mut state_rx) = setup()?;
+        candidate.role.pre_vote = true;
+        let mut node = Node::Candidate(candidate);
+
+        // A pre-vote for the current term is a stray, and ignored.
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::GrantPreVote,
+        })?;
+        assert_node(&node).is_candidate().term(3);
+
+        node = node.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::GrantPreVote,
+        })?;
+        assert_node(&node).is_candidate().term(3);
+        assert_messages(&mut node_rx, vec![]);
+
+        node = node.step(Message {
+            from: Address::Peer("e".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::GrantPreVote,
+        })?;
+        assert_node(&node).is_candidate().term(4);
         assert_messages(
             &mut node_rx,
             vec![Message {
                 from: Address::Local,
                 to: Address::Peers,
-                term: 3,
-                event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+                term: 4,
+                event: Event::SolicitVote { last_index: 3, last_term: 2 },
             }],
         );
-        assert_messages(
-            &mut state_rx,
-            vec![
-                Instruction::Query {
-                    id: vec![0xaf],
-                    address: Address::Client,
-                    command: vec![0xf0],
-                    term: 3,
-                    index: 2,
-                    quorum: 3,
-                },
-                Instruction::Vote { term: 3, index: 2, address: Address::Local },
-            ],
-        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // Votes are not counted as pre-votes.
+    fn step_grantvote_prevote() -> Result<()> {
+        let (mut candidate, mut node_rx, mut state_rx) = setup()?;
+        candidate.role.pre_vote = true;
+        let mut node = Node::Candidate(candidate);
+        for peer in ["b", "c", "d"] {
+            node = node.step(Message {
+                from: Address::Peer(peer.into()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::GrantVote,
+            })?;
+        }
+        assert_node(&node).is_candidate().term(3);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
 
@@ -359,7 +453,7 @@ mod tests {
             assert_node(&node).is_candidate().term(3);
             node = node.tick()?;
         }
-        assert_node(&node).is_candidate().term(4);
+        assert_node(&node).is_candidate().term(3);
 
         assert_messages(
             &mut node_rx,
@@ -367,7 +461,7 @@ mod tests {
                 from: Address::Local,
                 to: Address::Peers,
                 term: 4,
-                event: Event::SolicitVote { last_index: 3, last_term: 2 },
+                event: Event::PreVote { last_index: 3, last_term: 2 },
             }],
         );
         assert_messages(&mut state_rx, vec![]);
@@ -1,9 +1,8 @@
-use super::super::{Address, Event, Instruction, Message, Response};
-use super::{Candidate, Node, RoleNode, ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN};
+use super::super::{Address, Event, Instruction, Message, Request, Response};
+use super::{Candidate, Node, RoleNode};
 use crate::error::Result;
 
 use ::log::{debug, info, warn};
-use rand::Rng as _;
 
 // A follower replicates state from a leader.
 #[derive(Debug)]
@@ -19,30 +18,22 @@ pub struct Follower {
 }
 
 impl Follower {
-    /// Creates a new follower role.
-    pub fn new(leader: Option<&str>, voted_for: Option<&str>) -> Self {
+    /// Creates a new follower role, with the given election timeout in ticks.
+    pub fn new(leader: Option<&str>, voted_for: Option<&str>, election_timeout: u64) -> Self {
         Self {
             leader: leader.map(String::from),
             voted_for: voted_for.map(String::from),
             leader_seen_ticks: 0,
-            leader_seen_timeout: rand::thread_rng()
-                .gen_range(ELECTION_TIMEOUT_MIN..=ELECTION_TIMEOUT_MAX),
+            leader_seen_timeout: election_timeout,
         }
     }
 }
 
 impl RoleNode<Follower> {
-    /// Transforms the node into a candidate.
+    /// Transforms the node into a candidate, starting with a pre-vote.
     fn become_candidate(self) -> Result<RoleNode<Candidate>> {
-        info!("Starting election for term {}", self.term + 1);
-        let mut node = self.become_role(Candidate::new())?;
-        node.term += 1;
-        node.log.save_term(node.term, None)?;
-        node.send(
-            Address::Peers,
-            Event::SolicitVote { last_index: node.log.last_index, last_term: node.log.last_term },
-        )?;
-        Ok(node)
+        let election_timeout = self.config.election_timeout();
+        self.become_role(Candidate::new(true, election_timeout))?.pre_vote()
     }
 
     /// Transforms the node into a follower for a new leader.
@@ -56,7 +47,8 @@ impl RoleNode<Follower> {
             info!("Discovered leader {}, following", leader);
             voted_for = self.role.voted_for;
         };
-        self.role = Follower::new(Some(leader), voted_for.as_deref());
+        self.role =
+            Follower::new(Some(leader), voted_for.as_deref(), self.config.election_timeout());
         self.abort_proxied()?;
         self.forward_queued(Address::Peer(leader.to_string()))?;
         Ok(self)
@@ -73,8 +65,10 @@ impl RoleNode<Follower> {
             warn!("Ignoring invalid message: {}", err);
             return Ok(self.into());
         }
+        // Pre-votes are sent for the next term, but don't change it
+        let pre_vote = matches!(msg.event, Event::PreVote { .. } | Event::GrantPreVote);
         if let Address::Peer(from) = &msg.from {
-            if msg.term > self.term || self.role.leader.is_none() {
+            if !pre_vote && (msg.term > self.term || self.role.leader.is_none()) {
                 return self.become_follower(from, msg.term)?.step(msg);
             }
         }
@@ -89,15 +83,27 @@ impl RoleNode<Follower> {
                     if has_committed && commit_index > self.log.commit_index {
                         let old_commit_index = self.log.commit_index;
                         self.log.commit(commit_index)?;
-                        let mut scan = self.log.scan((old_commit_index + 1)..=commit_index);
-                        while let Some(entry) = scan.next().transpose()? {
-                            self.state_tx.send(Instruction::Apply { entry })?;
+                        let entries = self
+                            .log
+                            .scan((old_commit_index + 1)..=commit_index)
+                            .collect::<Result<Vec<_>>>()?;
+                        for entry in &entries {
+                            if let Some(config) = entry.config.clone() {
+                                self.apply_config(config)?;
+                            }
+                        }
+                        // Witnesses don't apply commands, they only vote.
+                        if !self.config.witness {
+                            self.apply_entries(entries)?;
                         }
                     }
                     self.send(msg.from, Event::ConfirmLeader { commit_index, has_committed })?;
                 }
             }
 
+            // Learners don't vote.
+            Event::SolicitVote { .. } | Event::PreVote { .. } if self.is_learner() => {}
+
             Event::SolicitVote { last_index, last_term } => {
                 if let Some(voted_for) = &self.role.voted_for {
                     if msg.from != Address::Peer(voted_for.clone()) {
@@ -112,24 +118,85 @@ impl RoleNode<Follower> {
                 }
                 if let Address::Peer(from) = msg.from {
                     info!("Voting for {} in term {} election", from, self.term);
-                    self.send(Address::Peer(from.clone()), Event::GrantVote)?;
                     self.log.save_term(self.term, Some(&from))?;
+                    self.send(Address::Peer(from.clone()), Event::GrantVote)?;
                     self.role.voted_for = Some(from);
                 }
             }
 
-            Event::ReplicateEntries { base_index, base_term, entries } => {
+            Event::PreVote { last_index, last_term } => {
+                // Don't grant pre-votes while we're hearing from a live leader
+                if self.role.leader.is_some()
+                    && self.role.leader_seen_ticks < *self.config.election_timeout_range.start()
+                {
+                    return Ok(self.into());
+                }
+                if msg.term <= self.term {
+                    return Ok(self.into());
+                }
+                if last_term < self.log.last_term {
+                    return Ok(self.into());
+                }
+                if last_term == self.log.last_term && last_index < self.log.last_index {
+                    return Ok(self.into());
+                }
+                if let Address::Peer(from) = msg.from {
+                    info!("Granting pre-vote to {} for term {}", from, msg.term);
+                    self.send_term(Address::Peer(from), msg.term, Event::GrantPreVote)?;
+                }
+            }
+
+            Event::TimeoutNow => {
+                if self.is_leader(&msg.from) && !self.is_learner() && !self.config.witness {
+                    info!("Leader {:?} is transferring leadership to us", msg.from);
+                    let election_timeout = self.config.election_timeout();
+                    return Ok(self
+                        .become_role(Candidate::new(false, election_timeout))?
+                        .campaign()?
+                        .into());
+                }
+            }
+
+            Event::ReplicateEntries { base_index, base_term, mut entries } => {
                 if self.is_leader(&msg.from) {
                     if base_index > 0 && !self.log.has(base_index, base_term)? {
                         debug!("Rejecting log entries at base {}", base_index);
                         self.send(msg.from, Event::RejectEntries)?
                     } else {
+                        // Witnesses only need entry indexes and terms for voting, so they discard
+                        // commands to save space.
+                        if self.config.witness {
+                            entries.iter_mut().for_each(|e| e.command = None);
+                        }
                         let last_index = self.log.splice(entries)?;
                         self.send(msg.from, Event::AcceptEntries { last_index })?
                     }
                 }
             }
 
+            Event::InstallSnapshot { snapshot } => {
+                if self.is_leader(&msg.from) {
+                    let index = snapshot.index;
+                    if index > self.log.commit_index {
+                        info!("Installing snapshot at index {} from leader", index);
+                        self.log.install_snapshot(&snapshot)?;
+                        for config in snapshot.configs.clone() {
+                            self.apply_config(config)?;
+                        }
+                        // Witnesses don't apply commands, so they have no state machine to restore.
+                        if !self.config.witness {
+                            self.state_tx.send(Instruction::Restore { snapshot: *snapshot })?;
+                        }
+                    }
+                    self.send(msg.from, Event::AcceptEntries { last_index: index })?
+                }
+            }
+
+            Event::ClientRequest { id, request: Request::UpdateConfig(config) } => {
+                self.update_config(msg.from, id, config)?;
+                self.role.leader_seen_timeout = self.config.election_timeout();
+            }
+
             Event::ClientRequest { ref id, .. } => {
                 if let Some(leader) = self.role.leader.as_deref() {
                     self.proxied_reqs.insert(id.clone(), msg.from);
@@ -148,11 +215,13 @@ impl RoleNode<Follower> {
             }
 
             // Ignore votes which are usually strays from the previous election that we lost.
-            Event::GrantVote => {}
+            Event::GrantVote | Event::GrantPreVote => {}
 
             Event::ConfirmLeader { .. }
             | Event::AcceptEntries { .. }
-            | Event::RejectEntries { .. } => warn!("Received unexpected message {:?}", msg),
+            | Event::RejectEntries
+            | Event::ConfigChange(_)
+            | Event::Snapshot(_) => warn!("Received unexpected message {:?}", msg),
         };
         Ok(self.into())
     }
@@ -160,7 +229,11 @@ impl RoleNode<Follower> {
     /// Processes a logical clock tick.
     pub fn tick(mut self) -> Result<Node> {
         self.role.leader_seen_ticks += 1;
-        if self.role.leader_seen_ticks >= self.role.leader_seen_timeout {
+        // Learners and witnesses never campaign.
+        if self.role.leader_seen_ticks >= self.role.leader_seen_timeout
+            && !self.is_learner()
+            && !self.config.witness
+        {
             Ok(self.become_candidate()?.into())
         } else {
             Ok(self.into())
@@ -170,8 +243,9 @@ impl RoleNode<Follower> {
 
 #[cfg(test)]
 pub mod tests {
-    use super::super::super::{Entry, Log, Request};
+    use super::super::super::{Entry, Log, Request, Snapshot};
     use super::super::tests::{assert_messages, assert_node};
+    use super::super::{RaftConfig, ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN};
     use super::*;
     use crate::error::Error;
     use crate::storage::log;
@@ -209,8 +283,10 @@ pub mod tests {
             node_tx,
             state_tx,
             proxied_reqs: HashMap::new(),
+            learners: Vec::new(),
             queued_reqs: Vec::new(),
-            role: Follower::new(Some("b"), None),
+            role: Follower::new(Some("b"), None, ELECTION_TIMEOUT_MAX),
+            config: RaftConfig::default(),
         };
         Ok((node, node_rx, state_rx))
     }
@@ -238,7 +314,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
             }],
         );
         Ok(())
@@ -312,7 +388,7 @@ pub mod tests {
     // Heartbeat when no current leader makes us follow the leader
     fn step_heartbeat_no_leader() -> Result<()> {
         let (mut follower, mut node_rx, mut state_rx) = setup()?;
-        follower.role = Follower::new(None, None);
+        follower.role = Follower::new(None, None, ELECTION_TIMEOUT_MAX);
         let node = follower.step(Message {
             from: Address::Peer("c".into()),
             to: Address::Peer("a".into()),
@@ -332,7 +408,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
             }],
         );
         Ok(())
@@ -385,7 +461,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
             }],
         );
         Ok(())
@@ -463,6 +539,44 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    // The vote must be flushed to durable storage before it's sent.
+    fn step_solicitvote_flush() -> Result<()> {
+        let store = log::Test::new();
+        let msg = Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::SolicitVote { last_index: 3, last_term: 2 },
+        };
+
+        // If the flush fails, no vote is sent.
+        let (mut follower, mut node_rx, _) = setup()?;
+        follower.log = Log::new(Box::new(store.clone()))?;
+        store.fail_flush(true);
+        assert!(follower.step(msg.clone()).is_err());
+        assert_eq!(store.flushes(), 0);
+        assert_messages(&mut node_rx, vec![]);
+
+        // Otherwise, the vote is flushed and sent.
+        let (mut follower, mut node_rx, _) = setup()?;
+        follower.log = Log::new(Box::new(store.clone()))?;
+        store.fail_flush(false);
+        let node = follower.step(msg)?;
+        assert_node(&node).is_follower().term(3).voted_for(Some("c"));
+        assert_eq!(store.flushes(), 1);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 3,
+                event: Event::GrantVote,
+            }],
+        );
+        Ok(())
+    }
+
     #[test]
     // GrantVote messages are ignored
     fn step_grantvote_noop() -> Result<()> {
@@ -511,6 +625,179 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    // PreVote is granted once the leader has gone quiet, without changing the term or vote.
+    fn step_prevote() -> Result<()> {
+        let (mut follower, mut node_rx, mut state_rx) = setup()?;
+        follower.role.leader_seen_ticks = ELECTION_TIMEOUT_MIN;
+        let node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peers,
+            term: 4,
+            event: Event::PreVote { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 4,
+                event: Event::GrantPreVote,
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // PreVote is rejected while we're hearing from the leader.
+    fn step_prevote_leader_seen() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peers,
+            term: 4,
+            event: Event::PreVote { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // PreVote is rejected if the candidate's log is outdated.
+    fn step_prevote_last_index_outdated() -> Result<()> {
+        let (mut follower, mut node_rx, mut state_rx) = setup()?;
+        follower.role.leader_seen_ticks = ELECTION_TIMEOUT_MIN;
+        let node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peers,
+            term: 4,
+            event: Event::PreVote { last_index: 2, last_term: 2 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // PreVote is rejected if it's not for a future term.
+    fn step_prevote_current_term() -> Result<()> {
+        let (mut follower, mut node_rx, mut state_rx) = setup()?;
+        follower.role.leader_seen_ticks = ELECTION_TIMEOUT_MIN;
+        let node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peers,
+            term: 3,
+            event: Event::PreVote { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // A partitioned node times out and solicits pre-votes, but when it rejoins the other followers
+    // are still hearing from the leader and reject them. The node's term is never incremented, so
+    // the leader isn't disrupted and the node simply resumes following it.
+    fn prevote_partitioned_rejoin() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let timeout = follower.role.leader_seen_timeout;
+        let mut node = Node::Follower(follower);
+        for _ in 0..timeout {
+            node = node.tick()?;
+        }
+        assert_node(&node).is_candidate().term(3);
+        let prevote = Message {
+            from: Address::Local,
+            to: Address::Peers,
+            term: 4,
+            event: Event::PreVote { last_index: 3, last_term: 2 },
+        };
+        assert_messages(&mut node_rx, vec![prevote.clone()]);
+
+        // Once rejoined, a peer which just heard from the leader rejects the pre-vote.
+        let (mut peer, mut peer_rx, _) = setup()?;
+        peer.id = "c".into();
+        let peer = peer.step(Message { from: Address::Peer("a".into()), ..prevote })?;
+        assert_node(&peer).is_follower().term(3).leader(Some("b"));
+        assert_messages(&mut peer_rx, vec![]);
+
+        // The leader's next heartbeat returns the node to following it, in the same term.
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::ConfirmLeader { commit_index: 2, has_committed: true },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // TimeoutNow from the leader starts an election immediately, skipping the pre-vote, such that
+    // we can become leader without waiting for an election timeout.
+    fn step_timeoutnow() -> Result<()> {
+        let (follower, mut node_rx, _) = setup()?;
+        let mut node = follower.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::TimeoutNow,
+        })?;
+        assert_node(&node).is_candidate().term(4);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peers,
+                term: 4,
+                event: Event::SolicitVote { last_index: 3, last_term: 2 },
+            }],
+        );
+
+        for peer in ["b", "c"] {
+            node = node.step(Message {
+                from: Address::Peer(peer.into()),
+                to: Address::Peer("a".into()),
+                term: 4,
+                event: Event::GrantVote,
+            })?;
+        }
+        assert_node(&node).is_leader().term(4);
+        Ok(())
+    }
+
+    #[test]
+    // TimeoutNow from a non-leader is ignored.
+    fn step_timeoutnow_fake_leader() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::TimeoutNow,
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
     #[test]
     // ReplicateEntries accepts some entries at base 0 without changes
     fn step_replicateentries_base0() -> Result<()> {
@@ -523,15 +810,15 @@ pub mod tests {
                 base_index: 0,
                 base_term: 0,
                 entries: vec![
-                    Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                    Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                    Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+                    Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
                 ],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -546,6 +833,62 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    // InstallSnapshot replaces the log and state machine with the leader's snapshot
+    fn step_installsnapshot() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let snapshot = Snapshot { index: 5, term: 3, configs: vec![], data: vec![0x05] };
+        let node = follower.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::InstallSnapshot { snapshot: Box::new(snapshot.clone()) },
+        })?;
+        assert_node(&node).is_follower().term(3).committed(5).last(5).entries(vec![]);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 5 },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![Instruction::Restore { snapshot }]);
+        Ok(())
+    }
+
+    #[test]
+    // InstallSnapshot at or below the commit index is accepted without being installed
+    fn step_installsnapshot_committed() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let node = follower.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::InstallSnapshot {
+                snapshot: Box::new(Snapshot {
+                    index: 2,
+                    term: 1,
+                    configs: vec![],
+                    data: vec![0x02],
+                }),
+            },
+        })?;
+        assert_node(&node).is_follower().term(3).committed(2).last(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 2 },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
     #[test]
     // ReplicateEntries appends entries but does not commit them
     fn step_replicateentries_append() -> Result<()> {
@@ -558,17 +901,17 @@ pub mod tests {
                 base_index: 3,
                 base_term: 2,
                 entries: vec![
-                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-                    Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+                    Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
                 ],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-            Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+            Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -595,17 +938,17 @@ pub mod tests {
                 base_index: 1,
                 base_term: 1,
                 entries: vec![
-                    Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                    Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                    Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
                 ],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -632,16 +975,16 @@ pub mod tests {
                 base_index: 2,
                 base_term: 1,
                 entries: vec![
-                    Entry { index: 3, term: 3, command: Some(vec![0x04]) },
-                    Entry { index: 4, term: 3, command: Some(vec![0x05]) },
+                    Entry { index: 3, term: 3, command: Some(vec![0x04]), config: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x05]), config: None },
                 ],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 3, command: Some(vec![0x04]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x05]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 3, command: Some(vec![0x04]), config: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x05]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -668,16 +1011,16 @@ pub mod tests {
                 base_index: 2,
                 base_term: 1,
                 entries: vec![
-                    Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
                 ],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -703,13 +1046,13 @@ pub mod tests {
             event: Event::ReplicateEntries {
                 base_index: 5,
                 base_term: 2,
-                entries: vec![Entry { index: 6, term: 3, command: Some(vec![0x04]) }],
+                entries: vec![Entry { index: 6, term: 3, command: Some(vec![0x04]), config: None }],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -735,13 +1078,13 @@ pub mod tests {
             event: Event::ReplicateEntries {
                 base_index: 1,
                 base_term: 2,
-                entries: vec![Entry { index: 2, term: 3, command: Some(vec![0x04]) }],
+                entries: vec![Entry { index: 2, term: 3, command: Some(vec![0x04]), config: None }],
             },
         })?;
         assert_node(&node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -818,7 +1161,7 @@ pub mod tests {
     // ClientRequest is queued when there is no leader, and forwarded when a leader appears.
     fn step_clientrequest_queued() -> Result<()> {
         let (mut follower, mut node_rx, mut state_rx) = setup()?;
-        follower.role = Follower::new(None, None);
+        follower.role = Follower::new(None, None, ELECTION_TIMEOUT_MAX);
         let mut node = Node::Follower(follower);
 
         node = node.step(Message {
@@ -870,7 +1213,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
             }],
         );
         Ok(())
@@ -936,7 +1279,91 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+            }],
+        );
+        Ok(())
+    }
+
+    #[test]
+    // A witness stores entries without commands, commits without applying them, votes, and never
+    // campaigns.
+    fn witness() -> Result<()> {
+        let (mut follower, mut node_rx, mut state_rx) = setup()?;
+        follower.config.witness = true;
+        let mut node = follower.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::ReplicateEntries {
+                base_index: 3,
+                base_term: 2,
+                entries: vec![
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+                    Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
+                ],
+            },
+        })?;
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::Heartbeat { commit_index: 5, commit_term: 3 },
+        })?;
+        assert_node(&node).is_follower().term(3).committed(5).entries(vec![
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), config: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+            Entry { index: 4, term: 3, command: None, config: None },
+            Entry { index: 5, term: 3, command: None, config: None },
+        ]);
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("b".into()),
+                    term: 3,
+                    event: Event::AcceptEntries { last_index: 5 },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("b".into()),
+                    term: 3,
+                    event: Event::ConfirmLeader { commit_index: 5, has_committed: true },
+                },
+            ],
+        );
+        assert_messages(&mut state_rx, vec![]);
+
+        // It ignores leadership transfers and election timeouts.
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::TimeoutNow,
+        })?;
+        for _ in 0..=ELECTION_TIMEOUT_MAX {
+            node = node.tick()?;
+        }
+        assert_node(&node).is_follower().term(3);
+        assert_messages(&mut node_rx, vec![]);
+
+        // But it votes for up-to-date candidates.
+        node = node.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::SolicitVote { last_index: 5, last_term: 3 },
+        })?;
+        assert_node(&node).is_follower().term(4);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 4,
+                event: Event::GrantVote,
             }],
         );
         Ok(())
@@ -974,7 +1401,7 @@ pub mod tests {
             assert_node(&node).is_follower().term(3).leader(Some("b"));
             node = node.tick()?;
         }
-        assert_node(&node).is_candidate().term(4);
+        assert_node(&node).is_candidate().term(3);
 
         assert_messages(
             &mut node_rx,
@@ -982,10 +1409,54 @@ pub mod tests {
                 from: Address::Local,
                 to: Address::Peers,
                 term: 4,
-                event: Event::SolicitVote { last_index: 3, last_term: 2 },
+                event: Event::PreVote { last_index: 3, last_term: 2 },
             }],
         );
         assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
+
+    #[test]
+    // A follower with a custom election timeout range doesn't campaign before the minimum timeout,
+    // and picks a new timeout in the range when following a new leader.
+    fn tick_custom_timeout() -> Result<()> {
+        let (mut follower, mut node_rx, _state_rx) = setup()?;
+        follower.config = RaftConfig {
+            tick: std::time::Duration::from_millis(10),
+            heartbeat_interval: 5,
+            election_timeout_range: 50..=60,
+            ..RaftConfig::default()
+        };
+        let mut node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+        })?;
+        assert_node(&node).is_follower().term(4).leader(Some("c"));
+        match &node {
+            Node::Follower(n) => assert!((50..=60).contains(&n.role.leader_seen_timeout)),
+            _ => panic!("Expected follower"),
+        }
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 4,
+                event: Event::ConfirmLeader { commit_index: 2, has_committed: true },
+            }],
+        );
+
+        for _ in 0..49 {
+            node = node.tick()?;
+            assert_node(&node).is_follower().term(4);
+        }
+        assert_messages(&mut node_rx, vec![]);
+        for _ in 49..60 {
+            node = node.tick()?;
+        }
+        assert_node(&node).is_candidate().term(4);
+        Ok(())
+    }
 }
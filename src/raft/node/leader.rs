@@ -1,9 +1,9 @@
-use super::super::{Address, Event, Instruction, Message, Request, Response, Status};
-use super::{Follower, Node, RoleNode, HEARTBEAT_INTERVAL};
+use super::super::{Address, ConfigChange, Event, Instruction, Message, Request, Response, Status};
+use super::{Follower, Node, RoleNode};
 use crate::error::{Error, Result};
 
 use ::log::{debug, info, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 // A leader serves requests and replicates the log to followers.
 #[derive(Debug)]
@@ -14,6 +14,38 @@ pub struct Leader {
     peer_next_index: HashMap<String, u64>,
     /// The last index known to be replicated on a peer.
     peer_last_index: HashMap<String, u64>,
+    /// The number of ticks since we last heard from a peer.
+    peer_last_contact: HashMap<String, u64>,
+    /// Batches of entries replicated to a peer but not yet acknowledged. New entries are batched
+    /// up until the peer responds, rather than sent one message per entry, and the in-flight
+    /// window is limited such that a slow peer can't make us queue up unbounded entries.
+    peer_inflight: HashMap<String, VecDeque<Batch>>,
+    /// A pending leadership transfer, if any.
+    transfer: Option<Transfer>,
+    /// Whether we've lost contact with a quorum. Writes are rejected until it returns.
+    quorum_lost: bool,
+}
+
+/// A batch of entries replicated to a peer, awaiting acknowledgement.
+#[derive(Debug)]
+struct Batch {
+    /// The index of the last entry in the batch.
+    last_index: u64,
+    /// The size of the batch's entry commands, in bytes.
+    size: u64,
+}
+
+/// A pending leadership transfer, waiting for the target to catch up.
+#[derive(Debug)]
+struct Transfer {
+    /// The node to transfer leadership to.
+    target: String,
+    /// The ID of the client request to respond to.
+    id: Vec<u8>,
+    /// The address of the client to respond to.
+    address: Address,
+    /// Ticks elapsed since the transfer started.
+    ticks: u64,
 }
 
 impl Leader {
@@ -23,10 +55,15 @@ impl Leader {
             heartbeat_ticks: 0,
             peer_next_index: HashMap::new(),
             peer_last_index: HashMap::new(),
+            peer_last_contact: HashMap::new(),
+            peer_inflight: HashMap::new(),
+            transfer: None,
+            quorum_lost: false,
         };
         for peer in peers {
             leader.peer_next_index.insert(peer.clone(), last_index + 1);
             leader.peer_last_index.insert(peer.clone(), 0);
+            leader.peer_last_contact.insert(peer.clone(), 0);
         }
         leader
     }
@@ -39,22 +76,162 @@ impl RoleNode<Leader> {
         self.term = term;
         self.log.save_term(term, None)?;
         self.state_tx.send(Instruction::Abort)?;
-        self.become_role(Follower::new(Some(leader), None))
+        if let Some(transfer) = self.role.transfer.take() {
+            self.send(
+                transfer.address,
+                Event::ClientResponse { id: transfer.id, response: Err(Error::Abort) },
+            )?;
+        }
+        for (address, event) in std::mem::take(&mut self.queued_reqs) {
+            if let Event::ClientRequest { id, .. } = event {
+                self.send(address, Event::ClientResponse { id, response: Err(Error::Abort) })?;
+            }
+        }
+        let election_timeout = self.config.election_timeout();
+        self.become_role(Follower::new(Some(leader), None, election_timeout))
+    }
+
+    /// Completes a pending leadership transfer if the target has caught up with our log, by
+    /// sending it a TimeoutNow to start an election and stepping down to follow it.
+    fn try_transfer(mut self) -> Result<Node> {
+        let caught_up = match &self.role.transfer {
+            Some(transfer) => {
+                self.role.peer_last_index.get(&transfer.target) >= Some(&self.log.last_index)
+            }
+            None => false,
+        };
+        if !caught_up {
+            return Ok(self.into());
+        }
+        let transfer = self.role.transfer.take().unwrap();
+        info!("Transferring leadership to {} in term {}", transfer.target, self.term);
+        let target = Address::Peer(transfer.target.clone());
+        self.send(target.clone(), Event::TimeoutNow)?;
+        self.send(
+            transfer.address,
+            Event::ClientResponse { id: transfer.id, response: Ok(Response::TransferLeadership) },
+        )?;
+        self.state_tx.send(Instruction::Abort)?;
+        let election_timeout = self.config.election_timeout();
+        let mut node =
+            self.become_role(Follower::new(Some(&transfer.target), None, election_timeout))?;
+        node.forward_queued(target)?;
+        Ok(node.into())
+    }
+
+    /// Aborts a pending leadership transfer, and processes any requests queued during it.
+    fn abort_transfer(mut self, error: Error) -> Result<Node> {
+        if let Some(transfer) = self.role.transfer.take() {
+            self.send(
+                transfer.address,
+                Event::ClientResponse { id: transfer.id, response: Err(error) },
+            )?;
+        }
+        self.step_queued()
+    }
+
+    /// Processes any queued requests.
+    fn step_queued(mut self) -> Result<Node> {
+        let queued = std::mem::take(&mut self.queued_reqs);
+        let mut node: Node = self.into();
+        for (from, event) in queued {
+            node = node.step(Message { from, to: Address::Local, term: 0, event })?;
+        }
+        Ok(node)
+    }
+
+    /// Checks whether we can serve linearizable reads. Until we've committed an entry in our own
+    /// term, our commit index may lag entries committed by previous leaders, so a read at it could
+    /// return stale data. Without peers, our commit index is always current.
+    fn can_read(&self) -> bool {
+        self.peers.is_empty() || self.log.commit_term == self.term
+    }
+
+    /// Checks whether we've heard from a quorum of voters within the quorum loss timeout, and
+    /// updates the quorum loss state accordingly.
+    fn check_quorum(&mut self) {
+        let timeout = self.config.quorum_loss_timeout;
+        let contacts = 1 + self
+            .peers
+            .iter()
+            .filter(|p| self.role.peer_last_contact.get(*p).is_some_and(|t| *t < timeout))
+            .count() as u64;
+        let quorum_lost = contacts < self.quorum();
+        if quorum_lost && !self.role.quorum_lost {
+            warn!("Lost contact with quorum in term {}, rejecting writes", self.term);
+        } else if !quorum_lost && self.role.quorum_lost {
+            info!("Regained contact with quorum in term {}, accepting writes", self.term);
+        }
+        self.role.quorum_lost = quorum_lost;
     }
 
     /// Appends an entry to the log and replicates it to peers.
     pub fn append(&mut self, command: Option<Vec<u8>>) -> Result<u64> {
         let entry = self.log.append(self.term, command)?;
-        for peer in self.peers.iter() {
-            self.replicate(peer)?;
-        }
+        self.replicate_idle()?;
+        Ok(entry.index)
+    }
+
+    /// Appends a cluster membership change to the log and replicates it to peers.
+    fn append_config(&mut self, config: ConfigChange) -> Result<u64> {
+        let entry = self.log.append_config(self.term, config)?;
+        self.replicate_idle()?;
         Ok(entry.index)
     }
 
+    /// Replicates pending entries to peers and learners that don't have a batch in flight.
+    /// The others will receive the entries in their next batch, once they respond.
+    fn replicate_idle(&mut self) -> Result<()> {
+        let peers: Vec<String> = self
+            .peers
+            .iter()
+            .chain(&self.learners)
+            .filter(|peer| self.role.peer_inflight.get(*peer).is_none_or(|b| b.is_empty()))
+            .cloned()
+            .collect();
+        for peer in peers {
+            self.replicate(&peer)?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether there is an uncommitted membership change in the log. Only a single
+    /// change may be in flight at a time, to guarantee that the quorums of the old and new
+    /// configurations overlap.
+    fn config_pending(&self) -> Result<bool> {
+        let mut scan = self.log.scan((self.log.commit_index + 1)..);
+        while let Some(entry) = scan.next().transpose()? {
+            if entry.config.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Starts or stops tracking replication progress for peers and learners after a membership
+    /// change, replicating the log to any new ones.
+    fn sync_peers(&mut self) -> Result<()> {
+        let peers: Vec<String> = self.peers.iter().chain(&self.learners).cloned().collect();
+        self.role.peer_next_index.retain(|peer, _| peers.contains(peer));
+        self.role.peer_last_index.retain(|peer, _| peers.contains(peer));
+        self.role.peer_last_contact.retain(|peer, _| peers.contains(peer));
+        self.role.peer_inflight.retain(|peer, _| peers.contains(peer));
+        for peer in peers {
+            if !self.role.peer_next_index.contains_key(&peer) {
+                self.role.peer_next_index.insert(peer.clone(), self.log.last_index + 1);
+                self.role.peer_last_index.insert(peer.clone(), 0);
+                self.role.peer_last_contact.insert(peer.clone(), 0);
+                self.replicate(&peer)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Commits any pending log entries.
     fn commit(&mut self) -> Result<u64> {
+        // Learners are replicated to, but don't count towards the quorum.
         let mut last_indexes = vec![self.log.last_index];
-        last_indexes.extend(self.role.peer_last_index.values());
+        last_indexes.extend(self.peers.iter().filter_map(|p| self.role.peer_last_index.get(p)));
         last_indexes.sort_unstable();
         last_indexes.reverse();
         let quorum_index = last_indexes[self.quorum() as usize - 1];
@@ -65,36 +242,130 @@ impl RoleNode<Leader> {
                 if entry.term == self.term {
                     let old_commit_index = self.log.commit_index;
                     self.log.commit(quorum_index)?;
-                    let mut scan = self.log.scan((old_commit_index + 1)..=self.log.commit_index);
-                    while let Some(entry) = scan.next().transpose()? {
-                        self.state_tx.send(Instruction::Apply { entry })?;
+                    let entries = self
+                        .log
+                        .scan((old_commit_index + 1)..=self.log.commit_index)
+                        .collect::<Result<Vec<_>>>()?;
+                    for entry in &entries {
+                        if let Some(config) = entry.config.clone() {
+                            self.apply_config(config)?;
+                            self.sync_peers()?;
+                        }
                     }
+                    self.apply_entries(entries)?;
                 }
             }
         }
         Ok(self.log.commit_index)
     }
 
-    /// Replicates the log to a peer.
-    fn replicate(&self, peer: &str) -> Result<()> {
+    /// Returns the number of entries and bytes in flight to a peer.
+    fn inflight(&self, peer: &str) -> (u64, u64) {
+        let batches = match self.role.peer_inflight.get(peer) {
+            Some(batches) => batches,
+            None => return (0, 0),
+        };
+        let next = self.role.peer_next_index.get(peer).cloned().unwrap_or(0);
+        let entries = batches.back().map_or(0, |b| (b.last_index + 1).saturating_sub(next));
+        (entries, batches.iter().map(|b| b.size).sum())
+    }
+
+    /// Replicates the log to a peer, starting at its next index and discarding any in-flight
+    /// batches. This is used to probe the peer's log, and to retry lost batches.
+    fn replicate(&mut self, peer: &str) -> Result<()> {
         let peer_next = self
             .role
             .peer_next_index
             .get(peer)
             .cloned()
             .ok_or_else(|| Error::Internal(format!("Unknown peer {}", peer)))?;
-        let base_index = if peer_next > 0 { peer_next - 1 } else { 0 };
+        self.role.peer_inflight.remove(peer);
+        self.send_batch(peer, peer_next)
+    }
+
+    /// Replicates further batches of pending entries to a peer, following any in-flight ones,
+    /// until the in-flight window is full.
+    fn replicate_pending(&mut self, peer: &str) -> Result<()> {
+        loop {
+            let next = match self.role.peer_inflight.get(peer).and_then(|b| b.back()) {
+                Some(batch) => batch.last_index + 1,
+                None => self.role.peer_next_index.get(peer).cloned().unwrap_or(0),
+            };
+            if next > self.log.last_index {
+                return Ok(());
+            }
+            let (entries, size) = self.inflight(peer);
+            if entries >= self.config.max_inflight_entries || size >= self.config.max_inflight_bytes
+            {
+                debug!("Throttling replication to {} with {} entries in flight", peer, entries);
+                return Ok(());
+            }
+            self.send_batch(peer, next)?;
+        }
+    }
+
+    /// Sends a batch of entries starting at the given index to a peer, limited by the maximum
+    /// batch size and the remaining in-flight window. At least one entry is sent, if any.
+    fn send_batch(&mut self, peer: &str, next: u64) -> Result<()> {
+        let base_index = if next > 0 { next - 1 } else { 0 };
         let base_term = match self.log.get(base_index)? {
             Some(base) => base.term,
             None if base_index == 0 => 0,
+            None if base_index == self.log.snapshot_index => self.log.snapshot_term,
+            // The peer needs entries that have been compacted, so it gets the snapshot instead.
+            None if base_index < self.log.snapshot_index => return self.send_snapshot(peer),
             None => return Err(Error::Internal(format!("Missing base entry {}", base_index))),
         };
-        let entries = self.log.scan(peer_next..).collect::<Result<Vec<_>>>()?;
+        let (inflight_entries, inflight_size) = self.inflight(peer);
+        let max_entries = std::cmp::min(
+            self.config.max_batch_entries as u64,
+            self.config.max_inflight_entries.saturating_sub(inflight_entries),
+        );
+        let max_size = std::cmp::min(
+            self.config.max_batch_bytes,
+            self.config.max_inflight_bytes.saturating_sub(inflight_size),
+        );
+        let mut entries = Vec::new();
+        let mut size = 0;
+        let mut scan = self.log.scan(next..);
+        while let Some(entry) = scan.next().transpose()? {
+            let entry_size = entry.command.as_ref().map_or(0, |c| c.len() as u64);
+            if !entries.is_empty()
+                && (entries.len() as u64 >= max_entries || size + entry_size > max_size)
+            {
+                break;
+            }
+            size += entry_size;
+            entries.push(entry);
+        }
         debug!("Replicating {} entries at base {} to {}", entries.len(), base_index, peer);
+        let last_index = base_index + entries.len() as u64;
         self.send(
             Address::Peer(peer.to_string()),
             Event::ReplicateEntries { base_index, base_term, entries },
         )?;
+        self.role
+            .peer_inflight
+            .entry(peer.to_string())
+            .or_default()
+            .push_back(Batch { last_index, size });
+        Ok(())
+    }
+
+    /// Sends the saved snapshot to a peer whose next entries have been compacted. The peer accepts
+    /// it like a batch of entries up to the snapshot index, and replication continues from there.
+    fn send_snapshot(&mut self, peer: &str) -> Result<()> {
+        let snapshot = self
+            .log
+            .load_snapshot()?
+            .ok_or_else(|| Error::Internal("Compacted log has no snapshot".into()))?;
+        info!("Sending snapshot at index {} to {}", snapshot.index, peer);
+        let batch = Batch { last_index: snapshot.index, size: snapshot.data.len() as u64 };
+        self.send(
+            Address::Peer(peer.to_string()),
+            Event::InstallSnapshot { snapshot: Box::new(snapshot) },
+        )?;
+        self.role.peer_inflight.entry(peer.to_string()).or_default().push_back(batch);
         Ok(())
     }
 
@@ -104,20 +375,32 @@ impl RoleNode<Leader> {
             warn!("Ignoring invalid message: {}", err);
             return Ok(self.into());
         }
-        if msg.term > self.term {
+        // Pre-votes are sent for the next term, but don't change it
+        let pre_vote = matches!(msg.event, Event::PreVote { .. } | Event::GrantPreVote);
+        if msg.term > self.term && !pre_vote {
             if let Address::Peer(from) = &msg.from {
                 return self.become_follower(msg.term, from)?.step(msg);
             }
         }
+        if let Address::Peer(from) = &msg.from {
+            if let Some(ticks) = self.role.peer_last_contact.get_mut(from) {
+                *ticks = 0;
+            }
+            if self.role.quorum_lost {
+                self.check_quorum();
+            }
+        }
 
         match msg.event {
             Event::ConfirmLeader { commit_index, has_committed } => {
                 if let Address::Peer(from) = msg.from.clone() {
-                    self.state_tx.send(Instruction::Vote {
-                        term: msg.term,
-                        index: commit_index,
-                        address: msg.from,
-                    })?;
+                    if self.is_voter(&msg.from) {
+                        self.state_tx.send(Instruction::Vote {
+                            term: msg.term,
+                            index: commit_index,
+                            address: msg.from,
+                        })?;
+                    }
                     if !has_committed {
                         self.replicate(&from)?;
                     }
@@ -127,7 +410,16 @@ impl RoleNode<Leader> {
             Event::AcceptEntries { last_index } => {
                 if let Address::Peer(from) = msg.from {
                     self.role.peer_last_index.insert(from.clone(), last_index);
-                    self.role.peer_next_index.insert(from, last_index + 1);
+                    self.role.peer_next_index.insert(from.clone(), last_index + 1);
+                    // Send further batches if entries were appended while these were in flight.
+                    if let Some(batches) = self.role.peer_inflight.get_mut(&from) {
+                        if !batches.is_empty() {
+                            while batches.front().is_some_and(|b| b.last_index <= last_index) {
+                                batches.pop_front();
+                            }
+                            self.replicate_pending(&from)?;
+                        }
+                    }
                 }
                 self.commit()?;
             }
@@ -143,6 +435,34 @@ impl RoleNode<Leader> {
                 }
             }
 
+            // Without a quorum we can't confirm that we're still the leader, so we serve
+            // possibly stale reads at our commit index instead.
+            Event::ClientRequest { id, request: Request::Query(command) }
+                if self.role.quorum_lost =>
+            {
+                self.state_tx.send(Instruction::Query {
+                    id,
+                    address: msg.from,
+                    command,
+                    term: self.term,
+                    index: self.log.commit_index,
+                    quorum: 1,
+                })?;
+                self.state_tx.send(Instruction::Vote {
+                    term: self.term,
+                    index: self.log.commit_index,
+                    address: Address::Local,
+                })?;
+            }
+
+            // Queries use the read index: we record our commit index, confirm that we're still the
+            // leader via a heartbeat quorum, and then execute the query once the commit index has
+            // been applied, without appending to the log. Reads are deferred until we've
+            // committed an entry in our term, such that our commit index is current.
+            Event::ClientRequest { request: Request::Query(_), .. } if !self.can_read() => {
+                self.queued_reqs.push((msg.from, msg.event))
+            }
+
             Event::ClientRequest { id, request: Request::Query(command) } => {
                 self.state_tx.send(Instruction::Query {
                     id,
@@ -168,6 +488,21 @@ impl RoleNode<Leader> {
                 }
             }
 
+            // Writes are queued during a leadership transfer, such that the target can catch up.
+            // They are forwarded to the new leader once the transfer completes.
+            Event::ClientRequest {
+                request: Request::Mutate(_) | Request::MutateBatch(_) | Request::ConfigChange(_),
+                ..
+            } if self.role.transfer.is_some() => self.queued_reqs.push((msg.from, msg.event)),
+
+            // Writes can't commit without a quorum, so fail them fast rather than hang.
+            Event::ClientRequest { id, request: Request::Mutate(_) | Request::MutateBatch(_) }
+                if self.role.quorum_lost =>
+            {
+                let response = Err(Error::NoQuorum);
+                self.send(msg.from, Event::ClientResponse { id, response })?
+            }
+
             Event::ClientRequest { id, request: Request::Mutate(command) } => {
                 let index = self.append(Some(command))?;
                 self.state_tx.send(Instruction::Notify { id, address: msg.from, index })?;
@@ -176,14 +511,49 @@ impl RoleNode<Leader> {
                 }
             }
 
+            Event::ClientRequest { id, request: Request::MutateBatch(commands) }
+                if commands.is_empty() =>
+            {
+                let response = Ok(Response::StateBatch(Vec::new()));
+                self.send(msg.from, Event::ClientResponse { id, response })?
+            }
+
+            // The commands are appended as a contiguous run of entries, and replicated together.
+            Event::ClientRequest { id, request: Request::MutateBatch(commands) } => {
+                let first = self.log.last_index + 1;
+                for command in commands {
+                    self.log.append(self.term, Some(command))?;
+                }
+                let last = self.log.last_index;
+                self.replicate_idle()?;
+                self.state_tx.send(Instruction::NotifyBatch {
+                    id,
+                    address: msg.from,
+                    first,
+                    last,
+                })?;
+                if self.peers.is_empty() {
+                    self.commit()?;
+                }
+            }
+
+            Event::ClientRequest { id, request: Request::UpdateConfig(config) } => {
+                self.update_config(msg.from, id, config)?
+            }
+
             Event::ClientRequest { id, request: Request::Status } => {
                 let mut status = Box::new(Status {
                     server: self.id.clone(),
                     leader: self.id.clone(),
                     term: self.term,
                     node_last_index: self.role.peer_last_index.clone(),
+                    node_next_index: self.role.peer_next_index.clone(),
+                    node_last_contact: self.role.peer_last_contact.clone(),
+                    last_index: self.log.last_index,
                     commit_index: self.log.commit_index,
                     apply_index: 0,
+                    pending_notifications: 0,
+                    pending_queries: 0,
                     storage: self.log.store.to_string(),
                     storage_size: self.log.store.size(),
                 });
@@ -191,6 +561,75 @@ impl RoleNode<Leader> {
                 self.state_tx.send(Instruction::Status { id, address: msg.from, status })?
             }
 
+            Event::ClientRequest { id, request: Request::TransferLeadership(target) } => {
+                let response = if target == self.id {
+                    Ok(Response::TransferLeadership)
+                } else if !self.peers.contains(&target) {
+                    Err(Error::Value(format!("Unknown node {}", target)))
+                } else if let Some(transfer) = &self.role.transfer {
+                    Err(Error::Value(format!(
+                        "Leadership transfer to {} already in progress",
+                        transfer.target
+                    )))
+                } else {
+                    info!("Starting leadership transfer to {}", target);
+                    if self.role.peer_last_index.get(&target) < Some(&self.log.last_index) {
+                        self.replicate(&target)?;
+                    }
+                    self.role.transfer = Some(Transfer { target, id, address: msg.from, ticks: 0 });
+                    return self.try_transfer();
+                };
+                self.send(msg.from, Event::ClientResponse { id, response })?;
+            }
+
+            Event::ClientRequest { id, request: Request::ConfigChange(config) } => {
+                let response = if self.config_pending()? {
+                    Err(Error::Value("A membership change is already in progress".into()))
+                } else {
+                    match &config {
+                        ConfigChange::AddServer { id: server, .. }
+                        | ConfigChange::AddLearner { id: server, .. }
+                            if self.is_member(server) =>
+                        {
+                            Err(Error::Value(format!("Server {} is already a member", server)))
+                        }
+                        ConfigChange::PromoteLearner { id: server }
+                            if !self.learners.contains(server) =>
+                        {
+                            Err(Error::Value(format!("Unknown learner {}", server)))
+                        }
+                        ConfigChange::PromoteLearner { id: server }
+                            if self.role.peer_last_index.get(server)
+                                < Some(&self.log.commit_index) =>
+                        {
+                            Err(Error::Value(format!("Learner {} has not caught up", server)))
+                        }
+                        ConfigChange::RemoveServer { id: server } if server == &self.id => {
+                            Err(Error::Value(
+                                "Can't remove the leader, transfer leadership first".into(),
+                            ))
+                        }
+                        ConfigChange::RemoveServer { id: server } if !self.is_member(server) => {
+                            Err(Error::Value(format!("Unknown node {}", server)))
+                        }
+                        _ => Ok(()),
+                    }
+                };
+                match response {
+                    Ok(()) => {
+                        info!("Proposing membership change {:?}", config);
+                        let index = self.append_config(config)?;
+                        self.state_tx.send(Instruction::Notify { id, address: msg.from, index })?;
+                        if self.peers.is_empty() {
+                            self.commit()?;
+                        }
+                    }
+                    Err(error) => {
+                        self.send(msg.from, Event::ClientResponse { id, response: Err(error) })?
+                    }
+                }
+            }
+
             Event::ClientResponse { id, mut response } => {
                 if let Ok(Response::Status(ref mut status)) = response {
                     status.server = self.id.clone();
@@ -202,20 +641,66 @@ impl RoleNode<Leader> {
             // election that we won after a quorum.
             Event::SolicitVote { .. } | Event::GrantVote => {}
 
-            Event::Heartbeat { .. } | Event::ReplicateEntries { .. } => {
-                warn!("Received unexpected message {:?}", msg)
-            }
+            // We ignore pre-votes, since we're a live leader and don't want to be disrupted.
+            Event::PreVote { .. } | Event::GrantPreVote => {}
+
+            Event::Heartbeat { .. }
+            | Event::ReplicateEntries { .. }
+            | Event::InstallSnapshot { .. }
+            | Event::TimeoutNow
+            | Event::ConfigChange(_)
+            | Event::Snapshot(_) => warn!("Received unexpected message {:?}", msg),
         }
 
-        Ok(self.into())
+        // Serve any reads that were deferred until we committed an entry in our term. Requests
+        // queued during a leadership transfer are handled when it completes or aborts.
+        if self.role.transfer.is_none() && !self.queued_reqs.is_empty() && self.can_read() {
+            return self.step_queued();
+        }
+        self.try_transfer()
     }
 
     /// Processes a logical clock tick.
     pub fn tick(mut self) -> Result<Node> {
+        // The state machine driver times out pending queries.
+        self.state_tx.send(Instruction::Tick)?;
+        // If a leadership transfer doesn't complete within an election timeout, abort it.
+        if let Some(transfer) = &mut self.role.transfer {
+            transfer.ticks += 1;
+            if transfer.ticks >= *self.config.election_timeout_range.end() {
+                let target = transfer.target.clone();
+                warn!("Leadership transfer to {} timed out", target);
+                return self.abort_transfer(Error::Value(format!(
+                    "Leadership transfer to {} timed out",
+                    target
+                )));
+            }
+        }
+        for ticks in self.role.peer_last_contact.values_mut() {
+            *ticks += 1;
+        }
+        self.check_quorum();
         if !self.peers.is_empty() {
             self.role.heartbeat_ticks += 1;
-            if self.role.heartbeat_ticks >= HEARTBEAT_INTERVAL {
+            if self.role.heartbeat_ticks >= self.config.heartbeat_interval {
                 self.role.heartbeat_ticks = 0;
+                // If we haven't heard from a peer since the last heartbeat, its in-flight batches
+                // may have been lost, so retry any pending entries along with the heartbeat.
+                let mut retry = Vec::new();
+                for (peer, batches) in self.role.peer_inflight.iter_mut() {
+                    if !batches.is_empty()
+                        && self.role.peer_last_contact.get(peer)
+                            >= Some(&self.config.heartbeat_interval)
+                    {
+                        batches.clear();
+                        retry.push(peer.clone());
+                    }
+                }
+                for peer in retry {
+                    if self.role.peer_next_index.get(&peer) <= Some(&self.log.last_index) {
+                        self.replicate(&peer)?;
+                    }
+                }
                 self.send(
                     Address::Peers,
                     Event::Heartbeat {
@@ -231,8 +716,11 @@ impl RoleNode<Leader> {
 
 #[cfg(test)]
 mod tests {
-    use super::super::super::{Entry, Log};
+    use super::super::super::{Entry, Log, Snapshot};
     use super::super::tests::{assert_messages, assert_node};
+    use super::super::{
+        RaftConfig, ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN, HEARTBEAT_INTERVAL,
+    };
     use super::*;
     use crate::storage::log;
     use futures::FutureExt;
@@ -262,15 +750,33 @@ mod tests {
             peers: peers.clone(),
             term: 3,
             role: Leader::new(peers, log.last_index),
+            config: RaftConfig::default(),
             log,
             node_tx,
             state_tx,
             proxied_reqs: HashMap::new(),
+            learners: Vec::new(),
             queued_reqs: Vec::new(),
         };
         Ok((node, node_rx, state_rx))
     }
 
+    #[test]
+    // PreVote is ignored by a live leader, and doesn't change its term.
+    fn step_prevote() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let node = leader.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peers,
+            term: 4,
+            event: Event::PreVote { last_index: 5, last_term: 3 },
+        })?;
+        assert_node(&node).is_leader().term(3);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
     #[test]
     // ConfirmLeader triggers vote
     fn step_confirmleader_vote() -> Result<()> {
@@ -408,14 +914,12 @@ mod tests {
         assert_messages(&mut node_rx, vec![]);
         assert_messages(
             &mut state_rx,
-            vec![
-                Instruction::Apply {
-                    entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                },
-                Instruction::Apply {
-                    entry: Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-                },
-            ],
+            vec![Instruction::ApplyBatch {
+                entries: vec![
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+                ],
+            }],
         );
 
         node = node.step(Message {
@@ -429,7 +933,7 @@ mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+                entry: Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
             }],
         );
 
@@ -438,58 +942,238 @@ mod tests {
     }
 
     #[test]
-    // Duplicate AcceptEntries from single node should not trigger commit.
-    fn step_acceptentries_duplicate() -> Result<()> {
+    // TransferLeadership waits for the target to catch up, queueing writes, then sends it
+    // TimeoutNow and steps down to follow it.
+    fn step_transferleadership() -> Result<()> {
         let (leader, mut node_rx, mut state_rx) = setup()?;
         let mut node: Node = leader.into();
 
-        for _ in 0..5 {
-            node = node.step(Message {
-                from: Address::Peer("b".into()),
-                to: Address::Peer("a".into()),
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest {
+                id: vec![0x01],
+                request: Request::TransferLeadership("b".into()),
+            },
+        })?;
+        assert_node(&node).is_leader().term(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
                 term: 3,
-                event: Event::AcceptEntries { last_index: 5 },
-            })?;
-            assert_node(&node).is_leader().term(3).committed(2);
-            assert_messages(&mut node_rx, vec![]);
-            assert_messages(&mut state_rx, vec![]);
-        }
+                event: Event::ReplicateEntries { base_index: 5, base_term: 3, entries: vec![] },
+            }],
+        );
+
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x02], request: Request::Mutate(vec![0xaf]) },
+        })?;
+        assert_node(&node).is_leader().term(3).last(5);
+        assert_messages(&mut node_rx, vec![]);
+
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::AcceptEntries { last_index: 5 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("b".into()),
+                    term: 3,
+                    event: Event::TimeoutNow,
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 3,
+                    event: Event::ClientResponse {
+                        id: vec![0x01],
+                        response: Ok(Response::TransferLeadership),
+                    },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("b".into()),
+                    term: 0,
+                    event: Event::ClientRequest {
+                        id: vec![0x02],
+                        request: Request::Mutate(vec![0xaf]),
+                    },
+                },
+            ],
+        );
+        assert_messages(&mut state_rx, vec![Instruction::Abort]);
         Ok(())
     }
 
     #[test]
-    // AcceptEntries quorum for entry in past term should not trigger commit
-    fn step_acceptentries_past_term() -> Result<()> {
+    // TransferLeadership errors for unknown nodes and concurrent transfers.
+    fn step_transferleadership_invalid() -> Result<()> {
         let (leader, mut node_rx, mut state_rx) = setup()?;
-        let peers = leader.peers.clone();
         let mut node: Node = leader.into();
+        let transfer = |id: u8, target: &str| Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest {
+                id: vec![id],
+                request: Request::TransferLeadership(target.into()),
+            },
+        };
 
-        for peer in peers.into_iter() {
-            node = node.step(Message {
-                from: Address::Peer(peer),
-                to: Address::Peer("a".into()),
-                term: 3,
-                event: Event::AcceptEntries { last_index: 3 },
-            })?;
-            assert_node(&node).is_leader().term(3).committed(2);
-            assert_messages(&mut node_rx, vec![]);
-            assert_messages(&mut state_rx, vec![]);
-        }
+        node = node.step(transfer(0x01, "x"))?;
+        node = node.step(transfer(0x02, "b"))?;
+        node = node.step(transfer(0x03, "c"))?;
+        assert_node(&node).is_leader().term(3);
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 3,
+                    event: Event::ClientResponse {
+                        id: vec![0x01],
+                        response: Err(Error::Value("Unknown node x".into())),
+                    },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("b".into()),
+                    term: 3,
+                    event: Event::ReplicateEntries { base_index: 5, base_term: 3, entries: vec![] },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 3,
+                    event: Event::ClientResponse {
+                        id: vec![0x03],
+                        response: Err(Error::Value(
+                            "Leadership transfer to b already in progress".into(),
+                        )),
+                    },
+                },
+            ],
+        );
+        assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
 
     #[test]
-    // AcceptEntries quorum for missing future entry
-    fn step_acceptentries_future_index() -> Result<()> {
+    // TransferLeadership is aborted if the target doesn't catch up within an election timeout.
+    fn step_transferleadership_timeout() -> Result<()> {
         let (leader, mut node_rx, mut state_rx) = setup()?;
-        let peers = leader.peers.clone();
         let mut node: Node = leader.into();
-
-        for (i, peer) in peers.into_iter().enumerate() {
-            node = node.step(Message {
-                from: Address::Peer(peer),
-                to: Address::Peer("a".into()),
-                term: 3,
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest {
+                id: vec![0x01],
+                request: Request::TransferLeadership("b".into()),
+            },
+        })?;
+        for _ in 0..ELECTION_TIMEOUT_MAX {
+            node = node.tick()?;
+        }
+        assert_node(&node).is_leader().term(3);
+
+        let mut expect = vec![Message {
+            from: Address::Local,
+            to: Address::Peer("b".into()),
+            term: 3,
+            event: Event::ReplicateEntries { base_index: 5, base_term: 3, entries: vec![] },
+        }];
+        for _ in 1..ELECTION_TIMEOUT_MAX {
+            expect.push(Message {
+                from: Address::Local,
+                to: Address::Peers,
+                term: 3,
+                event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+            });
+        }
+        expect.push(Message {
+            from: Address::Local,
+            to: Address::Client,
+            term: 3,
+            event: Event::ClientResponse {
+                id: vec![0x01],
+                response: Err(Error::Value("Leadership transfer to b timed out".into())),
+            },
+        });
+        assert_messages(&mut node_rx, expect);
+        assert_messages(
+            &mut state_rx,
+            (0..ELECTION_TIMEOUT_MAX).map(|_| Instruction::Tick).collect(),
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Duplicate AcceptEntries from single node should not trigger commit.
+    fn step_acceptentries_duplicate() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let mut node: Node = leader.into();
+
+        for _ in 0..5 {
+            node = node.step(Message {
+                from: Address::Peer("b".into()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 5 },
+            })?;
+            assert_node(&node).is_leader().term(3).committed(2);
+            assert_messages(&mut node_rx, vec![]);
+            assert_messages(&mut state_rx, vec![]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // AcceptEntries quorum for entry in past term should not trigger commit
+    fn step_acceptentries_past_term() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let peers = leader.peers.clone();
+        let mut node: Node = leader.into();
+
+        for peer in peers.into_iter() {
+            node = node.step(Message {
+                from: Address::Peer(peer),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 3 },
+            })?;
+            assert_node(&node).is_leader().term(3).committed(2);
+            assert_messages(&mut node_rx, vec![]);
+            assert_messages(&mut state_rx, vec![]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // AcceptEntries quorum for missing future entry
+    fn step_acceptentries_future_index() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let peers = leader.peers.clone();
+        let mut node: Node = leader.into();
+
+        for (i, peer) in peers.into_iter().enumerate() {
+            node = node.step(Message {
+                from: Address::Peer(peer),
+                to: Address::Peer("a".into()),
+                term: 3,
                 event: Event::AcceptEntries { last_index: 7 },
             })?;
             // The local leader will cast a vote to commit 5, thus when we have votes 2x7, 1x5, 2x0
@@ -500,17 +1184,13 @@ mod tests {
             if i == 1 {
                 assert_messages(
                     &mut state_rx,
-                    vec![
-                        Instruction::Apply {
-                            entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                        },
-                        Instruction::Apply {
-                            entry: Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-                        },
-                        Instruction::Apply {
-                            entry: Entry { index: 5, term: 3, command: Some(vec![0x05]) },
-                        },
-                    ],
+                    vec![Instruction::ApplyBatch {
+                        entries: vec![
+                            Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+                            Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+                            Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
+                        ],
+                    }],
                 );
             } else {
                 assert_messages(&mut state_rx, vec![]);
@@ -557,9 +1237,99 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Peers that need compacted entries are sent the snapshot instead, and replication continues
+    // after it once accepted.
+    fn step_rejectentries_snapshot() -> Result<()> {
+        let (mut leader, mut node_rx, mut state_rx) = setup()?;
+        let snapshot = Snapshot { index: 2, term: 1, configs: vec![], data: vec![0x02] };
+        leader.log.save_snapshot(&snapshot)?;
+        leader.log.compact(2)?;
+        let mut node: Node = leader.into();
+
+        let reject = Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::RejectEntries,
+        };
+        for _ in 0..3 {
+            node = node.step(reject.clone())?;
+            while node_rx.recv().now_or_never().flatten().is_some() {}
+        }
+        node = node.step(reject)?;
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::InstallSnapshot { snapshot: Box::new(snapshot) },
+            }],
+        );
+
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::AcceptEntries { last_index: 2 },
+        })?;
+        assert_node(&node).is_leader().term(3).committed(2);
+        assert_messages(&mut state_rx, vec![]);
+        let mut replicated = None;
+        while let Some(msg) = node_rx.recv().now_or_never().flatten() {
+            if let Event::ReplicateEntries { base_index, base_term, .. } = msg.event {
+                replicated = Some((base_index, base_term));
+            }
+        }
+        assert_eq!(Some((2, 1)), replicated);
+        Ok(())
+    }
+
     #[test]
     // Sending a client query request will pass it to the state machine and trigger heartbeats.
     fn step_clientrequest_query() -> Result<()> {
+        let (mut leader, mut node_rx, mut state_rx) = setup()?;
+        leader.log.commit(4)?;
+        let quorum = leader.quorum();
+        let mut node: Node = leader.into();
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x01], request: Request::Query(vec![0xaf]) },
+        })?;
+        assert_node(&node).is_leader().term(3).committed(4).last(5);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peers,
+                term: 3,
+                event: Event::Heartbeat { commit_index: 4, commit_term: 3 },
+            }],
+        );
+        assert_messages(
+            &mut state_rx,
+            vec![
+                Instruction::Query {
+                    id: vec![0x01],
+                    address: Address::Client,
+                    command: vec![0xaf],
+                    term: 3,
+                    index: 4,
+                    quorum,
+                },
+                Instruction::Vote { term: 3, index: 4, address: Address::Local },
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Queries are deferred until the leader has committed an entry in its own term, and are then
+    // served at the read index without appending to the log.
+    fn step_clientrequest_query_deferred() -> Result<()> {
         let (leader, mut node_rx, mut state_rx) = setup()?;
         let quorum = leader.quorum();
         let mut node: Node = leader.into();
@@ -570,27 +1340,46 @@ mod tests {
             event: Event::ClientRequest { id: vec![0x01], request: Request::Query(vec![0xaf]) },
         })?;
         assert_node(&node).is_leader().term(3).committed(2).last(5);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+
+        for peer in &["b", "c"] {
+            node = node.step(Message {
+                from: Address::Peer(peer.to_string()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 5 },
+            })?;
+        }
+        assert_node(&node).is_leader().term(3).committed(5).last(5);
         assert_messages(
             &mut node_rx,
             vec![Message {
                 from: Address::Local,
                 to: Address::Peers,
                 term: 3,
-                event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+                event: Event::Heartbeat { commit_index: 5, commit_term: 3 },
             }],
         );
         assert_messages(
             &mut state_rx,
             vec![
+                Instruction::ApplyBatch {
+                    entries: vec![
+                        Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+                        Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+                        Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
+                    ],
+                },
                 Instruction::Query {
                     id: vec![0x01],
                     address: Address::Client,
                     command: vec![0xaf],
                     term: 3,
-                    index: 2,
+                    index: 5,
                     quorum,
                 },
-                Instruction::Vote { term: 3, index: 2, address: Address::Local },
+                Instruction::Vote { term: 3, index: 5, address: Address::Local },
             ],
         );
         Ok(())
@@ -613,6 +1402,7 @@ mod tests {
             index: 6,
             term: 3,
             command: Some(vec![0xaf]),
+            config: None,
         });
 
         for peer in peers.iter().cloned() {
@@ -625,7 +1415,262 @@ mod tests {
                     event: Event::ReplicateEntries {
                         base_index: 5,
                         base_term: 3,
-                        entries: vec![Entry { index: 6, term: 3, command: Some(vec![0xaf]) },]
+                        entries: vec![Entry {
+                            index: 6,
+                            term: 3,
+                            command: Some(vec![0xaf]),
+                            config: None
+                        },]
+                    },
+                }))
+            )
+        }
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut state_rx,
+            vec![Instruction::Notify { id: vec![0x01], address: Address::Client, index: 6 }],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    // A batch of mutations is appended as consecutive entries, replicated together, and notified
+    // once all are applied.
+    fn step_clientrequest_mutatebatch() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let peers = leader.peers.clone();
+        let mut node: Node = leader.into();
+
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest {
+                id: vec![0x01],
+                request: Request::MutateBatch(vec![vec![0xa1], vec![0xa2], vec![0xa3]]),
+            },
+        })?;
+        let entries: Vec<Entry> = (6..=8)
+            .map(|index| Entry {
+                index,
+                term: 3,
+                command: Some(vec![0xa0 + index as u8 - 5]),
+                config: None,
+            })
+            .collect();
+        assert_node(&node).is_leader().term(3).committed(2).last(8).entry(entries[2].clone());
+
+        for peer in peers.iter().cloned() {
+            assert_eq!(
+                node_rx.recv().now_or_never(),
+                Some(Some(Message {
+                    from: Address::Local,
+                    to: Address::Peer(peer),
+                    term: 3,
+                    event: Event::ReplicateEntries {
+                        base_index: 5,
+                        base_term: 3,
+                        entries: entries.clone(),
+                    },
+                }))
+            )
+        }
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut state_rx,
+            vec![Instruction::NotifyBatch {
+                id: vec![0x01],
+                address: Address::Client,
+                first: 6,
+                last: 8,
+            }],
+        );
+
+        // An empty batch is answered right away.
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x02], request: Request::MutateBatch(vec![]) },
+        })?;
+        assert_node(&node).is_leader().last(8);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 3,
+                event: Event::ClientResponse {
+                    id: vec![0x02],
+                    response: Ok(Response::StateBatch(vec![])),
+                },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    // A burst of mutate requests is replicated in batches while earlier batches are in flight,
+    // limited by the configured batch size.
+    fn step_clientrequest_mutate_batch() -> Result<()> {
+        for (max_batch_entries, max_batch_bytes, expect_messages) in
+            [(4, u64::MAX, 4), (100, 2, 6), (100, u64::MAX, 2)]
+        {
+            let (mut leader, mut node_rx, _state_rx) = setup()?;
+            leader.config = RaftConfig { max_batch_entries, max_batch_bytes, ..leader.config };
+            let mut node: Node = leader.into();
+
+            for i in 0..10 {
+                node = node.step(Message {
+                    from: Address::Client,
+                    to: Address::Local,
+                    term: 0,
+                    event: Event::ClientRequest { id: vec![i], request: Request::Mutate(vec![i]) },
+                })?;
+            }
+            assert_node(&node).is_leader().last(15);
+
+            // Acknowledge each batch sent to b, until it has received all entries.
+            let mut messages = 0;
+            let mut replicated = Vec::new();
+            while let Some(Some(msg)) = node_rx.recv().now_or_never() {
+                match msg {
+                    Message {
+                        to: Address::Peer(to),
+                        event: Event::ReplicateEntries { base_index, entries, .. },
+                        ..
+                    } if to == "b" => {
+                        assert_eq!(base_index, 5 + replicated.len() as u64);
+                        messages += 1;
+                        replicated.extend(entries.into_iter().map(|e| e.index));
+                        node = node.step(Message {
+                            from: Address::Peer("b".into()),
+                            to: Address::Peer("a".into()),
+                            term: 3,
+                            event: Event::AcceptEntries { last_index: *replicated.last().unwrap() },
+                        })?;
+                    }
+                    _ => {}
+                }
+            }
+            assert_eq!(replicated, (6..=15).collect::<Vec<_>>());
+            assert_eq!(messages, expect_messages);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // Replication to a slow follower is throttled by the in-flight window, and resumes as it
+    // acknowledges entries, while healthy followers keep up.
+    fn step_clientrequest_mutate_inflight() -> Result<()> {
+        let (mut leader, mut node_rx, _state_rx) = setup()?;
+        leader.config =
+            RaftConfig { max_batch_entries: 2, max_inflight_entries: 4, ..leader.config };
+        let mut node: Node = leader.into();
+
+        // Acknowledges all batches sent to healthy followers, and returns the last indexes of
+        // batches sent to the slow follower c.
+        let mut process = |mut node: Node| -> Result<(Node, Vec<u64>)> {
+            let mut slow = Vec::new();
+            while let Some(Some(msg)) = node_rx.recv().now_or_never() {
+                if let Message {
+                    to: Address::Peer(to),
+                    event: Event::ReplicateEntries { base_index, entries, .. },
+                    ..
+                } = msg
+                {
+                    let last_index = base_index + entries.len() as u64;
+                    if to == "c" {
+                        slow.push(last_index);
+                        continue;
+                    }
+                    node = node.step(Message {
+                        from: Address::Peer(to),
+                        to: Address::Peer("a".into()),
+                        term: 3,
+                        event: Event::AcceptEntries { last_index },
+                    })?;
+                }
+            }
+            Ok((node, slow))
+        };
+        let inflight = |node: &Node, peer: &str| match node {
+            Node::Leader(leader) => leader.inflight(peer),
+            _ => panic!("Expected leader"),
+        };
+
+        let mut slow = Vec::new();
+        for i in 0..20 {
+            node = node.step(Message {
+                from: Address::Client,
+                to: Address::Local,
+                term: 0,
+                event: Event::ClientRequest { id: vec![i], request: Request::Mutate(vec![i]) },
+            })?;
+            let (n, sent) = process(node)?;
+            node = n;
+            slow.extend(sent);
+            assert!(inflight(&node, "c").0 <= 4);
+        }
+        assert_node(&node).is_leader().last(25).committed(25);
+        assert_eq!(inflight(&node, "b"), (0, 0));
+        assert_eq!(slow, vec![6]);
+
+        // As the slow follower acknowledges batches, it is sent more within the window.
+        let mut acked = 0;
+        while acked < 25 {
+            acked = slow.remove(0);
+            node = node.step(Message {
+                from: Address::Peer("c".into()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: acked },
+            })?;
+            let (n, sent) = process(node)?;
+            node = n;
+            slow.extend(sent);
+            assert!(inflight(&node, "c").0 <= 4);
+            assert!(slow.len() <= 2);
+        }
+        assert_eq!(inflight(&node, "c"), (0, 0));
+        assert!(slow.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    // A membership change is appended and replicated, and applied once committed. Only one
+    // change can be in flight at a time.
+    fn step_clientrequest_config() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let peers = leader.peers.clone();
+        let mut node: Node = leader.into();
+        let config = ConfigChange::AddServer { id: "f".into(), addr: "localhost:9706".into() };
+        let entry = Entry { index: 6, term: 3, command: None, config: Some(config.clone()) };
+
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest {
+                id: vec![0x01],
+                request: Request::ConfigChange(config.clone()),
+            },
+        })?;
+        assert_node(&node).is_leader().term(3).committed(2).last(6).entry(entry.clone());
+        for peer in peers.iter().cloned() {
+            assert_eq!(
+                node_rx.recv().now_or_never(),
+                Some(Some(Message {
+                    from: Address::Local,
+                    to: Address::Peer(peer),
+                    term: 3,
+                    event: Event::ReplicateEntries {
+                        base_index: 5,
+                        base_term: 3,
+                        entries: vec![entry.clone()],
                     },
                 }))
             )
@@ -636,6 +1681,192 @@ mod tests {
             vec![Instruction::Notify { id: vec![0x01], address: Address::Client, index: 6 }],
         );
 
+        // Another change is rejected while the first is uncommitted.
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest {
+                id: vec![0x02],
+                request: Request::ConfigChange(ConfigChange::RemoveServer { id: "b".into() }),
+            },
+        })?;
+        assert_node(&node).is_leader().last(6);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 3,
+                event: Event::ClientResponse {
+                    id: vec![0x02],
+                    response: Err(Error::Value(
+                        "A membership change is already in progress".into(),
+                    )),
+                },
+            }],
+        );
+
+        // Once committed by a quorum of the old configuration, the new server is added and
+        // the log is replicated to it.
+        for peer in &["b", "c"] {
+            node = node.step(Message {
+                from: Address::Peer(peer.to_string()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 6 },
+            })?;
+        }
+        assert_node(&node).is_leader().committed(6);
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Local,
+                    term: 3,
+                    event: Event::ConfigChange(config),
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("f".into()),
+                    term: 3,
+                    event: Event::ReplicateEntries { base_index: 6, base_term: 3, entries: vec![] },
+                },
+            ],
+        );
+        assert_messages(
+            &mut state_rx,
+            vec![Instruction::ApplyBatch {
+                entries: vec![
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), config: None },
+                    Entry { index: 5, term: 3, command: Some(vec![0x05]), config: None },
+                    entry,
+                ],
+            }],
+        );
+        match node {
+            Node::Leader(ref leader) => {
+                assert!(leader.peers.contains(&"f".to_string()));
+                assert_eq!(leader.quorum(), 4);
+                assert_eq!(leader.role.peer_last_index.get("f"), Some(&0));
+            }
+            _ => panic!("Expected leader"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    // A learner receives the log but doesn't count towards quorum until it's promoted, which
+    // requires it to have caught up.
+    fn step_clientrequest_learner() -> Result<()> {
+        let (leader, mut node_rx, _state_rx) = setup()?;
+        let mut node: Node = leader.into();
+
+        let step_config = |node: Node, id: u8, config: ConfigChange| {
+            node.step(Message {
+                from: Address::Client,
+                to: Address::Local,
+                term: 0,
+                event: Event::ClientRequest {
+                    id: vec![id],
+                    request: Request::ConfigChange(config),
+                },
+            })
+        };
+        let step_accept = |mut node: Node, peers: &[&str], last_index: u64| -> Result<Node> {
+            for peer in peers {
+                node = node.step(Message {
+                    from: Address::Peer(peer.to_string()),
+                    to: Address::Peer("a".into()),
+                    term: 3,
+                    event: Event::AcceptEntries { last_index },
+                })?;
+            }
+            Ok(node)
+        };
+        let drain = |rx: &mut mpsc::UnboundedReceiver<Message>| {
+            while let Some(Some(_)) = rx.recv().now_or_never() {}
+        };
+        let assert_members =
+            |node: &Node, peers: Vec<&str>, learners: Vec<&str>, quorum: u64| match node {
+                Node::Leader(leader) => {
+                    assert_eq!(leader.peers, peers);
+                    assert_eq!(leader.learners, learners);
+                    assert_eq!(leader.quorum(), quorum);
+                }
+                _ => panic!("Expected leader"),
+            };
+
+        // Adding the learner doesn't change the quorum, but replicates the log to it.
+        let learner = ConfigChange::AddLearner { id: "f".into(), addr: "localhost:9706".into() };
+        node = step_config(node, 0x01, learner.clone())?;
+        drain(&mut node_rx);
+        node = step_accept(node, &["b", "c"], 6)?;
+        assert_node(&node).is_leader().committed(6);
+        assert_members(&node, vec!["b", "c", "d", "e"], vec!["f"], 3);
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Local,
+                    term: 3,
+                    event: Event::ConfigChange(learner),
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("f".into()),
+                    term: 3,
+                    event: Event::ReplicateEntries { base_index: 6, base_term: 3, entries: vec![] },
+                },
+            ],
+        );
+
+        // The learner can't be promoted before it has caught up.
+        node = step_config(node, 0x02, ConfigChange::PromoteLearner { id: "f".into() })?;
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 3,
+                event: Event::ClientResponse {
+                    id: vec![0x02],
+                    response: Err(Error::Value("Learner f has not caught up".into())),
+                },
+            }],
+        );
+
+        // Writes are replicated to the learner, but its acknowledgement doesn't count towards
+        // the quorum.
+        node = step_accept(node, &["d", "e", "f"], 6)?;
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x03], request: Request::Mutate(vec![0xaf]) },
+        })?;
+        let replicated = std::iter::from_fn(|| node_rx.recv().now_or_never().flatten())
+            .filter_map(|msg| match msg {
+                Message {
+                    to: Address::Peer(to), event: Event::ReplicateEntries { .. }, ..
+                } => Some(to),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(replicated, vec!["b", "c", "d", "e", "f"]);
+        node = step_accept(node, &["b", "f"], 7)?;
+        assert_node(&node).is_leader().committed(6);
+
+        // Once caught up, the learner can be promoted, and then counts towards the quorum.
+        node = step_config(node, 0x04, ConfigChange::PromoteLearner { id: "f".into() })?;
+        assert_node(&node).is_leader().last(8);
+        node = step_accept(node, &["b", "c"], 8)?;
+        assert_node(&node).is_leader().committed(8);
+        assert_members(&node, vec!["b", "c", "d", "e", "f"], vec![], 4);
+
         Ok(())
     }
 
@@ -671,10 +1902,29 @@ mod tests {
                     ]
                     .into_iter()
                     .collect(),
+                    node_next_index: vec![
+                        ("b".into(), 6),
+                        ("c".into(), 6),
+                        ("d".into(), 6),
+                        ("e".into(), 6),
+                    ]
+                    .into_iter()
+                    .collect(),
+                    node_last_contact: vec![
+                        ("b".into(), 0),
+                        ("c".into(), 0),
+                        ("d".into(), 0),
+                        ("e".into(), 0),
+                    ]
+                    .into_iter()
+                    .collect(),
+                    last_index: 5,
                     commit_index: 2,
                     apply_index: 0,
+                    pending_notifications: 0,
+                    pending_queries: 0,
                     storage: "test".into(),
-                    storage_size: 130,
+                    storage_size: 135,
                 }),
             }],
         );
@@ -692,6 +1942,7 @@ mod tests {
                 assert_messages(&mut state_rx, vec![]);
                 node = node.tick()?;
                 assert_node(&node).is_leader().term(3).committed(2);
+                assert_messages(&mut state_rx, vec![Instruction::Tick]);
             }
 
             assert_eq!(
@@ -706,4 +1957,179 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    // The heartbeat interval can be changed at runtime, and invalid configurations are rejected.
+    fn step_clientrequest_updateconfig() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let mut node: Node = leader.into();
+        let update = |id: u8, config: RaftConfig| Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![id], request: Request::UpdateConfig(config) },
+        };
+        let heartbeat = Message {
+            from: Address::Local,
+            to: Address::Peers,
+            term: 3,
+            event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+        };
+
+        node = node.step(update(
+            0x01,
+            RaftConfig { heartbeat_interval: ELECTION_TIMEOUT_MAX, ..RaftConfig::default() },
+        ))?;
+        node = node.step(update(
+            0x02,
+            RaftConfig { tick: std::time::Duration::from_secs(1), ..RaftConfig::default() },
+        ))?;
+        node =
+            node.step(update(0x03, RaftConfig { heartbeat_interval: 3, ..RaftConfig::default() }))?;
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 3,
+                    event: Event::ClientResponse {
+                        id: vec![0x01],
+                        response: Err(Error::Config(format!(
+                            "Raft heartbeat interval {} must be less than minimum election timeout {}",
+                            ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN
+                        ))),
+                    },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 3,
+                    event: Event::ClientResponse {
+                        id: vec![0x02],
+                        response: Err(Error::Value("Raft tick can't be changed at runtime".into())),
+                    },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 3,
+                    event: Event::ClientResponse {
+                        id: vec![0x03],
+                        response: Ok(Response::UpdateConfig),
+                    },
+                },
+            ],
+        );
+
+        // Subsequent heartbeats are sent every 3 ticks.
+        for _ in 0..3 {
+            for _ in 0..2 {
+                node = node.tick()?;
+                assert_messages(&mut node_rx, vec![]);
+            }
+            node = node.tick()?;
+            assert_messages(&mut node_rx, vec![heartbeat.clone()]);
+        }
+        assert_messages(&mut state_rx, (0..9).map(|_| Instruction::Tick).collect());
+        Ok(())
+    }
+
+    #[test]
+    // Ticks since last contact are tracked per peer, and reset when hearing from the peer.
+    fn tick_last_contact() -> Result<()> {
+        let (leader, _node_rx, _state_rx) = setup()?;
+        let mut node: Node = leader.into();
+        for _ in 0..3 {
+            node = node.tick()?;
+        }
+        node = node.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::AcceptEntries { last_index: 5 },
+        })?;
+        match node {
+            Node::Leader(rolenode) => {
+                assert_eq!(
+                    rolenode.role.peer_last_contact,
+                    vec![("b".into(), 3), ("c".into(), 0), ("d".into(), 3), ("e".into(), 3)]
+                        .into_iter()
+                        .collect()
+                );
+                assert_eq!(rolenode.role.peer_last_index.get("c"), Some(&5));
+                assert_eq!(rolenode.role.peer_next_index.get("c"), Some(&6));
+            }
+            _ => panic!("Expected leader"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    // Without contact with a quorum, writes fail fast while stale reads are served, until quorum
+    // returns.
+    fn tick_quorum_lost() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let mut node: Node = leader.into();
+        let request = |id, request| Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![id], request },
+        };
+        let accept = |from: &str, last_index| Message {
+            from: Address::Peer(from.into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::AcceptEntries { last_index },
+        };
+
+        // Hearing from a single peer isn't enough for a quorum.
+        for _ in 0..RaftConfig::default().quorum_loss_timeout {
+            node = node.tick()?;
+            node = node.step(accept("b", 5))?;
+        }
+        while let Some(Some(_)) = node_rx.recv().now_or_never() {}
+        while let Some(Some(_)) = state_rx.recv().now_or_never() {}
+
+        node = node.step(request(0x01, Request::Mutate(vec![0xaf])))?;
+        assert_node(&node).is_leader().term(3).last(5);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 3,
+                event: Event::ClientResponse { id: vec![0x01], response: Err(Error::NoQuorum) },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+
+        node = node.step(request(0x02, Request::Query(vec![0xaf])))?;
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut state_rx,
+            vec![
+                Instruction::Query {
+                    id: vec![0x02],
+                    address: Address::Client,
+                    command: vec![0xaf],
+                    term: 3,
+                    index: 2,
+                    quorum: 1,
+                },
+                Instruction::Vote { term: 3, index: 2, address: Address::Local },
+            ],
+        );
+
+        // Once a quorum responds again, writes are accepted.
+        node = node.step(accept("c", 2))?;
+        node = node.step(request(0x03, Request::Mutate(vec![0xaf])))?;
+        assert_node(&node).is_leader().term(3).last(6);
+        assert_messages(
+            &mut state_rx,
+            vec![Instruction::Notify { id: vec![0x03], address: Address::Client, index: 6 }],
+        );
+        Ok(())
+    }
 }
@@ -2,35 +2,201 @@ mod candidate;
 mod follower;
 mod leader;
 
-use super::{Address, Driver, Event, Instruction, Log, Message, State};
+use super::{
+    Address, ConfigChange, Driver, Entry, Event, Instruction, Log, Message, Response, State,
+};
 use crate::error::{Error, Result};
 use candidate::Candidate;
 use follower::Follower;
 use leader::Leader;
 
 use ::log::{debug, info};
+use futures::future::{BoxFuture, FutureExt as _};
+use rand::rngs::StdRng;
+use rand::Rng as _;
 use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
-/// The interval between leader heartbeats, in ticks.
+/// The default duration of a Raft tick, the unit of time for e.g. heartbeats and elections.
+const TICK: Duration = Duration::from_millis(100);
+
+/// The default interval between leader heartbeats, in ticks.
 const HEARTBEAT_INTERVAL: u64 = 1;
 
-/// The minimum election timeout, in ticks.
+/// The default minimum election timeout, in ticks.
 const ELECTION_TIMEOUT_MIN: u64 = 8 * HEARTBEAT_INTERVAL;
 
-/// The maximum election timeout, in ticks.
+/// The default maximum election timeout, in ticks.
 const ELECTION_TIMEOUT_MAX: u64 = 15 * HEARTBEAT_INTERVAL;
 
+/// The default number of ticks without contact with a quorum before a leader rejects writes.
+const QUORUM_LOSS_TIMEOUT: u64 = ELECTION_TIMEOUT_MAX;
+
+/// The default number of ticks a read query can wait to execute before it's aborted.
+const QUERY_TIMEOUT: u64 = 4 * ELECTION_TIMEOUT_MAX;
+
+/// The default maximum number of entries to replicate in a single message.
+const MAX_BATCH_ENTRIES: usize = 256;
+
+/// The default maximum size of entry commands to replicate in a single message, in bytes.
+const MAX_BATCH_BYTES: u64 = 1024 * 1024;
+
+/// The default maximum number of unacknowledged entries in flight to a peer.
+const MAX_INFLIGHT_ENTRIES: u64 = 4 * MAX_BATCH_ENTRIES as u64;
+
+/// The default maximum size of unacknowledged entry commands in flight to a peer, in bytes.
+const MAX_INFLIGHT_BYTES: u64 = 4 * MAX_BATCH_BYTES;
+
+thread_local! {
+    /// A seeded RNG used instead of the thread RNG if set, for deterministic simulation.
+    static SEEDED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Seeds the RNG used by Raft nodes on the current thread, such that a single-threaded
+/// simulation is reproducible by seed.
+#[cfg(test)]
+pub fn seed_rng(seed: u64) {
+    use rand::SeedableRng as _;
+    SEEDED_RNG.with(|rng| *rng.borrow_mut() = Some(StdRng::seed_from_u64(seed)))
+}
+
+/// Raft node configuration. Tight timings suit low-latency networks, while loose timings avoid
+/// spurious elections across high-latency links.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RaftConfig {
+    /// The duration of a tick, the unit of time for the intervals below.
+    pub tick: Duration,
+    /// The interval between leader heartbeats, in ticks.
+    pub heartbeat_interval: u64,
+    /// The range of election timeouts, in ticks. Each follower and candidate picks a random
+    /// timeout in this range, to avoid split votes.
+    pub election_timeout_range: RangeInclusive<u64>,
+    /// The number of ticks a leader can go without hearing from a quorum before it considers
+    /// quorum lost. It then rejects writes, since they can't commit, until quorum returns.
+    pub quorum_loss_timeout: u64,
+    /// The number of ticks a leader's read query can wait for a read quorum and for its index to
+    /// be applied, before it's aborted. Otherwise, queries submitted before losing contact with a
+    /// quorum would wait until leadership changes.
+    pub query_timeout: u64,
+    /// The maximum number of log entries to replicate to a peer in a single message.
+    pub max_batch_entries: usize,
+    /// The maximum size of entry commands to replicate to a peer in a single message, in bytes.
+    /// A single entry larger than this is still replicated on its own.
+    pub max_batch_bytes: u64,
+    /// The maximum number of unacknowledged entries in flight to a peer. Replication to a peer
+    /// that falls behind is throttled until it acknowledges earlier entries.
+    pub max_inflight_entries: u64,
+    /// The maximum size of unacknowledged entry commands in flight to a peer, in bytes.
+    pub max_inflight_bytes: u64,
+    /// Whether the node is a witness: it votes and acknowledges log entries towards the quorum,
+    /// but discards their commands and never applies them, nor becomes leader. This allows a
+    /// cheap tiebreaker node, e.g. in a third datacenter.
+    pub witness: bool,
+    /// Entry commands larger than this many bytes are compressed when replicated to peers, or
+    /// never if None. Peers always accept compressed entries, so this can be set per node.
+    pub compression_threshold: Option<u64>,
+    /// The state machine is snapshotted every this many applied entries, or never if None. The
+    /// snapshot is saved in the log, which then compacts the entries it covers. Initial replay
+    /// starts from it, and peers needing compacted entries are sent it instead.
+    pub snapshot_interval: Option<u64>,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            tick: TICK,
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+            election_timeout_range: ELECTION_TIMEOUT_MIN..=ELECTION_TIMEOUT_MAX,
+            quorum_loss_timeout: QUORUM_LOSS_TIMEOUT,
+            query_timeout: QUERY_TIMEOUT,
+            max_batch_entries: MAX_BATCH_ENTRIES,
+            max_batch_bytes: MAX_BATCH_BYTES,
+            max_inflight_entries: MAX_INFLIGHT_ENTRIES,
+            max_inflight_bytes: MAX_INFLIGHT_BYTES,
+            witness: false,
+            compression_threshold: None,
+            snapshot_interval: None,
+        }
+    }
+}
+
+impl RaftConfig {
+    /// Validates the configuration. The heartbeat interval must be shorter than the minimum
+    /// election timeout, otherwise followers would start elections against a live leader.
+    pub fn validate(&self) -> Result<()> {
+        if self.tick.as_nanos() == 0 {
+            return Err(Error::Config("Raft tick must be non-zero".into()));
+        }
+        if self.heartbeat_interval == 0 {
+            return Err(Error::Config("Raft heartbeat interval must be non-zero".into()));
+        }
+        if self.election_timeout_range.is_empty() {
+            return Err(Error::Config(format!(
+                "Invalid Raft election timeout range {:?}",
+                self.election_timeout_range
+            )));
+        }
+        if self.heartbeat_interval >= *self.election_timeout_range.start() {
+            return Err(Error::Config(format!(
+                "Raft heartbeat interval {} must be less than minimum election timeout {}",
+                self.heartbeat_interval,
+                self.election_timeout_range.start()
+            )));
+        }
+        if self.quorum_loss_timeout <= self.heartbeat_interval {
+            return Err(Error::Config(format!(
+                "Raft quorum loss timeout {} must be greater than heartbeat interval {}",
+                self.quorum_loss_timeout, self.heartbeat_interval
+            )));
+        }
+        if self.query_timeout == 0 {
+            return Err(Error::Config("Raft query timeout must be non-zero".into()));
+        }
+        if self.max_batch_entries == 0 || self.max_batch_bytes == 0 {
+            return Err(Error::Config("Raft batch limits must be non-zero".into()));
+        }
+        if self.max_inflight_entries == 0 || self.max_inflight_bytes == 0 {
+            return Err(Error::Config("Raft in-flight limits must be non-zero".into()));
+        }
+        if self.snapshot_interval == Some(0) {
+            return Err(Error::Config("Raft snapshot interval must be non-zero".into()));
+        }
+        Ok(())
+    }
+
+    /// Picks a random election timeout within the configured range.
+    fn election_timeout(&self) -> u64 {
+        let range = self.election_timeout_range.clone();
+        SEEDED_RNG.with(|rng| match rng.borrow_mut().as_mut() {
+            Some(rng) => rng.gen_range(range),
+            None => rand::thread_rng().gen_range(range),
+        })
+    }
+}
+
 /// Node status
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Status {
     pub server: String,
     pub leader: String,
     pub term: u64,
+    /// The last log index of each node (i.e. its match index), as seen by the leader.
     pub node_last_index: HashMap<String, u64>,
+    /// The next log index the leader will replicate to each peer.
+    pub node_next_index: HashMap<String, u64>,
+    /// The number of ticks since the leader last heard from each peer.
+    pub node_last_contact: HashMap<String, u64>,
+    pub last_index: u64,
     pub commit_index: u64,
     pub apply_index: u64,
+    /// The number of applied entries the state machine driver has yet to notify clients of.
+    pub pending_notifications: usize,
+    /// The number of read queries the state machine driver has yet to execute.
+    pub pending_queries: usize,
     pub storage: String,
     pub storage_size: u64,
 }
@@ -48,9 +214,30 @@ impl Node {
         id: &str,
         peers: Vec<String>,
         log: Log,
-        mut state: Box<dyn State>,
+        state: Box<dyn State>,
         node_tx: mpsc::UnboundedSender<Message>,
+        config: RaftConfig,
     ) -> Result<Self> {
+        let (node, driver) = Self::new_undriven(id, peers, log, state, node_tx, config)?;
+        tokio::spawn(driver);
+        Ok(node)
+    }
+
+    /// Creates a new Raft node like new(), but returns its state machine driver for the caller
+    /// to run instead of spawning it. The cluster simulator runs the drivers itself, so that
+    /// everything happens on a single thread.
+    pub(crate) fn new_undriven(
+        id: &str,
+        peers: Vec<String>,
+        log: Log,
+        mut state: Box<dyn State>,
+        node_tx: mpsc::UnboundedSender<Message>,
+        config: RaftConfig,
+    ) -> Result<(Self, BoxFuture<'static, Result<()>>)> {
+        config.validate()?;
+        if config.witness && peers.is_empty() {
+            return Err(Error::Config("A witness node must have peers".into()));
+        }
         let applied_index = state.applied_index();
         if applied_index > log.commit_index {
             return Err(Error::Internal(format!(
@@ -60,15 +247,28 @@ impl Node {
         }
 
         let (state_tx, state_rx) = mpsc::unbounded_channel();
-        let mut driver = Driver::new(state_rx, node_tx.clone());
-        if log.commit_index > applied_index {
-            info!("Replaying log entries {} to {}", applied_index + 1, log.commit_index);
-            driver.replay(&mut *state, log.scan((applied_index + 1)..=log.commit_index))?;
+        let mut driver = Driver::new(state_rx, node_tx.clone())
+            .with_snapshot_interval(config.snapshot_interval)
+            .with_query_timeout(config.query_timeout);
+        let mut replay_index = applied_index;
+        let snapshot = log.load_snapshot()?;
+        let snapshot_configs = snapshot.as_ref().map(|s| s.configs.clone()).unwrap_or_default();
+        match snapshot {
+            Some(snapshot) if snapshot.index > applied_index && !config.witness => {
+                info!("Restoring state machine snapshot at index {}", snapshot.index);
+                replay_index = snapshot.index;
+                driver.restore(&mut *state, snapshot)?;
+            }
+            _ => {}
+        }
+        if log.commit_index > replay_index && !config.witness {
+            info!("Replaying log entries {} to {}", replay_index + 1, log.commit_index);
+            driver.replay(&mut *state, log.scan((replay_index + 1)..=log.commit_index))?;
         };
-        tokio::spawn(driver.drive(state));
+        let driver = driver.drive(state).boxed();
 
         let (term, voted_for) = log.load_term()?;
-        let node = RoleNode {
+        let mut node = RoleNode {
             id: id.to_owned(),
             peers,
             term,
@@ -77,14 +277,27 @@ impl Node {
             state_tx,
             queued_reqs: Vec::new(),
             proxied_reqs: HashMap::new(),
-            role: Follower::new(None, voted_for.as_deref()),
+            learners: Vec::new(),
+            role: Follower::new(None, voted_for.as_deref(), config.election_timeout()),
+            config,
         };
+        // Membership changes up to the snapshot are recorded in it, since their log entries may
+        // have been compacted.
+        let configs = node
+            .log
+            .scan((node.log.snapshot_index + 1)..=node.log.commit_index)
+            .collect::<Result<Vec<_>>>()?;
+        for config in
+            snapshot_configs.into_iter().chain(configs.into_iter().filter_map(|e| e.config))
+        {
+            node.apply_config(config)?;
+        }
         if node.peers.is_empty() {
             info!("No peers specified, starting as leader");
             let last_index = node.log.last_index;
-            Ok(node.become_role(Leader::new(vec![], last_index))?.into())
+            Ok((node.become_role(Leader::new(vec![], last_index))?.into(), driver))
         } else {
-            Ok(node.into())
+            Ok((node.into(), driver))
         }
     }
 
@@ -98,8 +311,15 @@ impl Node {
     }
 
     /// Processes a message.
-    pub fn step(self, msg: Message) -> Result<Self> {
+    pub fn step(mut self, msg: Message) -> Result<Self> {
         debug!("Stepping {:?}", msg);
+        // Snapshots from the local state machine driver are saved regardless of role, and the log
+        // entries they cover are compacted. Peers that still need them are sent the snapshot.
+        if let Message { from: Address::Local, event: Event::Snapshot(snapshot), .. } = msg {
+            self.log_mut().save_snapshot(&snapshot)?;
+            self.log_mut().compact(snapshot.index)?;
+            return Ok(self);
+        }
         match self {
             Node::Candidate(n) => n.step(msg),
             Node::Follower(n) => n.step(msg),
@@ -107,8 +327,19 @@ impl Node {
         }
     }
 
-    /// Moves time forward by a tick.
-    pub fn tick(self) -> Result<Self> {
+    /// Returns the node's log.
+    fn log_mut(&mut self) -> &mut Log {
+        match self {
+            Node::Candidate(n) => &mut n.log,
+            Node::Follower(n) => &mut n.log,
+            Node::Leader(n) => &mut n.log,
+        }
+    }
+
+    /// Moves time forward by a tick. Log entries whose fsync was deferred are synced on ticks, so
+    /// they don't wait for the next commit.
+    pub fn tick(mut self) -> Result<Self> {
+        self.log_mut().sync_deferred()?;
         match self {
             Node::Candidate(n) => n.tick(),
             Node::Follower(n) => n.tick(),
@@ -147,6 +378,11 @@ pub struct RoleNode<R> {
     queued_reqs: Vec<(Address, Event)>,
     /// Keeps track of proxied client requests, to abort on new leader election.
     proxied_reqs: HashMap<Vec<u8>, Address>,
+    /// Non-voting peers that receive the log but don't count towards quorum. This includes our
+    /// own ID if we're a learner.
+    learners: Vec<String>,
+    /// Timing configuration.
+    config: RaftConfig,
     role: R,
 }
 
@@ -162,6 +398,8 @@ impl<R> RoleNode<R> {
             state_tx: self.state_tx,
             queued_reqs: self.queued_reqs,
             proxied_reqs: self.proxied_reqs,
+            learners: self.learners,
+            config: self.config,
             role,
         })
     }
@@ -174,6 +412,31 @@ impl<R> RoleNode<R> {
         Ok(())
     }
 
+    /// Updates the node configuration at runtime, validating it and responding to the client.
+    /// The tick duration and compression are handled by the server, the snapshot interval by the
+    /// state machine driver, and witness mode determines what's stored in the log, so these can't
+    /// be changed.
+    fn update_config(&mut self, address: Address, id: Vec<u8>, config: RaftConfig) -> Result<()> {
+        let response = if config.tick != self.config.tick {
+            Err(Error::Value("Raft tick can't be changed at runtime".into()))
+        } else if config.witness != self.config.witness {
+            Err(Error::Value("Raft witness mode can't be changed at runtime".into()))
+        } else if config.compression_threshold != self.config.compression_threshold {
+            Err(Error::Value("Raft compression can't be changed at runtime".into()))
+        } else if config.snapshot_interval != self.config.snapshot_interval {
+            Err(Error::Value("Raft snapshot interval can't be changed at runtime".into()))
+        } else if config.query_timeout != self.config.query_timeout {
+            Err(Error::Value("Raft query timeout can't be changed at runtime".into()))
+        } else {
+            config.validate().map(|()| {
+                info!("Updating Raft configuration to {:?}", config);
+                self.config = config;
+                Response::UpdateConfig
+            })
+        };
+        self.send(address, Event::ClientResponse { id, response })
+    }
+
     /// Sends any queued requests to the given leader.
     fn forward_queued(&mut self, leader: Address) -> Result<()> {
         for (from, event) in std::mem::take(&mut self.queued_reqs) {
@@ -193,14 +456,77 @@ impl<R> RoleNode<R> {
         Ok(())
     }
 
+    /// Applies a committed cluster membership change, and notifies the local server about it
+    /// such that it can connect to the new peer set.
+    fn apply_config(&mut self, config: ConfigChange) -> Result<()> {
+        match &config {
+            ConfigChange::AddServer { id, .. } if id != &self.id && !self.peers.contains(id) => {
+                info!("Adding server {} to cluster", id);
+                self.peers.push(id.clone());
+            }
+            ConfigChange::AddLearner { id, .. }
+                if !self.peers.contains(id) && !self.learners.contains(id) =>
+            {
+                info!("Adding learner {} to cluster", id);
+                self.learners.push(id.clone());
+            }
+            ConfigChange::PromoteLearner { id } if self.learners.contains(id) => {
+                info!("Promoting learner {} to voter", id);
+                self.learners.retain(|learner| learner != id);
+                if id != &self.id {
+                    self.peers.push(id.clone());
+                }
+            }
+            ConfigChange::RemoveServer { id } if self.is_member(id) && id != &self.id => {
+                info!("Removing server {} from cluster", id);
+                self.peers.retain(|peer| peer != id);
+                self.learners.retain(|learner| learner != id);
+            }
+            _ => {}
+        }
+        self.send(Address::Local, Event::ConfigChange(config))
+    }
+
+    /// Hands committed entries to the state machine driver for applying. Entries committed
+    /// together are sent as a single batch.
+    fn apply_entries(&self, mut entries: Vec<Entry>) -> Result<()> {
+        match entries.len() {
+            0 => Ok(()),
+            1 => Ok(self.state_tx.send(Instruction::Apply { entry: entries.remove(0) })?),
+            _ => Ok(self.state_tx.send(Instruction::ApplyBatch { entries })?),
+        }
+    }
+
+    /// Checks whether the given node is a member of the cluster, either as a voter or learner.
+    fn is_member(&self, id: &str) -> bool {
+        id == self.id
+            || self.peers.iter().any(|peer| peer == id)
+            || self.learners.iter().any(|learner| learner == id)
+    }
+
+    /// Checks whether the local node is a learner, which can't vote or campaign.
+    fn is_learner(&self) -> bool {
+        self.learners.contains(&self.id)
+    }
+
+    /// Checks whether an address is a voting peer.
+    fn is_voter(&self, address: &Address) -> bool {
+        matches!(address, Address::Peer(id) if self.peers.contains(id))
+    }
+
     /// Returns the quorum size of the cluster.
     fn quorum(&self) -> u64 {
-        (self.peers.len() as u64 + 1) / 2 + 1
+        (self.peers.len() as u64).div_ceil(2) + 1
     }
 
     /// Sends an event
     fn send(&self, to: Address, event: Event) -> Result<()> {
-        let msg = Message { term: self.term, from: Address::Local, to, event };
+        self.send_term(to, self.term, event)
+    }
+
+    /// Sends an event for the given term rather than the current one, used for pre-votes
+    fn send_term(&self, to: Address, term: u64, event: Event) -> Result<()> {
+        let msg = Message { term, from: Address::Local, to, event };
         debug!("Sending {:?}", msg);
         Ok(self.node_tx.send(msg)?)
     }
@@ -238,7 +564,7 @@ impl<R> RoleNode<R> {
 #[cfg(test)]
 mod tests {
     pub use super::super::state::tests::TestState;
-    use super::super::Entry;
+    use super::super::{Entry, Snapshot};
     use super::follower::tests::{follower_leader, follower_voted_for};
     use super::*;
     use crate::storage::log;
@@ -421,7 +747,9 @@ mod tests {
             node_tx,
             state_tx,
             proxied_reqs: HashMap::new(),
+            learners: Vec::new(),
             queued_reqs: Vec::new(),
+            config: RaftConfig::default(),
         };
         Ok((node, node_rx))
     }
@@ -435,6 +763,7 @@ mod tests {
             Log::new(Box::new(log::Test::new()))?,
             Box::new(TestState::new(0)),
             node_tx,
+            RaftConfig::default(),
         )
         .await?;
         match node {
@@ -448,6 +777,26 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn tick_syncs_log() -> Result<()> {
+        let (node_tx, _) = mpsc::unbounded_channel();
+        let store = log::Test::new();
+        let mut node = Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            Log::new(Box::new(store.clone()))?,
+            Box::new(TestState::new(0)),
+            node_tx,
+            RaftConfig::default(),
+        )
+        .await?;
+        assert_eq!(store.syncs(), 0);
+        node = node.tick()?;
+        node.tick()?;
+        assert_eq!(store.syncs(), 2);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn new_loads_term() -> Result<()> {
         let (node_tx, _) = mpsc::unbounded_channel();
@@ -459,6 +808,7 @@ mod tests {
             Log::new(store)?,
             Box::new(TestState::new(0)),
             node_tx,
+            RaftConfig::default(),
         )
         .await?;
         match node {
@@ -479,7 +829,15 @@ mod tests {
         log.append(2, Some(vec![0x03]))?;
         let state = Box::new(TestState::new(0));
 
-        Node::new("a", vec!["b".into(), "c".into()], log, state.clone(), node_tx).await?;
+        Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            log,
+            state.clone(),
+            node_tx,
+            RaftConfig::default(),
+        )
+        .await?;
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         assert_eq!(state.list(), vec![vec![0x01], vec![0x02]]);
         assert_eq!(state.applied_index(), 3);
@@ -497,7 +855,15 @@ mod tests {
         log.append(2, Some(vec![0x03]))?;
         let state = Box::new(TestState::new(2));
 
-        Node::new("a", vec!["b".into(), "c".into()], log, state.clone(), node_tx).await?;
+        Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            log,
+            state.clone(),
+            node_tx,
+            RaftConfig::default(),
+        )
+        .await?;
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         assert_eq!(state.list(), vec![vec![0x02]]);
         assert_eq!(state.applied_index(), 3);
@@ -516,7 +882,16 @@ mod tests {
         let state = Box::new(TestState::new(4));
 
         assert_eq!(
-            Node::new("a", vec!["b".into(), "c".into()], log, state.clone(), node_tx).await.err(),
+            Node::new(
+                "a",
+                vec!["b".into(), "c".into()],
+                log,
+                state.clone(),
+                node_tx,
+                RaftConfig::default()
+            )
+            .await
+            .err(),
             Some(Error::Internal(
                 "State machine applied index 4 greater than log committed index 3".into()
             ))
@@ -524,6 +899,113 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn new_state_apply_snapshot() -> Result<()> {
+        let (node_tx, _) = mpsc::unbounded_channel();
+        let mut log = Log::new(Box::new(log::Test::new()))?;
+        log.append(1, Some(vec![0x01]))?;
+        log.append(2, None)?;
+        log.append(2, Some(vec![0x02]))?;
+        log.commit(3)?;
+        let mut snapshot = TestState::new(0);
+        snapshot.mutate(1, vec![0x01])?;
+        log.save_snapshot(&Snapshot {
+            index: 2,
+            term: 2,
+            configs: vec![],
+            data: snapshot.snapshot()?,
+        })?;
+        log.compact(2)?;
+        let state = Box::new(TestState::new(0));
+
+        Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            log,
+            state.clone(),
+            node_tx,
+            RaftConfig::default(),
+        )
+        .await?;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(state.list(), vec![vec![0x01], vec![0x02]]);
+        assert_eq!(state.applied_index(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn step_snapshot() -> Result<()> {
+        let (node_tx, _) = mpsc::unbounded_channel();
+        let mut log = Log::new(Box::new(log::Test::new()))?;
+        log.append(1, Some(vec![0x01]))?;
+        log.commit(1)?;
+        let mut node = Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            log,
+            Box::new(TestState::new(1)),
+            node_tx,
+            RaftConfig::default(),
+        )
+        .await?;
+
+        let snapshot = Snapshot { index: 1, term: 1, configs: vec![], data: vec![0x01] };
+        node = node.step(Message {
+            from: Address::Local,
+            to: Address::Local,
+            term: 0,
+            event: Event::Snapshot(Box::new(snapshot.clone())),
+        })?;
+        assert_eq!(node.log_mut().load_snapshot()?, Some(snapshot));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn new_applies_config() -> Result<()> {
+        let (node_tx, mut node_rx) = mpsc::unbounded_channel();
+        let mut log = Log::new(Box::new(log::Test::new()))?;
+        let add = ConfigChange::AddServer { id: "d".into(), addr: "localhost:9704".into() };
+        let remove = ConfigChange::RemoveServer { id: "b".into() };
+        log.append_config(1, add.clone())?;
+        log.append_config(1, remove.clone())?;
+        log.commit(2)?;
+        log.append_config(1, ConfigChange::RemoveServer { id: "c".into() })?;
+
+        let node = Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            log,
+            Box::new(TestState::new(0)),
+            node_tx,
+            RaftConfig::default(),
+        )
+        .await?;
+        match node {
+            Node::Follower(rolenode) => {
+                assert_eq!(rolenode.peers, vec!["c".to_owned(), "d".to_owned()]);
+            }
+            _ => panic!("Expected node to start as follower"),
+        }
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Local,
+                    term: 0,
+                    event: Event::ConfigChange(add),
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Local,
+                    term: 0,
+                    event: Event::ConfigChange(remove),
+                },
+            ],
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn new_single() -> Result<()> {
         let (node_tx, _) = mpsc::unbounded_channel();
@@ -533,6 +1015,7 @@ mod tests {
             Log::new(Box::new(log::Test::new()))?,
             Box::new(TestState::new(0)),
             node_tx,
+            RaftConfig::default(),
         )
         .await?;
         match node {
@@ -546,6 +1029,63 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn config_validate() -> Result<()> {
+        assert_eq!(RaftConfig::default().validate(), Ok(()));
+
+        let config = RaftConfig {
+            heartbeat_interval: 10,
+            election_timeout_range: 10..=20,
+            ..RaftConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(Error::Config(
+                "Raft heartbeat interval 10 must be less than minimum election timeout 10".into()
+            ))
+        );
+
+        let config = RaftConfig { heartbeat_interval: 0, ..RaftConfig::default() };
+        assert!(config.validate().is_err());
+
+        let config = RaftConfig { snapshot_interval: Some(0), ..RaftConfig::default() };
+        assert!(config.validate().is_err());
+
+        let config = RaftConfig { query_timeout: 0, ..RaftConfig::default() };
+        assert!(config.validate().is_err());
+
+        let config =
+            RaftConfig { quorum_loss_timeout: HEARTBEAT_INTERVAL, ..RaftConfig::default() };
+        assert!(config.validate().is_err());
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let config = RaftConfig { election_timeout_range: 20..=10, ..RaftConfig::default() };
+        assert!(config.validate().is_err());
+
+        let config = RaftConfig { election_timeout_range: 30..=40, ..RaftConfig::default() };
+        for _ in 0..100 {
+            assert!((30..=40).contains(&config.election_timeout()));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn new_invalid_config() -> Result<()> {
+        let (node_tx, _) = mpsc::unbounded_channel();
+        let config = RaftConfig { heartbeat_interval: 20, ..RaftConfig::default() };
+        assert!(Node::new(
+            "a",
+            vec!["b".into(), "c".into()],
+            Log::new(Box::new(log::Test::new()))?,
+            Box::new(TestState::new(0)),
+            node_tx,
+            config,
+        )
+        .await
+        .is_err());
+        Ok(())
+    }
+
     #[test]
     fn become_role() -> Result<()> {
         let (node, _) = setup_rolenode()?;
@@ -1,9 +1,19 @@
-use super::{Address, Event, Log, Message, Node, Request, Response, State};
+use super::{
+    Address, Clock, ConfigChange, Event, Log, Message, Node, RaftConfig, Request, Response, State,
+    TokioClock,
+};
 use crate::error::{Error, Result};
 
 use ::log::{debug, error};
+use flate2::write::{DeflateDecoder, DeflateEncoder};
+use flate2::Compression;
+use futures::stream::BoxStream;
 use futures::{sink::SinkExt as _, FutureExt as _};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::io::Write as _;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{mpsc, oneshot};
@@ -12,14 +22,78 @@ use tokio_stream::StreamExt as _;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 use uuid::Uuid;
 
-/// The duration of a Raft tick, the unit of time for e.g. heartbeats and elections.
-const TICK: Duration = Duration::from_millis(100);
-
 /// A Raft server.
 pub struct Server {
     node: Node,
     peers: HashMap<String, String>,
     node_rx: mpsc::UnboundedReceiver<Message>,
+    tick: Duration,
+    compression_threshold: Option<u64>,
+    clock: Arc<dyn Clock>,
+}
+
+/// A frame sent between Raft peers. Entry commands above the sender's compression threshold are
+/// compressed, and transparently decompressed by the receiver before stepping the node.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Frame {
+    /// An uncompressed message.
+    Message(Message),
+    /// A ReplicateEntries message, with the commands at the given entry positions compressed.
+    Compressed { message: Message, compressed: Vec<usize> },
+}
+
+impl Frame {
+    /// Encodes a message into a frame, compressing entry commands above the given threshold.
+    fn encode(mut message: Message, threshold: Option<u64>) -> Result<Self> {
+        let (threshold, entries) = match (threshold, &mut message.event) {
+            (Some(threshold), Event::ReplicateEntries { entries, .. }) => (threshold, entries),
+            _ => return Ok(Self::Message(message)),
+        };
+        let mut compressed = Vec::new();
+        for (i, entry) in entries.iter_mut().enumerate() {
+            if let Some(command) = &mut entry.command {
+                if command.len() as u64 > threshold {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+                    encoder.write_all(command)?;
+                    let output = encoder.finish()?;
+                    if output.len() < command.len() {
+                        *command = output;
+                        compressed.push(i);
+                    }
+                }
+            }
+        }
+        if compressed.is_empty() {
+            Ok(Self::Message(message))
+        } else {
+            Ok(Self::Compressed { message, compressed })
+        }
+    }
+
+    /// Decodes a frame into a message, decompressing any compressed entry commands.
+    fn decode(self) -> Result<Message> {
+        let (mut message, compressed) = match self {
+            Self::Message(message) => return Ok(message),
+            Self::Compressed { message, compressed } => (message, compressed),
+        };
+        let entries = match &mut message.event {
+            Event::ReplicateEntries { entries, .. } => entries,
+            event => {
+                return Err(Error::Internal(format!("Unexpected compressed event {:?}", event)))
+            }
+        };
+        for i in compressed {
+            match entries.get_mut(i).and_then(|e| e.command.as_mut()) {
+                Some(command) => {
+                    let mut decoder = DeflateDecoder::new(Vec::new());
+                    decoder.write_all(command)?;
+                    *command = decoder.finish()?;
+                }
+                None => return Err(Error::Internal(format!("Invalid compressed entry {}", i))),
+            }
+        }
+        Ok(message)
+    }
 }
 
 impl Server {
@@ -29,8 +103,11 @@ impl Server {
         peers: HashMap<String, String>,
         log: Log,
         state: Box<dyn State>,
+        config: RaftConfig,
     ) -> Result<Self> {
         let (node_tx, node_rx) = mpsc::unbounded_channel();
+        let tick = config.tick;
+        let compression_threshold = config.compression_threshold;
         Ok(Self {
             node: Node::new(
                 id,
@@ -38,13 +115,23 @@ impl Server {
                 log,
                 state,
                 node_tx,
+                config,
             )
             .await?,
             peers,
             node_rx,
+            tick,
+            compression_threshold,
+            clock: Arc::new(TokioClock::new()),
         })
     }
 
+    /// Sets the clock used for ticks and reconnection backoff, e.g. a virtual clock in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Connects to peers and serves requests.
     pub async fn serve(
         self,
@@ -55,12 +142,24 @@ impl Server {
         let (tcp_out_tx, tcp_out_rx) = mpsc::unbounded_channel::<Message>();
         let (task, tcp_receiver) = Self::tcp_receive(listener, tcp_in_tx).remote_handle();
         tokio::spawn(task);
-        let (task, tcp_sender) =
-            Self::tcp_send(self.node.id(), self.peers, tcp_out_rx).remote_handle();
+        let (task, tcp_sender) = Self::tcp_send(
+            self.node.id(),
+            self.peers,
+            self.compression_threshold,
+            self.clock.clone(),
+            tcp_out_rx,
+        )
+        .remote_handle();
         tokio::spawn(task);
-        let (task, eventloop) =
-            Self::eventloop(self.node, self.node_rx, client_rx, tcp_in_rx, tcp_out_tx)
-                .remote_handle();
+        let (task, eventloop) = Self::eventloop(
+            self.node,
+            self.clock.ticker(self.tick),
+            self.node_rx,
+            client_rx,
+            tcp_in_rx,
+            tcp_out_tx,
+        )
+        .remote_handle();
         tokio::spawn(task);
 
         tokio::try_join!(tcp_receiver, tcp_sender, eventloop)?;
@@ -70,6 +169,7 @@ impl Server {
     /// Runs the event loop.
     async fn eventloop(
         mut node: Node,
+        mut ticker: BoxStream<'static, ()>,
         node_rx: mpsc::UnboundedReceiver<Message>,
         client_rx: mpsc::UnboundedReceiver<(Request, oneshot::Sender<Result<Response>>)>,
         tcp_rx: mpsc::UnboundedReceiver<Message>,
@@ -79,11 +179,10 @@ impl Server {
         let mut tcp_rx = UnboundedReceiverStream::new(tcp_rx);
         let mut client_rx = UnboundedReceiverStream::new(client_rx);
 
-        let mut ticker = tokio::time::interval(TICK);
         let mut requests = HashMap::<Vec<u8>, oneshot::Sender<Result<Response>>>::new();
         loop {
             tokio::select! {
-                _ = ticker.tick() => node = node.tick()?,
+                Some(()) = ticker.next() => node = node.tick()?,
 
                 Some(msg) = tcp_rx.next() => node = node.step(msg)?,
 
@@ -91,6 +190,8 @@ impl Server {
                     match msg {
                         Message{to: Address::Peer(_), ..} => tcp_tx.send(msg)?,
                         Message{to: Address::Peers, ..} => tcp_tx.send(msg)?,
+                        Message{event: Event::ConfigChange(_), ..} => tcp_tx.send(msg)?,
+                        Message{to: Address::Local, event: Event::Snapshot(_), ..} => node = node.step(msg)?,
                         Message{to: Address::Client, event: Event::ClientResponse{ id, response }, ..} => {
                             if let Some(response_tx) = requests.remove(&id) {
                                 response_tx
@@ -141,12 +242,12 @@ impl Server {
         socket: TcpStream,
         in_tx: mpsc::UnboundedSender<Message>,
     ) -> Result<()> {
-        let mut stream = tokio_serde::SymmetricallyFramed::<_, Message, _>::new(
+        let mut stream = tokio_serde::SymmetricallyFramed::<_, Frame, _>::new(
             Framed::new(socket, LengthDelimitedCodec::new()),
-            tokio_serde::formats::SymmetricalBincode::<Message>::default(),
+            tokio_serde::formats::SymmetricalBincode::<Frame>::default(),
         );
-        while let Some(message) = stream.try_next().await? {
-            in_tx.send(message)?;
+        while let Some(frame) = stream.try_next().await? {
+            in_tx.send(frame.decode()?)?;
         }
         Ok(())
     }
@@ -155,6 +256,8 @@ impl Server {
     async fn tcp_send(
         node_id: String,
         peers: HashMap<String, String>,
+        compression_threshold: Option<u64>,
+        clock: Arc<dyn Clock>,
         out_rx: mpsc::UnboundedReceiver<Message>,
     ) -> Result<()> {
         let mut out_rx = UnboundedReceiverStream::new(out_rx);
@@ -163,10 +266,35 @@ impl Server {
         for (id, addr) in peers.into_iter() {
             let (tx, rx) = mpsc::channel::<Message>(1000);
             peer_txs.insert(id, tx);
-            tokio::spawn(Self::tcp_send_peer(addr, rx));
+            tokio::spawn(Self::tcp_send_peer(addr, compression_threshold, clock.clone(), rx));
         }
 
         while let Some(mut message) = out_rx.next().await {
+            if let Event::ConfigChange(config) = message.event {
+                match config {
+                    ConfigChange::AddServer { id, addr }
+                    | ConfigChange::AddLearner { id, addr }
+                        if id != node_id =>
+                    {
+                        if let Entry::Vacant(entry) = peer_txs.entry(id) {
+                            let (tx, rx) = mpsc::channel::<Message>(1000);
+                            entry.insert(tx);
+                            tokio::spawn(Self::tcp_send_peer(
+                                addr,
+                                compression_threshold,
+                                clock.clone(),
+                                rx,
+                            ));
+                        }
+                    }
+                    // Dropping the sender disconnects from the peer.
+                    ConfigChange::RemoveServer { id } => {
+                        peer_txs.remove(&id);
+                    }
+                    _ => {}
+                }
+                continue;
+            }
             if message.from == Address::Local {
                 message.from = Address::Peer(node_id.clone())
             }
@@ -195,20 +323,27 @@ impl Server {
     }
 
     /// Sends outbound messages to a peer, continuously reconnecting.
-    async fn tcp_send_peer(addr: String, out_rx: mpsc::Receiver<Message>) {
+    async fn tcp_send_peer(
+        addr: String,
+        compression_threshold: Option<u64>,
+        clock: Arc<dyn Clock>,
+        out_rx: mpsc::Receiver<Message>,
+    ) {
         let mut out_rx = ReceiverStream::new(out_rx);
         loop {
             match TcpStream::connect(&addr).await {
                 Ok(socket) => {
                     debug!("Connected to Raft peer {}", addr);
-                    match Self::tcp_send_peer_session(socket, &mut out_rx).await {
+                    match Self::tcp_send_peer_session(socket, compression_threshold, &mut out_rx)
+                        .await
+                    {
                         Ok(()) => break,
                         Err(err) => error!("Failed sending to Raft peer {}: {}", addr, err),
                     }
                 }
                 Err(err) => error!("Failed connecting to Raft peer {}: {}", addr, err),
             }
-            tokio::time::sleep(Duration::from_millis(1000)).await;
+            clock.sleep(Duration::from_millis(1000)).await;
         }
         debug!("Disconnected from Raft peer {}", addr);
     }
@@ -216,15 +351,76 @@ impl Server {
     /// Sends outbound messages to a peer via a TCP session.
     async fn tcp_send_peer_session(
         socket: TcpStream,
+        compression_threshold: Option<u64>,
         out_rx: &mut ReceiverStream<Message>,
     ) -> Result<()> {
-        let mut stream = tokio_serde::SymmetricallyFramed::<_, Message, _>::new(
+        let mut stream = tokio_serde::SymmetricallyFramed::<_, Frame, _>::new(
             Framed::new(socket, LengthDelimitedCodec::new()),
-            tokio_serde::formats::SymmetricalBincode::<Message>::default(),
+            tokio_serde::formats::SymmetricalBincode::<Frame>::default(),
         );
         while let Some(message) = out_rx.next().await {
-            stream.send(message).await?;
+            stream.send(Frame::encode(message, compression_threshold)?).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Entry;
+    use super::*;
+
+    fn replicate(commands: Vec<Vec<u8>>) -> Message {
+        Message {
+            term: 1,
+            from: Address::Peer("a".into()),
+            to: Address::Peer("b".into()),
+            event: Event::ReplicateEntries {
+                base_index: 0,
+                base_term: 0,
+                entries: commands
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, c)| Entry {
+                        index: i as u64 + 1,
+                        term: 1,
+                        command: Some(c),
+                        config: None,
+                    })
+                    .collect(),
+            },
         }
+    }
+
+    #[test]
+    fn frame_compressed() -> Result<()> {
+        let message = replicate(vec![vec![0x01; 4096], vec![0x02; 8], b"abc".repeat(1024)]);
+
+        let plain = Frame::encode(message.clone(), None)?;
+        assert_eq!(plain, Frame::Message(message.clone()));
+
+        let frame = Frame::encode(message.clone(), Some(64))?;
+        match &frame {
+            Frame::Compressed { compressed, .. } => assert_eq!(compressed, &vec![0, 2]),
+            frame => panic!("Unexpected frame {:?}", frame),
+        }
+        assert!(bincode::serialize(&frame)?.len() < bincode::serialize(&plain)?.len() / 10);
+
+        let frame: Frame = bincode::deserialize(&bincode::serialize(&frame)?)?;
+        assert_eq!(frame.decode()?, message);
+        Ok(())
+    }
+
+    #[test]
+    fn frame_uncompressed() -> Result<()> {
+        // Small and incompressible commands are sent as-is.
+        let message = replicate(vec![vec![0x01; 32], (0..=255).collect()]);
+        assert_eq!(Frame::encode(message.clone(), Some(64))?, Frame::Message(message.clone()));
+
+        // Other events are never compressed.
+        let message =
+            Message { event: Event::Heartbeat { commit_index: 1, commit_term: 1 }, ..message };
+        assert_eq!(Frame::encode(message.clone(), Some(0))?, Frame::Message(message));
         Ok(())
     }
 }
@@ -0,0 +1,120 @@
+use crate::error::{Error, Result};
+
+use std::collections::BTreeMap;
+
+/// The ID of a Raft group, from 0 to the group count.
+pub type GroupId = u32;
+
+/// The number of points each group has on the hash ring. More points even out the share of
+/// keys each group owns.
+const POINTS_PER_GROUP: u32 = 64;
+
+/// Routes keys to the Raft group owning them, using consistent hashing. Keys are the encoded
+/// storage keys (see storage::kv::encoding), and are hashed onto a ring where each group owns
+/// the arcs preceding its points. Adding a group only moves keys to the new group, so changing
+/// the group count moves roughly 1/N of the keys. A single group owns every key.
+pub struct ShardRouter {
+    ring: BTreeMap<u32, GroupId>,
+    groups: u32,
+}
+
+impl ShardRouter {
+    /// Creates a new shard router for the given number of groups.
+    pub fn new(groups: u32) -> Result<Self> {
+        if groups == 0 {
+            return Err(Error::Value("Must have at least one Raft group".into()));
+        }
+        let mut ring = BTreeMap::new();
+        for group in 0..groups {
+            for point in 0..POINTS_PER_GROUP {
+                // Hash collisions are resolved in favor of the lowest group, so that the ring
+                // does not depend on insertion order.
+                ring.entry(Self::hash(format!("group-{}-{}", group, point).as_bytes()))
+                    .or_insert(group);
+            }
+        }
+        Ok(Self { ring, groups })
+    }
+
+    /// Returns the number of groups.
+    pub fn groups(&self) -> u32 {
+        self.groups
+    }
+
+    /// Returns the group owning the given key.
+    pub fn route(&self, key: &[u8]) -> GroupId {
+        let hash = Self::hash(key);
+        match self.ring.range(hash..).next() {
+            Some((_, group)) => *group,
+            None => *self.ring.values().next().expect("ring has no points"),
+        }
+    }
+
+    /// Hashes a key or ring point. This must be stable across nodes and versions, since all
+    /// nodes must agree on key ownership.
+    fn hash(bytes: &[u8]) -> u32 {
+        crc32fast::hash(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::kv::encoding;
+    use pretty_assertions::assert_eq;
+
+    fn key(i: u64) -> Vec<u8> {
+        encoding::encode_string(&format!("key{}", i))
+    }
+
+    #[test]
+    fn new() {
+        assert!(ShardRouter::new(0).is_err());
+        assert_eq!(ShardRouter::new(3).unwrap().groups(), 3);
+    }
+
+    #[test]
+    fn route_single() -> Result<()> {
+        let router = ShardRouter::new(1)?;
+        for i in 0..100 {
+            assert_eq!(router.route(&key(i)), 0);
+        }
+        assert_eq!(router.route(&[]), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn route() -> Result<()> {
+        let router = ShardRouter::new(4)?;
+        let mut counts = vec![0; 4];
+        for i in 0..4000 {
+            let group = router.route(&key(i));
+            counts[group as usize] += 1;
+            // The same key always routes to the same group, also in a new router.
+            assert_eq!(router.route(&key(i)), group);
+            assert_eq!(ShardRouter::new(4)?.route(&key(i)), group);
+        }
+        // Keys are spread across all groups, each with a reasonable share.
+        for count in counts {
+            assert!(count > 500, "group has {} of 4000 keys", count);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn route_add_group() -> Result<()> {
+        let before = ShardRouter::new(3)?;
+        let after = ShardRouter::new(4)?;
+        let mut moved = 0;
+        for i in 0..4000 {
+            let (from, to) = (before.route(&key(i)), after.route(&key(i)));
+            if from != to {
+                // Keys only move to the new group.
+                assert_eq!(to, 3);
+                moved += 1;
+            }
+        }
+        assert!(moved > 500 && moved < 1500, "{} of 4000 keys moved", moved);
+        Ok(())
+    }
+}
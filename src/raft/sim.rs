@@ -0,0 +1,718 @@
+//! An in-process Raft cluster simulator for fault-injection testing. Nodes exchange messages via
+//! a controllable transport which can partition nodes, pause delivery, and drop or reorder
+//! messages. Client operations are recorded in a history, which can be checked for
+//! linearizability.
+//!
+//! Simulations are deterministic: time is a virtual clock advanced by the test, everything runs
+//! on the test's single thread, and messages are delivered in a canonical order, shuffled by a
+//! seeded RNG when reordering. The node RNG is seeded too, so a failing run can be reproduced
+//! from its seed. Tests use the multi-threaded runtime nonetheless, since state machine drivers
+//! apply entries via block_in_place(), but nothing is spawned onto its worker threads.
+
+use super::node::seed_rng;
+use super::{
+    Address, Clock, Event, Log, Message, Node, RaftConfig, Request, Response, State, VirtualClock,
+};
+use crate::error::{Error, Result};
+use crate::storage::log::{self, Store as _};
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt as _, StreamExt as _};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom as _;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The maximum number of delivery rounds before giving up on the cluster going quiet.
+const MAX_DELIVERY_ROUNDS: usize = 100;
+
+/// A state machine holding an append-only list of values. Mutations append a bincode-encoded
+/// u64 value, and queries return the entire list.
+#[derive(Clone)]
+struct ListState {
+    values: Arc<Mutex<Vec<u64>>>,
+    applied_index: u64,
+}
+
+impl ListState {
+    fn new() -> Self {
+        Self { values: Arc::new(Mutex::new(Vec::new())), applied_index: 0 }
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.values.lock().unwrap().clone()
+    }
+}
+
+impl State for ListState {
+    fn applied_index(&self) -> u64 {
+        self.applied_index
+    }
+
+    fn mutate(&mut self, index: u64, command: Vec<u8>) -> Result<Vec<u8>> {
+        self.values.lock()?.push(bincode::deserialize(&command)?);
+        self.applied_index = index;
+        Ok(command)
+    }
+
+    fn query(&self, _: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&*self.values.lock()?)?)
+    }
+
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&(&*self.values.lock()?, self.applied_index))?)
+    }
+
+    fn restore(&mut self, snapshot: Vec<u8>) -> Result<()> {
+        let (values, applied_index) = bincode::deserialize(&snapshot)?;
+        *self.values.lock()? = values;
+        self.applied_index = applied_index;
+        Ok(())
+    }
+}
+
+/// A client operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// Appends a value to the list.
+    Append(u64),
+    /// Reads the list, with the values seen once completed.
+    Read(Vec<u64>),
+}
+
+/// A client operation in the history, with logical invocation and completion times. Operations
+/// that failed or never completed have no completion time: appends may or may not have taken
+/// effect, while reads are disregarded.
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub op: Op,
+    pub invoked: u64,
+    pub completed: Option<u64>,
+}
+
+/// A controllable transport between nodes.
+struct Transport {
+    /// Seeded RNG for dropping and reordering messages.
+    rng: StdRng,
+    /// Queued messages, along with their source and destination node.
+    queue: VecDeque<(String, String, Message)>,
+    /// Cut links between nodes, as (from, to) pairs. Messages across them are dropped.
+    cuts: HashSet<(String, String)>,
+    /// If true, messages are queued but not delivered.
+    paused: bool,
+    /// The probability of dropping a message.
+    drop_rate: f64,
+    /// If true, messages are delivered in random order.
+    reorder: bool,
+}
+
+impl Transport {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            queue: VecDeque::new(),
+            cuts: HashSet::new(),
+            paused: false,
+            drop_rate: 0.0,
+            reorder: false,
+        }
+    }
+
+    /// Queues a message for delivery.
+    fn send(&mut self, from: &str, to: &str, msg: Message) {
+        self.queue.push_back((from.to_string(), to.to_string(), msg))
+    }
+
+    /// Takes all deliverable messages off the queue, applying any faults.
+    fn take(&mut self) -> Vec<(String, Message)> {
+        if self.paused {
+            return Vec::new();
+        }
+        // Order messages canonically by link, preserving the order within each link, since
+        // nodes may emit messages to different peers in hash map order.
+        let mut queue: Vec<_> = std::mem::take(&mut self.queue).into_iter().collect();
+        queue.sort_by(|(a_from, a_to, _), (b_from, b_to, _)| (a_from, a_to).cmp(&(b_from, b_to)));
+        let mut msgs = Vec::new();
+        for (from, to, msg) in queue {
+            if self.cuts.contains(&(from, to.clone())) || self.rng.gen_bool(self.drop_rate) {
+                continue;
+            }
+            msgs.push((to, msg));
+        }
+        if self.reorder {
+            msgs.shuffle(&mut self.rng);
+        }
+        msgs
+    }
+}
+
+/// A simulated Raft cluster.
+pub struct Cluster {
+    ids: Vec<String>,
+    nodes: HashMap<String, Node>,
+    node_rxs: BTreeMap<String, mpsc::UnboundedReceiver<Message>>,
+    clock: VirtualClock,
+    tick_duration: Duration,
+    tickers: BTreeMap<String, BoxStream<'static, ()>>,
+    states: BTreeMap<String, ListState>,
+    /// The nodes' log stores, for inspection.
+    stores: BTreeMap<String, log::Test>,
+    /// The nodes' state machine drivers, run by collect().
+    drivers: BTreeMap<String, BoxFuture<'static, Result<()>>>,
+    transport: Transport,
+    history: Vec<Call>,
+    /// Pending client requests, by request ID, as indexes into the history.
+    pending: HashMap<Vec<u8>, usize>,
+    next_id: u64,
+    time: u64,
+}
+
+impl Cluster {
+    /// Creates a new cluster with the given node IDs, seeding the transport and node RNGs.
+    pub async fn new(ids: &[&str], seed: u64) -> Result<Self> {
+        Self::with_config(ids, seed, RaftConfig::default()).await
+    }
+
+    /// Creates a new cluster like new(), using the given Raft configuration for all nodes.
+    pub async fn with_config(ids: &[&str], seed: u64, config: RaftConfig) -> Result<Self> {
+        seed_rng(seed);
+        let mut cluster = Self {
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            nodes: HashMap::new(),
+            node_rxs: BTreeMap::new(),
+            clock: VirtualClock::new(),
+            tick_duration: config.tick,
+            tickers: BTreeMap::new(),
+            states: BTreeMap::new(),
+            stores: BTreeMap::new(),
+            drivers: BTreeMap::new(),
+            transport: Transport::new(seed),
+            history: Vec::new(),
+            pending: HashMap::new(),
+            next_id: 0,
+            time: 0,
+        };
+        for id in ids {
+            let peers = ids.iter().filter(|p| p != &id).map(|p| p.to_string()).collect();
+            let (node_tx, node_rx) = mpsc::unbounded_channel();
+            let state = ListState::new();
+            let store = log::Test::new();
+            let (node, driver) = Node::new_undriven(
+                id,
+                peers,
+                Log::new(Box::new(store.clone()))?,
+                Box::new(state.clone()),
+                node_tx,
+                config.clone(),
+            )?;
+            cluster.tickers.insert(id.to_string(), cluster.clock.ticker(config.tick));
+            cluster.nodes.insert(id.to_string(), node);
+            cluster.node_rxs.insert(id.to_string(), node_rx);
+            cluster.states.insert(id.to_string(), state);
+            cluster.stores.insert(id.to_string(), store);
+            cluster.drivers.insert(id.to_string(), driver);
+        }
+        Ok(cluster)
+    }
+
+    /// Returns the values of all acknowledged appends.
+    pub fn acknowledged(&self) -> Vec<u64> {
+        self.history
+            .iter()
+            .filter(|c| c.completed.is_some())
+            .filter_map(|c| match c.op {
+                Op::Append(value) => Some(value),
+                Op::Read(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the values applied to a node's state machine.
+    pub fn applied(&self, id: &str) -> Vec<u64> {
+        self.states.get(id).map(|s| s.values()).unwrap_or_default()
+    }
+
+    /// Returns the index of the first entry in a node's log, i.e. the index following any
+    /// compacted entries.
+    pub fn first_index(&self, id: &str) -> Result<u64> {
+        let store =
+            self.stores.get(id).ok_or_else(|| Error::Internal(format!("Unknown node {}", id)))?;
+        let mut index = 1;
+        while index <= store.len() && store.get(index)?.is_none() {
+            index += 1;
+        }
+        Ok(index)
+    }
+
+    /// Returns the leader among the given nodes, if any. A partitioned node may still consider
+    /// itself leader, so callers should only consider nodes that can reach a quorum.
+    pub fn leader_among(&self, ids: &[&str]) -> Option<String> {
+        ids.iter()
+            .find(|id| matches!(self.nodes.get(**id), Some(Node::Leader(_))))
+            .map(|id| id.to_string())
+    }
+
+    /// Ticks the cluster until a leader is elected, returning it.
+    pub async fn elect(&mut self) -> Result<String> {
+        let ids = self.ids.clone();
+        let ids: Vec<&str> = ids.iter().map(|id| id.as_str()).collect();
+        for _ in 0..100 {
+            self.tick().await?;
+            if let Some(leader) = self.leader_among(&ids) {
+                return Ok(leader);
+            }
+        }
+        Err(Error::Internal("No leader elected".into()))
+    }
+
+    /// Submits a client append to the given node.
+    pub fn append(&mut self, id: &str, value: u64) -> Result<()> {
+        self.request(id, Op::Append(value), Request::Mutate(bincode::serialize(&value)?))
+    }
+
+    /// Submits a client read to the given node.
+    pub fn read(&mut self, id: &str) -> Result<()> {
+        self.request(id, Op::Read(Vec::new()), Request::Query(Vec::new()))
+    }
+
+    /// Submits a client request to the given node, recording it in the history.
+    fn request(&mut self, id: &str, op: Op, request: Request) -> Result<()> {
+        self.next_id += 1;
+        self.time += 1;
+        let request_id = self.next_id.to_be_bytes().to_vec();
+        self.pending.insert(request_id.clone(), self.history.len());
+        self.history.push(Call { op, invoked: self.time, completed: None });
+        self.step(
+            id,
+            Message {
+                from: Address::Client,
+                to: Address::Local,
+                term: 0,
+                event: Event::ClientRequest { id: request_id, request },
+            },
+        )
+    }
+
+    /// Cuts all links between the given nodes and the rest of the cluster.
+    pub fn partition(&mut self, ids: &[&str]) {
+        for a in ids {
+            for b in self.ids.iter().filter(|b| !ids.contains(&b.as_str())) {
+                self.transport.cuts.insert((a.to_string(), b.clone()));
+                self.transport.cuts.insert((b.clone(), a.to_string()));
+            }
+        }
+    }
+
+    /// Heals all partitions.
+    pub fn heal(&mut self) {
+        self.transport.cuts.clear()
+    }
+
+    /// Pauses message delivery, queueing messages until resumed.
+    pub fn pause(&mut self) {
+        self.transport.paused = true
+    }
+
+    /// Resumes message delivery.
+    pub fn resume(&mut self) {
+        self.transport.paused = false
+    }
+
+    /// Sets the probability of dropping a message.
+    pub fn set_drop_rate(&mut self, drop_rate: f64) {
+        self.transport.drop_rate = drop_rate
+    }
+
+    /// Sets whether messages are delivered in random order.
+    pub fn set_reorder(&mut self, reorder: bool) {
+        self.transport.reorder = reorder
+    }
+
+    /// Runs the cluster for the given number of ticks, delivering messages after each tick.
+    pub async fn run(&mut self, ticks: u64) -> Result<()> {
+        for _ in 0..ticks {
+            self.tick().await?;
+        }
+        Ok(())
+    }
+
+    /// Advances the virtual clock by a tick, ticking the nodes whose tickers fired, and delivers
+    /// messages until the cluster goes quiet.
+    pub async fn tick(&mut self) -> Result<()> {
+        self.clock.advance(self.tick_duration);
+        for id in self.ids.clone() {
+            let ticker = self
+                .tickers
+                .get_mut(&id)
+                .ok_or_else(|| Error::Internal(format!("Unknown node {}", id)))?;
+            let mut ticks = 0;
+            while let Some(Some(())) = ticker.next().now_or_never() {
+                ticks += 1;
+            }
+            for _ in 0..ticks {
+                let node = self.take_node(&id)?;
+                self.nodes.insert(id.clone(), node.tick()?);
+            }
+        }
+        self.deliver().await
+    }
+
+    /// Delivers queued messages until the cluster goes quiet, or delivery is paused.
+    pub async fn deliver(&mut self) -> Result<()> {
+        for _ in 0..MAX_DELIVERY_ROUNDS {
+            self.collect().await?;
+            let msgs = self.transport.take();
+            if msgs.is_empty() {
+                return Ok(());
+            }
+            for (to, msg) in msgs {
+                self.step(&to, msg)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects outbound messages from nodes and their state machine drivers, first running the
+    /// drivers until they've processed their pending instructions. We yield between rounds, since
+    /// Tokio's cooperative scheduling budget may leave instructions for a later poll.
+    async fn collect(&mut self) -> Result<()> {
+        for _ in 0..10 {
+            for (id, driver) in self.drivers.iter_mut() {
+                // Drivers only finish when their node is dropped, or on errors.
+                if let Some(result) = driver.now_or_never() {
+                    result?;
+                    return Err(Error::Internal(format!("State machine driver {} stopped", id)));
+                }
+            }
+            let _ = tokio::task::yield_now().await;
+        }
+        let mut outbound = Vec::new();
+        for (id, node_rx) in self.node_rxs.iter_mut() {
+            while let Some(Some(msg)) = node_rx.recv().now_or_never() {
+                outbound.push((id.clone(), msg));
+            }
+        }
+        for (from, msg) in outbound {
+            self.route(&from, msg)?;
+        }
+        Ok(())
+    }
+
+    /// Routes an outbound message from a node.
+    fn route(&mut self, from: &str, mut msg: Message) -> Result<()> {
+        // Membership changes aren't simulated.
+        if let Event::ConfigChange(_) = msg.event {
+            return Ok(());
+        }
+        // Snapshots from the state machine driver go straight back to its node.
+        if let Event::Snapshot(_) = msg.event {
+            return self.step(from, msg);
+        }
+        if msg.from == Address::Local {
+            msg.from = Address::Peer(from.to_string());
+        }
+        match msg.to.clone() {
+            Address::Client => self.respond(msg),
+            Address::Peers => {
+                for to in self.ids.iter().filter(|id| *id != from) {
+                    self.transport.send(from, to, msg.clone());
+                }
+            }
+            Address::Peer(to) => self.transport.send(from, &to, msg),
+            Address::Local => {
+                return Err(Error::Internal(format!("Unexpected local message {:?}", msg)))
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a client response in the history.
+    fn respond(&mut self, msg: Message) {
+        let (id, response) = match msg.event {
+            Event::ClientResponse { id, response } => (id, response),
+            _ => return,
+        };
+        let call = match self.pending.remove(&id) {
+            Some(index) => &mut self.history[index],
+            None => return,
+        };
+        self.time += 1;
+        match (&mut call.op, response) {
+            (Op::Append(_), Ok(_)) => call.completed = Some(self.time),
+            (Op::Read(values), Ok(Response::State(state))) => {
+                if let Ok(state) = bincode::deserialize(&state) {
+                    *values = state;
+                    call.completed = Some(self.time);
+                }
+            }
+            (_, _) => {}
+        }
+    }
+
+    /// Steps a node with a message.
+    fn step(&mut self, id: &str, msg: Message) -> Result<()> {
+        let node = self.take_node(id)?;
+        self.nodes.insert(id.to_string(), node.step(msg)?);
+        Ok(())
+    }
+
+    /// Removes a node from the cluster, to step or tick it.
+    fn take_node(&mut self, id: &str) -> Result<Node> {
+        self.nodes.remove(id).ok_or_else(|| Error::Internal(format!("Unknown node {}", id)))
+    }
+
+    /// Checks that the client history is linearizable, and that all nodes have applied the same
+    /// values, containing every acknowledged append exactly once.
+    pub fn check(&self) -> Result<()> {
+        if !linearizable(&self.history) {
+            return Err(Error::Value(format!("History is not linearizable: {:?}", self.history)));
+        }
+        let acknowledged = self.acknowledged();
+        let expect = self.applied(&self.ids[0]);
+        for id in &self.ids {
+            let applied = self.applied(id);
+            if applied != expect {
+                return Err(Error::Value(format!(
+                    "Node {} applied {:?}, but node {} applied {:?}",
+                    id, applied, self.ids[0], expect
+                )));
+            }
+            for value in &acknowledged {
+                match applied.iter().filter(|v| *v == value).count() {
+                    1 => {}
+                    0 => return Err(Error::Value(format!("Node {} lost value {}", id, value))),
+                    _ => {
+                        return Err(Error::Value(format!("Node {} duplicated value {}", id, value)))
+                    }
+                }
+            }
+            if applied.iter().collect::<HashSet<_>>().len() != applied.len() {
+                return Err(Error::Value(format!("Node {} applied duplicates {:?}", id, applied)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether a history is linearizable with respect to an append-only list, by searching
+/// for a total order of operations that respects real time and the list semantics. Operations
+/// without a completion time may take effect at any point after invocation, or not at all.
+pub fn linearizable(history: &[Call]) -> bool {
+    let calls: Vec<&Call> =
+        history.iter().filter(|c| matches!(c.op, Op::Append(_)) || c.completed.is_some()).collect();
+    assert!(calls.len() <= 64, "History too long to check");
+
+    // Recursively linearizes the next operation, memoizing failed (done, state) pairs.
+    fn search(
+        calls: &[&Call],
+        done: u64,
+        state: &mut Vec<u64>,
+        failed: &mut HashSet<(u64, Vec<u64>)>,
+    ) -> bool {
+        if failed.contains(&(done, state.clone())) {
+            return false;
+        }
+        let pending: Vec<(usize, &Call)> = calls
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| done & (1 << i) == 0)
+            .map(|(i, c)| (i, *c))
+            .collect();
+        // The next operation must be invoked before the first pending one completed.
+        let deadline = match pending.iter().filter_map(|(_, c)| c.completed).min() {
+            Some(deadline) => deadline,
+            None => return true,
+        };
+        for (i, call) in pending.into_iter().filter(|(_, c)| c.invoked < deadline) {
+            match &call.op {
+                Op::Append(value) => {
+                    state.push(*value);
+                    if search(calls, done | 1 << i, state, failed) {
+                        return true;
+                    }
+                    state.pop();
+                }
+                Op::Read(values) if *values == *state => {
+                    if search(calls, done | 1 << i, state, failed) {
+                        return true;
+                    }
+                }
+                Op::Read(_) => {}
+            }
+        }
+        failed.insert((done, state.clone()));
+        false
+    }
+    search(&calls, 0, &mut Vec::new(), &mut HashSet::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(op: Op, invoked: u64, completed: Option<u64>) -> Call {
+        Call { op, invoked, completed }
+    }
+
+    #[test]
+    fn linearizable_history() {
+        // Concurrent appends can be ordered either way.
+        assert!(linearizable(&[
+            call(Op::Append(1), 1, Some(4)),
+            call(Op::Append(2), 2, Some(3)),
+            call(Op::Read(vec![2, 1]), 5, Some(6)),
+        ]));
+        // A failed append may or may not take effect.
+        assert!(linearizable(&[
+            call(Op::Append(1), 1, None),
+            call(Op::Read(vec![]), 2, Some(3)),
+            call(Op::Read(vec![1]), 4, Some(5)),
+        ]));
+        // A read can't miss an append that completed before it was invoked.
+        assert!(!linearizable(&[
+            call(Op::Append(1), 1, Some(2)),
+            call(Op::Read(vec![]), 3, Some(4)),
+        ]));
+        // A read can't see an append that was invoked after it completed.
+        assert!(!linearizable(&[
+            call(Op::Read(vec![1]), 1, Some(2)),
+            call(Op::Append(1), 3, Some(4)),
+        ]));
+        // Reads can't go back in time.
+        assert!(!linearizable(&[
+            call(Op::Append(1), 1, None),
+            call(Op::Read(vec![1]), 2, Some(3)),
+            call(Op::Read(vec![]), 4, Some(5)),
+        ]));
+        // Appends can't be duplicated.
+        assert!(!linearizable(&[
+            call(Op::Append(1), 1, Some(2)),
+            call(Op::Read(vec![1, 1]), 3, Some(4)),
+        ]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    // Partitioning the leader mid-write must not lose or duplicate committed writes.
+    async fn partition_leader_mid_write() -> Result<()> {
+        let ids = ["a", "b", "c", "d", "e"];
+        let mut cluster = Cluster::new(&ids, 0).await?;
+        let leader = cluster.elect().await?;
+        cluster.append(&leader, 1)?;
+        cluster.append(&leader, 2)?;
+        cluster.run(3).await?;
+
+        // Submit writes to the leader, but partition it before they replicate.
+        cluster.append(&leader, 3)?;
+        cluster.partition(&[leader.as_str()]);
+        cluster.append(&leader, 4)?;
+
+        // The majority elects a new leader, which accepts writes.
+        let majority: Vec<&str> = ids.iter().copied().filter(|id| *id != leader).collect();
+        cluster.run(50).await?;
+        let new_leader = cluster.leader_among(&majority).expect("No leader in majority");
+        cluster.append(&new_leader, 5)?;
+        cluster.append(&new_leader, 6)?;
+        cluster.run(3).await?;
+        cluster.read(&new_leader)?;
+        cluster.run(3).await?;
+
+        // Once healed, the old leader rejoins and discards its uncommitted writes.
+        cluster.heal();
+        cluster.run(50).await?;
+        cluster.read(&leader)?;
+        cluster.run(5).await?;
+
+        assert_eq!(cluster.acknowledged(), vec![1, 2, 5, 6]);
+        for id in &ids {
+            assert_eq!(cluster.applied(id), vec![1, 2, 5, 6]);
+        }
+        cluster.check()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    // A follower that lags behind the leader's log compaction catches up via a snapshot.
+    async fn lagging_follower_snapshot() -> Result<()> {
+        let ids = ["a", "b", "c"];
+        let config = RaftConfig { snapshot_interval: Some(5), ..RaftConfig::default() };
+        let mut cluster = Cluster::with_config(&ids, 0, config).await?;
+        let leader = cluster.elect().await?;
+        let lagging = *ids.iter().find(|id| **id != leader).unwrap();
+
+        // Partition a follower while the rest of the cluster writes and compacts its log.
+        cluster.partition(&[lagging]);
+        for value in 1..=20 {
+            cluster.append(&leader, value)?;
+            cluster.run(1).await?;
+        }
+        cluster.run(3).await?;
+        assert!(cluster.first_index(&leader)? > 1, "Leader log not compacted");
+        assert!(cluster.applied(lagging).is_empty());
+
+        // Once healed, the follower installs the leader's snapshot and replicates the rest.
+        cluster.heal();
+        cluster.run(20).await?;
+        cluster.append(&leader, 21)?;
+        cluster.run(5).await?;
+
+        let expect: Vec<u64> = (1..=21).collect();
+        assert_eq!(cluster.acknowledged(), expect);
+        for id in &ids {
+            assert_eq!(cluster.applied(id), expect);
+        }
+        assert!(cluster.first_index(lagging)? > 1, "Follower log not replaced by snapshot");
+        cluster.check()
+    }
+
+    /// Runs a randomized sequence of client operations and faults with the given seed.
+    async fn random_run(seed: u64) -> Result<Cluster> {
+        let ids = ["a", "b", "c", "d", "e"];
+        let mut cluster = Cluster::new(&ids, seed).await?;
+        let mut rng = StdRng::seed_from_u64(seed);
+        cluster.elect().await?;
+        cluster.set_drop_rate(0.05);
+        cluster.set_reorder(true);
+        for value in 1..=30 {
+            let id = ids[rng.gen_range(0..ids.len())];
+            match rng.gen_range(0..10) {
+                0 => cluster.partition(&[id]),
+                1 => cluster.heal(),
+                2 => cluster.pause(),
+                3 => cluster.resume(),
+                4..=6 => cluster.read(id)?,
+                _ => cluster.append(id, value)?,
+            }
+            cluster.run(rng.gen_range(0..5)).await?;
+        }
+        cluster.heal();
+        cluster.resume();
+        cluster.set_drop_rate(0.0);
+        cluster.run(100).await?;
+        Ok(cluster)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    // Random faults must preserve linearizability, for a handful of seeds.
+    async fn random_faults() -> Result<()> {
+        for seed in 0..5 {
+            random_run(seed).await?.check()?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    // A run is reproducible from its seed.
+    async fn deterministic() -> Result<()> {
+        let a = random_run(7).await?;
+        let b = random_run(7).await?;
+        assert_eq!(format!("{:?}", a.history), format!("{:?}", b.history));
+        assert_eq!(a.clock.now(), b.clock.now());
+        for id in &a.ids {
+            assert_eq!(a.applied(id), b.applied(id));
+        }
+        Ok(())
+    }
+}
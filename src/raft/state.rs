@@ -1,7 +1,7 @@
-use super::{Address, Entry, Event, Message, Response, Scan, Status};
+use super::{Address, Entry, Event, Message, Response, Scan, Snapshot, Status};
 use crate::error::{Error, Result};
 
-use log::{debug, error};
+use log::{debug, error, info};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
@@ -18,6 +18,13 @@ pub trait State: Send {
 
     /// Queries the state machine. All errors are propagated to the caller.
     fn query(&self, command: Vec<u8>) -> Result<Vec<u8>>;
+
+    /// Serializes the entire state machine, including its applied index, for restore(). Used to
+    /// snapshot the state machine such that the log entries it covers are no longer needed.
+    fn snapshot(&self) -> Result<Vec<u8>>;
+
+    /// Replaces the entire state machine with a snapshot taken by snapshot().
+    fn restore(&mut self, snapshot: Vec<u8>) -> Result<()>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -27,12 +34,23 @@ pub enum Instruction {
     Abort,
     /// Apply a log entry.
     Apply { entry: Entry },
+    /// Apply a batch of consecutive log entries in order, e.g. all entries committed at once.
+    /// Pending queries are only checked once the whole batch is applied.
+    ApplyBatch { entries: Vec<Entry> },
     /// Notify the given address with the result of applying the entry at the given index.
     Notify { id: Vec<u8>, address: Address, index: u64 },
+    /// Notify the given address with the results of applying the entries from first to last,
+    /// once all of them are applied.
+    NotifyBatch { id: Vec<u8>, address: Address, first: u64, last: u64 },
+    /// Replace the state machine with a snapshot from the leader, e.g. when the log entries a
+    /// lagging follower needs have been compacted.
+    Restore { snapshot: Snapshot },
     /// Query the state machine when the given term and index has been confirmed by vote.
     Query { id: Vec<u8>, address: Address, command: Vec<u8>, term: u64, index: u64, quorum: u64 },
     /// Extend the given server status and return it to the given address.
     Status { id: Vec<u8>, address: Address, status: Box<Status> },
+    /// Advance the driver's logical clock, aborting any queries that have timed out.
+    Tick,
     /// Votes for queries at the given term and commit index.
     Vote { term: u64, index: u64, address: Address },
 }
@@ -45,6 +63,18 @@ struct Query {
     command: Vec<u8>,
     quorum: u64,
     votes: HashSet<Address>,
+    /// The driver tick at which the query was submitted.
+    submitted: u64,
+}
+
+/// A pending notification of a batch of entries.
+struct NotifyBatch {
+    address: Address,
+    id: Vec<u8>,
+    /// The index of the first entry of the batch.
+    first: u64,
+    /// The responses of the entries applied so far, or the first error.
+    result: Result<Vec<Vec<u8>>>,
 }
 
 /// Drives a state machine, taking operations from state_rx and sending results via node_tx.
@@ -52,10 +82,22 @@ pub struct Driver {
     state_rx: UnboundedReceiverStream<Instruction>,
     node_tx: mpsc::UnboundedSender<Message>,
     applied_index: u64,
+    /// The term of the last applied entry, recorded in snapshots.
+    applied_term: u64,
+    /// The index of the last snapshot, taken or restored.
+    snapshot_index: u64,
+    /// Snapshot the state machine every snapshot_interval applied entries, if given.
+    snapshot_interval: Option<u64>,
     /// Notify clients when their mutation is applied. <index, (client, id)>
     notify: HashMap<u64, (Address, Vec<u8>)>,
+    /// Notify clients when their batch of mutations is applied. <last index, batch>
+    notify_batches: BTreeMap<u64, NotifyBatch>,
     /// Execute client queries when they receive a quorum. <index, <id, query>>
     queries: BTreeMap<u64, BTreeMap<Vec<u8>, Query>>,
+    /// The number of ticks received, used to time out queries.
+    ticks: u64,
+    /// Abort queries that haven't executed within this many ticks, if given.
+    query_timeout: Option<u64>,
 }
 
 impl Driver {
@@ -68,11 +110,31 @@ impl Driver {
             state_rx: UnboundedReceiverStream::new(state_rx),
             node_tx,
             applied_index: 0,
+            applied_term: 0,
+            snapshot_index: 0,
+            snapshot_interval: None,
             notify: HashMap::new(),
+            notify_batches: BTreeMap::new(),
             queries: BTreeMap::new(),
+            ticks: 0,
+            query_timeout: None,
         }
     }
 
+    /// Periodically snapshots the state machine every given number of applied entries, handing
+    /// the snapshots to the local node to save in its log.
+    pub fn with_snapshot_interval(mut self, snapshot_interval: Option<u64>) -> Self {
+        self.snapshot_interval = snapshot_interval;
+        self
+    }
+
+    /// Aborts queries that haven't executed within the given number of ticks, e.g. because a
+    /// read quorum can't be reached, rather than keeping them pending until leadership changes.
+    pub fn with_query_timeout(mut self, query_timeout: u64) -> Self {
+        self.query_timeout = Some(query_timeout);
+        self
+    }
+
     /// Drives a state machine.
     pub async fn drive(mut self, mut state: Box<dyn State>) -> Result<()> {
         debug!("Starting state machine driver");
@@ -86,6 +148,17 @@ impl Driver {
         Ok(())
     }
 
+    /// Restores the state machine from a snapshot, for initial sync. Entries following the
+    /// snapshot must then be replayed.
+    pub fn restore(&mut self, state: &mut dyn State, snapshot: Snapshot) -> Result<()> {
+        debug!("Restoring {:?}", snapshot);
+        state.restore(snapshot.data)?;
+        self.applied_index = snapshot.index;
+        self.applied_term = snapshot.term;
+        self.snapshot_index = snapshot.index;
+        Ok(())
+    }
+
     /// Synchronously (re)plays a set of log entries, for initial sync.
     pub fn replay<'a>(&mut self, state: &mut dyn State, mut scan: Scan<'a>) -> Result<()> {
         while let Some(entry) = scan.next().transpose()? {
@@ -93,7 +166,10 @@ impl Driver {
             if let Some(command) = entry.command {
                 match state.mutate(entry.index, command) {
                     Err(error @ Error::Internal(_)) => return Err(error),
-                    _ => self.applied_index = entry.index,
+                    _ => {
+                        self.applied_index = entry.index;
+                        self.applied_term = entry.term;
+                    }
                 }
             }
         }
@@ -109,22 +185,20 @@ impl Driver {
                 self.query_abort()?;
             }
 
-            Instruction::Apply { entry: Entry { index, command, .. } } => {
-                if let Some(command) = command {
-                    debug!("Applying state machine command {}: {:?}", index, command);
-                    match tokio::task::block_in_place(|| state.mutate(index, command)) {
-                        Err(error @ Error::Internal(_)) => return Err(error),
-                        result => self.notify_applied(index, result)?,
-                    };
-                }
-                // We have to track applied_index here, separately from the state machine, because
-                // no-op log entries are significant for whether a query should be executed.
-                self.applied_index = index;
+            Instruction::Apply { entry } => {
+                self.apply(entry, state)?;
                 // Try to execute any pending queries, since they may have been submitted for a
                 // commit_index which hadn't been applied yet.
                 self.query_execute(state)?;
             }
 
+            Instruction::ApplyBatch { entries } => {
+                for entry in entries {
+                    self.apply(entry, state)?;
+                }
+                self.query_execute(state)?;
+            }
+
             Instruction::Notify { id, address, index } => {
                 if index > state.applied_index() {
                     self.notify.insert(index, (address, id));
@@ -133,21 +207,51 @@ impl Driver {
                 }
             }
 
+            Instruction::NotifyBatch { id, address, first, last } => {
+                if first > state.applied_index() {
+                    self.notify_batches
+                        .insert(last, NotifyBatch { address, id, first, result: Ok(Vec::new()) });
+                } else {
+                    self.send(address, Event::ClientResponse { id, response: Err(Error::Abort) })?;
+                }
+            }
+
             Instruction::Query { id, address, command, index, term, quorum } => {
                 self.queries.entry(index).or_default().insert(
                     id.clone(),
-                    Query { id, term, address, command, quorum, votes: HashSet::new() },
+                    Query {
+                        id,
+                        term,
+                        address,
+                        command,
+                        quorum,
+                        votes: HashSet::new(),
+                        submitted: self.ticks,
+                    },
                 );
             }
 
+            Instruction::Restore { snapshot } => {
+                info!("Restoring state machine snapshot at index {}", snapshot.index);
+                tokio::task::block_in_place(|| self.restore(state, snapshot))?;
+                self.query_execute(state)?;
+            }
+
             Instruction::Status { id, address, mut status } => {
                 status.apply_index = state.applied_index();
+                status.pending_notifications = self.notify.len() + self.notify_batches.len();
+                status.pending_queries = self.queries.values().map(|queries| queries.len()).sum();
                 self.send(
                     address,
-                    Event::ClientResponse { id, response: Ok(Response::Status(*status)) },
+                    Event::ClientResponse { id, response: Ok(Response::Status(status)) },
                 )?;
             }
 
+            Instruction::Tick => {
+                self.ticks += 1;
+                self.query_expire()?;
+            }
+
             Instruction::Vote { term, index, address } => {
                 self.query_vote(term, index, address);
                 self.query_execute(state)?;
@@ -156,18 +260,90 @@ impl Driver {
         Ok(())
     }
 
+    /// Applies a log entry to the state machine, and notifies the client that submitted it.
+    fn apply(&mut self, entry: Entry, state: &mut dyn State) -> Result<()> {
+        let Entry { index, term, command, config } = entry;
+        if let Some(command) = command {
+            debug!("Applying state machine command {}: {:?}", index, command);
+            match tokio::task::block_in_place(|| state.mutate(index, command)) {
+                Err(error @ Error::Internal(_)) => return Err(error),
+                result => self.notify_applied(index, result.map(Response::State))?,
+            };
+        } else if config.is_some() {
+            // Membership changes are applied by the Raft nodes themselves on commit, we
+            // only need to tell the client that requested it.
+            self.notify_applied(index, Ok(Response::ConfigChange))?;
+        }
+        // We have to track applied_index here, separately from the state machine, because
+        // no-op log entries are significant for whether a query should be executed.
+        self.applied_index = index;
+        self.applied_term = term;
+        self.maybe_snapshot(state)
+    }
+
+    /// Snapshots the state machine if snapshot_interval entries have been applied since the last
+    /// snapshot, and hands it to the local node. The node saves it in the log, where it allows
+    /// compacting the entries it covers and lets replay start from it.
+    fn maybe_snapshot(&mut self, state: &dyn State) -> Result<()> {
+        match self.snapshot_interval {
+            Some(interval) if self.applied_index >= self.snapshot_index + interval => {}
+            _ => return Ok(()),
+        }
+        debug!("Snapshotting state machine at index {}", self.applied_index);
+        let data = tokio::task::block_in_place(|| state.snapshot())?;
+        self.snapshot_index = self.applied_index;
+        // The membership changes are filled in from the log when the node saves the snapshot.
+        self.send(
+            Address::Local,
+            Event::Snapshot(Box::new(Snapshot {
+                index: self.applied_index,
+                term: self.applied_term,
+                configs: vec![],
+                data,
+            })),
+        )
+    }
+
     /// Aborts all pending notifications.
     fn notify_abort(&mut self) -> Result<()> {
         for (_, (address, id)) in std::mem::take(&mut self.notify) {
             self.send(address, Event::ClientResponse { id, response: Err(Error::Abort) })?;
         }
+        for (_, batch) in std::mem::take(&mut self.notify_batches) {
+            let response = Err(Error::Abort);
+            self.send(batch.address, Event::ClientResponse { id: batch.id, response })?;
+        }
         Ok(())
     }
 
-    /// Notifies a client about an applied log entry, if any.
-    fn notify_applied(&mut self, index: u64, result: Result<Vec<u8>>) -> Result<()> {
+    /// Notifies a client about an applied log entry, if any. Entries of a batch are collected
+    /// until the last one is applied.
+    fn notify_applied(&mut self, index: u64, response: Result<Response>) -> Result<()> {
         if let Some((to, id)) = self.notify.remove(&index) {
-            self.send(to, Event::ClientResponse { id, response: result.map(Response::State) })?;
+            return self.send(to, Event::ClientResponse { id, response });
+        }
+        let last = match self.notify_batches.range_mut(index..).next() {
+            Some((last, batch)) if batch.first <= index => {
+                match (&mut batch.result, response) {
+                    (Ok(responses), Ok(Response::State(response))) => responses.push(response),
+                    (Ok(_), Ok(response)) => {
+                        return Err(Error::Internal(format!(
+                            "Unexpected response {:?} in batch",
+                            response
+                        )))
+                    }
+                    (Ok(_), Err(error)) => batch.result = Err(error),
+                    (Err(_), _) => {}
+                }
+                *last
+            }
+            _ => return Ok(()),
+        };
+        if index == last {
+            if let Some(batch) = self.notify_batches.remove(&last) {
+                let response = batch.result.map(Response::StateBatch);
+                self.send(batch.address, Event::ClientResponse { id: batch.id, response })?;
+            }
         }
         Ok(())
     }
@@ -185,6 +361,37 @@ impl Driver {
         Ok(())
     }
 
+    /// Aborts any queries that have been pending for query_timeout ticks or more.
+    fn query_expire(&mut self) -> Result<()> {
+        let timeout = match self.query_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(()),
+        };
+        let ticks = self.ticks;
+        let mut expired = Vec::new();
+        for queries in self.queries.values_mut() {
+            let expired_ids: Vec<_> = queries
+                .iter()
+                .filter(|(_, query)| ticks - query.submitted >= timeout)
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in expired_ids {
+                if let Some(query) = queries.remove(&id) {
+                    expired.push(query)
+                }
+            }
+        }
+        self.queries.retain(|_, queries| !queries.is_empty());
+        for query in expired {
+            debug!("Query {:?} timed out after {} ticks", query.command, timeout);
+            self.send(
+                query.address,
+                Event::ClientResponse { id: query.id, response: Err(Error::Abort) },
+            )?;
+        }
+        Ok(())
+    }
+
     /// Executes any queries that are ready.
     fn query_execute(&mut self, state: &mut dyn State) -> Result<()> {
         for query in self.query_ready(self.applied_index) {
@@ -248,7 +455,9 @@ impl Driver {
 
 #[cfg(test)]
 pub mod tests {
+    use super::super::{ConfigChange, Log};
     use super::*;
+    use crate::storage::log;
     use pretty_assertions::assert_eq;
     use std::sync::{Arc, Mutex};
 
@@ -288,6 +497,18 @@ pub mod tests {
             self.commands.lock()?.push(command.clone());
             Ok(command)
         }
+
+        // Serializes the commands list and applied index.
+        fn snapshot(&self) -> Result<Vec<u8>> {
+            Ok(bincode::serialize(&(&*self.commands.lock()?, *self.applied_index.lock()?))?)
+        }
+
+        fn restore(&mut self, snapshot: Vec<u8>) -> Result<()> {
+            let (commands, applied_index) = bincode::deserialize(&snapshot)?;
+            *self.commands.lock()? = commands;
+            *self.applied_index.lock()? = applied_index;
+            Ok(())
+        }
     }
 
     async fn setup() -> Result<(
@@ -347,6 +568,114 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn driver_status() -> Result<()> {
+        let (_state, state_tx, node_rx) = setup().await?;
+
+        for index in 1..=2 {
+            state_tx.send(Instruction::Notify {
+                id: vec![index as u8],
+                index,
+                address: Address::Client,
+            })?;
+        }
+        state_tx.send(Instruction::Query {
+            id: vec![0x03],
+            address: Address::Client,
+            command: vec![0xf0],
+            term: 1,
+            index: 1,
+            quorum: 2,
+        })?;
+        let status = Status {
+            server: "a".into(),
+            leader: "a".into(),
+            term: 1,
+            node_last_index: HashMap::new(),
+            node_next_index: HashMap::new(),
+            node_last_contact: HashMap::new(),
+            last_index: 2,
+            commit_index: 0,
+            apply_index: 0,
+            pending_notifications: 0,
+            pending_queries: 0,
+            storage: "memory".into(),
+            storage_size: 0,
+        };
+        state_tx.send(Instruction::Status {
+            id: vec![0x04],
+            address: Address::Client,
+            status: Box::new(status.clone()),
+        })?;
+        std::mem::drop(state_tx);
+
+        let node_rx = UnboundedReceiverStream::new(node_rx);
+        assert_eq!(
+            node_rx.collect::<Vec<_>>().await,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 0,
+                event: Event::ClientResponse {
+                    id: vec![0x04],
+                    response: Ok(Response::Status(Box::new(Status {
+                        pending_notifications: 2,
+                        pending_queries: 1,
+                        ..status
+                    })))
+                }
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    // Queries that don't get a quorum within the query timeout are aborted.
+    async fn driver_query_timeout() -> Result<()> {
+        let state = Box::new(TestState::new(0));
+        let (state_tx, state_rx) = mpsc::unbounded_channel();
+        let (node_tx, node_rx) = mpsc::unbounded_channel();
+        tokio::spawn(Driver::new(state_rx, node_tx).with_query_timeout(3).drive(state.clone()));
+
+        state_tx.send(Instruction::Query {
+            id: vec![0x01],
+            address: Address::Client,
+            command: vec![0xf0],
+            term: 1,
+            index: 1,
+            quorum: 2,
+        })?;
+        state_tx.send(Instruction::Vote { term: 1, index: 1, address: Address::Local })?;
+        state_tx.send(Instruction::Tick)?;
+        state_tx.send(Instruction::Query {
+            id: vec![0x02],
+            address: Address::Peer("b".into()),
+            command: vec![0xf1],
+            term: 1,
+            index: 1,
+            quorum: 2,
+        })?;
+        state_tx.send(Instruction::Tick)?;
+        state_tx.send(Instruction::Tick)?;
+        std::mem::drop(state_tx);
+
+        // The first query times out after 3 ticks, the second isn't due yet. Neither executes.
+        let node_rx = UnboundedReceiverStream::new(node_rx);
+        assert_eq!(
+            node_rx.collect::<Vec<_>>().await,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 0,
+                event: Event::ClientResponse { id: vec![0x01], response: Err(Error::Abort) }
+            }]
+        );
+        assert_eq!(state.list(), Vec::<Vec<u8>>::new());
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn driver_apply() -> Result<()> {
         let (state, state_tx, node_rx) = setup().await?;
@@ -356,9 +685,11 @@ pub mod tests {
             index: 2,
             address: Address::Client,
         })?;
-        state_tx.send(Instruction::Apply { entry: Entry { index: 1, term: 1, command: None } })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 2, term: 1, command: Some(vec![0xaf]) },
+            entry: Entry { index: 1, term: 1, command: None, config: None },
+        })?;
+        state_tx.send(Instruction::Apply {
+            entry: Entry { index: 2, term: 1, command: Some(vec![0xaf]), config: None },
         })?;
         std::mem::drop(state_tx);
 
@@ -381,6 +712,147 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn driver_apply_batch() -> Result<()> {
+        let (state, state_tx, node_rx) = setup().await?;
+
+        for index in 1..=5 {
+            state_tx.send(Instruction::Notify {
+                id: vec![index],
+                index: index as u64,
+                address: Address::Client,
+            })?;
+        }
+        state_tx.send(Instruction::ApplyBatch {
+            entries: (1..=5)
+                .map(|index| Entry {
+                    index,
+                    term: 1,
+                    command: Some(vec![index as u8]),
+                    config: None,
+                })
+                .collect(),
+        })?;
+        std::mem::drop(state_tx);
+
+        let node_rx = UnboundedReceiverStream::new(node_rx);
+        assert_eq!(
+            node_rx.collect::<Vec<_>>().await,
+            (1..=5)
+                .map(|index| Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 0,
+                    event: Event::ClientResponse {
+                        id: vec![index],
+                        response: Ok(Response::State(vec![index])),
+                    },
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(state.list(), (1..=5).map(|index| vec![index]).collect::<Vec<_>>());
+        assert_eq!(state.applied_index(), 5);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn driver_notify_batch() -> Result<()> {
+        let (state, state_tx, node_rx) = setup().await?;
+
+        state_tx.send(Instruction::NotifyBatch {
+            id: vec![0x01],
+            address: Address::Client,
+            first: 2,
+            last: 4,
+        })?;
+        state_tx.send(Instruction::Notify {
+            id: vec![0x02],
+            index: 5,
+            address: Address::Client,
+        })?;
+        state_tx.send(Instruction::Apply {
+            entry: Entry { index: 1, term: 1, command: None, config: None },
+        })?;
+        state_tx.send(Instruction::ApplyBatch {
+            entries: (2..=5)
+                .map(|index| Entry {
+                    index,
+                    term: 1,
+                    command: Some(vec![index as u8]),
+                    config: None,
+                })
+                .collect(),
+        })?;
+        std::mem::drop(state_tx);
+
+        // The batch is notified once its last entry is applied, with responses in order.
+        let node_rx = UnboundedReceiverStream::new(node_rx);
+        assert_eq!(
+            node_rx.collect::<Vec<_>>().await,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 0,
+                    event: Event::ClientResponse {
+                        id: vec![0x01],
+                        response: Ok(Response::StateBatch(vec![vec![2], vec![3], vec![4]])),
+                    }
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Client,
+                    term: 0,
+                    event: Event::ClientResponse {
+                        id: vec![0x02],
+                        response: Ok(Response::State(vec![5])),
+                    }
+                },
+            ]
+        );
+        assert_eq!(state.list(), vec![vec![2], vec![3], vec![4], vec![5]]);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn driver_apply_config() -> Result<()> {
+        let (state, state_tx, node_rx) = setup().await?;
+
+        state_tx.send(Instruction::Notify {
+            id: vec![0x01],
+            index: 1,
+            address: Address::Client,
+        })?;
+        state_tx.send(Instruction::Apply {
+            entry: Entry {
+                index: 1,
+                term: 1,
+                command: None,
+                config: Some(ConfigChange::RemoveServer { id: "b".into() }),
+            },
+        })?;
+        std::mem::drop(state_tx);
+
+        let node_rx = UnboundedReceiverStream::new(node_rx);
+        assert_eq!(
+            node_rx.collect::<Vec<_>>().await,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 0,
+                event: Event::ClientResponse {
+                    id: vec![0x01],
+                    response: Ok(Response::ConfigChange)
+                }
+            }]
+        );
+        assert_eq!(state.list(), Vec::<Vec<u8>>::new());
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn driver_query() -> Result<()> {
         let (_, state_tx, node_rx) = setup().await?;
@@ -394,7 +866,7 @@ pub mod tests {
             quorum: 2,
         })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 1, term: 2, command: Some(vec![0xaf]) },
+            entry: Entry { index: 1, term: 2, command: Some(vec![0xaf]), config: None },
         })?;
         state_tx.send(Instruction::Vote { term: 2, index: 1, address: Address::Local })?;
         state_tx.send(Instruction::Vote {
@@ -435,7 +907,7 @@ pub mod tests {
             quorum: 2,
         })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 1, term: 1, command: Some(vec![0xaf]) },
+            entry: Entry { index: 1, term: 1, command: Some(vec![0xaf]), config: None },
         })?;
         state_tx.send(Instruction::Vote { term: 2, index: 1, address: Address::Local })?;
         state_tx.send(Instruction::Vote {
@@ -463,7 +935,7 @@ pub mod tests {
             quorum: 2,
         })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 1, term: 1, command: Some(vec![0xaf]) },
+            entry: Entry { index: 1, term: 1, command: Some(vec![0xaf]), config: None },
         })?;
         state_tx.send(Instruction::Vote { term: 1, index: 1, address: Address::Local })?;
         std::mem::drop(state_tx);
@@ -473,4 +945,96 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn driver_snapshot() -> Result<()> {
+        let state = Box::new(TestState::new(0));
+        let (state_tx, state_rx) = mpsc::unbounded_channel();
+        let (node_tx, node_rx) = mpsc::unbounded_channel();
+        tokio::spawn(
+            Driver::new(state_rx, node_tx).with_snapshot_interval(Some(2)).drive(state.clone()),
+        );
+
+        state_tx.send(Instruction::Apply {
+            entry: Entry { index: 1, term: 1, command: Some(vec![0x01]), config: None },
+        })?;
+        state_tx.send(Instruction::Apply {
+            entry: Entry { index: 2, term: 1, command: None, config: None },
+        })?;
+        state_tx.send(Instruction::Apply {
+            entry: Entry { index: 3, term: 2, command: Some(vec![0x03]), config: None },
+        })?;
+        std::mem::drop(state_tx);
+
+        // The snapshot is taken after the no-op entry, while the state has applied entry 1.
+        let data = bincode::serialize(&(vec![vec![0x01_u8]], 1_u64))?;
+        let node_rx = UnboundedReceiverStream::new(node_rx);
+        assert_eq!(
+            node_rx.collect::<Vec<_>>().await,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Local,
+                term: 0,
+                event: Event::Snapshot(Box::new(Snapshot {
+                    index: 2,
+                    term: 1,
+                    configs: vec![],
+                    data
+                })),
+            }]
+        );
+        assert_eq!(state.list(), vec![vec![0x01], vec![0x03]]);
+
+        Ok(())
+    }
+
+    // Restoring a snapshot and replaying the entries that follow it from a compacted log yields
+    // the same state as replaying the entire log.
+    #[test]
+    fn driver_restore_replay() -> Result<()> {
+        fn driver() -> Driver {
+            let (_, state_rx) = mpsc::unbounded_channel();
+            let (node_tx, _) = mpsc::unbounded_channel();
+            Driver::new(state_rx, node_tx)
+        }
+
+        let mut log = Log::new(Box::new(log::Test::new()))?;
+        for i in 1..=5 {
+            log.append(1, Some(vec![i]))?;
+        }
+        log.append(2, None)?;
+        for i in 7..=10 {
+            log.append(2, Some(vec![i]))?;
+        }
+        log.commit(10)?;
+
+        let mut full = TestState::new(0);
+        let mut full_driver = driver();
+        full_driver.replay(&mut full, log.scan(1..))?;
+
+        // Snapshot a state replayed up to the no-op entry, and compact the log up to it.
+        let mut partial = TestState::new(0);
+        driver().replay(&mut partial, log.scan(1..=6))?;
+        log.save_snapshot(&Snapshot {
+            index: 6,
+            term: 2,
+            configs: vec![],
+            data: partial.snapshot()?,
+        })?;
+        assert_eq!(6, log.compact(6)?);
+        assert_eq!(None, log.get(5)?);
+
+        let mut restored = TestState::new(0);
+        let mut restored_driver = driver();
+        let snapshot = log.load_snapshot()?.expect("snapshot not found");
+        restored_driver.restore(&mut restored, snapshot)?;
+        restored_driver.replay(&mut restored, log.scan(1..))?;
+
+        assert_eq!(full.list(), restored.list());
+        assert_eq!(full.applied_index(), restored.applied_index());
+        assert_eq!(full_driver.applied_index, restored_driver.applied_index);
+        assert_eq!(full_driver.applied_term, restored_driver.applied_term);
+
+        Ok(())
+    }
 }
@@ -1,50 +1,137 @@
+use crate::audit::AuditLog;
 use crate::error::{Error, Result};
 use crate::raft;
 use crate::sql;
-use crate::sql::engine::{Engine as _, Mode};
-use crate::sql::execution::ResultSet;
+use crate::sql::engine::{Engine as _, Mode, Transaction as _};
+use crate::sql::execution::{Cancel, ResultSet};
 use crate::sql::schema::{Catalog as _, Table};
-use crate::sql::types::Row;
+use crate::sql::types::{Row, Value};
 use crate::storage::{kv, log};
 
-use ::log::{error, info};
+use ::log::{error, info, warn};
+use bincode::Options as _;
+use bytes::BytesMut;
 use futures::sink::SinkExt as _;
+use futures::stream::Stream;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
-use tokio_stream::wrappers::TcpListenerStream;
+use tokio_serde::formats::{Bincode, Json};
+use tokio_serde::{Deserializer, Serializer};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
 use tokio_stream::StreamExt as _;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
+/// The number of malformed requests in a row after which a client connection is closed, since
+/// the client is likely speaking another protocol version.
+const MAX_MALFORMED_REQUESTS: usize = 3;
+
+/// The number of query result rows buffered between the thread reading them and the connection.
+const ROW_BUFFER_SIZE: usize = 64;
+
+/// The maximum interval between checks for long-running transactions.
+const TRANSACTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The interval at which the Balanced durability preset fsyncs committed Raft log entries.
+const GROUP_COMMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The interval at which the Fast durability preset fsyncs committed Raft log entries, off the
+/// commit path.
+const DEFERRED_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A durability versus performance trade-off for a server's Raft log, which all SQL data is
+/// replayed from, applied to the Raft log store by Server::new. With any preset, data committed
+/// by a Raft quorum survives the loss of a minority of nodes, and Raft term and vote changes are
+/// fsynced so Raft safety holds across crashes. The presets differ in what committed data
+/// survives a machine crash, e.g. a power loss, of a majority of nodes at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DurabilityPreset {
+    /// Fsyncs every commit. Committed data survives any crash, but every commit waits for the
+    /// disk.
+    Safe,
+    /// Groups commits into at most one fsync per 100ms, done by the first commit after the
+    /// interval or else by the next Raft tick. A crash of a majority of nodes can lose the data
+    /// committed since their last fsync.
+    Balanced,
+    /// Groups commits and defers their fsync to the Raft tick, at most once per second, so
+    /// commits never wait for the disk. A crash of a majority of nodes can lose up to about a
+    /// second of committed data.
+    Fast,
+}
+
+impl DurabilityPreset {
+    /// Returns the Raft log sync policy of the preset.
+    pub fn sync_policy(&self) -> log::SyncPolicy {
+        match self {
+            Self::Safe => log::SyncPolicy::Always,
+            Self::Balanced => log::SyncPolicy::Grouped(GROUP_COMMIT_INTERVAL),
+            Self::Fast => log::SyncPolicy::Deferred(DEFERRED_SYNC_INTERVAL),
+        }
+    }
+}
+
 /// A toyDB server.
 pub struct Server {
     raft: raft::Server,
     raft_listener: Option<TcpListener>,
     sql_listener: Option<TcpListener>,
+    #[cfg(feature = "metrics")]
+    metrics_listener: Option<TcpListener>,
+    max_frame_size: usize,
+    audit_log: Option<Arc<AuditLog>>,
+    txn_warn_after: Option<Duration>,
+    txn_abort_after: Option<Duration>,
+    sync_policy: Option<log::SyncPolicy>,
+    allow_admin: bool,
 }
 
 impl Server {
-    /// Creates a new toyDB server.
+    /// Creates a new toyDB server, configuring the Raft log store for the given durability
+    /// preset. SQL client requests and responses are limited to max_frame_size bytes each, with
+    /// query results limited per row.
     pub async fn new(
         id: &str,
         peers: HashMap<String, String>,
-        raft_store: Box<dyn log::Store>,
+        mut raft_store: Box<dyn log::Store>,
+        durability: DurabilityPreset,
         sql_store: Box<dyn kv::Store>,
+        raft_config: raft::RaftConfig,
+        max_frame_size: usize,
     ) -> Result<Self> {
+        raft_store.set_sync_policy(durability.sync_policy());
+        let sync_policy = raft_store.sync_policy();
         Ok(Server {
             raft: raft::Server::new(
                 id,
                 peers,
                 raft::Log::new(raft_store)?,
                 Box::new(sql::engine::Raft::new_state(kv::MVCC::new(sql_store))?),
+                raft_config,
             )
             .await?,
             raft_listener: None,
             sql_listener: None,
+            #[cfg(feature = "metrics")]
+            metrics_listener: None,
+            max_frame_size,
+            audit_log: None,
+            txn_warn_after: None,
+            txn_abort_after: None,
+            sync_policy,
+            allow_admin: false,
         })
     }
 
+    /// Returns the effective sync policy of the Raft log store, if it has durable storage.
+    pub fn sync_policy(&self) -> Option<log::SyncPolicy> {
+        self.sync_policy
+    }
+
     /// Starts listening on the given ports. Must be called before serve.
     pub async fn listen(mut self, sql_addr: &str, raft_addr: &str) -> Result<Self> {
         let (sql, raft) =
@@ -55,6 +142,47 @@ impl Server {
         Ok(self)
     }
 
+    /// Starts listening for Prometheus metrics scrapes on the given address. Optional, and
+    /// must be called before serve.
+    #[cfg(feature = "metrics")]
+    pub async fn listen_metrics(mut self, metrics_addr: &str) -> Result<Self> {
+        let metrics = TcpListener::bind(metrics_addr).await?;
+        info!("Listening on {} (metrics)", metrics.local_addr()?);
+        self.metrics_listener = Some(metrics);
+        Ok(self)
+    }
+
+    /// Records the SQL statements executed by clients in the given audit log. Optional, and must
+    /// be called before serve.
+    pub fn audit(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(Arc::new(audit_log));
+        self
+    }
+
+    /// Logs explicit transactions that have been open for longer than warn_after, and rolls back
+    /// transactions that have been open for longer than abort_after while their session is idle,
+    /// since they hold on to their MVCC snapshot and uncommitted writes. The session's further
+    /// statements error until it rolls the transaction back. Optional, and must be called before
+    /// serve.
+    pub fn monitor_transactions(
+        mut self,
+        warn_after: Option<Duration>,
+        abort_after: Option<Duration>,
+    ) -> Self {
+        self.txn_warn_after = warn_after;
+        self.txn_abort_after = abort_after;
+        self
+    }
+
+    /// Allows SQL clients to administer the Raft cluster, e.g. transfer its leadership or change
+    /// its membership. Disabled
+    /// by default, since SQL clients aren't authenticated. Optional, and must be called before
+    /// serve.
+    pub fn allow_admin_requests(mut self, allow: bool) -> Self {
+        self.allow_admin = allow;
+        self
+    }
+
     /// Serves Raft and SQL requests until the returned future is dropped. Consumes the server.
     pub async fn serve(self) -> Result<()> {
         let sql_listener = self
@@ -66,19 +194,120 @@ impl Server {
         let (raft_tx, raft_rx) = mpsc::unbounded_channel();
         let sql_engine = sql::engine::Raft::new(raft::Client::new(raft_tx));
 
+        #[cfg(feature = "metrics")]
+        let metrics = Self::serve_metrics(self.metrics_listener, sql_engine.clone());
+        #[cfg(not(feature = "metrics"))]
+        let metrics = async { Ok::<_, Error>(()) };
+
         tokio::try_join!(
             self.raft.serve(raft_listener, raft_rx),
-            Self::serve_sql(sql_listener, sql_engine),
+            Self::serve_transaction_monitor(
+                sql_engine.clone(),
+                self.txn_warn_after,
+                self.txn_abort_after
+            ),
+            Self::serve_sql(
+                sql_listener,
+                sql_engine,
+                self.max_frame_size,
+                self.audit_log,
+                self.allow_admin
+            ),
+            metrics,
         )?;
         Ok(())
     }
 
+    /// Serves Prometheus metrics, if listening for them.
+    #[cfg(feature = "metrics")]
+    async fn serve_metrics(listener: Option<TcpListener>, engine: sql::engine::Raft) -> Result<()> {
+        match listener {
+            Some(listener) => crate::metrics::serve(listener, engine).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Periodically checks for long-running transactions, if enabled.
+    async fn serve_transaction_monitor(
+        engine: sql::engine::Raft,
+        warn_after: Option<Duration>,
+        abort_after: Option<Duration>,
+    ) -> Result<()> {
+        let min_age = match warn_after.into_iter().chain(abort_after).min() {
+            Some(min_age) => min_age,
+            None => return Ok(()),
+        };
+        let period = std::cmp::min(min_age / 2, TRANSACTION_CHECK_INTERVAL);
+        let mut interval = tokio::time::interval(std::cmp::max(period, Duration::from_millis(1)));
+        let mut warned = HashSet::new();
+        loop {
+            interval.tick().await;
+            if let Err(err) = tokio::task::block_in_place(|| {
+                Self::check_transactions(&engine, warn_after, abort_after, &mut warned)
+            }) {
+                error!("Failed to check for long-running transactions: {}", err);
+            }
+        }
+    }
+
+    /// Logs transactions that have been open for longer than warn_after, once per transaction,
+    /// and rolls back idle transactions that have been open for longer than abort_after.
+    fn check_transactions(
+        engine: &sql::engine::Raft,
+        warn_after: Option<Duration>,
+        abort_after: Option<Duration>,
+        warned: &mut HashSet<u64>,
+    ) -> Result<()> {
+        let min_age = warn_after.into_iter().chain(abort_after).min().unwrap_or_default();
+        let open = engine.activity().open_transactions(min_age)?;
+        warned.retain(|id| open.iter().any(|txn| txn.id == *id));
+        for txn in open {
+            let state = match txn.idle {
+                Some(idle) => format!("idle for {:.1}s", idle.as_secs_f64()),
+                None => "active".to_string(),
+            };
+            let statement = txn.statement.as_deref().unwrap_or("");
+            if txn.idle.is_some()
+                && abort_after.is_some_and(|limit| txn.age >= limit)
+                && engine.activity().abort(txn.session, txn.id)?
+            {
+                warn!(
+                    "Rolling back transaction {} of client {}, open for {:.1}s and {}, last \
+                     statement: {}",
+                    txn.id,
+                    txn.client,
+                    txn.age.as_secs_f64(),
+                    state,
+                    statement
+                );
+                engine.resume(txn.id)?.rollback()?;
+            } else if warn_after.is_some_and(|limit| txn.age >= limit) && warned.insert(txn.id) {
+                warn!(
+                    "Transaction {} of client {} has been open for {:.1}s and is {}, statement: {}",
+                    txn.id,
+                    txn.client,
+                    txn.age.as_secs_f64(),
+                    state,
+                    statement
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Serves SQL clients.
-    async fn serve_sql(listener: TcpListener, engine: sql::engine::Raft) -> Result<()> {
+    async fn serve_sql(
+        listener: TcpListener,
+        engine: sql::engine::Raft,
+        max_frame_size: usize,
+        audit_log: Option<Arc<AuditLog>>,
+        allow_admin: bool,
+    ) -> Result<()> {
         let mut listener = TcpListenerStream::new(listener);
         while let Some(socket) = listener.try_next().await? {
             let peer = socket.peer_addr()?;
-            let session = Session::new(engine.clone())?;
+            let session =
+                Session::new(engine.clone(), max_frame_size, peer, audit_log.clone(), allow_admin)?;
             tokio::spawn(async move {
                 info!("Client {} connected", peer);
                 match session.handle(socket).await {
@@ -94,10 +323,44 @@ impl Server {
 /// A client request.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
-    Execute(String),
+    Execute {
+        sql: String,
+        params: Vec<Value>,
+    },
     GetTable(String),
-    ListTables,
+    /// Lists the names of tables starting with the prefix, if any, in name order. At most limit
+    /// names are returned, if given.
+    ListTables {
+        prefix: Option<String>,
+        limit: Option<usize>,
+    },
     Status,
+    TransferLeadership(String),
+    AddServer {
+        id: String,
+        addr: String,
+    },
+    AddLearner {
+        id: String,
+        addr: String,
+    },
+    PromoteLearner(String),
+    RemoveServer(String),
+    Ping,
+    Validate(String),
+    ResetStatementStats,
+    Prepare(String),
+    ExecutePrepared {
+        handle: u64,
+        params: Vec<Value>,
+    },
+    /// Cancels the client's requests sent before it. A statement being executed fails with a
+    /// cancellation error, and a query whose rows are being streamed ends early with a final
+    /// Response::Row(None). Later requests are unaffected. Cancel itself is handled as soon as
+    /// it's read, and gets no response.
+    Cancel,
+    /// Deallocates a prepared statement by handle.
+    Deallocate(u64),
 }
 
 /// A server response.
@@ -106,39 +369,170 @@ pub enum Response {
     Execute(ResultSet),
     Row(Option<Row>),
     GetTable(Table),
-    ListTables(Vec<String>),
+    /// The listed table names, and whether more tables beyond the limit match the prefix.
+    ListTables {
+        tables: Vec<String>,
+        more: bool,
+    },
     Status(sql::engine::Status),
+    TransferLeadership,
+    ChangeMembership,
+    Ping,
+    Validate,
+    ResetStatementStats,
+    Prepare(u64),
+    Deallocate,
+}
+
+/// The wire format of a client session's requests and responses, each sent in a length-prefixed
+/// frame. Bincode is used by the toyDB client, while JSON suits clients in other languages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Bincode,
+    Json,
+}
+
+impl Format {
+    /// Detects the format of a client's first request frame, which negotiates the format of the
+    /// session. A bincode request starts with its little-endian variant index, which is never
+    /// the start of a JSON request, i.e. an object or a string for requests without fields.
+    pub fn detect(frame: &[u8]) -> Self {
+        match frame.first() {
+            Some(b'{') | Some(b'"') => Self::Json,
+            _ => Self::Bincode,
+        }
+    }
 }
 
 /// A client session coupled to a SQL session.
 pub struct Session {
     engine: sql::engine::Raft,
     sql: sql::engine::Session<sql::engine::Raft>,
+    max_frame_size: usize,
+    peer: SocketAddr,
+    audit_log: Option<Arc<AuditLog>>,
+    /// Whether the client may administer the Raft cluster, see Server::allow_admin_requests()
+    allow_admin: bool,
+    /// The session's ID in the engine's activity registry
+    activity_id: u64,
+    /// The sequence number of the request being served, counting from 1
+    request_seq: u64,
+    /// The sequence number of the last request cancelled by the client, see Request::Cancel
+    cancelled: Arc<AtomicU64>,
 }
 
 impl Session {
-    /// Creates a new client session.
-    fn new(engine: sql::engine::Raft) -> Result<Self> {
-        Ok(Self { sql: engine.session()?, engine })
-    }
-
-    /// Handles a client connection.
-    async fn handle(mut self, socket: TcpStream) -> Result<()> {
-        let mut stream = tokio_serde::Framed::new(
-            Framed::new(socket, LengthDelimitedCodec::new()),
-            tokio_serde::formats::Bincode::default(),
-        );
-        while let Some(request) = stream.try_next().await? {
+    /// Creates a new client session for the client at the given address.
+    fn new(
+        engine: sql::engine::Raft,
+        max_frame_size: usize,
+        peer: SocketAddr,
+        audit_log: Option<Arc<AuditLog>>,
+        allow_admin: bool,
+    ) -> Result<Self> {
+        let activity_id = engine.activity().register(&peer.to_string())?;
+        Ok(Self {
+            sql: engine.session()?,
+            engine,
+            max_frame_size,
+            peer,
+            audit_log,
+            allow_admin,
+            activity_id,
+            request_seq: 1,
+            cancelled: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Handles a client connection. The session uses the format of the client's first request,
+    /// see Format::detect.
+    async fn handle(self, socket: TcpStream) -> Result<()> {
+        let codec =
+            LengthDelimitedCodec::builder().max_frame_length(self.max_frame_size).new_codec();
+        let mut framed = Framed::new(socket, codec);
+        let first = match framed.next().await {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        match first.as_ref().map_or(Format::Bincode, |frame| Format::detect(frame)) {
+            Format::Bincode => self.serve(framed, first, Format::Bincode, Bincode::default()).await,
+            Format::Json => self.serve(framed, first, Format::Json, Json::default()).await,
+        }
+    }
+
+    /// Serves a client connection using the given format codec, starting with the first
+    /// request frame, which has already been read. Requests are read by a separate task, so that
+    /// a Cancel request can be handled while a request is being executed.
+    async fn serve<C>(
+        mut self,
+        framed: Framed<TcpStream, LengthDelimitedCodec>,
+        first: std::io::Result<BytesMut>,
+        format: Format,
+        mut codec: C,
+    ) -> Result<()>
+    where
+        C: Deserializer<Request> + Serializer<Result<Response>> + Unpin + Send + 'static,
+        std::io::Error: From<<C as Deserializer<Request>>::Error>,
+        <C as Serializer<Result<Response>>>::Error: Into<std::io::Error>,
+    {
+        let max_frame_size = self.max_frame_size;
+        let first =
+            first.and_then(|frame| Ok(Some(std::pin::Pin::new(&mut codec).deserialize(&frame)?)));
+        let (mut sink, requests) =
+            futures::StreamExt::split(tokio_serde::Framed::new(framed, codec));
+        let (request_tx, mut request_rx) = mpsc::channel(1);
+        let _reader = AbortOnDrop(tokio::spawn(Self::read_requests(
+            requests,
+            first,
+            request_tx,
+            self.cancelled.clone(),
+        )));
+        let mut malformed = 0;
+        while let Some((seq, next)) = request_rx.recv().await {
+            let request = match next {
+                Ok(Some(request)) => {
+                    malformed = 0;
+                    request
+                }
+                Ok(None) => break,
+                // A whole frame was read, so the connection can continue past it.
+                Err(err) if is_malformed(&err) => {
+                    malformed += 1;
+                    sink.send(Err(Error::Value("malformed request".into()))).await?;
+                    if malformed >= MAX_MALFORMED_REQUESTS {
+                        return Err(Error::Value(format!(
+                            "{} malformed requests in a row, last: {}",
+                            malformed, err
+                        )));
+                    }
+                    continue;
+                }
+                // Framing errors, e.g. oversized requests that are rejected without reading
+                // them, leave the connection out of sync. Tell the client why it's closed.
+                Err(err) => {
+                    let error = Error::Value(format!("Request rejected: {}", err));
+                    sink.send(Err(error)).await.ok();
+                    return Err(err.into());
+                }
+            };
+            self.request_seq = seq;
             let mut response = tokio::task::block_in_place(|| self.request(request));
-            let mut rows: Box<dyn Iterator<Item = Result<Response>> + Send> =
-                Box::new(std::iter::empty());
+            let mut rows: Option<Box<dyn Iterator<Item = Result<Response>> + Send>> = None;
             if let Ok(Response::Execute(ResultSet::Query { rows: ref mut resultrows, .. })) =
                 &mut response
             {
-                rows = Box::new(
+                // A cancelled query's rows end with an abort, which is dropped to end the rows as
+                // usual.
+                let cancelled = self.cancelled.clone();
+                rows = Some(Box::new(
                     std::mem::replace(resultrows, Box::new(std::iter::empty()))
+                        .take_while(move |result| {
+                            !matches!(result, Err(Error::Abort))
+                                || cancelled.load(Ordering::SeqCst) < seq
+                        })
                         .map(|result| result.map(|row| Response::Row(Some(row))))
                         .chain(std::iter::once(Ok(Response::Row(None))))
+                        .map(move |response| limit_frame(response, format, max_frame_size))
                         .scan(false, |err_sent, response| match (&err_sent, &response) {
                             (true, _) => None,
                             (_, Err(error)) => {
@@ -148,33 +542,251 @@ impl Session {
                             _ => Some(response),
                         })
                         .fuse(),
-                );
+                ));
+            }
+            // Rows may be read from storage as they're iterated, e.g. by batched Raft scans, so
+            // they're iterated on a blocking thread. If the client goes away, the thread stops and
+            // drops the rows once the channel is closed.
+            let rows = rows.map(|rows| {
+                let (row_tx, row_rx) = mpsc::channel(ROW_BUFFER_SIZE);
+                tokio::task::spawn_blocking(move || {
+                    for row in rows {
+                        if row_tx.blocking_send(row).is_err() {
+                            break;
+                        }
+                    }
+                });
+                row_rx
+            });
+            sink.send(limit_frame(response, format, max_frame_size)).await?;
+            if let Some(rows) = rows {
+                sink.send_all(&mut ReceiverStream::new(rows).map(Ok)).await?;
             }
-            stream.send(response).await?;
-            stream.send_all(&mut tokio_stream::iter(rows.map(Ok))).await?;
         }
         Ok(())
     }
 
+    /// Reads a client's requests, starting with the first one, and passes them on numbered by
+    /// sequence until the connection is closed or fails, or the receiver is dropped. Cancel
+    /// requests are handled here instead, by recording the last request sent before them as
+    /// cancelled, since the session is likely busy with that request.
+    async fn read_requests<S>(
+        mut requests: S,
+        first: std::io::Result<Option<Request>>,
+        request_tx: mpsc::Sender<(u64, std::io::Result<Option<Request>>)>,
+        cancelled: Arc<AtomicU64>,
+    ) where
+        S: Stream<Item = std::io::Result<Request>> + Unpin,
+    {
+        let mut first = Some(first);
+        let mut seq = 0;
+        loop {
+            let next = match first.take() {
+                Some(request) => request,
+                None => requests.try_next().await,
+            };
+            if let Ok(Some(Request::Cancel)) = next {
+                cancelled.store(seq, Ordering::SeqCst);
+                continue;
+            }
+            let last = match &next {
+                Ok(Some(_)) => false,
+                Ok(None) => true,
+                Err(err) => !is_malformed(err),
+            };
+            seq += 1;
+            if request_tx.send((seq, next)).await.is_err() || last {
+                break;
+            }
+        }
+    }
+
     /// Executes a request.
     pub fn request(&mut self, request: Request) -> Result<Response> {
         Ok(match request {
-            Request::Execute(query) => Response::Execute(self.sql.execute(&query)?),
+            Request::TransferLeadership(_)
+            | Request::AddServer { .. }
+            | Request::AddLearner { .. }
+            | Request::PromoteLearner(_)
+            | Request::RemoveServer(_)
+                if !self.allow_admin =>
+            {
+                return Err(Error::Value("Cluster administration requests are disabled".into()))
+            }
+            Request::Execute { sql, params } => {
+                self.execute(&sql, |session| session.execute_with_params(&sql, params))?
+            }
+            Request::Prepare(sql) => Response::Prepare(self.sql.prepare(&sql)?),
+            Request::ExecutePrepared { handle, params } => {
+                let sql = self.sql.prepared_query(handle)?.to_string();
+                self.execute(&sql, |session| session.execute_prepared(handle, params))?
+            }
+            Request::Deallocate(handle) => {
+                self.sql.deallocate(handle)?;
+                Response::Deallocate
+            }
             Request::GetTable(table) => Response::GetTable(
                 self.sql.with_txn(Mode::ReadOnly, |txn| txn.must_read_table(&table))?,
             ),
-            Request::ListTables => {
-                Response::ListTables(self.sql.with_txn(Mode::ReadOnly, |txn| {
-                    Ok(txn.scan_tables()?.map(|t| t.name).collect())
-                })?)
+            Request::ListTables { prefix, limit } => {
+                let (tables, more) = self.sql.with_txn(Mode::ReadOnly, |txn| {
+                    let prefix = prefix.unwrap_or_default();
+                    let mut names =
+                        txn.scan_tables()?.map(|t| t.name).filter(|name| name.starts_with(&prefix));
+                    let tables = names.by_ref().take(limit.unwrap_or(usize::MAX)).collect();
+                    Ok((tables, names.next().is_some()))
+                })?;
+                Response::ListTables { tables, more }
             }
             Request::Status => Response::Status(self.engine.status()?),
+            Request::TransferLeadership(target) => {
+                self.engine.transfer_leadership(&target)?;
+                Response::TransferLeadership
+            }
+            Request::AddServer { id, addr } => {
+                self.engine.change_config(raft::ConfigChange::AddServer { id, addr })?;
+                Response::ChangeMembership
+            }
+            Request::AddLearner { id, addr } => {
+                self.engine.change_config(raft::ConfigChange::AddLearner { id, addr })?;
+                Response::ChangeMembership
+            }
+            Request::PromoteLearner(id) => {
+                self.engine.change_config(raft::ConfigChange::PromoteLearner { id })?;
+                Response::ChangeMembership
+            }
+            Request::RemoveServer(id) => {
+                self.engine.change_config(raft::ConfigChange::RemoveServer { id })?;
+                Response::ChangeMembership
+            }
+            Request::Ping => Response::Ping,
+            Request::Validate(sql) => {
+                self.sql.validate(&sql)?;
+                Response::Validate
+            }
+            Request::ResetStatementStats => {
+                self.engine.statements().reset()?;
+                Response::ResetStatementStats
+            }
+            // Handled as soon as it's read, see read_requests().
+            Request::Cancel => return Err(Error::Internal("Unexpected cancel request".into())),
         })
     }
+
+    /// Executes a statement given by its SQL text, tracking it in the activity registry, audit log
+    /// and statement statistics.
+    fn execute<F>(&mut self, sql: &str, f: F) -> Result<Response>
+    where
+        F: FnOnce(&mut sql::engine::Session<sql::engine::Raft>) -> Result<ResultSet>,
+    {
+        if self.engine.activity().start(self.activity_id, sql)?.is_some() {
+            // The transaction was rolled back by the transaction monitor while idle.
+            self.sql.abort_transaction();
+        }
+        // The statement is cancelled by a Cancel request read after this one, see read_requests().
+        let (cancelled, seq) = (self.cancelled.clone(), self.request_seq);
+        let cancel = Cancel::new(move || cancelled.load(Ordering::SeqCst) >= seq);
+        self.sql.set_cancel(cancel.clone());
+        let start = Instant::now();
+        let result = match f(&mut self.sql) {
+            Err(Error::Abort) if cancel.check().is_err() => {
+                Err(Error::Value("Statement was cancelled".into()))
+            }
+            result => result,
+        };
+        self.engine.activity().finish(self.activity_id, self.sql.transaction_id())?;
+        if let Some(audit_log) = &self.audit_log {
+            // The statement has already run, so a failed audit write can't undo it.
+            if let Err(err) = audit_log.record(&self.peer, sql, &result) {
+                error!("Failed to write audit log entry: {}", err);
+            }
+        }
+        Ok(Response::Execute(self.engine.statements().track(sql, start, result?)))
+    }
+}
+
+/// A task handle which aborts the task when dropped.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort()
+    }
+}
+
+/// Replaces a response that doesn't fit in a frame of the given size when encoded in the given
+/// format with an error, so that the client learns of it and the connection stays usable.
+fn limit_frame(
+    response: Result<Response>,
+    format: Format,
+    max_frame_size: usize,
+) -> Result<Response> {
+    let size = match format {
+        // Uses the same bincode options as tokio_serde::formats::Bincode.
+        Format::Bincode => bincode::DefaultOptions::new().serialized_size(&response).ok(),
+        Format::Json => serde_json::to_vec(&response).ok().map(|json| json.len() as u64),
+    };
+    match size {
+        Some(size) if size > max_frame_size as u64 => Err(Error::Value(format!(
+            "Response of {} bytes exceeds the maximum frame size of {} bytes",
+            size, max_frame_size
+        ))),
+        _ => response,
+    }
+}
+
+/// Checks whether a stream error is a request frame that couldn't be deserialized, rather than a
+/// framing or connection error.
+fn is_malformed(err: &std::io::Error) -> bool {
+    err.get_ref().is_some_and(|err| err.is::<bincode::Error>() || err.is::<serde_json::Error>())
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
         tokio::task::block_in_place(|| self.sql.execute("ROLLBACK").ok());
+        self.engine.activity().deregister(self.activity_id).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn durability_preset() -> Result<()> {
+        for (preset, sync) in [
+            (DurabilityPreset::Safe, log::SyncPolicy::Always),
+            (DurabilityPreset::Balanced, log::SyncPolicy::Grouped(GROUP_COMMIT_INTERVAL)),
+            (DurabilityPreset::Fast, log::SyncPolicy::Deferred(DEFERRED_SYNC_INTERVAL)),
+        ] {
+            assert_eq!(preset.sync_policy(), sync);
+            let dir = tempdir::TempDir::new("toydb")?;
+            let server = Server::new(
+                "test",
+                HashMap::new(),
+                Box::new(log::Hybrid::new(dir.path(), false)?),
+                preset,
+                Box::new(kv::Memory::new()),
+                raft::RaftConfig::default(),
+                1024,
+            )
+            .await?;
+            assert_eq!(server.sync_policy(), Some(sync));
+        }
+
+        // Stores without durable storage have no sync policy.
+        let server = Server::new(
+            "test",
+            HashMap::new(),
+            Box::new(log::Memory::new()),
+            DurabilityPreset::Safe,
+            Box::new(kv::Memory::new()),
+            raft::RaftConfig::default(),
+            1024,
+        )
+        .await?;
+        assert_eq!(server.sync_policy(), None);
+        Ok(())
     }
 }
@@ -0,0 +1,338 @@
+use super::super::schema::Table;
+use super::super::types::{DataType, Row, Value};
+use super::system::{column, SystemTable};
+use crate::error::{Error, Result};
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The name of the system table exposing session activity, computed from the activity registry.
+pub const ACTIVITY_TABLE: &str = "toydb_activity";
+
+/// A registry of the activity of client sessions, shared by all sessions of an engine. Sessions
+/// register when they connect, record each statement they execute, and are removed when they
+/// disconnect.
+pub struct Activity {
+    /// The registry contents
+    inner: Mutex<Inner>,
+}
+
+/// Activity registry contents, protected by a mutex
+struct Inner {
+    /// The ID of the next registered session
+    next_id: u64,
+    /// Registered sessions by ID
+    sessions: BTreeMap<u64, Session>,
+}
+
+/// The activity of a session
+struct Session {
+    /// The client's address
+    client: String,
+    /// The session state
+    state: State,
+    /// The statement being executed if active, otherwise the last executed statement, if any
+    statement: Option<String>,
+    /// The time at which the session entered its state
+    since: Instant,
+    /// The session's explicit transaction ID and the time it began, if any
+    txn: Option<(u64, Instant)>,
+    /// The ID of the session's transaction if it was aborted while idle, until the session
+    /// learns of it
+    aborted: Option<u64>,
+}
+
+/// A session state
+#[derive(Clone, Copy)]
+enum State {
+    /// Waiting for a statement, outside of a transaction
+    Idle,
+    /// Executing a statement
+    Active,
+    /// Waiting for a statement, inside an explicit transaction
+    IdleInTransaction,
+}
+
+/// An explicit transaction that has been open for a while, as returned by open_transactions()
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenTransaction {
+    /// The ID of the session holding the transaction
+    pub session: u64,
+    /// The client's address
+    pub client: String,
+    /// The transaction ID
+    pub id: u64,
+    /// The time since the transaction began
+    pub age: Duration,
+    /// The time the session has been idle for, or None if it's executing a statement
+    pub idle: Option<Duration>,
+    /// The statement being executed if active, otherwise the last executed statement
+    pub statement: Option<String>,
+}
+
+impl State {
+    /// Returns the state name, as shown in the activity table
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Active => "active",
+            Self::IdleInTransaction => "idle in transaction",
+        }
+    }
+}
+
+impl Activity {
+    /// Creates a new, empty activity registry
+    pub fn new() -> Self {
+        Self { inner: Mutex::new(Inner { next_id: 1, sessions: BTreeMap::new() }) }
+    }
+
+    /// Registers an idle session for the client at the given address, returning its ID
+    pub fn register(&self, client: &str) -> Result<u64> {
+        let mut inner = self.inner.lock()?;
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.sessions.insert(
+            id,
+            Session {
+                client: client.to_string(),
+                state: State::Idle,
+                statement: None,
+                since: Instant::now(),
+                txn: None,
+                aborted: None,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Removes a session from the registry
+    pub fn deregister(&self, id: u64) -> Result<()> {
+        self.inner.lock()?.sessions.remove(&id);
+        Ok(())
+    }
+
+    /// Records that a session started executing a statement. If the session's transaction was
+    /// aborted while it was idle, returns the transaction's ID.
+    pub fn start(&self, id: u64, statement: &str) -> Result<Option<u64>> {
+        let mut inner = self.inner.lock()?;
+        let session = Self::session(&mut inner, id)?;
+        session.state = State::Active;
+        session.since = Instant::now();
+        session.statement = Some(statement.to_string());
+        Ok(session.aborted.take())
+    }
+
+    /// Records that a session finished executing its statement, leaving it idle either inside the
+    /// given explicit transaction or outside of a transaction. The statement is kept as the last
+    /// executed statement.
+    pub fn finish(&self, id: u64, txn_id: Option<u64>) -> Result<()> {
+        let mut inner = self.inner.lock()?;
+        let session = Self::session(&mut inner, id)?;
+        session.txn = match (txn_id, session.txn) {
+            (Some(txn_id), Some((current, began))) if txn_id == current => Some((current, began)),
+            // The transaction began with the statement, which started when the session entered
+            // the active state.
+            (Some(txn_id), _) => Some((txn_id, session.since)),
+            (None, _) => None,
+        };
+        session.state = if txn_id.is_some() { State::IdleInTransaction } else { State::Idle };
+        session.since = Instant::now();
+        Ok(())
+    }
+
+    /// Returns the explicit transactions that have been open for at least the given duration,
+    /// ordered by session ID.
+    pub fn open_transactions(&self, min_age: Duration) -> Result<Vec<OpenTransaction>> {
+        Ok(self
+            .inner
+            .lock()?
+            .sessions
+            .iter()
+            .filter_map(|(id, session)| {
+                let (txn_id, began) = session.txn?;
+                Some(OpenTransaction {
+                    session: *id,
+                    client: session.client.clone(),
+                    id: txn_id,
+                    age: began.elapsed(),
+                    idle: match session.state {
+                        State::Active => None,
+                        State::Idle | State::IdleInTransaction => Some(session.since.elapsed()),
+                    },
+                    statement: session.statement.clone(),
+                })
+            })
+            .filter(|txn| txn.age >= min_age)
+            .collect())
+    }
+
+    /// Marks a session's transaction as aborted, if the session is still idle in it. The caller
+    /// is responsible for rolling the transaction back, and the session learns of it when it
+    /// next starts a statement. Returns false if the session has moved on in the meanwhile.
+    pub fn abort(&self, id: u64, txn_id: u64) -> Result<bool> {
+        let mut inner = self.inner.lock()?;
+        match inner.sessions.get_mut(&id) {
+            Some(session)
+                if matches!(session.state, State::IdleInTransaction)
+                    && session.txn.map(|(current, _)| current) == Some(txn_id) =>
+            {
+                session.state = State::Idle;
+                session.since = Instant::now();
+                session.txn = None;
+                session.aborted = Some(txn_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Looks up a registered session
+    fn session(inner: &mut Inner, id: u64) -> Result<&mut Session> {
+        inner
+            .sessions
+            .get_mut(&id)
+            .ok_or_else(|| Error::Internal(format!("Session {} is not registered", id)))
+    }
+}
+
+impl SystemTable for Activity {
+    fn schema(&self) -> Table {
+        Table {
+            name: ACTIVITY_TABLE.to_string(),
+            columns: vec![
+                column("id", DataType::Integer, true, false),
+                column("client", DataType::String, false, false),
+                column("state", DataType::String, false, false),
+                column("statement", DataType::String, false, true),
+                column("elapsed_ms", DataType::Integer, false, false),
+            ],
+        }
+    }
+
+    fn rows(&self) -> Result<Vec<Row>> {
+        Ok(self
+            .inner
+            .lock()?
+            .sessions
+            .iter()
+            .map(|(id, session)| {
+                vec![
+                    Value::Integer(*id as i64),
+                    Value::String(session.client.clone()),
+                    Value::String(session.state.name().to_string()),
+                    session.statement.clone().map(Value::String).unwrap_or(Value::Null),
+                    Value::Integer(session.since.elapsed().as_millis() as i64),
+                ]
+            })
+            .collect())
+    }
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::types::Expression;
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn states(activity: &Activity) -> Result<Vec<(Value, Value, Value)>> {
+        activity
+            .scan(None)?
+            .map(|r| r.map(|row| (row[0].clone(), row[2].clone(), row[3].clone())))
+            .collect()
+    }
+
+    #[test]
+    fn sessions() -> Result<()> {
+        let activity = Activity::new();
+        let a = activity.register("127.0.0.1:1")?;
+        let b = activity.register("127.0.0.1:2")?;
+        activity.start(a, "BEGIN")?;
+        activity.finish(a, Some(7))?;
+        activity.start(b, "SELECT 1")?;
+
+        let string = |s: &str| Value::String(s.to_string());
+        assert_eq!(
+            states(&activity)?,
+            vec![
+                (Value::Integer(1), string("idle in transaction"), string("BEGIN")),
+                (Value::Integer(2), string("active"), string("SELECT 1")),
+            ]
+        );
+
+        activity.finish(b, None)?;
+        activity.deregister(a)?;
+        assert_eq!(
+            states(&activity)?,
+            vec![(Value::Integer(2), string("idle"), string("SELECT 1"))]
+        );
+        assert_eq!(activity.read(&Value::Integer(1))?, None);
+        assert_eq!(activity.stats()?.rows, 1);
+        assert!(activity.start(a, "SELECT 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn transactions() -> Result<()> {
+        let activity = Activity::new();
+        let a = activity.register("127.0.0.1:1")?;
+        let b = activity.register("127.0.0.1:2")?;
+        activity.start(a, "BEGIN")?;
+        activity.finish(a, Some(7))?;
+        activity.start(b, "SELECT 1")?;
+        activity.finish(b, None)?;
+        std::thread::sleep(Duration::from_millis(10));
+        activity.start(a, "SELECT 1")?;
+        activity.finish(a, Some(7))?;
+
+        // The transaction's age spans statements, and only open transactions are returned.
+        let open = activity.open_transactions(Duration::from_millis(10))?;
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].session, open[0].id), (a, 7));
+        assert_eq!(open[0].statement, Some("SELECT 1".into()));
+        assert!(open[0].idle.is_some());
+        assert_eq!(activity.open_transactions(Duration::from_secs(10))?, vec![]);
+
+        // Active sessions, or sessions in another transaction, aren't aborted.
+        activity.start(a, "SELECT 2")?;
+        assert!(!activity.abort(a, 7)?);
+        assert_eq!(activity.open_transactions(Duration::from_millis(0))?[0].idle, None);
+        activity.finish(a, Some(7))?;
+        assert!(!activity.abort(a, 8)?);
+
+        // An aborted transaction is no longer open, and reported to the session once.
+        assert!(activity.abort(a, 7)?);
+        assert!(!activity.abort(a, 7)?);
+        assert_eq!(activity.open_transactions(Duration::from_millis(0))?, vec![]);
+        assert_eq!(activity.start(a, "SELECT 3")?, Some(7));
+        activity.finish(a, None)?;
+        assert_eq!(activity.start(a, "SELECT 4")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn scan_filter() -> Result<()> {
+        let activity = Activity::new();
+        activity.register("127.0.0.1:1")?;
+        let b = activity.register("127.0.0.1:2")?;
+        activity.start(b, "SELECT 1")?;
+
+        let filter = Expression::Equal(
+            Box::new(Expression::Field(2, None)),
+            Box::new(Expression::Constant(Value::String("active".into()))),
+        );
+        let rows = activity.scan(Some(filter))?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], Value::Integer(2));
+
+        assert!(activity.scan(Some(Expression::Constant(Value::Integer(1)))).is_err());
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
-use super::super::schema::{Catalog, Table, Tables};
+use super::super::plan::PlanCache;
+use super::super::schema::{Catalog, Index, Table, TableStats, Tables};
 use super::super::types::{Expression, Row, Value};
 use super::Transaction as _;
 use crate::error::{Error, Result};
@@ -8,24 +9,27 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::clone::Clone;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 /// A SQL engine based on an underlying MVCC key/value store
 pub struct KV {
     /// The underlying key/value store
     pub(super) kv: kv::MVCC,
+    /// The plan cache, shared between clones
+    plan_cache: Arc<PlanCache>,
 }
 
 // FIXME Implement Clone manually due to https://github.com/rust-lang/rust/issues/26925
 impl Clone for KV {
     fn clone(&self) -> Self {
-        KV::new(self.kv.clone())
+        KV { kv: self.kv.clone(), plan_cache: self.plan_cache.clone() }
     }
 }
 
 impl KV {
     /// Creates a new key/value-based SQL engine
     pub fn new(kv: kv::MVCC) -> Self {
-        Self { kv }
+        Self { kv, plan_cache: Arc::new(PlanCache::new(super::PLAN_CACHE_CAPACITY)) }
     }
 
     /// Fetches an unversioned metadata value
@@ -49,6 +53,10 @@ impl super::Engine for KV {
     fn resume(&self, id: u64) -> Result<Self::Transaction> {
         Ok(Self::Transaction::new(self.kv.resume(id)?))
     }
+
+    fn plan_cache(&self) -> &PlanCache {
+        &self.plan_cache
+    }
 }
 
 /// Serializes SQL metadata.
@@ -97,6 +105,72 @@ impl Transaction {
             self.txn.set(&key, serialize(&index)?)
         }
     }
+
+    /// Bumps a table's version after a schema or statistics change. The version is set to the ID
+    /// of the changing transaction, which is unique even if the transaction is later rolled back.
+    fn table_bump(&mut self, table: &str) -> Result<()> {
+        self.txn.set(&Key::TableVersion(table.into()).encode(), serialize(&self.txn.id())?)
+    }
+
+    /// Scans up to limit rows of a table in primary key order, starting after the given primary
+    /// key if any. Returns the scanned rows that match the filter, and the primary key to resume
+    /// the scan after, or None if all rows were scanned.
+    pub fn scan_batch(
+        &self,
+        table: &str,
+        filter: Option<Expression>,
+        after: Option<&Value>,
+        limit: usize,
+    ) -> Result<(Vec<Row>, Option<Value>)> {
+        let table = self.must_read_table(table)?;
+        let prefix = Key::Row((&table.name).into(), None).encode();
+        let scan = match after {
+            Some(pk) => self.txn.scan_prefix_after(
+                &prefix,
+                &Key::Row((&table.name).into(), Some(pk.into())).encode(),
+            )?,
+            None => self.txn.scan_prefix(&prefix)?,
+        };
+        let rows = scan
+            .take(limit)
+            .map(|r| r.and_then(|(_, v)| deserialize(&v)))
+            .collect::<Result<Vec<Row>>>()?;
+        let next = match rows.last() {
+            Some(row) if rows.len() == limit => Some(table.get_row_key(row)?),
+            _ => None,
+        };
+        let rows = rows
+            .into_iter()
+            .filter_map(|row| Self::filter_row(Ok(row), &filter))
+            .collect::<Result<_>>()?;
+        Ok((rows, next))
+    }
+
+    /// Applies a scan filter to a row, returning None if the row is filtered out
+    fn filter_row(row: Result<Row>, filter: &Option<Expression>) -> Option<Result<Row>> {
+        match row {
+            Ok(row) => match filter {
+                Some(filter) => match filter.evaluate(Some(&row)) {
+                    Ok(Value::Boolean(b)) if b => Some(Ok(row)),
+                    Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
+                    Ok(v) => {
+                        Some(Err(Error::Value(format!("Filter returned {}, expected boolean", v))))
+                    }
+                    Err(err) => Some(Err(err)),
+                },
+                None => Some(Ok(row)),
+            },
+            err => Some(err),
+        }
+    }
+
+    /// Loads a named index definition
+    fn index_read(&self, name: &str) -> Result<Option<Index>> {
+        self.txn
+            .get(&Key::IndexSchema(Some(name.into())).encode())?
+            .map(|v| deserialize(&v))
+            .transpose()
+    }
 }
 
 impl super::Transaction for Transaction {
@@ -194,21 +268,7 @@ impl super::Transaction for Transaction {
             self.txn
                 .scan_prefix(&Key::Row((&table.name).into(), None).encode())?
                 .map(|r| r.and_then(|(_, v)| deserialize(&v)))
-                .filter_map(move |r| match r {
-                    Ok(row) => match &filter {
-                        Some(filter) => match filter.evaluate(Some(&row)) {
-                            Ok(Value::Boolean(b)) if b => Some(Ok(row)),
-                            Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
-                            Ok(v) => Some(Err(Error::Value(format!(
-                                "Filter returned {}, expected boolean",
-                                v
-                            )))),
-                            Err(err) => Some(Err(err)),
-                        },
-                        None => Some(Ok(row)),
-                    },
-                    err => Some(err),
-                }),
+                .filter_map(move |r| Self::filter_row(r, &filter)),
         ))
     }
 
@@ -272,6 +332,7 @@ impl Catalog for Transaction {
             return Err(Error::Value(format!("Table {} already exists", table.name)));
         }
         table.validate(self)?;
+        self.table_bump(&table.name)?;
         self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)
     }
 
@@ -287,6 +348,16 @@ impl Catalog for Transaction {
         while let Some(row) = scan.next().transpose()? {
             self.delete(&table.name, &table.get_row_key(&row)?)?
         }
+        let indexes = self
+            .txn
+            .scan_prefix(&Key::IndexSchema(None).encode())?
+            .map(|r| r.and_then(|(_, v)| deserialize::<Index>(&v)))
+            .collect::<Result<Vec<_>>>()?;
+        for index in indexes.into_iter().filter(|i| i.table == table.name) {
+            self.txn.delete(&Key::IndexSchema(Some(index.name.into())).encode())?;
+        }
+        self.txn.delete(&Key::TableStats((&table.name).into()).encode())?;
+        self.txn.delete(&Key::TableVersion((&table.name).into()).encode())?;
         self.txn.delete(&Key::Table(Some(table.name.into())).encode())
     }
 
@@ -303,6 +374,101 @@ impl Catalog for Transaction {
                 .into_iter(),
         ))
     }
+
+    fn create_index(&mut self, index: Index) -> Result<()> {
+        if self.index_read(&index.name)?.is_some() {
+            return Err(Error::Value(format!("Index {} already exists", index.name)));
+        }
+        let mut table = self.must_read_table(&index.table)?;
+        let i = table.get_column_index(&index.column)?;
+        if table.columns[i].primary_key || table.columns[i].index {
+            return Err(Error::Value(format!(
+                "Column {}.{} is already indexed",
+                table.name, table.columns[i].name
+            )));
+        }
+
+        // Build index entries for existing rows, checking uniqueness if requested
+        let rows = self.scan(&table.name, None)?.collect::<Result<Vec<_>>>()?;
+        for row in rows {
+            let id = table.get_row_key(&row)?;
+            let mut entry = self.index_load(&table.name, &index.column, &row[i])?;
+            if index.unique && row[i] != Value::Null && !entry.is_empty() {
+                return Err(Error::Value(format!(
+                    "Unique value {} already exists for column {}",
+                    row[i], index.column
+                )));
+            }
+            entry.insert(id);
+            self.index_save(&table.name, &index.column, &row[i], entry)?;
+        }
+
+        table.columns[i].index = true;
+        table.columns[i].unique |= index.unique;
+        self.table_bump(&table.name)?;
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)?;
+        self.txn.set(&Key::IndexSchema(Some((&index.name).into())).encode(), serialize(&index)?)
+    }
+
+    fn delete_index(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .index_read(name)?
+            .ok_or_else(|| Error::Value(format!("Index {} does not exist", name)))?;
+        let mut table = self.must_read_table(&index.table)?;
+        let i = table.get_column_index(&index.column)?;
+
+        let keys = self
+            .txn
+            .scan_prefix(&Key::Index((&table.name).into(), (&index.column).into(), None).encode())?
+            .map(|r| r.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            self.txn.delete(&key)?;
+        }
+
+        table.columns[i].index = false;
+        if index.unique && !table.columns[i].primary_key {
+            table.columns[i].unique = false;
+        }
+        self.table_bump(&table.name)?;
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)?;
+        self.txn.delete(&Key::IndexSchema(Some(index.name.into())).encode())
+    }
+
+    fn analyze_table(&mut self, table: &str) -> Result<TableStats> {
+        let table = self.must_read_table(table)?;
+        let mut stats = TableStats {
+            rows: self.txn.scan_prefix(&Key::Row((&table.name).into(), None).encode())?.count()
+                as u64,
+            ..Default::default()
+        };
+        for column in table.columns.iter().filter(|c| c.index) {
+            let distinct = self
+                .txn
+                .scan_prefix(
+                    &Key::Index((&table.name).into(), (&column.name).into(), None).encode(),
+                )?
+                .count();
+            stats.distinct.insert(column.name.clone(), distinct as u64);
+        }
+        self.table_bump(&table.name)?;
+        self.txn.set(&Key::TableStats((&table.name).into()).encode(), serialize(&stats)?)?;
+        Ok(stats)
+    }
+
+    fn read_table_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        self.txn
+            .get(&Key::TableStats(self.must_read_table(table)?.name.into()).encode())?
+            .map(|v| deserialize(&v))
+            .transpose()
+    }
+
+    fn table_version(&self, table: &str) -> Result<u64> {
+        self.txn
+            .get(&Key::TableVersion(table.into()).encode())?
+            .map(|v| deserialize(&v))
+            .unwrap_or(Ok(0))
+    }
 }
 
 /// Encodes SQL keys, using an order-preserving encoding - see kv::encoding for details. Options can
@@ -316,6 +482,12 @@ enum Key<'a> {
     Index(Cow<'a, str>, Cow<'a, str>, Option<Cow<'a, Value>>),
     /// A key for a row identified by table name and row primary key
     Row(Cow<'a, str>, Option<Cow<'a, Value>>),
+    /// A named index definition key for the given index name
+    IndexSchema(Option<Cow<'a, str>>),
+    /// A table statistics key for the given table name
+    TableStats(Cow<'a, str>),
+    /// A table version key for the given table name
+    TableVersion(Cow<'a, str>),
 }
 
 impl<'a> Key<'a> {
@@ -339,6 +511,10 @@ impl<'a> Key<'a> {
             Self::Row(table, Some(pk)) => {
                 [&[0x03][..], &encode_string(&table), &encode_value(&pk)].concat()
             }
+            Self::IndexSchema(None) => vec![0x04],
+            Self::IndexSchema(Some(name)) => [&[0x04][..], &encode_string(&name)].concat(),
+            Self::TableStats(table) => [&[0x05][..], &encode_string(&table)].concat(),
+            Self::TableVersion(table) => [&[0x06][..], &encode_string(&table)].concat(),
         }
     }
 
@@ -354,6 +530,9 @@ impl<'a> Key<'a> {
                 Some(take_value(bytes)?.into()),
             ),
             0x03 => Self::Row(take_string(bytes)?.into(), Some(take_value(bytes)?.into())),
+            0x04 => Self::IndexSchema(Some(take_string(bytes)?.into())),
+            0x05 => Self::TableStats(take_string(bytes)?.into()),
+            0x06 => Self::TableVersion(take_string(bytes)?.into()),
             b => return Err(Error::Internal(format!("Unknown SQL key prefix {:x?}", b))),
         };
         if !bytes.is_empty() {
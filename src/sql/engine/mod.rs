@@ -1,33 +1,55 @@
 //! The SQL engine provides fundamental CRUD storage operations.
+mod activity;
 mod kv;
 pub mod raft;
+mod statements;
+mod system;
+pub use activity::{Activity, OpenTransaction, ACTIVITY_TABLE};
 pub use kv::KV;
-pub use raft::{Raft, Status};
+pub use raft::{Raft, ScatterGatherScan, Status};
+pub use statements::{Statements, STATEMENTS_TABLE};
+pub use system::SystemTable;
 
-use super::execution::ResultSet;
-use super::parser::{ast, Parser};
-use super::plan::Plan;
+use super::execution::{Cancel, ResultSet};
+use super::parser::{ast, normalize, Parser};
+use super::plan::{Plan, PlanCache, PreparedPlan};
 use super::schema::Catalog;
-use super::types::{Expression, Row, Value};
+use super::types::{Expression, Row, Rows, Value};
 use crate::error::{Error, Result};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+/// The maximum number of plans in an engine's plan cache
+const PLAN_CACHE_CAPACITY: usize = 256;
+
+/// The maximum number of prepared statements per session
+pub const MAX_PREPARED_STATEMENTS: usize = 1024;
 
 /// The SQL engine interface
 pub trait Engine: Clone {
     /// The transaction type
-    type Transaction: Transaction;
+    type Transaction: Transaction + Send + 'static;
 
     /// Begins a transaction in the given mode
     fn begin(&self, mode: Mode) -> Result<Self::Transaction>;
 
     /// Begins a session for executing individual statements
     fn session(&self) -> Result<Session<Self>> {
-        Ok(Session { engine: self.clone(), txn: None })
+        Ok(Session {
+            engine: self.clone(),
+            txn: None,
+            aborted: None,
+            prepared: HashMap::new(),
+            next_handle: 1,
+            cancel: Cancel::never(),
+        })
     }
 
     /// Resumes an active transaction with the given ID
     fn resume(&self, id: u64) -> Result<Self::Transaction>;
+
+    /// Returns the plan cache, shared by all sessions of the engine
+    fn plan_cache(&self) -> &PlanCache;
 }
 
 /// An SQL transaction
@@ -63,15 +85,153 @@ pub struct Session<E: Engine> {
     engine: E,
     /// The current session transaction, if any
     txn: Option<E::Transaction>,
+    /// The ID of the session transaction if it was aborted, until the client ends it
+    aborted: Option<u64>,
+    /// Prepared statements by handle
+    prepared: HashMap<u64, Prepared>,
+    /// The handle of the next prepared statement
+    next_handle: u64,
+    /// The cancellation check of executed statements, see set_cancel()
+    cancel: Cancel,
+}
+
+/// A prepared statement
+struct Prepared {
+    /// The SQL text
+    query: String,
+    /// The parsed statement
+    statement: ast::Statement,
+    /// The statement's plan with parameter slots, if built, and the versions of the tables it
+    /// accesses as of building it
+    plan: Option<(PreparedPlan, Vec<(String, u64)>)>,
+}
+
+impl Prepared {
+    /// Builds an optimized plan for the statement with the given parameter values. The plan with
+    /// parameter slots is built on first execution, and reused as long as the versions of the
+    /// tables it accesses are unchanged. Statements that can't be planned with slots, e.g. due to
+    /// LIMIT ?, are planned with their values on every execution.
+    fn plan<T: Transaction>(
+        &mut self,
+        statement: ast::Statement,
+        params: Vec<Value>,
+        txn: &mut T,
+    ) -> Result<Plan> {
+        if let Some((plan, tables)) = &self.plan {
+            let mut current = true;
+            for (table, version) in tables {
+                if txn.table_version(table)? != *version {
+                    current = false;
+                    break;
+                }
+            }
+            if current {
+                return plan.bind(params, txn);
+            }
+            self.plan = None;
+        }
+        match PreparedPlan::build(statement.clone(), txn) {
+            Ok(plan) => {
+                let tables = plan
+                    .tables()
+                    .into_iter()
+                    .map(|table| txn.table_version(&table).map(|version| (table, version)))
+                    .collect::<Result<_>>()?;
+                let result = plan.bind(params, txn);
+                self.plan = Some((plan, tables));
+                result
+            }
+            Err(_) => Plan::build_with_params(statement, params, txn)?.optimize(txn),
+        }
+    }
 }
 
 impl<E: Engine + 'static> Session<E> {
     /// Executes a query, managing transaction status for the session
     pub fn execute(&mut self, query: &str) -> Result<ResultSet> {
+        self.execute_with_params(query, Vec::new())
+    }
+
+    /// Executes a query with values bound to its ? parameter placeholders, managing transaction
+    /// status for the session
+    pub fn execute_with_params(&mut self, query: &str, params: Vec<Value>) -> Result<ResultSet> {
+        let statement = Parser::new(query).parse()?;
+        self.execute_statement(query, statement, params, None)
+    }
+
+    /// Prepares a query for repeated execution by execute_prepared(), returning its handle.
+    /// Prepared statements live until they're deallocated or the session ends, and a session can
+    /// hold at most MAX_PREPARED_STATEMENTS of them.
+    pub fn prepare(&mut self, query: &str) -> Result<u64> {
+        if self.prepared.len() >= MAX_PREPARED_STATEMENTS {
+            return Err(Error::Value(format!(
+                "Too many prepared statements, at most {} are allowed per session",
+                MAX_PREPARED_STATEMENTS
+            )));
+        }
+        let statement = Parser::new(query).parse()?;
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.prepared.insert(handle, Prepared { query: query.to_string(), statement, plan: None });
+        Ok(handle)
+    }
+
+    /// Returns the SQL text of a prepared statement
+    pub fn prepared_query(&self, handle: u64) -> Result<&str> {
+        self.prepared
+            .get(&handle)
+            .map(|prepared| prepared.query.as_str())
+            .ok_or_else(|| Error::Value(format!("Prepared statement {} does not exist", handle)))
+    }
+
+    /// Deallocates a prepared statement, freeing its handle
+    pub fn deallocate(&mut self, handle: u64) -> Result<()> {
+        self.prepared
+            .remove(&handle)
+            .map(|_| ())
+            .ok_or_else(|| Error::Value(format!("Prepared statement {} does not exist", handle)))
+    }
+
+    /// Executes a prepared statement with values bound to its ? parameter placeholders, managing
+    /// transaction status for the session
+    pub fn execute_prepared(&mut self, handle: u64, params: Vec<Value>) -> Result<ResultSet> {
+        // The statement is taken out of the session while it executes, so it can cache its plan.
+        let mut prepared = self
+            .prepared
+            .remove(&handle)
+            .ok_or_else(|| Error::Value(format!("Prepared statement {} does not exist", handle)))?;
+        let query = prepared.query.clone();
+        let statement = prepared.statement.clone();
+        let result = self.execute_statement(&query, statement, params, Some(&mut prepared));
+        self.prepared.insert(handle, prepared);
+        result
+    }
+
+    /// Executes a parsed query with values bound to its ? parameter placeholders, planning it via
+    /// the given prepared statement if any
+    fn execute_statement(
+        &mut self,
+        query: &str,
+        statement: ast::Statement,
+        params: Vec<Value>,
+        prepared: Option<&mut Prepared>,
+    ) -> Result<ResultSet> {
         // FIXME We should match on self.txn as well, but get this error:
         // error[E0009]: cannot bind by-move and by-ref in the same pattern
         // ...which seems like an arbitrary compiler limitation
-        match Parser::new(query).parse()? {
+        if let Some(id) = self.aborted {
+            return match statement {
+                ast::Statement::Commit | ast::Statement::Rollback => {
+                    self.aborted = None;
+                    Ok(ResultSet::Rollback { id })
+                }
+                _ => Err(Error::Value(format!(
+                    "Transaction {} was aborted, statements are ignored until it is rolled back",
+                    id
+                ))),
+            };
+        }
+        match statement {
             ast::Statement::Begin { .. } if self.txn.is_some() => {
                 Err(Error::Value("Already in a transaction".into()))
             }
@@ -124,21 +284,36 @@ impl<E: Engine + 'static> Session<E> {
                 Ok(ResultSet::Rollback { id })
             }
             ast::Statement::Explain(statement) => self.with_txn(Mode::ReadOnly, |txn| {
-                Ok(ResultSet::Explain(Plan::build(*statement, txn)?.optimize(txn)?.0))
+                Ok(ResultSet::Explain(
+                    Plan::build_with_params(*statement, params, txn)?.optimize(txn)?.0,
+                ))
             }),
-            statement if self.txn.is_some() => Plan::build(statement, self.txn.as_mut().unwrap())?
-                .optimize(self.txn.as_mut().unwrap())?
-                .execute(self.txn.as_mut().unwrap()),
+            statement if self.txn.is_some() => {
+                let txn = self.txn.as_mut().unwrap();
+                Self::plan(&self.engine, query, statement, params, prepared, txn)?
+                    .execute_with_cancel(txn, &self.cancel)
+            }
             statement @ ast::Statement::Select { .. } => {
                 let mut txn = self.engine.begin(Mode::ReadOnly)?;
-                let result =
-                    Plan::build(statement, &mut txn)?.optimize(&mut txn)?.execute(&mut txn);
-                txn.rollback()?;
-                result
+                let result = Self::plan(&self.engine, query, statement, params, prepared, &mut txn)
+                    .and_then(|plan| plan.execute_with_cancel(&mut txn, &self.cancel));
+                match result {
+                    // The rows may still be read from the transaction, e.g. by a batched scan.
+                    Ok(ResultSet::Query { columns, rows }) => Ok(ResultSet::Query {
+                        columns,
+                        rows: Box::new(TransactionRows { rows, txn: Some(txn) }),
+                    }),
+                    result => {
+                        txn.rollback()?;
+                        result
+                    }
+                }
             }
             statement => {
                 let mut txn = self.engine.begin(Mode::ReadWrite)?;
-                match Plan::build(statement, &mut txn)?.optimize(&mut txn)?.execute(&mut txn) {
+                match Self::plan(&self.engine, query, statement, params, prepared, &mut txn)
+                    .and_then(|plan| plan.execute_with_cancel(&mut txn, &self.cancel))
+                {
                     Ok(result) => {
                         txn.commit()?;
                         Ok(result)
@@ -152,6 +327,83 @@ impl<E: Engine + 'static> Session<E> {
         }
     }
 
+    /// Sets the cancellation check of the statements executed from now on. A cancelled statement
+    /// stops reading rows and fails with Error::Abort, or if its rows are being returned, they end
+    /// with one.
+    pub fn set_cancel(&mut self, cancel: Cancel) {
+        self.cancel = cancel;
+    }
+
+    /// Returns the ID of the session's active explicit transaction, if any
+    pub fn transaction_id(&self) -> Option<u64> {
+        self.txn.as_ref().map(|txn| txn.id())
+    }
+
+    /// Abandons the session's explicit transaction, if any, after it was rolled back elsewhere,
+    /// e.g. for idling too long. Until the client ends the transaction with COMMIT or ROLLBACK,
+    /// which both roll it back, other statements error.
+    pub fn abort_transaction(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            self.aborted = Some(txn.id());
+        }
+    }
+
+    /// Validates a query without executing it, by parsing and planning it the way EXPLAIN does.
+    /// Transaction control statements are only parsed, and the session transaction is left as
+    /// is. Parameter placeholders can't be planned without values, so they fail validation.
+    pub fn validate(&mut self, query: &str) -> Result<()> {
+        let statement = match Parser::new(query).parse()? {
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+                return Ok(())
+            }
+            ast::Statement::Explain(statement) => *statement,
+            statement => statement,
+        };
+        self.with_txn(Mode::ReadOnly, |txn| Plan::build(statement, txn)?.optimize(txn).map(|_| ()))
+    }
+
+    /// Builds an optimized plan for a statement. DML and query plans of prepared statements are
+    /// built with parameter slots and kept in the prepared statement, see Prepared::plan(). Other
+    /// DML and query plans without parameters are cached in the engine's plan cache by normalized
+    /// SQL text, and reused as long as the versions of the tables they access are unchanged.
+    /// Parameterized plans have the parameter values folded into them, and are not cached.
+    fn plan(
+        engine: &E,
+        query: &str,
+        statement: ast::Statement,
+        params: Vec<Value>,
+        prepared: Option<&mut Prepared>,
+        txn: &mut E::Transaction,
+    ) -> Result<Plan> {
+        let dml = matches!(
+            statement,
+            ast::Statement::Select { .. }
+                | ast::Statement::Insert { .. }
+                | ast::Statement::Update { .. }
+                | ast::Statement::Delete { .. }
+        );
+        match prepared {
+            Some(prepared) if dml => return prepared.plan(statement, params, txn),
+            _ if !dml || !params.is_empty() => {
+                return Plan::build_with_params(statement, params, txn)?.optimize(txn)
+            }
+            _ => {}
+        }
+        let cache = engine.plan_cache();
+        let sql = normalize(query)?;
+        if let Some(plan) = cache.get(&sql, |table| txn.table_version(table))? {
+            return Ok(plan);
+        }
+        let plan = Plan::build(statement, txn)?.optimize(txn)?;
+        let tables = plan
+            .tables()
+            .into_iter()
+            .map(|table| txn.table_version(&table).map(|version| (table, version)))
+            .collect::<Result<_>>()?;
+        cache.put(sql, tables, plan.clone())?;
+        Ok(plan)
+    }
+
     /// Runs a closure in the session's transaction, or a new transaction if none is active.
     pub fn with_txn<R, F>(&mut self, mode: Mode, f: F) -> Result<R>
     where
@@ -172,11 +424,37 @@ impl<E: Engine + 'static> Session<E> {
     }
 }
 
+/// Query rows read in a read-only transaction of their own, which is rolled back once the rows
+/// have all been read, or are dropped.
+struct TransactionRows<T: Transaction> {
+    rows: Rows,
+    txn: Option<T>,
+}
+
+impl<T: Transaction> Iterator for TransactionRows<T> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rows.next() {
+            None => self.txn.take().and_then(|txn| txn.rollback().err()).map(Err),
+            row => row,
+        }
+    }
+}
+
+impl<T: Transaction> Drop for TransactionRows<T> {
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            txn.rollback().ok();
+        }
+    }
+}
+
 /// The transaction mode
 pub type Mode = crate::storage::kv::mvcc::Mode;
 
 /// A row scan iterator
-pub type Scan = Box<dyn DoubleEndedIterator<Item = Result<Row>> + Send>;
+pub type Scan = Box<dyn Iterator<Item = Result<Row>> + Send>;
 
 /// An index scan iterator
 pub type IndexScan = Box<dyn DoubleEndedIterator<Item = Result<(Value, HashSet<Value>)>> + Send>;
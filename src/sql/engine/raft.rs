@@ -1,6 +1,10 @@
-use super::super::schema::{Catalog, Table, Tables};
+use super::super::plan::{Direction, PlanCache};
+use super::super::schema::{Catalog, Index, Table, TableStats, Tables};
 use super::super::types::{Expression, Row, Value};
-use super::{Engine as _, IndexScan, Mode, Scan, Transaction as _};
+use super::{
+    Activity, Engine as _, IndexScan, Mode, Scan, Statements, SystemTable, Transaction as _,
+    ACTIVITY_TABLE, STATEMENTS_TABLE,
+};
 use crate::error::{Error, Result};
 use crate::raft;
 use crate::storage::kv;
@@ -8,6 +12,10 @@ use crate::storage::kv;
 use serde::{Deserialize, Serialize};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The number of rows scanned per batch by a read-only table scan, see BatchScan
+const SCAN_BATCH_SIZE: u64 = 1000;
 
 /// A Raft state machine mutation
 #[derive(Clone, Serialize, Deserialize)]
@@ -30,6 +38,12 @@ enum Mutation {
     CreateTable { txn_id: u64, schema: Table },
     /// Deletes a table
     DeleteTable { txn_id: u64, table: String },
+    /// Creates an index
+    CreateIndex { txn_id: u64, index: Index },
+    /// Deletes an index
+    DeleteIndex { txn_id: u64, name: String },
+    /// Computes and stores a table's statistics
+    AnalyzeTable { txn_id: u64, table: String },
 }
 
 /// A Raft state machine query
@@ -46,6 +60,14 @@ enum Query {
     ReadIndex { txn_id: u64, table: String, column: String, value: Value },
     /// Scans a table's rows
     Scan { txn_id: u64, table: String, filter: Option<Expression> },
+    /// Scans up to limit rows of a table, after the given primary key if any
+    ScanBatch {
+        txn_id: u64,
+        table: String,
+        filter: Option<Expression>,
+        after: Option<Value>,
+        limit: u64,
+    },
     /// Scans an index
     ScanIndex { txn_id: u64, table: String, column: String },
 
@@ -53,6 +75,10 @@ enum Query {
     ScanTables { txn_id: u64 },
     /// Reads a table
     ReadTable { txn_id: u64, table: String },
+    /// Reads a table's statistics
+    ReadTableStats { txn_id: u64, table: String },
+    /// Reads a table's version
+    TableVersion { txn_id: u64, table: String },
 }
 
 /// Status for the Raft SQL engine.
@@ -66,12 +92,30 @@ pub struct Status {
 #[derive(Clone)]
 pub struct Raft {
     client: raft::Client,
+    plan_cache: Arc<PlanCache>,
+    system: SystemTables,
 }
 
 impl Raft {
     /// Creates a new Raft SQL engine.
     pub fn new(client: raft::Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            plan_cache: Arc::new(PlanCache::new(super::PLAN_CACHE_CAPACITY)),
+            system: SystemTables::new(),
+        }
+    }
+
+    /// Returns the activity registry of the engine's sessions, served as the toydb_activity
+    /// table.
+    pub fn activity(&self) -> &Activity {
+        &self.system.activity
+    }
+
+    /// Returns the statement statistics of the engine's sessions, served as the
+    /// toydb_stat_statements table.
+    pub fn statements(&self) -> &Arc<Statements> {
+        &self.system.statements
     }
 
     /// Creates an underlying state machine for a Raft engine.
@@ -89,6 +133,16 @@ impl Raft {
         })
     }
 
+    /// Transfers Raft leadership to the given node.
+    pub fn transfer_leadership(&self, target: &str) -> Result<()> {
+        futures::executor::block_on(self.client.transfer_leadership(target))
+    }
+
+    /// Adds or removes a node from the Raft cluster.
+    pub fn change_config(&self, config: raft::ConfigChange) -> Result<()> {
+        futures::executor::block_on(self.client.change_config(config))
+    }
+
     /// Serializes a command for the Raft SQL state machine.
     fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>> {
         Ok(bincode::serialize(value)?)
@@ -104,11 +158,47 @@ impl super::Engine for Raft {
     type Transaction = Transaction;
 
     fn begin(&self, mode: Mode) -> Result<Self::Transaction> {
-        Transaction::begin(self.client.clone(), mode)
+        Transaction::begin(self.client.clone(), self.system.clone(), mode)
     }
 
     fn resume(&self, id: u64) -> Result<Self::Transaction> {
-        Transaction::resume(self.client.clone(), id)
+        Transaction::resume(self.client.clone(), self.system.clone(), id)
+    }
+
+    fn plan_cache(&self) -> &PlanCache {
+        &self.plan_cache
+    }
+}
+
+/// The system tables of a Raft SQL engine. They're computed from the local node's state, and
+/// can't be written to or replaced by a stored table.
+#[derive(Clone)]
+struct SystemTables {
+    activity: Arc<Activity>,
+    statements: Arc<Statements>,
+}
+
+impl SystemTables {
+    /// Creates new, empty system tables
+    fn new() -> Self {
+        Self { activity: Arc::new(Activity::new()), statements: Arc::new(Statements::new()) }
+    }
+
+    /// Looks up a system table by name
+    fn get(&self, table: &str) -> Option<&dyn SystemTable> {
+        match table {
+            ACTIVITY_TABLE => Some(&*self.activity),
+            STATEMENTS_TABLE => Some(&*self.statements),
+            _ => None,
+        }
+    }
+
+    /// Errors if the given table is a system table, which can't be written to
+    fn check_writable(&self, table: &str) -> Result<()> {
+        match self.get(table) {
+            Some(_) => Err(Error::Value(format!("Table {} is read-only", table))),
+            None => Ok(()),
+        }
     }
 }
 
@@ -121,23 +211,25 @@ pub struct Transaction {
     id: u64,
     /// The transaction mode
     mode: Mode,
+    /// The engine's system tables, served alongside the stored tables
+    system: SystemTables,
 }
 
 impl Transaction {
     /// Starts a transaction in the given mode
-    fn begin(client: raft::Client, mode: Mode) -> Result<Self> {
+    fn begin(client: raft::Client, system: SystemTables, mode: Mode) -> Result<Self> {
         let id = Raft::deserialize(&futures::executor::block_on(
             client.mutate(Raft::serialize(&Mutation::Begin(mode))?),
         )?)?;
-        Ok(Self { client, id, mode })
+        Ok(Self { client, id, mode, system })
     }
 
     /// Resumes an active transaction
-    fn resume(client: raft::Client, id: u64) -> Result<Self> {
+    fn resume(client: raft::Client, system: SystemTables, id: u64) -> Result<Self> {
         let (id, mode) = Raft::deserialize(&futures::executor::block_on(
             client.query(Raft::serialize(&Query::Resume(id))?),
         )?)?;
-        Ok(Self { client, id, mode })
+        Ok(Self { client, id, mode, system })
     }
 
     /// Executes a mutation
@@ -169,6 +261,7 @@ impl super::Transaction for Transaction {
     }
 
     fn create(&mut self, table: &str, row: Row) -> Result<()> {
+        self.system.check_writable(table)?;
         Raft::deserialize(&self.mutate(Mutation::Create {
             txn_id: self.id,
             table: table.to_string(),
@@ -177,6 +270,7 @@ impl super::Transaction for Transaction {
     }
 
     fn delete(&mut self, table: &str, id: &Value) -> Result<()> {
+        self.system.check_writable(table)?;
         Raft::deserialize(&self.mutate(Mutation::Delete {
             txn_id: self.id,
             table: table.to_string(),
@@ -185,6 +279,9 @@ impl super::Transaction for Transaction {
     }
 
     fn read(&self, table: &str, id: &Value) -> Result<Option<Row>> {
+        if let Some(system) = self.system.get(table) {
+            return system.read(id);
+        }
         Raft::deserialize(&self.query(Query::Read {
             txn_id: self.id,
             table: table.to_string(),
@@ -202,15 +299,23 @@ impl super::Transaction for Transaction {
     }
 
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Scan> {
-        Ok(Box::new(
-            Raft::deserialize::<Vec<_>>(&self.query(Query::Scan {
-                txn_id: self.id,
-                table: table.to_string(),
-                filter,
-            })?)?
-            .into_iter()
-            .map(Ok),
-        ))
+        if let Some(system) = self.system.get(table) {
+            return system.scan(filter);
+        }
+        // Read-write transactions fetch all rows up front, since the statement may change the
+        // rows and shouldn't see its own changes in later batches.
+        if self.mode.mutable() {
+            return Ok(Box::new(
+                Raft::deserialize::<Vec<_>>(&self.query(Query::Scan {
+                    txn_id: self.id,
+                    table: table.to_string(),
+                    filter,
+                })?)?
+                .into_iter()
+                .map(Ok),
+            ));
+        }
+        Ok(Box::new(BatchScan::new(self.client.clone(), self.id, table.to_string(), filter)))
     }
 
     fn scan_index(&self, table: &str, column: &str) -> Result<IndexScan> {
@@ -226,6 +331,7 @@ impl super::Transaction for Transaction {
     }
 
     fn update(&mut self, table: &str, id: &Value, row: Row) -> Result<()> {
+        self.system.check_writable(table)?;
         Raft::deserialize(&self.mutate(Mutation::Update {
             txn_id: self.id,
             table: table.to_string(),
@@ -237,16 +343,59 @@ impl super::Transaction for Transaction {
 
 impl Catalog for Transaction {
     fn create_table(&mut self, table: Table) -> Result<()> {
+        if self.system.get(&table.name).is_some() {
+            return Err(Error::Value(format!("Table name {} is reserved", table.name)));
+        }
         Raft::deserialize(&self.mutate(Mutation::CreateTable { txn_id: self.id, schema: table })?)
     }
 
     fn delete_table(&mut self, table: &str) -> Result<()> {
+        self.system.check_writable(table)?;
         Raft::deserialize(
             &self.mutate(Mutation::DeleteTable { txn_id: self.id, table: table.to_string() })?,
         )
     }
 
+    fn create_index(&mut self, index: Index) -> Result<()> {
+        self.system.check_writable(&index.table)?;
+        Raft::deserialize(&self.mutate(Mutation::CreateIndex { txn_id: self.id, index })?)
+    }
+
+    fn delete_index(&mut self, index: &str) -> Result<()> {
+        Raft::deserialize(
+            &self.mutate(Mutation::DeleteIndex { txn_id: self.id, name: index.to_string() })?,
+        )
+    }
+
+    fn analyze_table(&mut self, table: &str) -> Result<TableStats> {
+        self.system.check_writable(table)?;
+        Raft::deserialize(
+            &self.mutate(Mutation::AnalyzeTable { txn_id: self.id, table: table.to_string() })?,
+        )
+    }
+
+    fn read_table_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        if let Some(system) = self.system.get(table) {
+            return system.stats().map(Some);
+        }
+        Raft::deserialize(
+            &self.query(Query::ReadTableStats { txn_id: self.id, table: table.to_string() })?,
+        )
+    }
+
+    fn table_version(&self, table: &str) -> Result<u64> {
+        if self.system.get(table).is_some() {
+            return Ok(0);
+        }
+        Raft::deserialize(
+            &self.query(Query::TableVersion { txn_id: self.id, table: table.to_string() })?,
+        )
+    }
+
     fn read_table(&self, table: &str) -> Result<Option<Table>> {
+        if let Some(system) = self.system.get(table) {
+            return Ok(Some(system.schema()));
+        }
         Raft::deserialize(
             &self.query(Query::ReadTable { txn_id: self.id, table: table.to_string() })?,
         )
@@ -260,6 +409,164 @@ impl Catalog for Transaction {
     }
 }
 
+/// A table scan in a read-only transaction, which fetches rows in batches as it's iterated, so
+/// that a scan that's dropped part-way, e.g. because its statement was cancelled, doesn't read the
+/// rest of the table.
+struct BatchScan {
+    client: raft::Client,
+    txn_id: u64,
+    table: String,
+    filter: Option<Expression>,
+    /// The remaining rows of the current batch
+    rows: std::vec::IntoIter<Row>,
+    /// The primary key to fetch the next batch after, if any
+    after: Option<Value>,
+    /// Whether the last batch has been fetched
+    done: bool,
+}
+
+impl BatchScan {
+    fn new(client: raft::Client, txn_id: u64, table: String, filter: Option<Expression>) -> Self {
+        Self {
+            client,
+            txn_id,
+            table,
+            filter,
+            rows: Vec::new().into_iter(),
+            after: None,
+            done: false,
+        }
+    }
+
+    /// Fetches the next batch of rows
+    fn fetch(&mut self) -> Result<()> {
+        let query = Query::ScanBatch {
+            txn_id: self.txn_id,
+            table: self.table.clone(),
+            filter: self.filter.clone(),
+            after: self.after.take(),
+            limit: SCAN_BATCH_SIZE,
+        };
+        let (rows, after): (Vec<Row>, Option<Value>) = Raft::deserialize(
+            &futures::executor::block_on(self.client.query(Raft::serialize(&query)?))?,
+        )?;
+        self.rows = rows.into_iter();
+        self.done = after.is_none();
+        self.after = after;
+        Ok(())
+    }
+}
+
+impl Iterator for BatchScan {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Some(Ok(row));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.fetch() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// A table scan across shards, i.e. Raft groups that each hold part of the table's rows. The
+/// scan is sent to every shard concurrently, and the shards' rows are merged into one result.
+pub struct ScatterGatherScan {
+    /// A transaction on each shard
+    shards: Vec<Transaction>,
+}
+
+impl ScatterGatherScan {
+    /// Creates a new scatter-gather scan over the given shard transactions
+    pub fn new(shards: Vec<Transaction>) -> Self {
+        Self { shards }
+    }
+
+    /// Scans a table's rows on all shards. Without an order, the rows are returned shard by
+    /// shard. With an order, each shard's rows are ordered and then k-way merged, with ties
+    /// going to the lowest shard. Fails if any shard fails, rather than return partial results.
+    pub fn scan(
+        &self,
+        table: &str,
+        filter: Option<Expression>,
+        order: &[(Expression, Direction)],
+    ) -> Result<Scan> {
+        let mut queries = Vec::new();
+        for shard in &self.shards {
+            let query =
+                Query::Scan { txn_id: shard.id, table: table.to_string(), filter: filter.clone() };
+            queries.push(shard.client.query(Raft::serialize(&query)?));
+        }
+        let mut shard_rows = Vec::new();
+        for (shard, response) in
+            futures::executor::block_on(futures::future::join_all(queries)).into_iter().enumerate()
+        {
+            let rows: Vec<Row> = Raft::deserialize(&response.map_err(|err| {
+                Error::Internal(format!(
+                    "Scan of table {} failed on shard {}: {}",
+                    table, shard, err
+                ))
+            })?)?;
+            shard_rows.push(rows);
+        }
+        if order.is_empty() {
+            return Ok(Box::new(shard_rows.into_iter().flatten().map(Ok)));
+        }
+
+        // As in the ORDER BY executor, sort values are evaluated up front since comparisons
+        // can't fail, and values that can't be compared are considered equal.
+        let compare = |a: &[Value], b: &[Value]| {
+            for ((value_a, value_b), (_, direction)) in a.iter().zip(b).zip(order) {
+                match value_a.partial_cmp(value_b) {
+                    Some(std::cmp::Ordering::Equal) | None => {}
+                    Some(o) if *direction == Direction::Ascending => return o,
+                    Some(o) => return o.reverse(),
+                }
+            }
+            std::cmp::Ordering::Equal
+        };
+        let mut streams = Vec::new();
+        for rows in shard_rows {
+            let mut items = Vec::new();
+            for row in rows {
+                let values = order
+                    .iter()
+                    .map(|(expr, _)| expr.evaluate(Some(&row)))
+                    .collect::<Result<Vec<_>>>()?;
+                items.push((values, row));
+            }
+            items.sort_by(|a, b| compare(&a.0, &b.0));
+            streams.push(items.into_iter().peekable());
+        }
+
+        let mut rows = Vec::new();
+        loop {
+            let heads: Vec<_> =
+                streams.iter_mut().map(|s| s.peek().map(|(values, _)| values)).collect();
+            let mut next: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                if let Some(values) = *head {
+                    if next.is_none_or(|n| compare(values, heads[n].unwrap()).is_lt()) {
+                        next = Some(i);
+                    }
+                }
+            }
+            match next {
+                Some(n) => rows.push(streams[n].next().unwrap().1),
+                None => break,
+            }
+        }
+        Ok(Box::new(rows.into_iter().map(Ok)))
+    }
+}
+
 /// The Raft state machine for the Raft-based SQL engine, using a KV SQL engine
 pub struct State {
     /// The underlying KV SQL engine
@@ -302,6 +609,15 @@ impl State {
             Mutation::DeleteTable { txn_id, table } => {
                 Raft::serialize(&self.engine.resume(txn_id)?.delete_table(&table)?)
             }
+            Mutation::CreateIndex { txn_id, index } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.create_index(index)?)
+            }
+            Mutation::DeleteIndex { txn_id, name } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.delete_index(&name)?)
+            }
+            Mutation::AnalyzeTable { txn_id, table } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.analyze_table(&table)?)
+            }
         }
     }
 }
@@ -324,6 +640,17 @@ impl raft::State for State {
         }
     }
 
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        Raft::serialize(&self.engine.kv.dump()?)
+    }
+
+    fn restore(&mut self, snapshot: Vec<u8>) -> Result<()> {
+        self.engine.kv.load(Raft::deserialize(&snapshot)?)?;
+        // The applied index is restored from the snapshot's metadata.
+        *self = Self::new(self.engine.kv.clone())?;
+        Ok(())
+    }
+
     fn query(&self, command: Vec<u8>) -> Result<Vec<u8>> {
         match Raft::deserialize(&command)? {
             Query::Resume(id) => {
@@ -341,6 +668,14 @@ impl raft::State for State {
             Query::Scan { txn_id, table, filter } => Raft::serialize(
                 &self.engine.resume(txn_id)?.scan(&table, filter)?.collect::<Result<Vec<_>>>()?,
             ),
+            Query::ScanBatch { txn_id, table, filter, after, limit } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.scan_batch(
+                    &table,
+                    filter,
+                    after.as_ref(),
+                    limit as usize,
+                )?)
+            }
             Query::ScanIndex { txn_id, table, column } => Raft::serialize(
                 &self
                     .engine
@@ -353,9 +688,139 @@ impl raft::State for State {
             Query::ReadTable { txn_id, table } => {
                 Raft::serialize(&self.engine.resume(txn_id)?.read_table(&table)?)
             }
+            Query::ReadTableStats { txn_id, table } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.read_table_stats(&table)?)
+            }
+            Query::TableVersion { txn_id, table } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.table_version(&table)?)
+            }
             Query::ScanTables { txn_id } => {
                 Raft::serialize(&self.engine.resume(txn_id)?.scan_tables()?.collect::<Vec<_>>())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tokio::sync::{mpsc, oneshot};
+
+    /// Starts a fake shard which answers scans of table "t" with the given rows, or fails
+    /// them if None.
+    fn shard(rows: Option<Vec<Row>>) -> Transaction {
+        let (request_tx, mut request_rx) =
+            mpsc::unbounded_channel::<(raft::Request, oneshot::Sender<Result<raft::Response>>)>();
+        std::thread::spawn(move || {
+            while let Some((request, response_tx)) = futures::executor::block_on(request_rx.recv())
+            {
+                let response = match (request, &rows) {
+                    (raft::Request::Query(command), Some(rows)) => {
+                        match Raft::deserialize(&command) {
+                            Ok(Query::Scan { table, .. }) if table == "t" => {
+                                Raft::serialize(rows).map(raft::Response::State)
+                            }
+                            _ => Err(Error::Internal("Unexpected query".into())),
+                        }
+                    }
+                    _ => Err(Error::Internal("Shard unavailable".into())),
+                };
+                response_tx.send(response).ok();
+            }
+        });
+        Transaction {
+            client: raft::Client::new(request_tx),
+            id: 1,
+            mode: Mode::ReadOnly,
+            system: SystemTables::new(),
+        }
+    }
+
+    fn rows(ids: &[i64]) -> Vec<Row> {
+        ids.iter()
+            .map(|id| vec![Value::Integer(*id), Value::String(format!("row {}", id))])
+            .collect()
+    }
+
+    #[test]
+    fn scatter_gather_scan() -> Result<()> {
+        let scan = ScatterGatherScan::new(vec![
+            shard(Some(rows(&[1, 4, 5]))),
+            shard(Some(rows(&[6, 3, 2]))),
+        ]);
+
+        // Without an order, shards are returned one after the other.
+        let result = scan.scan("t", None, &[])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(result, rows(&[1, 4, 5, 6, 3, 2]));
+
+        // With an order, the shards are merged.
+        let field = Expression::Field(0, None);
+        let result = scan
+            .scan("t", None, &[(field.clone(), Direction::Ascending)])?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(result, rows(&[1, 2, 3, 4, 5, 6]));
+
+        let result =
+            scan.scan("t", None, &[(field, Direction::Descending)])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(result, rows(&[6, 5, 4, 3, 2, 1]));
+        Ok(())
+    }
+
+    #[test]
+    fn scatter_gather_scan_shard_failure() -> Result<()> {
+        let scan = ScatterGatherScan::new(vec![shard(Some(rows(&[1, 2]))), shard(None)]);
+        let order = [(Expression::Field(0, None), Direction::Ascending)];
+        assert!(scan.scan("t", None, &[]).is_err());
+        assert!(scan.scan("t", None, &order).is_err());
+        assert!(scan.scan("unknown", None, &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn state_scan_batch() -> Result<()> {
+        use raft::State as _;
+
+        let kv = kv::MVCC::new(Box::new(kv::Memory::new()));
+        let engine = super::super::KV::new(kv.clone());
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, value STRING)")?;
+        session.execute("INSERT INTO t VALUES (1, 'row 1'), (2, 'row 2'), (3, 'row 3')")?;
+        let txn_id = engine.begin(Mode::ReadOnly)?.id();
+        let state = State::new(kv)?;
+        let scan = |filter: Option<Expression>, after: Option<Value>| {
+            let query = Query::ScanBatch { txn_id, table: "t".into(), filter, after, limit: 2 };
+            Raft::deserialize::<(Vec<Row>, Option<Value>)>(&state.query(Raft::serialize(&query)?)?)
+        };
+
+        // Batches resume after the last scanned row, until all rows are scanned.
+        assert_eq!(scan(None, None)?, (rows(&[1, 2]), Some(Value::Integer(2))));
+        assert_eq!(scan(None, Some(Value::Integer(2)))?, (rows(&[3]), None));
+
+        // Rows are filtered after they're scanned, so a batch may return no rows.
+        let filter = Expression::Equal(
+            Box::new(Expression::Field(1, None)),
+            Box::new(Expression::Constant(Value::String("row 3".into()))),
+        );
+        assert_eq!(scan(Some(filter.clone()), None)?, (vec![], Some(Value::Integer(2))));
+        assert_eq!(scan(Some(filter), Some(Value::Integer(2)))?, (rows(&[3]), None));
+        Ok(())
+    }
+
+    #[test]
+    fn state_snapshot_restore() -> Result<()> {
+        use raft::State as _;
+
+        let mut state = State::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        state.mutate(1, Raft::serialize(&Mutation::Begin(Mode::ReadWrite))?)?;
+        state.mutate(3, Raft::serialize(&Mutation::Commit(1))?)?;
+        let snapshot = state.snapshot()?;
+
+        let mut restored = State::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        restored.mutate(1, Raft::serialize(&Mutation::Begin(Mode::ReadOnly))?)?;
+        restored.restore(snapshot.clone())?;
+        assert_eq!(3, restored.applied_index());
+        assert_eq!(snapshot, restored.snapshot()?);
+        Ok(())
+    }
+}
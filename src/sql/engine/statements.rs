@@ -0,0 +1,262 @@
+use super::super::execution::ResultSet;
+use super::super::parser::normalize;
+use super::super::schema::Table;
+use super::super::types::{DataType, Row, Rows, Value};
+use super::system::{column, SystemTable};
+use crate::error::Result;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The name of the system table exposing statement statistics.
+pub const STATEMENTS_TABLE: &str = "toydb_stat_statements";
+
+/// The maximum number of distinct statements to keep statistics for. Since literals are part of
+/// the normalized statement, the least called statements are evicted to make room for new ones.
+const STATEMENTS_CAPACITY: usize = 1024;
+
+/// Execution statistics for SQL statements, aggregated by normalized statement text and shared by
+/// all sessions of an engine.
+pub struct Statements {
+    /// Statistics by normalized statement
+    stats: Mutex<BTreeMap<String, Stats>>,
+    /// The maximum number of statements to keep statistics for
+    capacity: usize,
+}
+
+/// Statistics for a statement
+#[derive(Default)]
+struct Stats {
+    /// The number of times the statement was executed
+    calls: u64,
+    /// The total execution time
+    total: Duration,
+    /// The total number of rows returned or affected
+    rows: u64,
+}
+
+impl Statements {
+    /// Creates a new, empty statement statistics accumulator
+    pub fn new() -> Self {
+        Self::with_capacity(STATEMENTS_CAPACITY)
+    }
+
+    /// Creates a new statement statistics accumulator for at most the given number of statements
+    fn with_capacity(capacity: usize) -> Self {
+        Self { stats: Mutex::new(BTreeMap::new()), capacity }
+    }
+
+    /// Records an execution of a statement. Statements that fail to lex are recorded as is.
+    pub fn record(&self, statement: &str, duration: Duration, rows: u64) -> Result<()> {
+        let statement = normalize(statement).unwrap_or_else(|_| statement.to_string());
+        let mut stats = self.stats.lock()?;
+        if !stats.contains_key(&statement) && stats.len() >= self.capacity {
+            let evict = stats.iter().min_by_key(|(_, s)| s.calls).map(|(k, _)| k.clone());
+            if let Some(evict) = evict {
+                stats.remove(&evict);
+            }
+        }
+        let entry = stats.entry(statement).or_default();
+        entry.calls += 1;
+        entry.total += duration;
+        entry.rows += rows;
+        Ok(())
+    }
+
+    /// Records an execution of a statement started at the given time, given its result. Query
+    /// rows are produced lazily, so queries are recorded once their rows have been consumed.
+    pub fn track(
+        self: &Arc<Self>,
+        statement: &str,
+        start: Instant,
+        result: ResultSet,
+    ) -> ResultSet {
+        let rows = match result {
+            ResultSet::Create { count }
+            | ResultSet::Delete { count }
+            | ResultSet::Update { count } => count,
+            ResultSet::Query { columns, rows } => {
+                return ResultSet::Query {
+                    columns,
+                    rows: Box::new(TrackedRows {
+                        rows,
+                        statements: self.clone(),
+                        statement: Some(statement.to_string()),
+                        start,
+                        count: 0,
+                    }),
+                }
+            }
+            _ => 0,
+        };
+        self.record(statement, start.elapsed(), rows).ok();
+        result
+    }
+
+    /// Clears all statement statistics
+    pub fn reset(&self) -> Result<()> {
+        self.stats.lock()?.clear();
+        Ok(())
+    }
+}
+
+impl SystemTable for Statements {
+    fn schema(&self) -> Table {
+        Table {
+            name: STATEMENTS_TABLE.to_string(),
+            columns: vec![
+                column("statement", DataType::String, true, false),
+                column("calls", DataType::Integer, false, false),
+                column("total_ms", DataType::Float, false, false),
+                column("mean_ms", DataType::Float, false, false),
+                column("rows", DataType::Integer, false, false),
+            ],
+        }
+    }
+
+    fn rows(&self) -> Result<Vec<Row>> {
+        Ok(self
+            .stats
+            .lock()?
+            .iter()
+            .map(|(statement, stats)| {
+                let total_ms = stats.total.as_secs_f64() * 1000.0;
+                vec![
+                    Value::String(statement.clone()),
+                    Value::Integer(stats.calls as i64),
+                    Value::Float(total_ms),
+                    Value::Float(total_ms / stats.calls as f64),
+                    Value::Integer(stats.rows as i64),
+                ]
+            })
+            .collect())
+    }
+}
+
+impl Default for Statements {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A query's row iterator which records the statement once the rows are exhausted or dropped.
+struct TrackedRows {
+    rows: Rows,
+    statements: Arc<Statements>,
+    /// The statement, until recorded
+    statement: Option<String>,
+    start: Instant,
+    count: u64,
+}
+
+impl TrackedRows {
+    /// Records the statement, unless already recorded
+    fn record(&mut self) {
+        if let Some(statement) = self.statement.take() {
+            self.statements.record(&statement, self.start.elapsed(), self.count).ok();
+        }
+    }
+}
+
+impl Iterator for TrackedRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.rows.next();
+        match next {
+            Some(Ok(_)) => self.count += 1,
+            Some(Err(_)) => {}
+            None => self.record(),
+        }
+        next
+    }
+}
+
+impl Drop for TrackedRows {
+    fn drop(&mut self) {
+        self.record()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Returns the statement, calls and rows of each recorded statement
+    fn stats(statements: &Statements) -> Result<Vec<(String, i64, i64)>> {
+        Ok(statements
+            .rows()?
+            .into_iter()
+            .map(|row| match (&row[0], &row[1], &row[4]) {
+                (Value::String(s), Value::Integer(calls), Value::Integer(rows)) => {
+                    (s.clone(), *calls, *rows)
+                }
+                _ => panic!("Unexpected row {:?}", row),
+            })
+            .collect())
+    }
+
+    #[test]
+    fn record() -> Result<()> {
+        let statements = Statements::new();
+        statements.record("SELECT * FROM t", Duration::from_millis(4), 3)?;
+        statements.record("select  *\nfrom t", Duration::from_millis(2), 3)?;
+        statements.record("DELETE FROM t", Duration::from_millis(1), 2)?;
+        assert_eq!(
+            stats(&statements)?,
+            vec![("DELETE FROM t".into(), 1, 2), ("SELECT * FROM t".into(), 2, 6)]
+        );
+        assert_eq!(
+            statements
+                .read(&Value::String("SELECT * FROM t".into()))?
+                .map(|row| row[2..4].to_vec()),
+            Some(vec![Value::Float(6.0), Value::Float(3.0)])
+        );
+
+        statements.reset()?;
+        assert_eq!(stats(&statements)?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn track() -> Result<()> {
+        let statements = Arc::new(Statements::new());
+        let rows = vec![vec![Value::Integer(1)], vec![Value::Integer(2)]];
+        let result = statements.track(
+            "SELECT id FROM t",
+            Instant::now(),
+            ResultSet::Query { columns: Vec::new(), rows: Box::new(rows.into_iter().map(Ok)) },
+        );
+        statements.track("UPDATE t SET id = 0", Instant::now(), ResultSet::Update { count: 2 });
+
+        // Queries are recorded when their rows are consumed.
+        assert_eq!(stats(&statements)?, vec![("UPDATE t SET id = 0".into(), 1, 2)]);
+        match result {
+            ResultSet::Query { mut rows, .. } => {
+                assert!(rows.next().is_some());
+                assert!(rows.next().is_some());
+                assert!(rows.next().is_none());
+                assert_eq!(stats(&statements)?.len(), 2);
+            }
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert_eq!(
+            stats(&statements)?,
+            vec![("SELECT id FROM t".into(), 1, 2), ("UPDATE t SET id = 0".into(), 1, 2)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn capacity() -> Result<()> {
+        let statements = Statements::with_capacity(2);
+        statements.record("SELECT 1", Duration::from_millis(1), 1)?;
+        statements.record("SELECT 1", Duration::from_millis(1), 1)?;
+        statements.record("SELECT 2", Duration::from_millis(1), 1)?;
+        statements.record("SELECT 3", Duration::from_millis(1), 1)?;
+        assert_eq!(stats(&statements)?, vec![("SELECT 1".into(), 2, 2), ("SELECT 3".into(), 1, 1)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use super::super::schema::{Column, Table, TableStats};
+use super::super::types::{DataType, Expression, Row, Value};
+use super::Scan;
+use crate::error::{Error, Result};
+
+use std::collections::BTreeMap;
+
+/// A read-only system table, whose rows are computed on the fly from engine state rather than
+/// stored. The first column is the primary key.
+pub trait SystemTable: Send + Sync {
+    /// Returns the table schema
+    fn schema(&self) -> Table;
+
+    /// Computes the table rows, ordered by primary key
+    fn rows(&self) -> Result<Vec<Row>>;
+
+    /// Reads a row by primary key, if it exists
+    fn read(&self, id: &Value) -> Result<Option<Row>> {
+        Ok(self.rows()?.into_iter().find(|row| row.first() == Some(id)))
+    }
+
+    /// Scans the table's rows, keeping the rows the filter evaluates to true for
+    fn scan(&self, filter: Option<Expression>) -> Result<Scan> {
+        let mut rows = Vec::new();
+        for row in self.rows()? {
+            match &filter {
+                Some(filter) => match filter.evaluate(Some(&row))? {
+                    Value::Boolean(true) => rows.push(row),
+                    Value::Boolean(false) | Value::Null => {}
+                    v => {
+                        return Err(Error::Value(format!(
+                            "Filter returned {}, expected boolean",
+                            v
+                        )))
+                    }
+                },
+                None => rows.push(row),
+            }
+        }
+        Ok(Box::new(rows.into_iter().map(Ok)))
+    }
+
+    /// Returns the table statistics
+    fn stats(&self) -> Result<TableStats> {
+        Ok(TableStats { rows: self.rows()?.len() as u64, distinct: BTreeMap::new() })
+    }
+}
+
+/// Builds a system table column. The first column of a table is its primary key.
+pub(super) fn column(name: &str, datatype: DataType, primary_key: bool, nullable: bool) -> Column {
+    Column {
+        name: name.to_string(),
+        datatype,
+        primary_key,
+        nullable,
+        default: None,
+        unique: primary_key,
+        references: None,
+        index: false,
+    }
+}
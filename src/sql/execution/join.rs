@@ -1,17 +1,18 @@
 use super::super::engine::Transaction;
 use super::super::types::{Expression, Rows};
-use super::{Executor, ResultSet, Row, Value};
+use super::{Cancel, Executor, ResultSet, Row, Value};
 use crate::error::{Error, Result};
 
 use std::collections::HashMap;
 
 /// A nested loop join executor, which checks each row in the left source against every row in
-/// the right source using the given predicate.
+/// the right source using the given predicate. The join stops once the statement is cancelled.
 pub struct NestedLoopJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
     right: Box<dyn Executor<T>>,
     predicate: Option<Expression>,
     outer: bool,
+    cancel: Cancel,
 }
 
 impl<T: Transaction> NestedLoopJoin<T> {
@@ -20,8 +21,9 @@ impl<T: Transaction> NestedLoopJoin<T> {
         right: Box<dyn Executor<T>>,
         predicate: Option<Expression>,
         outer: bool,
+        cancel: Cancel,
     ) -> Box<Self> {
-        Box::new(Self { left, right, predicate, outer })
+        Box::new(Self { left, right, predicate, outer, cancel })
     }
 }
 
@@ -41,6 +43,7 @@ impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
                         right_width,
                         self.predicate,
                         self.outer,
+                        self.cancel,
                     )),
                     columns,
                 });
@@ -59,6 +62,7 @@ struct NestedLoopRows {
     right_hit: bool,
     predicate: Option<Expression>,
     outer: bool,
+    cancel: Cancel,
 }
 
 impl NestedLoopRows {
@@ -68,6 +72,7 @@ impl NestedLoopRows {
         right_width: usize,
         predicate: Option<Expression>,
         outer: bool,
+        cancel: Cancel,
     ) -> Self {
         Self {
             left_row: left.next(),
@@ -78,6 +83,7 @@ impl NestedLoopRows {
             right_hit: false,
             predicate,
             outer,
+            cancel,
         }
     }
 
@@ -91,7 +97,9 @@ impl NestedLoopRows {
                 return Ok(Some(row));
             }
 
-            // Otherwise, continue with the next left row and reset the right source.
+            // Otherwise, continue with the next left row and reset the right source. Every left
+            // row is joined with all of the right rows, so check for cancellation here too.
+            self.cancel.check()?;
             self.left_row = self.left.next();
             self.right = Box::new(self.right_vec.clone().into_iter());
 
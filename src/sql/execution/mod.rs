@@ -9,7 +9,7 @@ use aggregation::Aggregation;
 use join::{HashJoin, NestedLoopJoin};
 use mutation::{Delete, Insert, Update};
 use query::{Filter, Limit, Offset, Order, Projection};
-use schema::{CreateTable, DropTable};
+use schema::{Analyze, CreateIndex, CreateTable, DropIndex, DropTable};
 use source::{IndexLookup, KeyLookup, Nothing, Scan};
 
 use super::engine::{Mode, Transaction};
@@ -19,6 +19,8 @@ use crate::error::{Error, Result};
 
 use derivative::Derivative;
 use serde_derive::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::Arc;
 
 /// A plan executor
 pub trait Executor<T: Transaction> {
@@ -27,20 +29,26 @@ pub trait Executor<T: Transaction> {
 }
 
 impl<T: Transaction + 'static> dyn Executor<T> {
-    /// Builds an executor for a plan node, consuming it
-    pub fn build(node: Node) -> Box<dyn Executor<T>> {
+    /// Builds an executor for a plan node, consuming it. Executors that loop over rows check
+    /// the given cancellation as they go.
+    pub fn build(node: Node, cancel: &Cancel) -> Box<dyn Executor<T>> {
         match node {
             Node::Aggregation { source, aggregates } => {
-                Aggregation::new(Self::build(*source), aggregates)
+                Aggregation::new(Self::build(*source, cancel), aggregates)
             }
+            Node::Analyze { table } => Analyze::new(table),
+            Node::CreateIndex { index } => CreateIndex::new(index),
             Node::CreateTable { schema } => CreateTable::new(schema),
-            Node::Delete { table, source } => Delete::new(table, Self::build(*source)),
-            Node::DropTable { table } => DropTable::new(table),
-            Node::Filter { source, predicate } => Filter::new(Self::build(*source), predicate),
+            Node::Delete { table, source } => Delete::new(table, Self::build(*source, cancel)),
+            Node::DropIndex { name } => DropIndex::new(name),
+            Node::DropTable { table, if_exists } => DropTable::new(table, if_exists),
+            Node::Filter { source, predicate } => {
+                Filter::new(Self::build(*source, cancel), predicate)
+            }
             Node::HashJoin { left, left_field, right, right_field, outer } => HashJoin::new(
-                Self::build(*left),
+                Self::build(*left, cancel),
                 left_field.0,
-                Self::build(*right),
+                Self::build(*right, cancel),
                 right_field.0,
                 outer,
             ),
@@ -51,26 +59,84 @@ impl<T: Transaction + 'static> dyn Executor<T> {
                 Insert::new(table, columns, expressions)
             }
             Node::KeyLookup { table, alias: _, keys } => KeyLookup::new(table, keys),
-            Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
+            Node::Limit { source, limit } => Limit::new(Self::build(*source, cancel), limit),
             Node::NestedLoopJoin { left, left_size: _, right, predicate, outer } => {
-                NestedLoopJoin::new(Self::build(*left), Self::build(*right), predicate, outer)
+                NestedLoopJoin::new(
+                    Self::build(*left, cancel),
+                    Self::build(*right, cancel),
+                    predicate,
+                    outer,
+                    cancel.clone(),
+                )
             }
             Node::Nothing => Nothing::new(),
-            Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
-            Node::Order { source, orders } => Order::new(Self::build(*source), orders),
+            Node::Offset { source, offset } => Offset::new(Self::build(*source, cancel), offset),
+            Node::Order { source, orders } => Order::new(Self::build(*source, cancel), orders),
             Node::Projection { source, expressions } => {
-                Projection::new(Self::build(*source), expressions)
+                Projection::new(Self::build(*source, cancel), expressions)
             }
-            Node::Scan { table, filter, alias: _ } => Scan::new(table, filter),
+            Node::Scan { table, filter, alias: _ } => Scan::new(table, filter, cancel.clone()),
             Node::Update { table, source, expressions } => Update::new(
                 table,
-                Self::build(*source),
+                Self::build(*source, cancel),
                 expressions.into_iter().map(|(i, _, e)| (i, e)).collect(),
             ),
         }
     }
 }
 
+/// A cancellation check for a statement, made by executors as they loop over rows, so that a
+/// cancelled statement stops early even if it reads many rows before returning one. Clones share
+/// the check.
+#[derive(Clone)]
+pub struct Cancel(Arc<dyn Fn() -> bool + Send + Sync>);
+
+impl Cancel {
+    /// Creates a cancellation check, which cancels the statement once it returns true
+    pub fn new<F>(cancelled: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(cancelled))
+    }
+
+    /// Creates a cancellation check that never cancels
+    pub fn never() -> Self {
+        Self::new(|| false)
+    }
+
+    /// Errors with Error::Abort if the statement was cancelled
+    pub fn check(&self) -> Result<()> {
+        if (self.0)() {
+            return Err(Error::Abort);
+        }
+        Ok(())
+    }
+
+    /// Makes rows cancellable: the check is made before each row, and once the statement is
+    /// cancelled the rows end with an Error::Abort.
+    pub fn rows(&self, mut rows: Rows) -> Rows {
+        let cancel = self.clone();
+        let mut aborted = false;
+        Box::new(std::iter::from_fn(move || {
+            if aborted {
+                None
+            } else if let Err(err) = cancel.check() {
+                aborted = true;
+                Some(Err(err))
+            } else {
+                rows.next()
+            }
+        }))
+    }
+}
+
+impl Default for Cancel {
+    fn default() -> Self {
+        Self::never()
+    }
+}
+
 /// An executor result set
 #[derive(Derivative, Serialize, Deserialize)]
 #[derivative(Debug, PartialEq)]
@@ -108,6 +174,19 @@ pub enum ResultSet {
     DropTable {
         name: String,
     },
+    // Index created
+    CreateIndex {
+        name: String,
+    },
+    // Index dropped
+    DropIndex {
+        name: String,
+    },
+    // Table analyzed
+    Analyze {
+        name: String,
+        rows: u64,
+    },
     // Query result
     Query {
         columns: Columns,
@@ -139,4 +218,190 @@ impl ResultSet {
     pub fn into_value(self) -> Result<Value> {
         self.into_row()?.into_iter().next().ok_or_else(|| Error::Value("No value returned".into()))
     }
+
+    /// Makes a query result's rows cancellable, see Cancel::rows(). Other results are returned
+    /// as is.
+    pub fn cancellable(self, cancel: &Cancel) -> Self {
+        match self {
+            ResultSet::Query { columns, rows } => {
+                ResultSet::Query { columns, rows: cancel.rows(rows) }
+            }
+            resultset => resultset,
+        }
+    }
+
+    /// Writes a query result as CSV, with a header of column names (? if unnamed). Fields
+    /// containing commas, quotes or line breaks are quoted, and NULLs are written as empty
+    /// unquoted fields, so they can be told apart from empty strings, which are quoted.
+    /// Returns the number of rows written.
+    pub fn to_csv<W: Write>(self, writer: &mut W) -> Result<u64> {
+        let (columns, rows) = self.into_query()?;
+        let header: Vec<_> =
+            columns.iter().map(|c| csv_field(c.name.as_deref().unwrap_or("?"))).collect();
+        writeln!(writer, "{}", header.join(","))?;
+        let mut count = 0;
+        for row in rows {
+            let fields: Vec<_> = row?
+                .iter()
+                .map(|value| match value {
+                    Value::Null => String::new(),
+                    Value::String(s) if s.is_empty() => "\"\"".into(),
+                    value => csv_field(&value.to_string()),
+                })
+                .collect();
+            writeln!(writer, "{}", fields.join(","))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Writes a query result as a JSON array with an object per row, keyed by column name
+    /// (? if unnamed). Values keep their types, except non-finite floats, which JSON can't
+    /// represent and are written as null.
+    pub fn to_json<W: Write>(self, writer: &mut W) -> Result<()> {
+        let (columns, rows) = self.into_query()?;
+        let names: Vec<_> =
+            columns.iter().map(|c| json_string(c.name.as_deref().unwrap_or("?"))).collect();
+        write!(writer, "[")?;
+        for (i, row) in rows.enumerate() {
+            let fields: Vec<_> = names
+                .iter()
+                .zip(row?.iter())
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::Null => "null".into(),
+                        Value::Boolean(b) => b.to_string(),
+                        Value::Integer(i) => i.to_string(),
+                        Value::Float(f) if f.is_finite() => f.to_string(),
+                        Value::Float(_) => "null".into(),
+                        Value::String(s) => json_string(s),
+                        Value::Bytes(_) => json_string(&value.to_string()),
+                    };
+                    format!("{}:{}", name, value)
+                })
+                .collect();
+            write!(writer, "{}{{{}}}", if i > 0 { "," } else { "" }, fields.join(","))?;
+        }
+        writeln!(writer, "]")?;
+        Ok(())
+    }
+
+    /// Splits a query result into its columns and rows, or errors if not a query result.
+    fn into_query(self) -> Result<(Columns, Rows)> {
+        match self {
+            ResultSet::Query { columns, rows } => Ok((columns, rows)),
+            resultset => Err(Error::Value(format!("Not a query result: {:?}", resultset))),
+        }
+    }
+}
+
+/// Quotes a CSV field if needed, doubling any quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Formats a JSON string literal, escaping quotes, backslashes and control characters.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::types::Column;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::Ordering;
+
+    fn resultset() -> ResultSet {
+        ResultSet::Query {
+            columns: vec![
+                Column { name: Some("id".into()) },
+                Column { name: Some("name, \"quoted\"".into()) },
+                Column { name: None },
+            ],
+            rows: Box::new(
+                vec![
+                    vec![Value::Integer(1), Value::String("a, \"b\"\nc".into()), Value::Null],
+                    vec![Value::Integer(-2), Value::String("".into()), Value::Boolean(true)],
+                    vec![Value::Null, Value::String("\\\t".into()), Value::Float(1.5)],
+                    vec![Value::Integer(4), Value::Null, Value::Float(f64::NAN)],
+                ]
+                .into_iter()
+                .map(Ok),
+            ),
+        }
+    }
+
+    #[test]
+    fn cancellable() -> Result<()> {
+        let cancelled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = cancelled.clone();
+        let cancel = Cancel::new(move || flag.load(Ordering::SeqCst));
+        let mut rows = match resultset().cancellable(&cancel) {
+            ResultSet::Query { rows, .. } => rows,
+            r => panic!("Unexpected result {:?}", r),
+        };
+        assert_eq!(rows.next().transpose()?.map(|row| row[0].clone()), Some(Value::Integer(1)));
+        cancelled.store(true, Ordering::SeqCst);
+        assert_eq!(cancel.check(), Err(Error::Abort));
+        assert_eq!(rows.next().transpose(), Err(Error::Abort));
+        assert!(rows.next().is_none());
+
+        let result = ResultSet::Create { count: 1 }.cancellable(&cancel);
+        assert_eq!(result, ResultSet::Create { count: 1 });
+        assert_eq!(Cancel::never().check(), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn to_csv() -> Result<()> {
+        let mut csv = Vec::new();
+        assert_eq!(resultset().to_csv(&mut csv)?, 4);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,\"name, \"\"quoted\"\"\",?\n\
+             1,\"a, \"\"b\"\"\nc\",\n\
+             -2,\"\",TRUE\n\
+             ,\\\t,1.5\n\
+             4,,NaN\n"
+        );
+        assert!(ResultSet::Create { count: 1 }.to_csv(&mut Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn to_json() -> Result<()> {
+        let mut json = Vec::new();
+        resultset().to_json(&mut json)?;
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[{\"id\":1,\"name, \\\"quoted\\\"\":\"a, \\\"b\\\"\\nc\",\"?\":null},\
+             {\"id\":-2,\"name, \\\"quoted\\\"\":\"\",\"?\":true},\
+             {\"id\":null,\"name, \\\"quoted\\\"\":\"\\\\\\t\",\"?\":1.5},\
+             {\"id\":4,\"name, \\\"quoted\\\"\":null,\"?\":null}]\n"
+        );
+        let mut json = Vec::new();
+        ResultSet::Query { columns: Vec::new(), rows: ResultSet::empty_rows() }
+            .to_json(&mut json)?;
+        assert_eq!(json, b"[]\n");
+        Ok(())
+    }
 }
@@ -1,5 +1,5 @@
 use super::super::engine::Transaction;
-use super::super::schema::Table;
+use super::super::schema::{Index, Table};
 use super::{Executor, ResultSet};
 use crate::error::Result;
 
@@ -25,17 +25,76 @@ impl<T: Transaction> Executor<T> for CreateTable {
 /// A DROP TABLE executor
 pub struct DropTable {
     table: String,
+    if_exists: bool,
 }
 
 impl DropTable {
-    pub fn new(table: String) -> Box<Self> {
-        Box::new(Self { table })
+    pub fn new(table: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self { table, if_exists })
     }
 }
 
 impl<T: Transaction> Executor<T> for DropTable {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if self.if_exists && txn.read_table(&self.table)?.is_none() {
+            return Ok(ResultSet::DropTable { name: self.table });
+        }
         txn.delete_table(&self.table)?;
         Ok(ResultSet::DropTable { name: self.table })
     }
 }
+
+/// A CREATE INDEX executor
+pub struct CreateIndex {
+    index: Index,
+}
+
+impl CreateIndex {
+    pub fn new(index: Index) -> Box<Self> {
+        Box::new(Self { index })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let name = self.index.name.clone();
+        txn.create_index(self.index)?;
+        Ok(ResultSet::CreateIndex { name })
+    }
+}
+
+/// A DROP INDEX executor
+pub struct DropIndex {
+    name: String,
+}
+
+impl DropIndex {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(Self { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.delete_index(&self.name)?;
+        Ok(ResultSet::DropIndex { name: self.name })
+    }
+}
+
+/// An ANALYZE executor
+pub struct Analyze {
+    table: String,
+}
+
+impl Analyze {
+    pub fn new(table: String) -> Box<Self> {
+        Box::new(Self { table })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Analyze {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let stats = txn.analyze_table(&self.table)?;
+        Ok(ResultSet::Analyze { name: self.table, rows: stats.rows })
+    }
+}
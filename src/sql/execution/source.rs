@@ -1,19 +1,20 @@
 use super::super::engine::Transaction;
 use super::super::types::{Column, Expression, Row, Value};
-use super::{Executor, ResultSet};
+use super::{Cancel, Executor, ResultSet};
 use crate::error::Result;
 
 use std::collections::HashSet;
 
-/// A table scan executor
+/// A table scan executor. The scan stops once the statement is cancelled.
 pub struct Scan {
     table: String,
     filter: Option<Expression>,
+    cancel: Cancel,
 }
 
 impl Scan {
-    pub fn new(table: String, filter: Option<Expression>) -> Box<Self> {
-        Box::new(Self { table, filter })
+    pub fn new(table: String, filter: Option<Expression>, cancel: Cancel) -> Box<Self> {
+        Box::new(Self { table, filter, cancel })
     }
 }
 
@@ -22,7 +23,7 @@ impl<T: Transaction> Executor<T> for Scan {
         let table = txn.must_read_table(&self.table)?;
         Ok(ResultSet::Query {
             columns: table.columns.iter().map(|c| Column { name: Some(c.name.clone()) }).collect(),
-            rows: Box::new(txn.scan(&table.name, self.filter)?),
+            rows: self.cancel.rows(Box::new(txn.scan(&table.name, self.filter)?)),
         })
     }
 }
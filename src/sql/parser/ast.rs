@@ -20,7 +20,18 @@ pub enum Statement {
         name: String,
         columns: Vec<Column>,
     },
-    DropTable(String),
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    CreateIndex {
+        name: String,
+        table: String,
+        columns: Vec<String>,
+        unique: bool,
+    },
+    DropIndex(String),
+    Analyze(String),
 
     Delete {
         table: String,
@@ -101,6 +112,7 @@ pub enum Expression {
     Literal(Literal),
     Function(String, Vec<Expression>),
     Operation(Operation),
+    Parameter(usize), // a positional ? query parameter, numbered from 0
 }
 
 impl From<Literal> for Expression {
@@ -215,7 +227,7 @@ impl Expression {
                 }
             }
 
-            Self::Literal(_) | Self::Field(_, _) | Self::Column(_) => {}
+            Self::Literal(_) | Self::Field(_, _) | Self::Column(_) | Self::Parameter(_) => {}
         };
         after(self)
     }
@@ -265,7 +277,7 @@ impl Expression {
                     true
                 }
 
-                Self::Literal(_) | Self::Field(_, _) | Self::Column(_) => true,
+                Self::Literal(_) | Self::Field(_, _) | Self::Column(_) | Self::Parameter(_) => true,
             }
     }
 }
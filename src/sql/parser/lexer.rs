@@ -72,10 +72,12 @@ impl From<Keyword> for Token {
 /// Lexer keywords
 #[derive(Clone, Debug, PartialEq)]
 pub enum Keyword {
+    Analyze,
     And,
     As,
     Asc,
     Begin,
+    Blob,
     Bool,
     Boolean,
     By,
@@ -88,12 +90,14 @@ pub enum Keyword {
     Desc,
     Double,
     Drop,
+    Exists,
     Explain,
     False,
     Float,
     From,
     Group,
     Having,
+    If,
     Index,
     Infinity,
     Inner,
@@ -143,10 +147,12 @@ impl Keyword {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(ident: &str) -> Option<Self> {
         Some(match ident.to_uppercase().as_ref() {
+            "ANALYZE" => Self::Analyze,
             "AS" => Self::As,
             "ASC" => Self::Asc,
             "AND" => Self::And,
             "BEGIN" => Self::Begin,
+            "BLOB" => Self::Blob,
             "BOOL" => Self::Bool,
             "BOOLEAN" => Self::Boolean,
             "BY" => Self::By,
@@ -159,12 +165,14 @@ impl Keyword {
             "DESC" => Self::Desc,
             "DOUBLE" => Self::Double,
             "DROP" => Self::Drop,
+            "EXISTS" => Self::Exists,
             "EXPLAIN" => Self::Explain,
             "FALSE" => Self::False,
             "FLOAT" => Self::Float,
             "FROM" => Self::From,
             "GROUP" => Self::Group,
             "HAVING" => Self::Having,
+            "IF" => Self::If,
             "INDEX" => Self::Index,
             "INFINITY" => Self::Infinity,
             "INNER" => Self::Inner,
@@ -214,10 +222,12 @@ impl Keyword {
 
     pub fn to_str(&self) -> &str {
         match self {
+            Self::Analyze => "ANALYZE",
             Self::As => "AS",
             Self::Asc => "ASC",
             Self::And => "AND",
             Self::Begin => "BEGIN",
+            Self::Blob => "BLOB",
             Self::Bool => "BOOL",
             Self::Boolean => "BOOLEAN",
             Self::By => "BY",
@@ -230,12 +240,14 @@ impl Keyword {
             Self::Desc => "DESC",
             Self::Double => "DOUBLE",
             Self::Drop => "DROP",
+            Self::Exists => "EXISTS",
             Self::Explain => "EXPLAIN",
             Self::False => "FALSE",
             Self::Float => "FLOAT",
             Self::From => "FROM",
             Self::Group => "GROUP",
             Self::Having => "HAVING",
+            Self::If => "IF",
             Self::Index => "INDEX",
             Self::Infinity => "INFINITY",
             Self::Inner => "INNER",
@@ -12,12 +12,14 @@ use std::collections::BTreeMap;
 /// An SQL parser
 pub struct Parser<'a> {
     lexer: std::iter::Peekable<Lexer<'a>>,
+    /// The number of ? parameter placeholders parsed so far
+    params: usize,
 }
 
 impl<'a> Parser<'a> {
     /// Creates a new parser for the given string input
     pub fn new(query: &str) -> Parser {
-        Parser { lexer: Lexer::new(query).peekable() }
+        Parser { lexer: Lexer::new(query).peekable(), params: 0 }
     }
 
     /// Parses the input string into an AST statement
@@ -105,6 +107,7 @@ impl<'a> Parser<'a> {
 
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Drop)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Analyze)) => self.parse_ddl(),
 
             Some(Token::Keyword(Keyword::Delete)) => self.parse_statement_delete(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_statement_insert(),
@@ -123,12 +126,19 @@ impl<'a> Parser<'a> {
         match self.next()? {
             Token::Keyword(Keyword::Create) => match self.next()? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(),
+                Token::Keyword(Keyword::Index) => self.parse_ddl_create_index(false),
+                Token::Keyword(Keyword::Unique) => {
+                    self.next_expect(Some(Keyword::Index.into()))?;
+                    self.parse_ddl_create_index(true)
+                }
                 token => Err(Error::Parse(format!("Unexpected token {}", token))),
             },
             Token::Keyword(Keyword::Drop) => match self.next()? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_drop_table(),
+                Token::Keyword(Keyword::Index) => Ok(ast::Statement::DropIndex(self.next_ident()?)),
                 token => Err(Error::Parse(format!("Unexpected token {}", token))),
             },
+            Token::Keyword(Keyword::Analyze) => Ok(ast::Statement::Analyze(self.next_ident()?)),
             token => Err(Error::Parse(format!("Unexpected token {}", token))),
         }
     }
@@ -153,7 +163,31 @@ impl<'a> Parser<'a> {
     /// Parses a DROP TABLE DDL statement. The DROP TABLE prefix has
     /// already been consumed.
     fn parse_ddl_drop_table(&mut self) -> Result<ast::Statement> {
-        Ok(ast::Statement::DropTable(self.next_ident()?))
+        let mut if_exists = false;
+        if self.next_if_token(Keyword::If.into()).is_some() {
+            self.next_expect(Some(Keyword::Exists.into()))?;
+            if_exists = true;
+        }
+        Ok(ast::Statement::DropTable { name: self.next_ident()?, if_exists })
+    }
+
+    /// Parses a CREATE [UNIQUE] INDEX DDL statement. The CREATE [UNIQUE] INDEX
+    /// prefix has already been consumed.
+    fn parse_ddl_create_index(&mut self, unique: bool) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        self.next_expect(Some(Keyword::On.into()))?;
+        let table = self.next_ident()?;
+        self.next_expect(Some(Token::OpenParen))?;
+
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.next_ident()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(ast::Statement::CreateIndex { name, table, columns, unique })
     }
 
     /// Parses a column specification
@@ -161,6 +195,7 @@ impl<'a> Parser<'a> {
         let mut column = ast::Column {
             name: self.next_ident()?,
             datatype: match self.next()? {
+                Token::Keyword(Keyword::Blob) => DataType::Bytes,
                 Token::Keyword(Keyword::Bool) => DataType::Boolean,
                 Token::Keyword(Keyword::Boolean) => DataType::Boolean,
                 Token::Keyword(Keyword::Char) => DataType::String,
@@ -568,6 +603,10 @@ impl<'a> Parser<'a> {
                 expr
             }
             Token::String(s) => ast::Literal::String(s).into(),
+            Token::Question => {
+                self.params += 1;
+                ast::Expression::Parameter(self.params - 1)
+            }
             Token::Keyword(Keyword::False) => ast::Literal::Boolean(false).into(),
             Token::Keyword(Keyword::Infinity) => ast::Literal::Float(std::f64::INFINITY).into(),
             Token::Keyword(Keyword::NaN) => ast::Literal::Float(std::f64::NAN).into(),
@@ -780,6 +819,22 @@ impl Operator for PostfixOperator {
     }
 }
 
+/// Normalizes an SQL query string by lexing it and joining the tokens with single spaces, such that
+/// queries which differ only in whitespace, keyword case or quoting normalize to the same string.
+/// Literal values are preserved.
+pub fn normalize(query: &str) -> Result<String> {
+    Ok(Lexer::new(query)
+        .map(|token| {
+            Ok(match token? {
+                Token::String(s) => format!("'{}'", s.replace("'", "''")),
+                Token::Ident(s) => format_ident(&s),
+                token => token.to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join(" "))
+}
+
 // Formats an identifier by quoting it as appropriate
 pub(super) fn format_ident(ident: &str) -> String {
     lazy_static! {
@@ -0,0 +1,145 @@
+use super::Plan;
+use crate::error::Result;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// A bounded LRU cache of optimized query plans, shared by all sessions of an engine. Plans are
+/// keyed by normalized SQL text (see parser::normalize), and record the versions of the tables
+/// they were planned against. A plan is only returned while all of its tables have the same
+/// versions, such that a schema or statistics change on a table invalidates the plans using it.
+pub struct PlanCache {
+    /// The maximum number of cached plans
+    capacity: usize,
+    /// The cache contents
+    inner: Mutex<Inner>,
+}
+
+/// Plan cache contents, protected by a mutex
+#[derive(Default)]
+struct Inner {
+    /// Cached plans by normalized SQL
+    plans: HashMap<String, Entry>,
+    /// Normalized SQL by last access tick, in least recently used order
+    lru: BTreeMap<u64, String>,
+    /// The current access tick
+    tick: u64,
+    /// The number of cache hits
+    hits: u64,
+    /// The number of cache misses
+    misses: u64,
+}
+
+/// A cached plan
+struct Entry {
+    /// The tables the plan was built against, with their versions
+    tables: Vec<(String, u64)>,
+    /// The optimized plan
+    plan: Plan,
+    /// The last access tick
+    tick: u64,
+}
+
+/// Plan cache statistics
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanCacheStats {
+    /// The number of cached plans
+    pub size: u64,
+    /// The number of lookups that returned a cached plan
+    pub hits: u64,
+    /// The number of lookups that did not, i.e. the number of plans built
+    pub misses: u64,
+}
+
+impl PlanCache {
+    /// Creates a new plan cache holding at most the given number of plans
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, inner: Mutex::new(Inner::default()) }
+    }
+
+    /// Fetches a cached plan for the given normalized SQL, if any. The plan's tables are checked
+    /// against their current versions as given by the closure, which is called without holding
+    /// the cache lock. Outdated plans are evicted.
+    pub fn get<F>(&self, sql: &str, version: F) -> Result<Option<Plan>>
+    where
+        F: Fn(&str) -> Result<u64>,
+    {
+        let (plan, tables) = {
+            let mut inner = self.inner.lock()?;
+            match inner.plans.get(sql) {
+                Some(entry) => (entry.plan.clone(), entry.tables.clone()),
+                None => {
+                    inner.misses += 1;
+                    return Ok(None);
+                }
+            }
+        };
+        let mut current = true;
+        for (table, v) in &tables {
+            if version(table)? != *v {
+                current = false;
+                break;
+            }
+        }
+
+        let mut inner = self.inner.lock()?;
+        inner.tick += 1;
+        let tick = inner.tick;
+        // The entry may have been replaced or evicted while we checked the versions.
+        let old_tick = match inner.plans.get_mut(sql) {
+            Some(entry) if entry.tables == tables => {
+                let old_tick = entry.tick;
+                entry.tick = tick;
+                Some(old_tick)
+            }
+            _ => None,
+        };
+        if current {
+            if let Some(old_tick) = old_tick {
+                inner.lru.remove(&old_tick);
+                inner.lru.insert(tick, sql.to_string());
+            }
+            inner.hits += 1;
+            Ok(Some(plan))
+        } else {
+            if let Some(old_tick) = old_tick {
+                inner.lru.remove(&old_tick);
+                inner.plans.remove(sql);
+            }
+            inner.misses += 1;
+            Ok(None)
+        }
+    }
+
+    /// Caches a plan for the given normalized SQL and table versions, evicting the least recently
+    /// used plan if the cache is full.
+    pub fn put(&self, sql: String, tables: Vec<(String, u64)>, plan: Plan) -> Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut inner = self.inner.lock()?;
+        inner.tick += 1;
+        let tick = inner.tick;
+        if let Some(entry) = inner.plans.remove(&sql) {
+            inner.lru.remove(&entry.tick);
+        }
+        while inner.plans.len() >= self.capacity {
+            let (&lru_tick, _) = inner.lru.iter().next().unwrap();
+            let lru_sql = inner.lru.remove(&lru_tick).unwrap();
+            inner.plans.remove(&lru_sql);
+        }
+        inner.lru.insert(tick, sql.clone());
+        inner.plans.insert(sql, Entry { tables, plan, tick });
+        Ok(())
+    }
+
+    /// Returns plan cache statistics
+    pub fn stats(&self) -> Result<PlanCacheStats> {
+        let inner = self.inner.lock()?;
+        Ok(PlanCacheStats {
+            size: inner.plans.len() as u64,
+            hits: inner.hits,
+            misses: inner.misses,
+        })
+    }
+}
@@ -1,20 +1,23 @@
+mod cache;
 mod optimizer;
 mod planner;
+pub use cache::{PlanCache, PlanCacheStats};
 use optimizer::Optimizer as _;
 use planner::Planner;
 
 use super::engine::Transaction;
-use super::execution::{Executor, ResultSet};
+use super::execution::{Cancel, Executor, ResultSet};
 use super::parser::ast;
-use super::schema::{Catalog, Table};
-use super::types::{Expression, Value};
+use super::schema::{Catalog, Index, Table};
+use super::types::{DataType, Expression, Value};
 use crate::error::Result;
 
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Display};
 
 /// A query plan
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Plan(pub Node);
 
 impl Display for Plan {
@@ -26,12 +29,31 @@ impl Display for Plan {
 impl Plan {
     /// Builds a plan from an AST statement.
     pub fn build<C: Catalog>(statement: ast::Statement, catalog: &mut C) -> Result<Self> {
-        Planner::new(catalog).build(statement)
+        Self::build_with_params(statement, Vec::new(), catalog)
+    }
+
+    /// Builds a plan from an AST statement, binding the given values to ? parameters.
+    pub fn build_with_params<C: Catalog>(
+        statement: ast::Statement,
+        params: Vec<Value>,
+        catalog: &mut C,
+    ) -> Result<Self> {
+        Planner::new(catalog, params).build(statement)
     }
 
     /// Executes the plan, consuming it.
     pub fn execute<T: Transaction + 'static>(self, txn: &mut T) -> Result<ResultSet> {
-        <dyn Executor<T>>::build(self.0).execute(txn)
+        self.execute_with_cancel(txn, &Cancel::never())
+    }
+
+    /// Executes the plan, consuming it, and stopping early with Error::Abort once the given
+    /// cancellation check fires.
+    pub fn execute_with_cancel<T: Transaction + 'static>(
+        self,
+        txn: &mut T,
+        cancel: &Cancel,
+    ) -> Result<ResultSet> {
+        Ok(<dyn Executor<T>>::build(self.0, cancel).execute(txn)?.cancellable(cancel))
     }
 
     /// Optimizes the plan, consuming it.
@@ -44,15 +66,71 @@ impl Plan {
         root = optimizer::JoinType.optimize(root)?;
         Ok(Plan(root))
     }
+
+    /// Returns the names of the tables the plan accesses, in sorted order.
+    pub fn tables(&self) -> Vec<String> {
+        let mut tables = BTreeSet::new();
+        self.0.collect_tables(&mut tables);
+        tables.into_iter().collect()
+    }
+}
+
+/// A query plan with ? parameter slots, which is built once and can then be executed repeatedly
+/// with different parameter values.
+#[derive(Clone, Debug)]
+pub struct PreparedPlan {
+    /// The unoptimized plan, since optimizations such as index lookups depend on the values
+    plan: Plan,
+    /// The parameter slots, with the data type their values must have, if any
+    slots: BTreeMap<usize, Option<DataType>>,
+}
+
+impl PreparedPlan {
+    /// Builds a plan with parameter slots from an AST statement. Statements whose parameters must
+    /// be known while planning, e.g. in LIMIT and OFFSET, fail to build.
+    pub fn build<C: Catalog>(statement: ast::Statement, catalog: &mut C) -> Result<Self> {
+        let mut planner = Planner::with_slots(catalog);
+        let plan = planner.build(statement)?;
+        Ok(Self { plan, slots: planner.slots() })
+    }
+
+    /// Binds values to the parameter slots, checking them against the slots' data types, and
+    /// optimizes the resulting plan.
+    pub fn bind<C: Catalog>(&self, params: Vec<Value>, catalog: &mut C) -> Result<Plan> {
+        for (i, datatype) in &self.slots {
+            let value = params.get(*i).ok_or_else(|| planner::missing_parameter(*i))?;
+            if let Some(datatype) = datatype {
+                planner::check_parameter_value(*i, value, datatype)?;
+            }
+        }
+        let root = self.plan.0.clone().transform(&Ok, &|n| {
+            n.transform_expressions(&Ok, &|e| match e {
+                Expression::Parameter(i) => Ok(Expression::Constant(params[i].clone())),
+                e => Ok(e),
+            })
+        })?;
+        Plan(root).optimize(catalog)
+    }
+
+    /// Returns the names of the tables the plan accesses, in sorted order.
+    pub fn tables(&self) -> Vec<String> {
+        self.plan.tables()
+    }
 }
 
 /// A plan node
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Node {
     Aggregation {
         source: Box<Node>,
         aggregates: Vec<Aggregate>,
     },
+    Analyze {
+        table: String,
+    },
+    CreateIndex {
+        index: Index,
+    },
     CreateTable {
         schema: Table,
     },
@@ -60,8 +138,12 @@ pub enum Node {
         table: String,
         source: Box<Node>,
     },
+    DropIndex {
+        name: String,
+    },
     DropTable {
         table: String,
+        if_exists: bool,
     },
     Filter {
         source: Box<Node>,
@@ -127,6 +209,41 @@ pub enum Node {
 }
 
 impl Node {
+    /// Recursively collects the names of the tables accessed by the node.
+    fn collect_tables(&self, tables: &mut BTreeSet<String>) {
+        match self {
+            Self::Analyze { table }
+            | Self::DropTable { table, .. }
+            | Self::IndexLookup { table, .. }
+            | Self::Insert { table, .. }
+            | Self::KeyLookup { table, .. }
+            | Self::Scan { table, .. } => {
+                tables.insert(table.clone());
+            }
+            Self::CreateIndex { index } => {
+                tables.insert(index.table.clone());
+            }
+            Self::CreateTable { schema } => {
+                tables.insert(schema.name.clone());
+            }
+            Self::Delete { table, source } | Self::Update { table, source, .. } => {
+                tables.insert(table.clone());
+                source.collect_tables(tables);
+            }
+            Self::Aggregation { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Order { source, .. }
+            | Self::Projection { source, .. } => source.collect_tables(tables),
+            Self::HashJoin { left, right, .. } | Self::NestedLoopJoin { left, right, .. } => {
+                left.collect_tables(tables);
+                right.collect_tables(tables);
+            }
+            Self::DropIndex { .. } | Self::Nothing => {}
+        }
+    }
+
     /// Recursively transforms nodes by applying functions before and after descending.
     pub fn transform<B, A>(mut self, before: &B, after: &A) -> Result<Self>
     where
@@ -135,7 +252,10 @@ impl Node {
     {
         self = before(self)?;
         self = match self {
-            n @ Self::CreateTable { .. }
+            n @ Self::Analyze { .. }
+            | n @ Self::CreateIndex { .. }
+            | n @ Self::CreateTable { .. }
+            | n @ Self::DropIndex { .. }
             | n @ Self::DropTable { .. }
             | n @ Self::IndexLookup { .. }
             | n @ Self::Insert { .. }
@@ -195,8 +315,11 @@ impl Node {
     {
         Ok(match self {
             n @ Self::Aggregation { .. }
+            | n @ Self::Analyze { .. }
+            | n @ Self::CreateIndex { .. }
             | n @ Self::CreateTable { .. }
             | n @ Self::Delete { .. }
+            | n @ Self::DropIndex { .. }
             | n @ Self::DropTable { .. }
             | n @ Self::HashJoin { .. }
             | n @ Self::IndexLookup { .. }
@@ -273,6 +396,18 @@ impl Node {
                 );
                 s += &source.format(indent, false, true);
             }
+            Self::Analyze { table } => {
+                s += &format!("Analyze: {}\n", table);
+            }
+            Self::CreateIndex { index } => {
+                s += &format!(
+                    "CreateIndex: {}{} on {} ({})\n",
+                    if index.unique { "unique " } else { "" },
+                    index.name,
+                    index.table,
+                    index.column
+                );
+            }
             Self::CreateTable { schema } => {
                 s += &format!("CreateTable: {}\n", schema.name);
             }
@@ -280,8 +415,15 @@ impl Node {
                 s += &format!("Delete: {}\n", table);
                 s += &source.format(indent, false, true);
             }
-            Self::DropTable { table } => {
-                s += &format!("DropTable: {}\n", table);
+            Self::DropIndex { name } => {
+                s += &format!("DropIndex: {}\n", name);
+            }
+            Self::DropTable { table, if_exists } => {
+                s += &format!(
+                    "DropTable: {}{}\n",
+                    table,
+                    if *if_exists { " if exists" } else { "" }
+                );
             }
             Self::Filter { source, predicate } => {
                 s += &format!("Filter: {}\n", predicate);
@@ -422,7 +564,7 @@ impl Display for Node {
 }
 
 /// An aggregate operation
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Aggregate {
     Average,
     Count,
@@ -450,7 +592,7 @@ impl Display for Aggregate {
 pub type Aggregates = Vec<Aggregate>;
 
 /// A sort order direction
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Ascending,
     Descending,
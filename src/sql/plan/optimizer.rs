@@ -1,4 +1,4 @@
-use super::super::schema::Catalog;
+use super::super::schema::{Catalog, TableStats};
 use super::super::types::{Expression, Value};
 use super::Node;
 use crate::error::Result;
@@ -42,7 +42,7 @@ impl Optimizer for FilterPushdown {
                     // We don't replace the filter node here, since doing so would cause transform()
                     // to skip the source as it won't reapply the transform to the "same" node.
                     // We leave a noop filter node instead, which will be cleaned up by NoopCleaner.
-                    if let Some(remainder) = self.pushdown(predicate, &mut *source) {
+                    if let Some(remainder) = self.pushdown(predicate, &mut source) {
                         Ok(Node::Filter { source, predicate: remainder })
                     } else {
                         Ok(Node::Filter {
@@ -157,7 +157,9 @@ impl FilterPushdown {
     }
 }
 
-/// An index lookup optimizer, which converts table scans to index lookups.
+/// An index lookup optimizer, which converts table scans to index lookups. Primary key lookups are
+/// always used when possible, while secondary index lookups are only used when the table
+/// statistics estimate them to be no more expensive than a sequential scan.
 pub struct IndexLookup<'a, C: Catalog> {
     catalog: &'a mut C,
 }
@@ -175,6 +177,30 @@ impl<'a, C: Catalog> IndexLookup<'a, C> {
             node
         }
     }
+
+    /// Estimates the cost of a sequential scan, as the number of rows read.
+    fn seq_scan_cost(stats: &TableStats) -> f64 {
+        stats.rows as f64
+    }
+
+    /// Estimates the cost of an index lookup for the given number of column values, as the
+    /// number of index entries and rows read. The rows matching each value are estimated from
+    /// the number of distinct values in the column. Returns None if the column has no statistics.
+    fn index_scan_cost(stats: &TableStats, column: &str, values: usize) -> Option<f64> {
+        let distinct = *stats.distinct.get(column)?;
+        let selectivity = (values as f64 / distinct.max(1) as f64).min(1.0);
+        Some(values as f64 + selectivity * stats.rows as f64)
+    }
+
+    /// Checks whether a sequential scan is estimated to be cheaper than an index lookup. Tables
+    /// that haven't been analyzed have no statistics, in which case the index is always used.
+    fn prefer_scan(&self, table: &str, column: &str, values: usize) -> Result<bool> {
+        Ok(match self.catalog.read_table_stats(table)? {
+            Some(stats) => Self::index_scan_cost(&stats, column, values)
+                .is_some_and(|cost| cost > Self::seq_scan_cost(&stats)),
+            None => false,
+        })
+    }
 }
 
 impl<'a, C: Catalog> Optimizer for IndexLookup<'a, C> {
@@ -195,6 +221,9 @@ impl<'a, C: Catalog> Optimizer for IndexLookup<'a, C> {
                     }
                     for (ci, column) in columns.iter().enumerate().filter(|(_, c)| c.index) {
                         if let Some(values) = cnf[i].as_lookup(ci) {
+                            if self.prefer_scan(&table, &column.name, values.len())? {
+                                continue;
+                            }
                             cnf.remove(i);
                             return Ok(self.wrap_cnf(
                                 Node::IndexLookup {
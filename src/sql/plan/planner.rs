@@ -1,21 +1,32 @@
 use super::super::parser::ast;
-use super::super::schema::{Catalog, Column, Table};
-use super::super::types::{Expression, Value};
+use super::super::schema::{Catalog, Column, Index, Table};
+use super::super::types::{DataType, Expression, Value};
 use super::{Aggregate, Direction, Node, Plan};
 use crate::error::{Error, Result};
 
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem::replace;
 
 /// A query plan builder.
 pub struct Planner<'a, C: Catalog> {
     catalog: &'a mut C,
+    /// The values bound to ? parameter placeholders, or None to leave parameter slots in the plan.
+    params: Option<Vec<Value>>,
+    /// The parameter slots left in the plan, with the data type their values must have, if any.
+    slots: RefCell<BTreeMap<usize, Option<DataType>>>,
 }
 
 impl<'a, C: Catalog> Planner<'a, C> {
-    /// Creates a new planner.
-    pub fn new(catalog: &'a mut C) -> Self {
-        Self { catalog }
+    /// Creates a new planner, binding the given values to ? parameter placeholders.
+    pub fn new(catalog: &'a mut C, params: Vec<Value>) -> Self {
+        Self { catalog, params: Some(params), slots: RefCell::new(BTreeMap::new()) }
+    }
+
+    /// Creates a new planner which leaves ? parameter placeholders as Expression::Parameter slots,
+    /// to be bound later.
+    pub fn with_slots(catalog: &'a mut C) -> Self {
+        Self { catalog, params: None, slots: RefCell::new(BTreeMap::new()) }
     }
 
     /// Builds a plan for an AST statement.
@@ -23,6 +34,11 @@ impl<'a, C: Catalog> Planner<'a, C> {
         Ok(Plan(self.build_statement(statement)?))
     }
 
+    /// Returns the parameter slots left in the plans built so far, see with_slots().
+    pub fn slots(&self) -> BTreeMap<usize, Option<DataType>> {
+        self.slots.borrow().clone()
+    }
+
     /// Builds a plan node for a statement.
     fn build_statement(&self, statement: ast::Statement) -> Result<Node> {
         Ok(match statement {
@@ -66,7 +82,25 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 )?,
             },
 
-            ast::Statement::DropTable(table) => Node::DropTable { table },
+            ast::Statement::DropTable { name, if_exists } => {
+                Node::DropTable { table: name, if_exists }
+            }
+
+            ast::Statement::CreateIndex { name, table, mut columns, unique } => {
+                if columns.len() != 1 {
+                    return Err(Error::Value(format!(
+                        "Index {} must have exactly one column, multi-column indexes are not supported",
+                        name
+                    )));
+                }
+                Node::CreateIndex {
+                    index: Index { name, table, column: columns.remove(0), unique },
+                }
+            }
+
+            ast::Statement::DropIndex(name) => Node::DropIndex { name },
+
+            ast::Statement::Analyze(table) => Node::Analyze { table },
 
             // DML statements (mutations).
             ast::Statement::Delete { table, r#where } => {
@@ -81,19 +115,38 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 }
             }
 
-            ast::Statement::Insert { table, columns, values } => Node::Insert {
-                table,
-                columns: columns.unwrap_or_else(Vec::new),
-                expressions: values
-                    .into_iter()
-                    .map(|exprs| {
-                        exprs
-                            .into_iter()
-                            .map(|expr| self.build_expression(&mut Scope::constant(), expr))
-                            .collect::<Result<_>>()
-                    })
-                    .collect::<Result<_>>()?,
-            },
+            ast::Statement::Insert { table, columns, values } => {
+                let columns = columns.unwrap_or_else(Vec::new);
+                // Check parameters against the types of the columns they're inserted into.
+                if values.iter().flatten().any(|e| matches!(e, ast::Expression::Parameter(_))) {
+                    let schema = self.catalog.must_read_table(&table)?;
+                    for exprs in &values {
+                        for (i, expr) in exprs.iter().enumerate() {
+                            let column = match columns.get(i) {
+                                Some(name) => schema.get_column(name).ok(),
+                                None if columns.is_empty() => schema.columns.get(i),
+                                None => None,
+                            };
+                            if let Some(column) = column {
+                                self.check_parameter(expr, &column.datatype)?;
+                            }
+                        }
+                    }
+                }
+                Node::Insert {
+                    table,
+                    columns,
+                    expressions: values
+                        .into_iter()
+                        .map(|exprs| {
+                            exprs
+                                .into_iter()
+                                .map(|expr| self.build_expression(&mut Scope::constant(), expr))
+                                .collect::<Result<_>>()
+                        })
+                        .collect::<Result<_>>()?,
+                }
+            }
 
             ast::Statement::Update { table, set, r#where } => {
                 let scope = &mut Scope::from_table(self.catalog.must_read_table(&table)?)?;
@@ -107,6 +160,9 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     expressions: set
                         .into_iter()
                         .map(|(c, e)| {
+                            if let Some(datatype) = scope.get_datatype(None, &c) {
+                                self.check_parameter(&e, &datatype)?;
+                            }
                             Ok((
                                 scope.resolve(None, &c)?,
                                 Some(c),
@@ -522,6 +578,29 @@ impl<'a, C: Catalog> Planner<'a, C> {
     /// Builds an expression from an AST expression
     fn build_expression(&self, scope: &mut Scope, expr: ast::Expression) -> Result<Expression> {
         use Expression::*;
+
+        // Check parameters compared with fields against the fields' data types.
+        if let ast::Expression::Operation(op) = &expr {
+            let operands = match op {
+                ast::Operation::Equal(lhs, rhs)
+                | ast::Operation::NotEqual(lhs, rhs)
+                | ast::Operation::GreaterThan(lhs, rhs)
+                | ast::Operation::GreaterThanOrEqual(lhs, rhs)
+                | ast::Operation::LessThan(lhs, rhs)
+                | ast::Operation::LessThanOrEqual(lhs, rhs) => Some((&**lhs, &**rhs)),
+                _ => None,
+            };
+            match operands {
+                Some((ast::Expression::Field(table, name), param))
+                | Some((param, ast::Expression::Field(table, name))) => {
+                    if let Some(datatype) = scope.get_datatype(table.as_deref(), name) {
+                        self.check_parameter(param, &datatype)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
         Ok(match expr {
             ast::Expression::Literal(l) => Constant(match l {
                 ast::Literal::Null => Value::Null,
@@ -531,6 +610,13 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 ast::Literal::String(s) => Value::String(s),
             }),
             ast::Expression::Column(i) => Field(i, scope.get_label(i)?),
+            ast::Expression::Parameter(i) => match self.params {
+                Some(_) => Constant(self.parameter(i)?),
+                None => {
+                    self.slots.borrow_mut().entry(i).or_insert(None);
+                    Parameter(i)
+                }
+            },
             ast::Expression::Field(table, name) => {
                 Field(scope.resolve(table.as_deref(), &name)?, Some((table, name)))
             }
@@ -631,12 +717,57 @@ impl<'a, C: Catalog> Planner<'a, C> {
         })
     }
 
+    /// Fetches the value bound to a ? parameter placeholder.
+    fn parameter(&self, index: usize) -> Result<Value> {
+        self.params
+            .as_ref()
+            .and_then(|params| params.get(index))
+            .cloned()
+            .ok_or_else(|| missing_parameter(index))
+    }
+
+    /// Checks that the value bound to a parameter expression, if any, has the given data type. When
+    /// leaving parameter slots, the data type is recorded for the slot instead.
+    fn check_parameter(&self, expr: &ast::Expression, datatype: &DataType) -> Result<()> {
+        if let ast::Expression::Parameter(i) = expr {
+            match self.params {
+                Some(_) => check_parameter_value(*i, &self.parameter(*i)?, datatype)?,
+                None => {
+                    self.slots.borrow_mut().insert(*i, Some(datatype.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Builds and evaluates a constant AST expression.
     fn evaluate_constant(&self, expr: ast::Expression) -> Result<Value> {
         self.build_expression(&mut Scope::constant(), expr)?.evaluate(None)
     }
 }
 
+/// Returns the error for a ? parameter placeholder without a value.
+pub(super) fn missing_parameter(index: usize) -> Error {
+    Error::Value(format!("No value given for parameter {}", index + 1))
+}
+
+/// Checks that the value bound to a ? parameter placeholder has the given data type.
+pub(super) fn check_parameter_value(
+    index: usize,
+    value: &Value,
+    datatype: &DataType,
+) -> Result<()> {
+    match value.datatype() {
+        Some(ref t) if t != datatype => Err(Error::Value(format!(
+            "Invalid datatype {} for parameter {}, expected {}",
+            t,
+            index + 1,
+            datatype
+        ))),
+        _ => Ok(()),
+    }
+}
+
 /// Manages names available to expressions and executors, and maps them onto columns/fields.
 #[derive(Clone, Debug)]
 pub struct Scope {
@@ -754,6 +885,20 @@ impl Scope {
         Ok(())
     }
 
+    /// Looks up the data type of a field, if it unambiguously refers to a table column.
+    fn get_datatype(&self, table: Option<&str>, name: &str) -> Option<DataType> {
+        let mut columns = self
+            .tables
+            .iter()
+            .filter(|(label, _)| table.is_none() || table == Some(label.as_str()))
+            .flat_map(|(_, t)| t.columns.iter())
+            .filter(|c| c.name == name);
+        match (columns.next(), columns.next()) {
+            (Some(column), None) => Some(column.datatype.clone()),
+            _ => None,
+        }
+    }
+
     /// Resolves a name, optionally qualified by a table name.
     fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize> {
         if self.constant {
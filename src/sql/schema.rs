@@ -4,6 +4,7 @@ use super::types::{DataType, Value};
 use crate::error::{Error, Result};
 
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
 
 /// The catalog stores schema information
@@ -16,6 +17,16 @@ pub trait Catalog {
     fn read_table(&self, table: &str) -> Result<Option<Table>>;
     /// Iterates over all tables
     fn scan_tables(&self) -> Result<Tables>;
+    /// Creates a new index on a table column, indexing any existing rows
+    fn create_index(&mut self, index: Index) -> Result<()>;
+    /// Deletes an existing index, or errors if it does not exist
+    fn delete_index(&mut self, index: &str) -> Result<()>;
+    /// Computes and stores the statistics for a table, or errors if it does not exist
+    fn analyze_table(&mut self, table: &str) -> Result<TableStats>;
+    /// Reads the statistics for a table as of its last analysis, if it has been analyzed
+    fn read_table_stats(&self, table: &str) -> Result<Option<TableStats>>;
+    /// Returns a table's version, which changes whenever its schema or statistics change
+    fn table_version(&self, table: &str) -> Result<u64>;
 
     /// Reads a table, and errors if it does not exist
     fn must_read_table(&self, table: &str) -> Result<Table> {
@@ -43,6 +54,15 @@ pub trait Catalog {
     }
 }
 
+/// Table statistics, computed by ANALYZE and used for cost-based planning
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TableStats {
+    /// The number of rows in the table
+    pub rows: u64,
+    /// The number of distinct values in each indexed column, by column name
+    pub distinct: BTreeMap<String, u64>,
+}
+
 /// A table scan iterator
 pub type Tables = Box<dyn DoubleEndedIterator<Item = Table> + Send>;
 
@@ -300,3 +320,25 @@ impl Display for Column {
         write!(f, "{}", sql)
     }
 }
+
+/// A named secondary index on a table column
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub column: String,
+    pub unique: bool,
+}
+
+impl Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CREATE {}INDEX {} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            format_ident(&self.name),
+            format_ident(&self.table),
+            format_ident(&self.column)
+        )
+    }
+}
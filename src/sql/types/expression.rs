@@ -12,6 +12,7 @@ pub enum Expression {
     // Values
     Constant(Value),
     Field(usize, Option<(Option<String>, String)>),
+    Parameter(usize),
 
     // Logical operations
     And(Box<Expression>, Box<Expression>),
@@ -47,6 +48,9 @@ impl Expression {
             // Constant values
             Self::Constant(c) => c.clone(),
             Self::Field(i, _) => row.and_then(|row| row.get(*i).cloned()).unwrap_or(Null),
+            Self::Parameter(i) => {
+                return Err(Error::Internal(format!("No value bound to parameter {}", i + 1)))
+            }
 
             // Logical operations
             Self::And(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
@@ -82,6 +86,7 @@ impl Expression {
                 (Float(lhs), Integer(rhs)) => Boolean(lhs == rhs as f64),
                 (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs == rhs),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs == rhs),
                 (Null, _) | (_, Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
@@ -95,6 +100,7 @@ impl Expression {
                 (Float(lhs), Integer(rhs)) => Boolean(lhs > rhs as f64),
                 (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs > rhs),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs > rhs),
                 (Null, _) | (_, Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
@@ -108,6 +114,7 @@ impl Expression {
                 (Float(lhs), Integer(rhs)) => Boolean(lhs < rhs as f64),
                 (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs < rhs),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs < rhs),
                 (Null, _) | (_, Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
@@ -304,7 +311,7 @@ impl Expression {
             | Self::Negate(expr)
             | Self::Not(expr) => Self::replace_with(expr, |e| e.transform(before, after))?,
 
-            Self::Constant(_) | Self::Field(_, _) => {}
+            Self::Constant(_) | Self::Field(_, _) | Self::Parameter(_) => {}
         };
         after(self)
     }
@@ -332,7 +339,7 @@ impl Expression {
                 | Self::Negate(expr)
                 | Self::Not(expr) => expr.walk(visitor),
 
-                Self::Constant(_) | Self::Field(_, _) => true,
+                Self::Constant(_) | Self::Field(_, _) | Self::Parameter(_) => true,
             }
     }
 
@@ -524,6 +531,7 @@ impl Display for Expression {
             Self::Field(i, None) => format!("#{}", i),
             Self::Field(_, Some((None, name))) => name.to_string(),
             Self::Field(_, Some((Some(table), name))) => format!("{}.{}", table, name),
+            Self::Parameter(_) => "?".to_string(),
 
             Self::And(lhs, rhs) => format!("{} AND {}", lhs, rhs),
             Self::Or(lhs, rhs) => format!("{} OR {}", lhs, rhs),
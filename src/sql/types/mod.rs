@@ -15,6 +15,7 @@ pub enum DataType {
     Integer,
     Float,
     String,
+    Bytes,
 }
 
 impl std::fmt::Display for DataType {
@@ -24,6 +25,7 @@ impl std::fmt::Display for DataType {
             Self::Integer => "INTEGER",
             Self::Float => "FLOAT",
             Self::String => "STRING",
+            Self::Bytes => "BLOB",
         })
     }
 }
@@ -36,6 +38,7 @@ pub enum Value {
     Integer(i64),
     Float(f64),
     String(String),
+    Bytes(Vec<u8>),
 }
 
 impl std::cmp::Eq for Value {}
@@ -50,6 +53,7 @@ impl Hash for Value {
             Value::Integer(v) => v.hash(state),
             Value::Float(v) => v.to_be_bytes().hash(state),
             Value::String(v) => v.hash(state),
+            Value::Bytes(v) => v.hash(state),
         }
     }
 }
@@ -75,6 +79,7 @@ impl Value {
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
             Self::String(_) => Some(DataType::String),
+            Self::Bytes(_) => Some(DataType::Bytes),
         }
     }
 
@@ -109,6 +114,14 @@ impl Value {
             v => Err(Error::Value(format!("Not a string: {:?}", v))),
         }
     }
+
+    /// Returns the inner bytes, or an error if not bytes
+    pub fn bytes(self) -> Result<Vec<u8>> {
+        match self {
+            Self::Bytes(b) => Ok(b),
+            v => Err(Error::Value(format!("Not bytes: {:?}", v))),
+        }
+    }
 }
 
 impl std::fmt::Display for Value {
@@ -121,6 +134,9 @@ impl std::fmt::Display for Value {
                 Self::Integer(i) => i.to_string(),
                 Self::Float(f) => f.to_string(),
                 Self::String(s) => s.clone(),
+                Self::Bytes(b) => {
+                    format!("x'{}'", b.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+                }
             }
             .as_ref(),
         )
@@ -139,6 +155,7 @@ impl PartialOrd for Value {
             (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
             (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
             (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::Bytes(a), Self::Bytes(b)) => a.partial_cmp(b),
             (_, _) => None,
         }
     }
@@ -168,6 +185,12 @@ impl From<String> for Value {
     }
 }
 
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Value::Bytes(v)
+    }
+}
+
 impl From<&str> for Value {
     fn from(v: &str) -> Self {
         Value::String(v.to_owned())
@@ -181,6 +181,7 @@ pub fn encode_value(value: &Value) -> Vec<u8> {
         Value::Float(f) => [&[0x02][..], &encode_f64(*f)].concat(),
         Value::Integer(i) => [&[0x03][..], &encode_i64(*i)].concat(),
         Value::String(s) => [&[0x04][..], &encode_string(s)].concat(),
+        Value::Bytes(b) => [&[0x05][..], &encode_bytes(b)].concat(),
     }
 }
 
@@ -192,6 +193,7 @@ pub fn take_value(bytes: &mut &[u8]) -> Result<Value> {
         0x02 => Ok(Value::Float(take_f64(bytes)?)),
         0x03 => Ok(Value::Integer(take_i64(bytes)?)),
         0x04 => Ok(Value::String(take_string(bytes)?)),
+        0x05 => Ok(Value::Bytes(take_bytes(bytes)?)),
         n => Err(Error::Internal(format!("Invalid value prefix {:x?}", n))),
     }
 }
@@ -466,6 +468,10 @@ mod tests {
             encode_value(&Value::String("abc".into())),
             vec![0x04, 0x61, 0x62, 0x63, 0x00, 0x00]
         );
+        assert_eq!(
+            encode_value(&Value::Bytes(vec![0x00, 0x01])),
+            vec![0x05, 0x00, 0xff, 0x01, 0x00, 0x00]
+        );
         Ok(())
     }
 
@@ -499,6 +505,10 @@ mod tests {
         assert_eq!(take_value(&mut bytes)?, Value::String("abc".into()));
         assert_eq!(bytes, &[0xaf]);
 
+        let mut bytes: &[u8] = &[0x05, 0x00, 0xff, 0x01, 0x00, 0x00, 0xaf];
+        assert_eq!(take_value(&mut bytes)?, Value::Bytes(vec![0x00, 0x01]));
+        assert_eq!(bytes, &[0xaf]);
+
         Ok(())
     }
 }
@@ -94,7 +94,7 @@ impl RangeBounds<Vec<u8>> for Range {
 pub type Scan = Box<dyn DoubleEndedIterator<Item = Result<(Vec<u8>, Vec<u8>)>> + Send>;
 
 #[cfg(test)]
-trait TestSuite<S: Store> {
+pub(crate) trait TestSuite<S: Store> {
     fn setup() -> Result<S>;
 
     fn test() -> Result<()> {
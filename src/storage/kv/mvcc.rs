@@ -63,6 +63,26 @@ impl MVCC {
         session.set(&Key::Metadata(key.into()).encode(), value)
     }
 
+    /// Returns all raw key/value pairs of the underlying store, including versions, transaction
+    /// state and metadata, e.g. to snapshot it.
+    pub fn dump(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.store.read()?.scan(Range::from(..)).collect()
+    }
+
+    /// Replaces the entire contents of the underlying store with key/value pairs from dump().
+    pub fn load(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let mut store = self.store.write()?;
+        let keys =
+            store.scan(Range::from(..)).map(|r| r.map(|(k, _)| k)).collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            store.delete(&key)?;
+        }
+        for (key, value) in pairs {
+            store.set(&key, value)?;
+        }
+        store.flush()
+    }
+
     /// Returns engine status
     //
     // Bizarrely, the return statement is in fact necessary - see:
@@ -231,11 +251,24 @@ impl Transaction {
 
     /// Scans keys under a given prefix.
     pub fn scan_prefix(&self, prefix: &[u8]) -> Result<super::Scan> {
+        self.scan(prefix.to_vec()..Self::prefix_end(prefix)?)
+    }
+
+    /// Scans keys under a given prefix that sort after the given key, e.g. to resume a scan.
+    pub fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Result<super::Scan> {
+        let end = Self::prefix_end(prefix)?;
+        if after < prefix {
+            return self.scan(prefix.to_vec()..end);
+        }
+        self.scan((Bound::Excluded(after.to_vec()), Bound::Excluded(end)))
+    }
+
+    /// Returns the first key after all keys under the given prefix.
+    fn prefix_end(prefix: &[u8]) -> Result<Vec<u8>> {
         if prefix.is_empty() {
             return Err(Error::Internal("Scan prefix cannot be empty".into()));
         }
-        let start = prefix.to_vec();
-        let mut end = start.clone();
+        let mut end = prefix.to_vec();
         for i in (0..end.len()).rev() {
             match end[i] {
                 // If all 0xff we could in principle use Range::Unbounded, but it won't happen
@@ -250,7 +283,7 @@ impl Transaction {
                 }
             }
         }
-        self.scan(start..end)
+        Ok(end)
     }
 
     /// Sets a key.
@@ -516,6 +549,35 @@ pub mod tests {
         MVCC::new(Box::new(Test::new()))
     }
 
+    #[test]
+    fn test_dump_load() -> Result<()> {
+        let mvcc = setup();
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.commit()?;
+        let mut txn = mvcc.begin()?;
+        txn.set(b"b", vec![0x02])?;
+        mvcc.set_metadata(b"m", vec![0x03])?;
+        let dump = mvcc.dump()?;
+
+        // Loading replaces existing data, including the active transaction's writes.
+        let other = setup();
+        let mut txn_other = other.begin()?;
+        txn_other.set(b"c", vec![0x04])?;
+        txn_other.commit()?;
+        other.load(dump.clone())?;
+        assert_eq!(dump, other.dump()?);
+        assert_eq!(Some(vec![0x03]), other.get_metadata(b"m")?);
+
+        let txn_other = other.resume(txn.id())?;
+        assert_eq!(Some(vec![0x02]), txn_other.get(b"b")?);
+        txn.rollback()?;
+        let txn_other = other.begin_with_mode(Mode::ReadOnly)?;
+        assert_eq!(Some(vec![0x01]), txn_other.get(b"a")?);
+        assert_eq!(None, txn_other.get(b"c")?);
+        Ok(())
+    }
+
     #[test]
     fn test_begin() -> Result<()> {
         let mvcc = setup();
@@ -957,6 +1019,17 @@ pub mod tests {
         assert_eq!(None, scan.next_back().transpose()?);
         std::mem::drop(scan);
 
+        // Resumed scan
+        assert_eq!(
+            vec![(b"bb".to_vec(), vec![0x02, 0x02]), (b"bc".to_vec(), vec![0x02, 0x03])],
+            txn.scan_prefix_after(b"b", b"ba")?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![(b"b".to_vec(), vec![0x02]), (b"ba".to_vec(), vec![0x02, 0x01])],
+            txn.scan_prefix_after(b"b", b"a")?.take(2).collect::<Result<Vec<_>>>()?
+        );
+        assert!(txn.scan_prefix_after(b"b", b"bc")?.next().is_none());
+
         txn.commit()?;
         Ok(())
     }
@@ -7,25 +7,35 @@ use std::fmt::Display;
 use std::fs::{create_dir_all, File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Seek as _, SeekFrom, Write};
 use std::ops::Bound;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Log file positions and sizes of entries, keyed by entry index.
+type Index = BTreeMap<u64, (u64, u32)>;
 
 /// A hybrid log store, storing committed entries in an append-only file, uncommitted entries
 /// in memory, and metadata in a separate file (should be an on-disk key-value store).
 ///
 /// The log file contains sequential binary log entries, length-prefixed with a big-endian u32.
 /// Entries are only flushed to disk when they are committed and permanent, thus the file is
-/// written append-only.
+/// written append-only. Once a prefix of the log has been compacted, the file is rewritten to start
+/// with a header of u32::MAX (never a valid entry length) followed by the big-endian u64 index of
+/// the last compacted entry.
 ///
 /// An index of entry positions and sizes is maintained in memory. This is rebuilt on startup by
 /// scanning the file, since maintaining the index in a separate file requires additional fsyncing
 /// which is expensive. Since datasets are expected to be small, scanning the file on startup is
 /// reasonably cheap.
 pub struct Hybrid {
+    /// The directory containing the log files.
+    dir: PathBuf,
     /// The append-only log file. Protected by a mutex for interior mutability (i.e. read seeks).
     file: Mutex<File>,
     /// Index of entry locations and sizes in the log file.
-    index: BTreeMap<u64, (u64, u32)>,
+    index: Index,
+    /// The index of the last compacted entry, if any.
+    compacted: u64,
     /// Uncommitted log entries.
     uncommitted: VecDeque<Vec<u8>>,
     /// Metadata cache. Flushed to disk on changes.
@@ -33,10 +43,36 @@ pub struct Hybrid {
     /// The file used to store metadata.
     /// FIXME Should be an on-disk B-tree key-value store.
     metadata_file: File,
-    /// If true, fsync writes.
-    sync: bool,
+    /// When to fsync writes.
+    sync: SyncPolicy,
+    /// The time of the last fsync of committed entries.
+    synced: Instant,
+    /// Whether committed entries were written since the last fsync.
+    unsynced: bool,
+}
+
+/// When a hybrid log fsyncs its writes. Unsynced writes are flushed by the OS eventually, and when
+/// the log is closed, but can be lost if the machine crashes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncPolicy {
+    /// Fsync committed entries on every commit, and metadata on every flush.
+    Always,
+    /// Fsync metadata on every flush, but committed entries at most once per interval, such that
+    /// a single fsync covers all entries committed during the interval. Entries committed since
+    /// the last fsync are synced by the first commit after the interval, or by sync_deferred()
+    /// if no commit arrives.
+    Grouped(Duration),
+    /// Fsync metadata on every flush, but never fsync committed entries on commit. They're synced
+    /// by sync_deferred() at most once per interval instead, so commits never wait for the disk.
+    Deferred(Duration),
+    /// Never fsync committed entries, leaving it to the OS. Metadata is still fsynced on every
+    /// flush.
+    Never,
 }
 
+/// The marker of a compaction header at the start of the log file.
+const COMPACTED_MARKER: u32 = u32::MAX;
+
 impl Display for Hybrid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "hybrid")
@@ -44,8 +80,15 @@ impl Display for Hybrid {
 }
 
 impl Hybrid {
-    /// Creates or opens a new hybrid log, with files in the given directory.
+    /// Creates or opens a new hybrid log, with files in the given directory. If sync is true,
+    /// all writes are fsynced, otherwise only metadata flushes are.
     pub fn new(dir: &Path, sync: bool) -> Result<Self> {
+        Self::with_sync_policy(dir, if sync { SyncPolicy::Always } else { SyncPolicy::Never })
+    }
+
+    /// Creates or opens a new hybrid log, with files in the given directory, fsyncing writes
+    /// according to the given policy.
+    pub fn with_sync_policy(dir: &Path, sync: SyncPolicy) -> Result<Self> {
         create_dir_all(dir)?;
 
         let file =
@@ -57,35 +100,85 @@ impl Hybrid {
             .create(true)
             .open(dir.join("raft-metadata"))?;
 
+        let (compacted, index) = Self::build_index(&file)?;
         Ok(Self {
-            index: Self::build_index(&file)?,
+            dir: dir.to_path_buf(),
+            index,
+            compacted,
             file: Mutex::new(file),
             uncommitted: VecDeque::new(),
             metadata: Self::load_metadata(&metadata_file)?,
             metadata_file,
             sync,
+            synced: Instant::now(),
+            unsynced: false,
         })
     }
 
-    /// Builds the index by scanning the log file.
-    fn build_index(file: &File) -> Result<BTreeMap<u64, (u64, u32)>> {
+    /// Builds the index by scanning the log file, returning it along with the compacted index.
+    fn build_index(file: &File) -> Result<(u64, Index)> {
         let filesize = file.metadata()?.len();
         let mut bufreader = BufReader::new(file);
         let mut index = BTreeMap::new();
         let mut sizebuf = [0; 4];
         let mut pos = 0;
+        let mut compacted = 0;
         let mut i = 1;
         while pos < filesize {
             bufreader.read_exact(&mut sizebuf)?;
             pos += 4;
             let size = u32::from_be_bytes(sizebuf);
+            if pos == 4 && size == COMPACTED_MARKER {
+                let mut compactedbuf = [0; 8];
+                bufreader.read_exact(&mut compactedbuf)?;
+                pos += 8;
+                compacted = u64::from_be_bytes(compactedbuf);
+                i = compacted + 1;
+                continue;
+            }
             index.insert(i, (pos, size));
             let mut buf = vec![0; size as usize];
             bufreader.read_exact(&mut buf)?;
             pos += size as u64;
             i += 1;
         }
-        Ok(index)
+        Ok((compacted, index))
+    }
+
+    /// Rewrites the log file to start with a compaction header at the given index, followed by
+    /// the committed entries after it up to and including the given end index.
+    fn rewrite(&mut self, compacted: u64, end: u64) -> Result<()> {
+        // Write the entries to a new file, and atomically replace the log file with it, such that
+        // a crash leaves either the old or the new log.
+        let path = self.dir.join("raft-log.compact");
+        let mut file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+        let mut index_new = BTreeMap::new();
+        let mut bufwriter = BufWriter::new(&mut file);
+        bufwriter.write_all(&COMPACTED_MARKER.to_be_bytes())?;
+        bufwriter.write_all(&compacted.to_be_bytes())?;
+        let mut pos = 12;
+        let entries = self.scan(Range::from((compacted + 1)..=end));
+        for (i, entry) in ((compacted + 1)..).zip(entries) {
+            let entry = entry?;
+            bufwriter.write_all(&(entry.len() as u32).to_be_bytes())?;
+            pos += 4;
+            index_new.insert(i, (pos, entry.len() as u32));
+            bufwriter.write_all(&entry)?;
+            pos += entry.len() as u64;
+        }
+        bufwriter.flush()?;
+        drop(bufwriter);
+        file.sync_all()?;
+        std::fs::rename(&path, self.dir.join("raft-log"))?;
+        if self.sync != SyncPolicy::Never {
+            File::open(&self.dir)?.sync_all()?;
+        }
+
+        *self.file.get_mut()? = file;
+        self.index = index_new;
+        self.compacted = compacted;
+        Ok(())
     }
 
     /// Loads metadata from a file.
@@ -114,20 +207,20 @@ impl Store for Hybrid {
         if index > self.len() {
             return Err(Error::Internal(format!("Cannot commit non-existant index {}", index)));
         }
-        if index < self.index.len() as u64 {
+        if index < self.committed() {
             return Err(Error::Internal(format!(
                 "Cannot commit below current committed index {}",
-                self.index.len() as u64
+                self.committed()
             )));
         }
-        if index == self.index.len() as u64 {
+        if index == self.committed() {
             return Ok(());
         }
 
         let mut file = self.file.lock()?;
         let mut pos = file.seek(SeekFrom::End(0))?;
         let mut bufwriter = BufWriter::new(&mut *file);
-        for i in (self.index.len() as u64 + 1)..=index {
+        for i in (self.committed() + 1)..=index {
             let entry = self
                 .uncommitted
                 .pop_front()
@@ -140,20 +233,46 @@ impl Store for Hybrid {
         }
         bufwriter.flush()?;
         drop(bufwriter);
-        if self.sync {
-            file.sync_data()?;
+        match self.sync {
+            SyncPolicy::Always => file.sync_data()?,
+            SyncPolicy::Grouped(interval) if self.synced.elapsed() >= interval => {
+                file.sync_data()?;
+                self.synced = Instant::now();
+                self.unsynced = false;
+            }
+            SyncPolicy::Grouped(_) | SyncPolicy::Deferred(_) => self.unsynced = true,
+            SyncPolicy::Never => {}
         }
         Ok(())
     }
 
+    fn compact(&mut self, index: u64) -> Result<u64> {
+        if index > self.committed() {
+            return Err(Error::Internal(format!("Cannot compact uncommitted index {}", index)));
+        }
+        if index <= self.compacted {
+            return Ok(self.compacted);
+        }
+
+        self.rewrite(index, self.committed())?;
+        Ok(index)
+    }
+
     fn committed(&self) -> u64 {
-        self.index.len() as u64
+        self.compacted + self.index.len() as u64
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // Committed entries are synced on commit or by sync_deferred(), so only metadata may be
+        // pending. It's synced regardless of the sync policy, since Raft votes must be durable.
+        self.metadata_file.sync_data()?;
+        Ok(())
     }
 
     fn get(&self, index: u64) -> Result<Option<Vec<u8>>> {
         match index {
-            0 => Ok(None),
-            i if i <= self.index.len() as u64 => {
+            i if i <= self.compacted => Ok(None),
+            i if i <= self.committed() => {
                 let (pos, size) = self.index.get(&i).copied().ok_or_else(|| {
                     Error::Internal(format!("Indexed position not found for entry {}", i))
                 })?;
@@ -163,12 +282,12 @@ impl Store for Hybrid {
                 file.read_exact(&mut entry)?;
                 Ok(Some(entry))
             }
-            i => Ok(self.uncommitted.get(i as usize - self.index.len() - 1).cloned()),
+            i => Ok(self.uncommitted.get((i - self.committed()) as usize - 1).cloned()),
         }
     }
 
     fn len(&self) -> u64 {
-        self.index.len() as u64 + self.uncommitted.len() as u64
+        self.committed() + self.uncommitted.len() as u64
     }
 
     fn scan(&self, range: Range) -> Scan {
@@ -184,6 +303,8 @@ impl Store for Hybrid {
             Bound::Excluded(n) => n - 1,
             Bound::Unbounded => self.len(),
         };
+        // Compacted entries are skipped.
+        let start = max(start, self.compacted + 1);
 
         let mut scan: Scan = Box::new(std::iter::empty());
         if start > end {
@@ -206,13 +327,14 @@ impl Store for Hybrid {
         }
 
         // Scan uncommitted entries in memory
-        if end > self.index.len() as u64 {
+        let committed = self.committed();
+        if end > committed {
             scan = Box::new(
                 scan.chain(
                     self.uncommitted
                         .iter()
-                        .skip(start as usize - min(start as usize, self.index.len() + 1))
-                        .take(end as usize - max(start as usize, self.index.len()) + 1)
+                        .skip((start - min(start, committed + 1)) as usize)
+                        .take((end - max(start, committed)) as usize + 1)
                         .cloned()
                         .map(Ok),
                 ),
@@ -226,14 +348,25 @@ impl Store for Hybrid {
         self.index.iter().next_back().map(|(_, (pos, size))| *pos + *size as u64).unwrap_or(0)
     }
 
+    fn reset(&mut self, index: u64) -> Result<()> {
+        if index < self.committed() {
+            return Err(Error::Internal(format!(
+                "Cannot reset below committed index {}",
+                self.committed()
+            )));
+        }
+        self.uncommitted.clear();
+        self.rewrite(index, index)
+    }
+
     fn truncate(&mut self, index: u64) -> Result<u64> {
-        if index < self.index.len() as u64 {
+        if index < self.committed() {
             return Err(Error::Internal(format!(
                 "Cannot truncate below committed index {}",
-                self.index.len() as u64
+                self.committed()
             )));
         }
-        self.uncommitted.truncate(index as usize - self.index.len());
+        self.uncommitted.truncate((index - self.committed()) as usize);
         Ok(self.len())
     }
 
@@ -246,8 +379,27 @@ impl Store for Hybrid {
         self.metadata_file.set_len(0)?;
         self.metadata_file.seek(SeekFrom::Start(0))?;
         bincode::serialize_into(&mut self.metadata_file, &self.metadata)?;
-        if self.sync {
-            self.metadata_file.sync_data()?;
+        Ok(())
+    }
+
+    fn sync_policy(&self) -> Option<SyncPolicy> {
+        Some(self.sync)
+    }
+
+    fn set_sync_policy(&mut self, sync: SyncPolicy) {
+        self.sync = sync;
+    }
+
+    fn sync_deferred(&mut self) -> Result<()> {
+        match self.sync {
+            SyncPolicy::Grouped(interval) | SyncPolicy::Deferred(interval)
+                if self.unsynced && self.synced.elapsed() >= interval =>
+            {
+                self.file.lock()?.sync_data()?;
+                self.synced = Instant::now();
+                self.unsynced = false;
+            }
+            _ => {}
         }
         Ok(())
     }
@@ -272,8 +424,9 @@ impl<'a> Read for MutexReader<'a> {
 #[cfg(test)]
 impl super::TestSuite<Hybrid> for Hybrid {
     fn setup() -> Result<Self> {
-        let dir = tempdir::TempDir::new("toydb")?;
-        Hybrid::new(dir.as_ref(), false)
+        // Compaction writes a new file to the directory, so it must outlive the store.
+        let dir = tempdir::TempDir::new("toydb")?.into_path();
+        Hybrid::new(&dir, false)
     }
 }
 
@@ -304,3 +457,98 @@ fn test_persistent() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_persistent_grouped() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let mut l =
+        Hybrid::with_sync_policy(dir.as_ref(), SyncPolicy::Grouped(Duration::from_secs(60)))?;
+    assert_eq!(l.sync_policy(), Some(SyncPolicy::Grouped(Duration::from_secs(60))));
+
+    // Commits within the interval aren't fsynced, but are written and flushed on close.
+    l.append(vec![0x01])?;
+    l.commit(1)?;
+    l.append(vec![0x02])?;
+    l.commit(2)?;
+    l.set_metadata(b"term", vec![0x01])?;
+    l.flush()?;
+    drop(l);
+
+    let l = Hybrid::with_sync_policy(dir.as_ref(), SyncPolicy::Always)?;
+    assert_eq!(vec![vec![1], vec![2]], l.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+    assert_eq!(Some(vec![0x01]), l.get_metadata(b"term")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_deferred() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let mut l =
+        Hybrid::with_sync_policy(dir.as_ref(), SyncPolicy::Grouped(Duration::from_secs(60)))?;
+
+    // Grouped commits are left unsynced until the interval has passed.
+    l.append(vec![0x01])?;
+    l.commit(1)?;
+    assert!(l.unsynced);
+    l.sync_deferred()?;
+    assert!(l.unsynced);
+
+    // Deferred commits are never synced on commit, only by sync_deferred().
+    l.set_sync_policy(SyncPolicy::Deferred(Duration::from_secs(0)));
+    assert_eq!(l.sync_policy(), Some(SyncPolicy::Deferred(Duration::from_secs(0))));
+    l.append(vec![0x02])?;
+    l.commit(2)?;
+    assert!(l.unsynced);
+    l.sync_deferred()?;
+    assert!(!l.unsynced);
+
+    Ok(())
+}
+
+#[test]
+fn test_persistent_compact() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let mut l = Hybrid::new(dir.as_ref(), true)?;
+
+    l.append(vec![0x01])?;
+    l.append(vec![0x02])?;
+    l.append(vec![0x03])?;
+    l.append(vec![0x04])?;
+    l.commit(3)?;
+    l.compact(2)?;
+    l.append(vec![0x05])?;
+    l.commit(4)?;
+    drop(l);
+
+    let l = Hybrid::new(dir.as_ref(), true)?;
+    assert_eq!(4, l.len());
+    assert_eq!(4, l.committed());
+    assert_eq!(None, l.get(2)?);
+    assert_eq!(Some(vec![0x03]), l.get(3)?);
+    assert_eq!(vec![vec![3], vec![4]], l.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_persistent_reset() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let mut l = Hybrid::new(dir.as_ref(), true)?;
+
+    l.append(vec![0x01])?;
+    l.append(vec![0x02])?;
+    l.commit(1)?;
+    l.reset(5)?;
+    l.append(vec![0x06])?;
+    l.commit(6)?;
+    drop(l);
+
+    let l = Hybrid::new(dir.as_ref(), true)?;
+    assert_eq!(6, l.len());
+    assert_eq!(6, l.committed());
+    assert_eq!(None, l.get(5)?);
+    assert_eq!(vec![vec![6]], l.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+
+    Ok(())
+}
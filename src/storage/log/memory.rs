@@ -7,15 +7,18 @@ use std::ops::Bound;
 
 // An in-memory log store.
 pub struct Memory {
+    /// Entries following the compacted index.
     log: Vec<Vec<u8>>,
     committed: u64,
+    /// The index of the last compacted entry, if any.
+    compacted: u64,
     metadata: HashMap<Vec<u8>, Vec<u8>>,
 }
 
 impl Memory {
     /// Creates a new in-memory log.
     pub fn new() -> Self {
-        Self { log: Vec::new(), committed: 0, metadata: HashMap::new() }
+        Self { log: Vec::new(), committed: 0, compacted: 0, metadata: HashMap::new() }
     }
 }
 
@@ -28,7 +31,7 @@ impl Display for Memory {
 impl Store for Memory {
     fn append(&mut self, entry: Vec<u8>) -> Result<u64> {
         self.log.push(entry);
-        Ok(self.log.len() as u64)
+        Ok(self.len())
     }
 
     fn commit(&mut self, index: u64) -> Result<()> {
@@ -45,37 +48,58 @@ impl Store for Memory {
         Ok(())
     }
 
+    fn compact(&mut self, index: u64) -> Result<u64> {
+        if index > self.committed {
+            return Err(Error::Internal(format!("Cannot compact uncommitted index {}", index)));
+        }
+        if index > self.compacted {
+            self.log.drain(..(index - self.compacted) as usize);
+            self.compacted = index;
+        }
+        Ok(self.compacted)
+    }
+
     fn committed(&self) -> u64 {
         self.committed
     }
 
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     fn get(&self, index: u64) -> Result<Option<Vec<u8>>> {
         match index {
-            0 => Ok(None),
-            i => Ok(self.log.get(i as usize - 1).cloned()),
+            i if i <= self.compacted => Ok(None),
+            i => Ok(self.log.get((i - self.compacted) as usize - 1).cloned()),
         }
     }
 
     fn len(&self) -> u64 {
-        self.log.len() as u64
+        self.compacted + self.log.len() as u64
     }
 
     fn scan(&self, range: Range) -> super::Scan {
+        let start = match range.start {
+            Bound::Included(n) => n,
+            Bound::Excluded(n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end {
+            Bound::Included(n) => n,
+            Bound::Excluded(0) => 0,
+            Bound::Excluded(n) => n - 1,
+            Bound::Unbounded => self.len(),
+        };
+        // Compacted entries are skipped.
+        let start = std::cmp::max(start, self.compacted + 1);
+        if start > end {
+            return Box::new(std::iter::empty());
+        }
         Box::new(
             self.log
                 .iter()
-                .take(match range.end {
-                    Bound::Included(n) => n as usize,
-                    Bound::Excluded(0) => 0,
-                    Bound::Excluded(n) => n as usize - 1,
-                    Bound::Unbounded => std::usize::MAX,
-                })
-                .skip(match range.start {
-                    Bound::Included(0) => 0,
-                    Bound::Included(n) => n as usize - 1,
-                    Bound::Excluded(n) => n as usize,
-                    Bound::Unbounded => 0,
-                })
+                .skip((start - self.compacted) as usize - 1)
+                .take((end - start) as usize + 1)
                 .cloned()
                 .map(Ok),
         )
@@ -85,6 +109,19 @@ impl Store for Memory {
         self.log.iter().map(|v| v.len() as u64).sum()
     }
 
+    fn reset(&mut self, index: u64) -> Result<()> {
+        if index < self.committed {
+            return Err(Error::Internal(format!(
+                "Cannot reset below committed index {}",
+                self.committed
+            )));
+        }
+        self.log.clear();
+        self.committed = index;
+        self.compacted = index;
+        Ok(())
+    }
+
     fn truncate(&mut self, index: u64) -> Result<u64> {
         if index < self.committed {
             return Err(Error::Internal(format!(
@@ -92,8 +129,8 @@ impl Store for Memory {
                 self.committed
             )));
         }
-        self.log.truncate(index as usize);
-        Ok(self.log.len() as u64)
+        self.log.truncate((index - self.compacted) as usize);
+        Ok(self.len())
     }
 
     fn get_metadata(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
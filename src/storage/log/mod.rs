@@ -3,7 +3,7 @@ mod memory;
 #[cfg(test)]
 mod test;
 
-pub use hybrid::Hybrid;
+pub use hybrid::{Hybrid, SyncPolicy};
 pub use memory::Memory;
 #[cfg(test)]
 pub use test::Test;
@@ -21,13 +21,22 @@ pub trait Store: Display + Sync + Send {
     /// Commits log entries up to and including the given index, making them immutable.
     fn commit(&mut self, index: u64) -> Result<()>;
 
+    /// Removes committed entries up to and including the given index, e.g. once they're covered
+    /// by a state machine snapshot, and returns the highest compacted index. Later entries keep
+    /// their indexes, and compacted entries are no longer returned by get or scan. Errors if asked
+    /// to compact uncommitted entries.
+    fn compact(&mut self, index: u64) -> Result<u64>;
+
     /// Returns the committed index, if any.
     fn committed(&self) -> u64;
 
+    /// Flushes any buffered writes, including metadata, to durable storage.
+    fn flush(&mut self) -> Result<()>;
+
     /// Fetches a log entry, if it exists.
     fn get(&self, index: u64) -> Result<Option<Vec<u8>>>;
 
-    /// Returns the number of entries in the log.
+    /// Returns the index of the last entry, i.e. the number of entries including compacted ones.
     fn len(&self) -> u64;
 
     /// Scans the log between the given indexes.
@@ -36,6 +45,11 @@ pub trait Store: Display + Sync + Send {
     /// Returns the size of the log, in bytes.
     fn size(&self) -> u64;
 
+    /// Discards all entries and restarts the log after the given index as committed and compacted,
+    /// e.g. when the log is replaced by a snapshot. Errors if asked to reset below the committed
+    /// index.
+    fn reset(&mut self, index: u64) -> Result<()>;
+
     /// Truncates the log be removing any entries above the given index, and returns the
     /// highest index. Errors if asked to truncate any committed entries.
     fn truncate(&mut self, index: u64) -> Result<u64>;
@@ -50,6 +64,21 @@ pub trait Store: Display + Sync + Send {
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Returns when the store fsyncs its writes, or None if it has no durable storage.
+    fn sync_policy(&self) -> Option<SyncPolicy> {
+        None
+    }
+
+    /// Sets when the store fsyncs its writes. Ignored by stores without durable storage.
+    fn set_sync_policy(&mut self, _sync: SyncPolicy) {}
+
+    /// Fsyncs committed entries whose fsync was deferred by the sync policy, once the policy's
+    /// interval has passed. Called periodically, so that they're synced even if no further
+    /// commits arrive.
+    fn sync_deferred(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// A scan range.
@@ -90,8 +119,10 @@ trait TestSuite<S: Store> {
     fn test() -> Result<()> {
         Self::test_append()?;
         Self::test_commit_truncate()?;
+        Self::test_compact()?;
         Self::test_get()?;
         Self::test_metadata()?;
+        Self::test_reset()?;
         Self::test_scan()?;
         Ok(())
     }
@@ -144,6 +175,53 @@ trait TestSuite<S: Store> {
         Ok(())
     }
 
+    fn test_compact() -> Result<()> {
+        let mut s = Self::setup()?;
+
+        // Compacting an empty store should be fine.
+        assert_eq!(0, s.compact(0)?);
+
+        s.append(vec![0x01])?;
+        s.append(vec![0x02])?;
+        s.append(vec![0x03])?;
+        s.append(vec![0x04])?;
+        s.commit(3)?;
+
+        // Compacting uncommitted entries should error.
+        assert_eq!(Err(Error::Internal("Cannot compact uncommitted index 4".into())), s.compact(4));
+
+        // Compacted entries are gone, but the others keep their indexes.
+        assert_eq!(2, s.compact(2)?);
+        assert_eq!(4, s.len());
+        assert_eq!(3, s.committed());
+        assert_eq!(None, s.get(2)?);
+        assert_eq!(Some(vec![0x03]), s.get(3)?);
+        assert_eq!(Some(vec![0x04]), s.get(4)?);
+        assert_eq!(vec![vec![3], vec![4]], s.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+        assert_eq!(vec![vec![3]], s.scan(Range::from(1..=3)).collect::<Result<Vec<_>>>()?);
+        assert!(s.scan(Range::from(..=2)).collect::<Result<Vec<_>>>()?.is_empty());
+
+        // Compacting below the compacted index does nothing.
+        assert_eq!(2, s.compact(1)?);
+        assert_eq!(Some(vec![0x03]), s.get(3)?);
+
+        // Appends, commits and truncates continue at the same indexes.
+        assert_eq!(4, s.truncate(4)?);
+        assert_eq!(3, s.truncate(3)?);
+        assert_eq!(4, s.append(vec![0x05])?);
+        s.commit(4)?;
+        assert_eq!(Some(vec![0x05]), s.get(4)?);
+
+        // Compacting all entries leaves an empty log at the same index.
+        assert_eq!(4, s.compact(4)?);
+        assert_eq!(4, s.len());
+        assert!(s.scan(Range::from(..)).collect::<Result<Vec<_>>>()?.is_empty());
+        assert_eq!(5, s.append(vec![0x06])?);
+        assert_eq!(vec![vec![6]], s.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+
+        Ok(())
+    }
+
     fn test_get() -> Result<()> {
         let mut s = Self::setup()?;
         s.append(vec![0x01])?;
@@ -163,6 +241,37 @@ trait TestSuite<S: Store> {
         Ok(())
     }
 
+    fn test_reset() -> Result<()> {
+        let mut s = Self::setup()?;
+        s.append(vec![0x01])?;
+        s.append(vec![0x02])?;
+        s.append(vec![0x03])?;
+        s.commit(2)?;
+
+        // Resetting below the committed index should error.
+        assert_eq!(Err(Error::Internal("Cannot reset below committed index 2".into())), s.reset(1));
+
+        // Resetting discards all entries, including ones after the reset index.
+        s.reset(2)?;
+        assert_eq!(2, s.len());
+        assert_eq!(2, s.committed());
+        assert_eq!(None, s.get(2)?);
+        assert!(s.scan(Range::from(..)).collect::<Result<Vec<_>>>()?.is_empty());
+
+        // Resetting beyond the end of the log works too, and appends continue after it.
+        s.reset(5)?;
+        assert_eq!(5, s.len());
+        assert_eq!(5, s.committed());
+        assert_eq!(6, s.append(vec![0x06])?);
+        assert_eq!(vec![vec![6]], s.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+        assert_eq!(
+            Err(Error::Internal("Cannot truncate below committed index 5".into())),
+            s.truncate(4)
+        );
+
+        Ok(())
+    }
+
     #[allow(clippy::reversed_empty_ranges)]
     fn test_scan() -> Result<()> {
         let mut s = Self::setup()?;
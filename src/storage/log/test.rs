@@ -1,21 +1,46 @@
 use super::{Hybrid, Memory, Range, Scan, Store};
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 use std::fmt::Display;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 /// Log storage backend for testing. Protects an inner Memory backend using a mutex, so it can
-/// be cloned and inspected.
+/// be cloned and inspected. Also counts flushes and deferred syncs, and can be made to fail
+/// flushes.
 #[derive(Clone)]
 pub struct Test {
     store: Arc<RwLock<Memory>>,
+    flushes: Arc<AtomicU64>,
+    syncs: Arc<AtomicU64>,
+    fail_flush: Arc<AtomicBool>,
 }
 
 impl Test {
     /// Creates a new Test key-value storage engine.
     pub fn new() -> Self {
-        Self { store: Arc::new(RwLock::new(Memory::new())) }
+        Self {
+            store: Arc::new(RwLock::new(Memory::new())),
+            flushes: Arc::new(AtomicU64::new(0)),
+            syncs: Arc::new(AtomicU64::new(0)),
+            fail_flush: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns the number of successful flushes.
+    pub fn flushes(&self) -> u64 {
+        self.flushes.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of deferred syncs, see Store::sync_deferred().
+    pub fn syncs(&self) -> u64 {
+        self.syncs.load(Ordering::SeqCst)
+    }
+
+    /// Makes subsequent flushes fail, or succeed again.
+    pub fn fail_flush(&self, fail: bool) {
+        self.fail_flush.store(fail, Ordering::SeqCst)
     }
 }
 
@@ -34,10 +59,23 @@ impl Store for Test {
         self.store.write()?.commit(index)
     }
 
+    fn compact(&mut self, index: u64) -> Result<u64> {
+        self.store.write()?.compact(index)
+    }
+
     fn committed(&self) -> u64 {
         self.store.read().unwrap().committed()
     }
 
+    fn flush(&mut self) -> Result<()> {
+        if self.fail_flush.load(Ordering::SeqCst) {
+            return Err(Error::Internal("Flush failed".into()));
+        }
+        self.store.write()?.flush()?;
+        self.flushes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
     fn get(&self, index: u64) -> Result<Option<Vec<u8>>> {
         self.store.read()?.get(index)
     }
@@ -55,6 +93,10 @@ impl Store for Test {
         self.store.read().unwrap().size()
     }
 
+    fn reset(&mut self, index: u64) -> Result<()> {
+        self.store.write()?.reset(index)
+    }
+
     fn truncate(&mut self, index: u64) -> Result<u64> {
         self.store.write()?.truncate(index)
     }
@@ -66,6 +108,12 @@ impl Store for Test {
     fn set_metadata(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
         self.store.write()?.set_metadata(key, value)
     }
+
+    fn sync_deferred(&mut self) -> Result<()> {
+        self.store.write()?.sync_deferred()?;
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,73 @@
+use super::disk_manager::DiskManager;
+use super::page::PAGE_SIZE;
+use crate::error::{Error, Result};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A DiskManager for async code, e.g. the tokio server. the blocking file I/O runs on the
+/// tokio blocking thread pool, so it doesn't stall the executor. clones share the DiskManager,
+/// and its calls are serialized
+#[derive(Clone)]
+pub struct AsyncDiskManager {
+    disk_manager: Arc<Mutex<DiskManager>>,
+}
+
+impl AsyncDiskManager {
+    /// Creates or opens a disk db, with files in the given directory.
+    pub async fn open(db_dir: &Path) -> Result<AsyncDiskManager> {
+        let db_dir = db_dir.to_path_buf();
+        let disk_manager = blocking(move || DiskManager::open(&db_dir)).await?;
+        Ok(AsyncDiskManager { disk_manager: Arc::new(Mutex::new(disk_manager)) })
+    }
+
+    /// Read the contents of the specified page
+    pub async fn read_page(&self, page_id: u32) -> Result<[u8; PAGE_SIZE]> {
+        self.with(move |disk_manager| {
+            let mut page_data = [0u8; PAGE_SIZE];
+            disk_manager.read_page(page_id, &mut page_data)?;
+            Ok(page_data)
+        })
+        .await
+    }
+
+    /// Write the contents of the specified page into disk file
+    pub async fn write_page(&self, page_id: u32, page_data: Vec<u8>) -> Result<()> {
+        self.with(move |disk_manager| disk_manager.write_page(page_id, &page_data)).await
+    }
+
+    /// return a page id for a new page, see DiskManager::allocate_page
+    pub async fn allocate_page(&self) -> Result<u32> {
+        self.with(|disk_manager| disk_manager.allocate_page()).await
+    }
+
+    /// add a page to the free list, see DiskManager::deallocate_page
+    pub async fn deallocate_page(&self, page_id: u32) -> Result<()> {
+        self.with(move |disk_manager| disk_manager.deallocate_page(page_id)).await
+    }
+
+    /// run a closure with the DiskManager on the blocking thread pool, e.g. for the calls
+    /// without an async variant
+    pub async fn with<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut DiskManager) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let disk_manager = Arc::clone(&self.disk_manager);
+        blocking(move || {
+            let mut disk_manager = disk_manager.lock()?;
+            f(&mut disk_manager)
+        })
+        .await
+    }
+}
+
+/// run a blocking closure on the tokio blocking thread pool
+pub(crate) async fn blocking<R, F>(f: F) -> Result<R>
+where
+    F: FnOnce() -> Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Internal(format!("blocking task failed: {}", err)))?
+}
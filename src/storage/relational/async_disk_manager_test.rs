@@ -0,0 +1,96 @@
+use crate::error::{Error, Result};
+use crate::storage::relational::async_disk_manager::AsyncDiskManager;
+use crate::storage::relational::buffer_pool::AsyncBufferPoolManager;
+use crate::storage::relational::page::PAGE_SIZE;
+use crate::storage::relational::tuple::{Tuple, RID};
+use std::time::{Duration, SystemTime};
+use tempdir::TempDir;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_concurrent_pages() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let disk_manager = AsyncDiskManager::open(dir.path()).await?;
+
+    // two tasks write and read back their own page at the same time
+    let tasks: Vec<_> = (1..=2u32)
+        .map(|page_id| {
+            let disk_manager = disk_manager.clone();
+            tokio::spawn(async move {
+                for round in 0..10u8 {
+                    let value = page_id as u8 * 16 + round;
+                    disk_manager.write_page(page_id, vec![value; PAGE_SIZE]).await?;
+                    let page_data = disk_manager.read_page(page_id).await?;
+                    assert!(page_data.iter().all(|b| *b == value), "page {}", page_id);
+                }
+                Ok::<_, Error>(())
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap()?;
+    }
+    assert!(disk_manager.read_page(1).await?.iter().all(|b| *b == 16 + 9));
+    assert!(disk_manager.read_page(2).await?.iter().all(|b| *b == 32 + 9));
+    assert!(disk_manager.read_page(3).await.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_buffer_pool() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let buffer_pool = AsyncBufferPoolManager::open(dir.path(), 4).await?;
+    let root_id = buffer_pool.create_table("a").await?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id)).await?.write()?.get_page_id();
+
+    // two tasks fill a page each, through the shared buffer pool
+    let tasks: Vec<_> = vec![root_id, second_id]
+        .into_iter()
+        .map(|page_id| {
+            let buffer_pool = buffer_pool.clone();
+            tokio::spawn(async move {
+                let page = buffer_pool.fetch_page(page_id).await?.unwrap();
+                let mut tuple = Tuple::from_data(vec![page_id as u8; 64]);
+                tuple.set_rid(RID::new(page_id, 0));
+                assert!(page.write()?.insert_tuple(&mut tuple)?);
+                buffer_pool.unpin_page(page_id, true).await
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert!(task.await.unwrap()?);
+    }
+    buffer_pool.flush_all().await?;
+    drop(buffer_pool);
+
+    let buffer_pool = AsyncBufferPoolManager::open(dir.path(), 4).await?;
+    for page_id in [root_id, second_id] {
+        let page = buffer_pool.fetch_page(page_id).await?.unwrap();
+        assert_eq!(1, page.write()?.tuples().count());
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_expiry() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let buffer_pool = AsyncBufferPoolManager::open(dir.path(), 4).await?;
+    let root_id = buffer_pool
+        .with(|buffer_pool| buffer_pool.create_table_with_ttl("a", Duration::from_millis(50)))
+        .await?
+        .unwrap();
+    buffer_pool
+        .with(move |buffer_pool| {
+            let mut tuple = Tuple::from_data(vec![0x01; 64]);
+            tuple.set_rid(RID::new(root_id, 0));
+            buffer_pool.insert_tuple("a", &mut tuple)
+        })
+        .await?;
+
+    // the background task deletes the tuple once it expires
+    tokio::spawn(buffer_pool.clone().serve_expiry(Duration::from_millis(10)));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let page = buffer_pool.fetch_page(root_id).await?.unwrap();
+    assert_eq!(0, page.write()?.tuples().count());
+    assert!(buffer_pool.with(|b| b.scan_table("a", SystemTime::now())).await?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use super::buffer_pool::BufferPoolManager;
+use crate::error::{Error, Result};
+use std::io::{self, Read};
+use std::sync::Mutex;
+
+/// A reader streaming a blob, holding at most one chunk of it in memory. a blob too large for
+/// a tuple is read from its chain of overflow pages, see BufferPoolManager::insert_blob, one
+/// page at a time. the buffer pool is only locked while a page is read, so other users of the
+/// buffer pool can go on in between
+pub struct BlobReader<'a> {
+    buffer_pool: &'a Mutex<BufferPoolManager>,
+    /// the chunk being read
+    chunk: Vec<u8>,
+    /// the position read up to in the chunk
+    position: usize,
+    /// the next overflow page to read, 0 after the last one
+    next_page_id: u32,
+    /// the bytes of the blob not read yet, including the rest of the chunk
+    remaining: u64,
+}
+
+impl<'a> BlobReader<'a> {
+    /// read a blob of the given length from its overflow pages
+    pub fn new(
+        buffer_pool: &'a Mutex<BufferPoolManager>,
+        first_page_id: u32,
+        len: u64,
+    ) -> BlobReader<'a> {
+        BlobReader {
+            buffer_pool,
+            chunk: Vec::new(),
+            position: 0,
+            next_page_id: first_page_id,
+            remaining: len,
+        }
+    }
+
+    /// read a blob stored inline in a tuple
+    pub fn inline(buffer_pool: &'a Mutex<BufferPoolManager>, data: Vec<u8>) -> BlobReader<'a> {
+        let remaining = data.len() as u64;
+        BlobReader { buffer_pool, chunk: data, position: 0, next_page_id: 0, remaining }
+    }
+
+    /// the bytes of the blob not read yet
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// replace the chunk read in full with the one of the next overflow page
+    fn next_chunk(&mut self) -> Result<()> {
+        if self.next_page_id == 0 {
+            return Err(Error::Value(format!("blob ends {} bytes early", self.remaining)));
+        }
+        let (chunk, next_page_id) = self.buffer_pool.lock()?.read_blob_chunk(self.next_page_id)?;
+        if chunk.is_empty() {
+            return Err(Error::Value(format!("blob page {} is empty", self.next_page_id)));
+        }
+        self.chunk = chunk;
+        self.position = 0;
+        self.next_page_id = next_page_id;
+        Ok(())
+    }
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.position == self.chunk.len() {
+            self.next_chunk().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+        let available = (self.chunk.len() - self.position).min(buf.len());
+        let len = available.min(self.remaining.min(usize::MAX as u64) as usize);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+}
@@ -1,53 +1,586 @@
 use std::{
+    collections::HashSet,
     path::Path,
-    sync::{Arc, Mutex},
+    sync::atomic::{AtomicU64, Ordering},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::storage::relational::page::HeaderPage;
+use crate::storage::relational::page::{HeaderPage, Page};
 use crate::{error::Error, error::Result, storage::relational::page::PAGE_SIZE};
 
-use super::{clock_replacer::ClockReplacer, disk_manager::DiskManager, page::TablePage};
+use super::{
+    async_disk_manager::blocking,
+    clock_replacer::ClockReplacer,
+    disk_manager::DiskManager,
+    page::TablePage,
+    replacer::Replacer,
+    tuple::{Tuple, RID},
+};
+
+/// Cache statistics of a buffer pool, counted since it was opened
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BufferStats {
+    /// fetched pages served from the cache
+    pub hits: u64,
+    /// fetched pages read from disk
+    pub misses: u64,
+    /// pages evicted from the cache to make room for another page
+    pub evictions: u64,
+}
+
+/// A page pinned for shared use by BufferPoolManager::fetch_page_shared. the page can't be
+/// evicted while the guard is alive, and it's unpinned when the guard is dropped
+pub struct SharedPage {
+    page: Arc<RwLock<TablePage>>,
+}
+
+impl SharedPage {
+    /// take a shared latch on the page, so several readers can use it at once
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, TablePage>> {
+        Ok(self.page.read()?)
+    }
+}
+
+impl Drop for SharedPage {
+    fn drop(&mut self) {
+        unpin(&self.page);
+    }
+}
+
+/// A page pinned for exclusive use by BufferPoolManager::fetch_page_exclusive. the page
+/// can't be evicted while the guard is alive, and it's unpinned when the guard is dropped
+pub struct ExclusivePage {
+    page: Arc<RwLock<TablePage>>,
+}
+
+impl ExclusivePage {
+    /// take an exclusive latch on the page, waiting for its shared latches to be released
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, TablePage>> {
+        Ok(self.page.write()?)
+    }
+}
+
+impl Drop for ExclusivePage {
+    fn drop(&mut self) {
+        unpin(&self.page);
+    }
+}
+
+/// unpin a page held by a guard. a poisoned latch is still unpinned, so a panicking reader
+/// doesn't leave the page pinned forever. this takes the page latch, so a thread must not
+/// drop a guard while it holds a latch on the same page through another guard
+fn unpin(page: &RwLock<TablePage>) {
+    page.write().unwrap_or_else(|err| err.into_inner()).unpin();
+}
 
 /// BufferPool struct
 pub struct BufferPoolManager {
     header_page: HeaderPage,
     disk_manager: DiskManager,
-    clock_replacer: ClockReplacer,
+    replacer: Box<dyn Replacer>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
 }
 
 impl BufferPoolManager {
     pub fn open(dir: &Path, cache_capacity: u32) -> Result<BufferPoolManager> {
-        let clock_replacer = ClockReplacer::new(cache_capacity)?;
+        BufferPoolManager::open_with_replacer(dir, Box::new(ClockReplacer::new(cache_capacity)?))
+    }
 
+    /// open the db in the given directory, caching pages with the given eviction policy
+    pub fn open_with_replacer(
+        dir: &Path,
+        replacer: Box<dyn Replacer>,
+    ) -> Result<BufferPoolManager> {
         let mut disk_manager = DiskManager::open(dir)?;
-        let mut header_page_data = [0u8; PAGE_SIZE];
-        disk_manager.read_page(0, &mut header_page_data)?;
-        let header_page = HeaderPage::new(header_page_data)?;
+        // a fresh db file has no header page yet
+        let mut header_page = if disk_manager.have_page(0)? {
+            let mut header_page_data = [0u8; PAGE_SIZE];
+            disk_manager.read_page(0, &mut header_page_data)?;
+            Page::verify_checksum(0, &header_page_data)?;
+            HeaderPage::from_data(header_page_data)?
+        } else {
+            HeaderPage::new([0u8; PAGE_SIZE])?
+        };
+        // the records may continue on more header pages
+        while let Some(page_id) = header_page.get_unloaded_page_id()? {
+            let mut header_page_data = [0u8; PAGE_SIZE];
+            disk_manager.read_page(page_id, &mut header_page_data)?;
+            Page::verify_checksum(page_id, &header_page_data)?;
+            header_page.push_page(page_id, header_page_data)?;
+        }
+
+        Ok(BufferPoolManager {
+            disk_manager,
+            replacer,
+            header_page,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        })
+    }
+
+    /// create a table with an empty root page, and record it in the header page.
+    /// return the root page id, or None if the name is too long or already exists
+    pub fn create_table(&mut self, name: &str) -> Result<Option<u32>> {
+        self.create_table_with_fill_factor(name, 1.0)
+    }
+
+    /// create a table whose pages only accept inserts until the given fraction of
+    /// their usable space is used, leaving room for updates to grow tuples in place
+    pub fn create_table_with_fill_factor(
+        &mut self,
+        name: &str,
+        fill_factor: f64,
+    ) -> Result<Option<u32>> {
+        self.create_table_with_options(name, fill_factor, 0)
+    }
+
+    /// create a table whose tuples expire the given time after they are inserted. expired
+    /// tuples are skipped by reads, and deleted by expire_tuples
+    pub fn create_table_with_ttl(&mut self, name: &str, ttl: Duration) -> Result<Option<u32>> {
+        // a ttl of 0 is recorded as tuples not expiring
+        let ttl = ttl.as_millis() as u64;
+        if ttl == 0 {
+            return Err(Error::Value(String::from("ttl must be at least 1 ms")));
+        }
+        self.create_table_with_options(name, 1.0, ttl)
+    }
+
+    /// create a table with a fill factor and a ttl in milliseconds, 0 if tuples don't expire
+    fn create_table_with_options(
+        &mut self,
+        name: &str,
+        fill_factor: f64,
+        ttl: u64,
+    ) -> Result<Option<u32>> {
+        if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+            return Err(Error::Value(format!("invalid fill factor {}", fill_factor)));
+        }
+        if name.len() > 32 || self.header_page.get_root_id(name)?.is_some() {
+            return Ok(None);
+        }
+        let root_page = self.allocate_page(None)?;
+        let root_id = *root_page.write()?.get_page_id();
+        if !self.header_page.insert_record(name, root_id)? {
+            // every header page is full, so chain another one
+            let page_id = self.disk_manager.allocate_page()?;
+            self.header_page.extend(page_id)?;
+            self.header_page.insert_record(name, root_id)?;
+        }
+        self.header_page.set_fill_factor(name, fill_factor)?;
+        self.header_page.set_ttl(name, ttl)?;
+        Ok(Some(root_id))
+    }
+
+    /// drop a table: every page of its chain is returned to the free list, which is kept on
+    /// disk so the pages are reused after a reopen too, then the record is removed from the
+    /// header page. return false if the table does not exist
+    pub fn drop_table(&mut self, name: &str) -> Result<bool> {
+        let mut page_id = match self.header_page.get_root_id(name)? {
+            Some(root_id) => root_id,
+            None => return Ok(false),
+        };
+        let mut freed = HashSet::new();
+        loop {
+            let page = self.fetch_page(page_id)?.ok_or_else(|| {
+                Error::Value(format!("page {} of table {} can not be found", page_id, name))
+            })?;
+            let next_page_id = page.write()?.get_next_page_id()?;
+            drop(page);
+            self.free_page(page_id)?;
+            freed.insert(page_id);
+
+            // page 0 is the header page, so it also marks the end of the chain
+            if next_page_id == 0 || freed.contains(&next_page_id) {
+                break;
+            }
+            page_id = next_page_id;
+        }
+        self.header_page.delete_record(name)
+    }
+
+    /// return the root page id of a table, if it exists
+    pub fn get_table_root_id(&self, name: &str) -> Result<Option<u32>> {
+        self.header_page.get_root_id(name)
+    }
+
+    /// return the fill factor of a table, if it exists
+    pub fn get_table_fill_factor(&self, name: &str) -> Result<Option<f64>> {
+        self.header_page.get_fill_factor(name)
+    }
+
+    /// return the time to live of a table's tuples, if the table exists and they expire
+    pub fn get_table_ttl(&self, name: &str) -> Result<Option<Duration>> {
+        Ok(match self.header_page.get_ttl(name)? {
+            Some(0) | None => None,
+            Some(ttl) => Some(Duration::from_millis(ttl)),
+        })
+    }
+
+    /// insert a tuple into the first page of a table with room for it under the table's
+    /// fill factor, appending a new page to the table if none has. if the table has a ttl,
+    /// the tuple is stored with its expiry time, which is set on the tuple.
+    /// return false if the table does not exist or the tuple has no rid
+    pub fn insert_tuple(&mut self, name: &str, tuple: &mut Tuple) -> Result<bool> {
+        if tuple.get_rid().is_none() {
+            return Ok(false);
+        }
+        match self.header_page.get_ttl(name)? {
+            Some(ttl) if ttl > 0 => {
+                let expires_at = unix_millis(SystemTime::now()).saturating_add(ttl);
+                let mut stored = tuple.to_expiring(expires_at);
+                if !self.insert_stored_tuple(name, &mut stored)? {
+                    return Ok(false);
+                }
+                *tuple = Tuple::from_expiring(stored)?;
+                Ok(true)
+            }
+            _ => self.insert_stored_tuple(name, tuple),
+        }
+    }
+
+    /// insert a tuple as stored in the pages of a table
+    fn insert_stored_tuple(&mut self, name: &str, tuple: &mut Tuple) -> Result<bool> {
+        let (mut page_id, fill_factor) =
+            match (self.header_page.get_root_id(name)?, self.header_page.get_fill_factor(name)?) {
+                (Some(root_id), Some(fill_factor)) => (root_id, fill_factor),
+                _ => return Ok(false),
+            };
+        loop {
+            let page = self.fetch_page(page_id)?.ok_or_else(|| {
+                Error::Value(format!("page {} of table {} can not be found", page_id, name))
+            })?;
+            let mut table_page = page.write()?;
+            table_page.unpin();
+            if table_page.insert_tuple_with_fill_factor(tuple, fill_factor)? {
+                return Ok(true);
+            }
+            match table_page.get_next_page_id()? {
+                0 => break,
+                next_page_id => page_id = next_page_id,
+            }
+        }
+
+        let page = self.allocate_page(Some(page_id))?;
+        let mut table_page = page.write()?;
+        if !table_page.insert_tuple_with_fill_factor(tuple, fill_factor)? {
+            return Err(Error::Value(format!(
+                "tuple of {} bytes does not fit in an empty page of table {}",
+                tuple.get_length(),
+                name
+            )));
+        }
+        Ok(true)
+    }
+
+    /// store a blob too large for a tuple in a chain of new overflow pages, linked by their
+    /// next page ids, each holding a single tuple with a chunk of the blob. an empty blob has
+    /// no chunk, so it can't be stored this way. return the first page id
+    pub fn insert_blob(&mut self, data: &[u8]) -> Result<u32> {
+        if data.is_empty() {
+            return Err(Error::Value(String::from("an empty blob has no overflow pages")));
+        }
+        // the chunks are stored last first, so each page is linked to the next one as it is
+        // written, without fetching the next one again
+        let mut next_page_id = 0;
+        for chunk in data.chunks(TablePage::MAX_TUPLE_SIZE).rev() {
+            let page = self.allocate_page(None)?;
+            let mut table_page = page.write()?;
+            let page_id = *table_page.get_page_id();
+            let mut tuple = Tuple::from_data(chunk.to_vec());
+            tuple.set_rid(RID::new(page_id, 0));
+            if !table_page.insert_tuple(&mut tuple)? {
+                return Err(Error::Internal(format!(
+                    "blob chunk does not fit in page {}",
+                    page_id
+                )));
+            }
+            table_page.set_next_page_id(next_page_id)?;
+            next_page_id = page_id;
+        }
+        Ok(next_page_id)
+    }
+
+    /// read the chunk held by an overflow page of a blob, with the id of the next overflow
+    /// page, 0 for the last one
+    pub fn read_blob_chunk(&mut self, page_id: u32) -> Result<(Vec<u8>, u32)> {
+        let page = self
+            .fetch_page(page_id)?
+            .ok_or_else(|| Error::Value(format!("blob page {} can not be found", page_id)))?;
+        let mut table_page = page.write()?;
+        table_page.unpin();
+        let chunk = table_page
+            .get_tuple(&RID::new(page_id, 0))?
+            .ok_or_else(|| Error::Value(format!("page {} holds no blob chunk", page_id)))?;
+        Ok((chunk.get_data().to_vec(), table_page.get_next_page_id()?))
+    }
+
+    /// delete the overflow pages of a blob given its first page id. like the pages of a
+    /// dropped table, they are returned to the free list
+    pub fn delete_blob(&mut self, first_page_id: u32) -> Result<()> {
+        let mut page_id = first_page_id;
+        let mut freed = HashSet::new();
+        // page 0 is the header page, so it marks the end of the chain
+        while page_id != 0 && !freed.contains(&page_id) {
+            let page = self
+                .fetch_page(page_id)?
+                .ok_or_else(|| Error::Value(format!("blob page {} can not be found", page_id)))?;
+            let next_page_id = page.write()?.get_next_page_id()?;
+            drop(page);
+            self.free_page(page_id)?;
+            freed.insert(page_id);
+            page_id = next_page_id;
+        }
+        Ok(())
+    }
+
+    /// read a tuple of a table, unless it expired at the given time. a tuple of a table with
+    /// a ttl is returned without its expiry time, which is set on the tuple instead
+    pub fn get_tuple(&mut self, name: &str, rid: &RID, now: SystemTime) -> Result<Option<Tuple>> {
+        let ttl = self
+            .header_page
+            .get_ttl(name)?
+            .ok_or_else(|| Error::Value(format!("table {} can not be found", name)))?;
+        let page = self.fetch_page(*rid.get_page_id())?.ok_or_else(|| {
+            Error::Value(format!("page {} of table {} can not be found", rid.get_page_id(), name))
+        })?;
+        let mut table_page = page.write()?;
+        table_page.unpin();
+        match table_page.get_tuple(rid)? {
+            Some(tuple) if ttl > 0 => {
+                let tuple = Tuple::from_expiring(tuple)?;
+                Ok(Some(tuple).filter(|tuple| !tuple.is_expired(unix_millis(now))))
+            }
+            tuple => Ok(tuple),
+        }
+    }
+
+    /// read the tuples of a table in page and slot order, skipping those expired at the given
+    /// time. tuples of a table with a ttl are returned as by get_tuple
+    pub fn scan_table(&mut self, name: &str, now: SystemTime) -> Result<Vec<Tuple>> {
+        let ttl = self
+            .header_page
+            .get_ttl(name)?
+            .ok_or_else(|| Error::Value(format!("table {} can not be found", name)))?;
+        let now = unix_millis(now);
+        let mut tuples = Vec::new();
+        self.for_each_table_page(name, |table_page| {
+            for item in table_page.tuples() {
+                let (_, tuple) = item?;
+                if ttl == 0 {
+                    tuples.push(tuple);
+                    continue;
+                }
+                let tuple = Tuple::from_expiring(tuple)?;
+                if !tuple.is_expired(now) {
+                    tuples.push(tuple);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(tuples)
+    }
+
+    /// delete the tuples of a table that expired at the given time, freeing their space.
+    /// the other tuples keep their RIDs. return the number of deleted tuples
+    pub fn expire_tuples(&mut self, name: &str, now: SystemTime) -> Result<usize> {
+        match self.header_page.get_ttl(name)? {
+            Some(ttl) if ttl > 0 => {}
+            _ => return Ok(0),
+        }
+        let now = unix_millis(now);
+        let mut expired = 0;
+        self.for_each_table_page(name, |table_page| {
+            let mut rids = Vec::new();
+            for item in table_page.tuples() {
+                let (rid, tuple) = item?;
+                if Tuple::from_expiring(tuple)?.is_expired(now) {
+                    rids.push(rid);
+                }
+            }
+            for rid in &rids {
+                table_page.mark_delete(rid)?;
+                table_page.apply_delete(rid)?;
+            }
+            expired += rids.len();
+            Ok(())
+        })?;
+        Ok(expired)
+    }
+
+    /// delete the expired tuples of every table with a ttl, see expire_tuples.
+    /// return the number of deleted tuples
+    pub fn expire_all(&mut self, now: SystemTime) -> Result<usize> {
+        let mut expired = 0;
+        for name in self.header_page.get_record_names()? {
+            expired += self.expire_tuples(&name, now)?;
+        }
+        Ok(expired)
+    }
+
+    /// call a visitor with every live table page of the db, in page id order, e.g. for
+    /// maintenance jobs. header pages, pages on the free list and deleted pages are skipped.
+    /// each page is pinned and exclusively latched while it is visited, and a page changed by
+    /// the visitor is written back like any other. stops at the first error
+    pub fn for_each_page<F>(&mut self, mut visitor: F) -> Result<()>
+    where
+        F: FnMut(&mut TablePage) -> Result<()>,
+    {
+        let mut skipped: HashSet<u32> = self.header_page.get_page_ids().into_iter().collect();
+        skipped.extend(self.disk_manager.get_free_pages()?);
+        for page_id in 1..self.disk_manager.get_num_pages()? {
+            if skipped.contains(&page_id) {
+                continue;
+            }
+            let page = self
+                .fetch_page(page_id)?
+                .ok_or_else(|| Error::Value(format!("page {} can not be found", page_id)))?;
+            let mut table_page = page.write()?;
+            let result = match table_page.page_is_deleted() {
+                Ok(true) => Ok(()),
+                Ok(false) => visitor(&mut table_page),
+                Err(err) => Err(err),
+            };
+            table_page.unpin();
+            result?;
+        }
+        Ok(())
+    }
+
+    /// call a function with each page of a table, in chain order.
+    /// return false if the table does not exist
+    fn for_each_table_page<F>(&mut self, name: &str, mut f: F) -> Result<bool>
+    where
+        F: FnMut(&mut TablePage) -> Result<()>,
+    {
+        let mut page_id = match self.header_page.get_root_id(name)? {
+            Some(root_id) => root_id,
+            None => return Ok(false),
+        };
+        loop {
+            let page = self.fetch_page(page_id)?.ok_or_else(|| {
+                Error::Value(format!("page {} of table {} can not be found", page_id, name))
+            })?;
+            let mut table_page = page.write()?;
+            table_page.unpin();
+            f(&mut table_page)?;
+            // page 0 is the header page, so it marks the end of the chain
+            match table_page.get_next_page_id()? {
+                0 => return Ok(true),
+                next_page_id => page_id = next_page_id,
+            }
+        }
+    }
+
+    /// allocate an empty table page from the disk manager, which reuses a free page if there
+    /// is one. if prev_page_id is given, the new page is linked after it
+    pub fn allocate_page(&mut self, prev_page_id: Option<u32>) -> Result<Arc<RwLock<TablePage>>> {
+        let page_id = self.disk_manager.allocate_page()?;
+        // the link to the previous page is set below, together with its link back
+        let mut table_page = TablePage::new(page_id, None, [0u8; PAGE_SIZE])?;
+        table_page.get_status_mut().edited();
+        let page = self.push_cache(table_page)?.ok_or_else(|| {
+            Error::Internal(format!("page {} was not cached after allocation", page_id))
+        })?;
+
+        if let Some(prev_id) = prev_page_id {
+            self.fetch_page(prev_id)?.ok_or_else(|| {
+                Error::Value(format!("previous page {} can not be found", prev_id))
+            })?;
+            for mut table_page in self.lock_pages_ordered(&[prev_id, page_id])? {
+                if *table_page.get_page_id() == prev_id {
+                    table_page.unpin();
+                    table_page.set_next_page_id(page_id)?;
+                } else {
+                    table_page.set_prev_page_id(prev_id)?;
+                }
+            }
+        }
+        Ok(page)
+    }
+
+    /// lock several cached pages at once, for operations spanning pages. the locks are
+    /// always taken in ascending page id order, so threads locking overlapping pages can't
+    /// deadlock. the guards are returned in that order, without duplicates.
+    /// every page must already be cached, e.g. by fetch_page
+    pub fn lock_pages_ordered(
+        &self,
+        page_ids: &[u32],
+    ) -> Result<Vec<RwLockWriteGuard<'_, TablePage>>> {
+        let mut page_ids = page_ids.to_vec();
+        page_ids.sort_unstable();
+        page_ids.dedup();
+        // finding a cached page latches every cached page, so find them all before locking
+        let pages = page_ids
+            .iter()
+            .map(|page_id| self.cached_page(*page_id))
+            .collect::<Result<Vec<_>>>()?;
+        pages.into_iter().map(|page| Ok(page.write()?)).collect()
+    }
+
+    /// fetch a page for shared use, reading it from disk on a miss. the returned guard keeps
+    /// the page pinned until it is dropped, so readers can latch it with SharedPage::read
+    /// without holding the buffer pool. a shared latch doesn't count as a use of the page
+    pub fn fetch_page_shared(&mut self, page_id: u32) -> Result<SharedPage> {
+        Ok(SharedPage { page: self.fetch_pinned(page_id)? })
+    }
+
+    /// fetch a page for exclusive use, reading it from disk on a miss. the returned guard
+    /// keeps the page pinned until it is dropped; ExclusivePage::write waits for the shared
+    /// latches of the page to be released
+    pub fn fetch_page_exclusive(&mut self, page_id: u32) -> Result<ExclusivePage> {
+        Ok(ExclusivePage { page: self.fetch_pinned(page_id)? })
+    }
+
+    fn fetch_pinned(&mut self, page_id: u32) -> Result<Arc<RwLock<TablePage>>> {
+        self.fetch_page(page_id)?
+            .ok_or_else(|| Error::Value(format!("page {} can not be found", page_id)))
+    }
 
-        Ok(BufferPoolManager { disk_manager, clock_replacer, header_page })
+    fn cached_page(&self, page_id: u32) -> Result<&Arc<RwLock<TablePage>>> {
+        self.replacer
+            .get(page_id)?
+            .ok_or_else(|| Error::Value(format!("page {} is not cached", page_id)))
     }
 
-    /// fetch a page from buffer pool
-    pub fn fetch_page(&mut self, page_id: u32) -> Result<Option<Arc<Mutex<TablePage>>>> {
-        if let Some(cache_page) = self.clock_replacer.poll(page_id)? {
+    /// fetch a page from buffer pool, pinned so it isn't evicted while in use.
+    /// the caller must release it with unpin_page once done
+    pub fn fetch_page(&mut self, page_id: u32) -> Result<Option<Arc<RwLock<TablePage>>>> {
+        if let Some(cache_page) = self.replacer.poll(page_id)? {
             // in cache
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            cache_page.write()?.pin();
             Ok(Some(cache_page))
         } else {
-            // read page from disk
+            // read page from disk, keeping its header, and check it before use
+            self.misses.fetch_add(1, Ordering::Relaxed);
             let page_data = self.read_disk_page(page_id)?;
-
-            let mut prev_page_id = None;
-            if page_id > 1 {
-                prev_page_id = Some(page_id - 1);
-            }
-
-            let table_page = TablePage::new(page_id, prev_page_id, page_data)?;
+            let mut table_page = TablePage::from_data(page_id, page_data)?;
+            table_page.validate()?;
+            table_page.pin();
 
             self.push_cache(table_page)
         }
     }
 
-    pub fn create_page(&mut self, page_id: u32) -> Result<Option<Arc<Mutex<TablePage>>>> {
+    /// release a page pinned by fetch_page. if the caller changed the page, it's marked
+    /// edited so it's written back. return false if the page isn't cached or pinned
+    pub fn unpin_page(&mut self, page_id: u32, is_dirty: bool) -> Result<bool> {
+        let page = match self.replacer.get(page_id)? {
+            Some(page) => page,
+            None => return Ok(false),
+        };
+        let mut table_page = page.write()?;
+        if is_dirty {
+            table_page.get_status_mut().edited();
+        }
+        Ok(table_page.unpin())
+    }
+
+    pub fn create_page(&mut self, page_id: u32) -> Result<Option<Arc<RwLock<TablePage>>>> {
         if self.disk_manager.have_page(page_id)? {
             return Ok(None);
         }
@@ -61,23 +594,28 @@ impl BufferPoolManager {
         self.push_cache(table_page)
     }
 
-    /// delete page by page_id
+    /// delete page by page_id, returning it to the free list
     pub fn delete_page(&mut self, page_id: u32) -> Result<bool> {
-        if let Some(page) = self.fetch_page(page_id)? {
-            let mut deleted_page = page.lock().unwrap();
-            deleted_page.get_status_mut().set_deleted(true);
-            deleted_page.get_status_mut().edited();
-
-            return Ok(true);
+        if self.fetch_page(page_id)?.is_none() {
+            return Ok(false);
         }
-        Ok(false)
+        self.free_page(page_id)?;
+        Ok(true)
+    }
+
+    /// return a page to the disk manager's free list. the cached copy is dropped without being
+    /// written, since the free list link is written over the page on disk
+    fn free_page(&mut self, page_id: u32) -> Result<()> {
+        self.replacer.discard(page_id)?;
+        self.disk_manager.deallocate_page(page_id)
     }
 
     /// flush edit data in to disk
     pub fn flush_page(&mut self, page_id: u32) -> Result<()> {
-        if let Some(page) = self.clock_replacer.poll(page_id)? {
-            let mut table_page = page.lock().unwrap();
+        if let Some(page) = self.replacer.poll(page_id)? {
+            let mut table_page = page.write().unwrap();
             if table_page.get_status_mut().is_edited() {
+                table_page.set_lsn(self.disk_manager.next_lsn())?;
                 let page_data = table_page.get_data();
                 self.disk_manager.write_page(page_id, page_data)?;
             }
@@ -86,37 +624,175 @@ impl BufferPoolManager {
         Ok(())
     }
 
+    /// return the ids of the cached pages with their access counts, most accessed first.
+    /// counts start over when a page is evicted and read back
+    pub fn heat_map(&self) -> Vec<(u32, u64)> {
+        self.replacer.heat_map()
+    }
+
+    /// return the cache statistics, e.g. to check whether a larger cache saves disk reads
+    pub fn stats(&self) -> BufferStats {
+        BufferStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
     pub fn flush_all(&mut self) -> Result<()> {
-        self.clock_replacer.flush_all(&mut self.disk_manager)
+        self.replacer.flush_all(&mut self.disk_manager)?;
+        self.header_page.flush(&mut self.disk_manager)
+    }
+
+    /// shrink the db file by moving pages into the pages freed by dropped tables, then
+    /// fix up the moved page ids in the pages and the header page. the cache is flushed
+    /// and emptied, so pages fetched before must not be used afterwards.
+    /// return the number of bytes reclaimed
+    pub fn shrink(&mut self) -> Result<u64> {
+        self.flush_all()?;
+        self.replacer.clear();
+        // the freed pages are all on the disk manager's free list
+        let (reclaimed, remap) = self.disk_manager.shrink(&[])?;
+        if remap.is_empty() {
+            return Ok(reclaimed);
+        }
+
+        for page_id in 1..self.disk_manager.get_num_pages()? {
+            let page_data = self.read_disk_page(page_id)?;
+            let mut table_page = TablePage::from_data(page_id, page_data)?;
+            let prev_page_id = table_page.get_prev_page_id()?;
+            let next_page_id = table_page.get_next_page_id()?;
+            let moved = table_page.get_table_page_id()? != page_id;
+            if !moved && !remap.contains_key(&prev_page_id) && !remap.contains_key(&next_page_id) {
+                continue;
+            }
+            if moved {
+                table_page.write_data(&page_id.to_le_bytes(), 0, 4)?;
+            }
+            if let Some(new_page_id) = remap.get(&prev_page_id) {
+                table_page.set_prev_page_id(*new_page_id)?;
+            }
+            if let Some(new_page_id) = remap.get(&next_page_id) {
+                table_page.set_next_page_id(*new_page_id)?;
+            }
+            table_page.set_lsn(self.disk_manager.next_lsn())?;
+            self.disk_manager.write_page(page_id, table_page.get_data())?;
+        }
+        // moved header pages were fixed up above as if they were table pages, which only
+        // covers their links, so write them again with the remapped records
+        self.header_page.remap_page_ids(&remap)?;
+        self.header_page.flush(&mut self.disk_manager)?;
+        Ok(reclaimed)
     }
 
     /// when buffer pool create or read a page, it should be push to cache.
-    /// then, the cache (clock_replacer) will return a ref
-    fn push_cache(&mut self, table_page: TablePage) -> Result<Option<Arc<Mutex<TablePage>>>> {
+    /// then, the cache (replacer) will return a ref
+    fn push_cache(&mut self, table_page: TablePage) -> Result<Option<Arc<RwLock<TablePage>>>> {
         let page_id = table_page.get_page_id().clone();
-        if let Some(remove_page) = self.clock_replacer.push(table_page)? {
-            let mut page = remove_page.lock().unwrap();
+        if let Some(remove_page) = self.replacer.push(table_page)? {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            let mut page = remove_page.write().unwrap();
             page.get_status_mut().set_removed(true);
 
             if page.get_status_mut().is_edited() {
+                page.set_lsn(self.disk_manager.next_lsn())?;
                 let page_data = page.get_data();
                 self.disk_manager.write_page(*page.get_page_id(), page_data)?;
             }
         }
 
-        if let Some(page) = self.clock_replacer.poll(page_id)? {
+        if let Some(page) = self.replacer.poll(page_id)? {
             Ok(Some(page))
         } else {
             Err(Error::Value(String::from(
-                r#"have a bug in clock replacer! when dbms push one page, it's can not find it!!!"#,
+                r#"have a bug in replacer! when dbms push one page, it's can not find it!!!"#,
             )))
         }
     }
 
-    /// read page data from disk by page_id
+    /// read page data from disk by page_id, checking its checksum
     fn read_disk_page(&mut self, page_id: u32) -> Result<[u8; PAGE_SIZE]> {
         let mut data = [0u8; PAGE_SIZE];
         self.disk_manager.read_page(page_id, &mut data)?;
+        Page::verify_checksum(page_id, &data)?;
         Ok(data)
     }
 }
+
+/// return a time in milliseconds since the UNIX epoch, as tuple expiry times are stored
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// A BufferPoolManager for async code, e.g. the tokio server. calls that may do disk I/O run on
+/// the tokio blocking thread pool, so they don't stall the executor. clones share the buffer
+/// pool, and its calls are serialized
+#[derive(Clone)]
+pub struct AsyncBufferPoolManager {
+    buffer_pool: Arc<Mutex<BufferPoolManager>>,
+}
+
+impl AsyncBufferPoolManager {
+    /// open the db in the given directory, caching at most cache_capacity pages
+    pub async fn open(dir: &Path, cache_capacity: u32) -> Result<AsyncBufferPoolManager> {
+        let dir = dir.to_path_buf();
+        let buffer_pool = blocking(move || BufferPoolManager::open(&dir, cache_capacity)).await?;
+        Ok(AsyncBufferPoolManager { buffer_pool: Arc::new(Mutex::new(buffer_pool)) })
+    }
+
+    /// see BufferPoolManager::create_table
+    pub async fn create_table(&self, name: &str) -> Result<Option<u32>> {
+        let name = name.to_string();
+        self.with(move |buffer_pool| buffer_pool.create_table(&name)).await
+    }
+
+    /// see BufferPoolManager::allocate_page
+    pub async fn allocate_page(&self, prev_page_id: Option<u32>) -> Result<Arc<RwLock<TablePage>>> {
+        self.with(move |buffer_pool| buffer_pool.allocate_page(prev_page_id)).await
+    }
+
+    /// see BufferPoolManager::fetch_page
+    pub async fn fetch_page(&self, page_id: u32) -> Result<Option<Arc<RwLock<TablePage>>>> {
+        self.with(move |buffer_pool| buffer_pool.fetch_page(page_id)).await
+    }
+
+    /// see BufferPoolManager::unpin_page
+    pub async fn unpin_page(&self, page_id: u32, is_dirty: bool) -> Result<bool> {
+        self.with(move |buffer_pool| buffer_pool.unpin_page(page_id, is_dirty)).await
+    }
+
+    /// see BufferPoolManager::flush_page
+    pub async fn flush_page(&self, page_id: u32) -> Result<()> {
+        self.with(move |buffer_pool| buffer_pool.flush_page(page_id)).await
+    }
+
+    /// see BufferPoolManager::flush_all
+    pub async fn flush_all(&self) -> Result<()> {
+        self.with(|buffer_pool| buffer_pool.flush_all()).await
+    }
+
+    /// delete the expired tuples of every table with a ttl once per interval, until an error
+    /// occurs. meant to be spawned as a background task
+    pub async fn serve_expiry(self, interval: Duration) -> Result<()> {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.with(|buffer_pool| buffer_pool.expire_all(SystemTime::now())).await?;
+        }
+    }
+
+    /// run a closure with the BufferPoolManager on the blocking thread pool, e.g. for the
+    /// calls without an async variant
+    pub async fn with<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut BufferPoolManager) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let buffer_pool = Arc::clone(&self.buffer_pool);
+        blocking(move || {
+            let mut buffer_pool = buffer_pool.lock()?;
+            f(&mut buffer_pool)
+        })
+        .await
+    }
+}
@@ -0,0 +1,632 @@
+use crate::error::{Error, Result};
+use crate::storage::relational::buffer_pool::{BufferPoolManager, BufferStats};
+use crate::storage::relational::disk_manager::DiskManager;
+use crate::storage::relational::page::{TablePage, PAGE_SIZE};
+use crate::storage::relational::tuple::{Tuple, RID};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tempdir::TempDir;
+
+#[test]
+fn test_drop_table_reuses_pages() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+    let third_id = *buffer_pool.allocate_page(Some(second_id))?.write()?.get_page_id();
+    let mut dropped_pages = vec![root_id, second_id, third_id];
+    dropped_pages.sort_unstable();
+
+    assert!(buffer_pool.drop_table("a")?);
+    assert_eq!(None, buffer_pool.get_table_root_id("a")?);
+    assert!(!buffer_pool.drop_table("a")?);
+
+    // the freed pages are handed out again before new pages are allocated
+    let new_root_id = buffer_pool.create_table("b")?.unwrap();
+    let new_second_id = *buffer_pool.allocate_page(Some(new_root_id))?.write()?.get_page_id();
+    let new_third_id = *buffer_pool.allocate_page(Some(new_second_id))?.write()?.get_page_id();
+    let mut reused_pages = vec![new_root_id, new_second_id, new_third_id];
+    reused_pages.sort_unstable();
+    assert_eq!(dropped_pages, reused_pages);
+    assert_eq!(Some(new_root_id), buffer_pool.get_table_root_id("b")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_drop_table_reuses_pages_after_reopen() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+    assert_eq!(Some(3), buffer_pool.create_table("b")?);
+    let mut dropped_pages = vec![root_id, second_id];
+    dropped_pages.sort_unstable();
+    assert!(buffer_pool.drop_table("a")?);
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // the free list is kept on disk, so the dropped pages are reused after a reopen
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert_eq!(None, buffer_pool.get_table_root_id("a")?);
+    let new_root_id = buffer_pool.create_table("c")?.unwrap();
+    let new_second_id = *buffer_pool.allocate_page(Some(new_root_id))?.write()?.get_page_id();
+    let mut reused_pages = vec![new_root_id, new_second_id];
+    reused_pages.sort_unstable();
+    assert_eq!(dropped_pages, reused_pages);
+
+    // the file only grows once the free pages are used up
+    let num_pages = std::fs::metadata(dir.path().join("toydb.db"))?.len() / PAGE_SIZE as u64;
+    let next_id = *buffer_pool.allocate_page(Some(new_second_id))?.write()?.get_page_id();
+    assert_eq!(num_pages, next_id as u64);
+    Ok(())
+}
+
+#[test]
+fn test_fetch_page_validates() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let mut tuple = Tuple::from_data(vec![0x01, 0x02, 0x03]);
+    tuple.set_rid(RID::new(root_id, 0));
+    assert!(buffer_pool.fetch_page(root_id)?.unwrap().write()?.insert_tuple(&mut tuple)?);
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // a page read back from disk keeps its tuples
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let page = buffer_pool.fetch_page(root_id)?.unwrap();
+    let tuple = page.write()?.get_tuple(&RID::new(root_id, 0))?.unwrap();
+    assert_eq!(tuple.get_data(), &[0x01, 0x02, 0x03]);
+    drop(buffer_pool);
+
+    // a corrupt page is rejected, even with a valid checksum
+    let mut page = TablePage::from_data(root_id, [0u8; PAGE_SIZE])?;
+    page.write_data(&root_id.to_le_bytes(), 0, 4)?;
+    page.write_data(&(PAGE_SIZE as u32 + 1).to_le_bytes(), 17, 4)?;
+    DiskManager::open(dir.path())?.write_page(root_id, page.get_data())?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert!(matches!(buffer_pool.fetch_page(root_id), Err(Error::PageCorrupt(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_fill_factor() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let full_id = buffer_pool.create_table("full")?.unwrap();
+    let half_id = buffer_pool.create_table_with_fill_factor("half", 0.5)?.unwrap();
+    assert!(buffer_pool.create_table_with_fill_factor("zero", 0.0).is_err());
+    assert_eq!(Some(1.0), buffer_pool.get_table_fill_factor("full")?);
+    assert_eq!(Some(0.5), buffer_pool.get_table_fill_factor("half")?);
+
+    // each tuple uses 100 bytes of data and an 8 byte slot
+    let mut count_root_tuples = |name: &str, root_id: u32| -> Result<usize> {
+        for _ in 0..40 {
+            let mut tuple = Tuple::from_data(vec![0xff; 100]);
+            tuple.set_rid(RID::new(root_id, 0));
+            assert!(buffer_pool.insert_tuple(name, &mut tuple)?);
+        }
+        let page = buffer_pool.fetch_page(root_id)?.unwrap();
+        let mut root_page = page.write()?;
+        assert_ne!(0, root_page.get_next_page_id()?);
+        let mut count = 0;
+        let mut next = root_page.get_first_tuple_rid()?;
+        while let Some(rid) = next {
+            count += 1;
+            next = root_page.get_next_tuple_rid(&rid)?;
+        }
+        Ok(count)
+    };
+    // the usable space is 4067 bytes, so the half full page stops at 2033 bytes
+    assert_eq!(37, count_root_tuples("full", full_id)?);
+    assert_eq!(18, count_root_tuples("half", half_id)?);
+
+    // the headroom can be used by updates growing a tuple in place
+    let page = buffer_pool.fetch_page(half_id)?.unwrap();
+    let mut root_page = page.write()?;
+    let mut tuple = Tuple::from_data(vec![0x01; 1000]);
+    tuple.set_rid(RID::new(half_id, 0));
+    root_page.update_tuple(&tuple)?;
+    assert_eq!(tuple.get_data(), root_page.get_tuple(&RID::new(half_id, 0))?.unwrap().get_data());
+
+    Ok(())
+}
+
+#[test]
+fn test_shrink_after_drop_table() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let db_size = || -> Result<u64> { Ok(std::fs::metadata(dir.path().join("toydb.db"))?.len()) };
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+
+    // table a uses pages 1-3, and table b pages 4-5 with a tuple on each
+    let a_id = buffer_pool.create_table("a")?.unwrap();
+    let a_second_id = *buffer_pool.allocate_page(Some(a_id))?.write()?.get_page_id();
+    buffer_pool.allocate_page(Some(a_second_id))?;
+    let b_id = buffer_pool.create_table("b")?.unwrap();
+    let b_second_id = *buffer_pool.allocate_page(Some(b_id))?.write()?.get_page_id();
+    assert_eq!((1, 4, 5), (a_id, b_id, b_second_id));
+    for page_id in &[b_id, b_second_id] {
+        let mut tuple = Tuple::from_data(vec![*page_id as u8; 3]);
+        tuple.set_rid(RID::new(*page_id, 0));
+        assert!(buffer_pool.fetch_page(*page_id)?.unwrap().write()?.insert_tuple(&mut tuple)?);
+    }
+    buffer_pool.flush_all()?;
+    assert_eq!(6 * PAGE_SIZE as u64, db_size()?);
+
+    // dropping table a frees pages 1-3, so b's pages are moved to 1-2
+    assert!(buffer_pool.drop_table("a")?);
+    assert_eq!(3 * PAGE_SIZE as u64, buffer_pool.shrink()?);
+    assert_eq!(3 * PAGE_SIZE as u64, db_size()?);
+    assert_eq!(Some(1), buffer_pool.get_table_root_id("b")?);
+
+    // polling the cache locks every cached page, so only lock one page at a time
+    let root_page = buffer_pool.fetch_page(1)?.unwrap();
+    let mut root_page = root_page.write()?;
+    assert_eq!(1, root_page.get_table_page_id()?);
+    assert_eq!(2, root_page.get_next_page_id()?);
+    assert_eq!(&[4, 4, 4], root_page.get_tuple(&RID::new(1, 0))?.unwrap().get_data());
+    drop(root_page);
+    let second_page = buffer_pool.fetch_page(2)?.unwrap();
+    let mut second_page = second_page.write()?;
+    assert_eq!(2, second_page.get_table_page_id()?);
+    assert_eq!(1, second_page.get_prev_page_id()?);
+    assert_eq!(&[5, 5, 5], second_page.get_tuple(&RID::new(2, 0))?.unwrap().get_data());
+    drop(second_page);
+
+    // new pages are allocated after the shrunk end
+    assert_eq!(Some(3), buffer_pool.create_table("c")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_fetch_page_checksum() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let mut tuple = Tuple::from_data(vec![0x01, 0x02, 0x03]);
+    tuple.set_rid(RID::new(root_id, 0));
+    assert!(buffer_pool.insert_tuple("a", &mut tuple)?);
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // flip a bit of the tuple in the db file
+    let mut db_file =
+        OpenOptions::new().read(true).write(true).open(dir.path().join("toydb.db"))?;
+    let offset = (root_id as u64 + 1) * PAGE_SIZE as u64 - 1;
+    let mut byte = [0u8];
+    db_file.seek(SeekFrom::Start(offset))?;
+    db_file.read_exact(&mut byte)?;
+    assert_eq!(0x03, byte[0]);
+    db_file.seek(SeekFrom::Start(offset))?;
+    db_file.write_all(&[byte[0] ^ 0x01])?;
+    drop(db_file);
+
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert!(matches!(buffer_pool.fetch_page(root_id), Err(Error::PageCorrupt(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_lock_pages_ordered() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let next_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+
+    // the guards come back in page id order, without duplicates
+    let mut pages = buffer_pool.lock_pages_ordered(&[next_id, root_id, next_id])?;
+    assert_eq!(vec![root_id, next_id], pages.iter().map(|p| *p.get_page_id()).collect::<Vec<_>>());
+    assert_eq!(next_id, pages[0].get_next_page_id()?);
+    assert_eq!(root_id, pages[1].get_prev_page_id()?);
+    drop(pages);
+    assert!(matches!(buffer_pool.lock_pages_ordered(&[root_id, 100]), Err(Error::Value(_))));
+
+    // two threads locking the pair in opposite order must not deadlock
+    let buffer_pool = Arc::new(buffer_pool);
+    let (tx, rx) = mpsc::channel();
+    for page_ids in [[root_id, next_id], [next_id, root_id]] {
+        let buffer_pool = Arc::clone(&buffer_pool);
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let result = (0..1000).try_for_each(|_| -> Result<()> {
+                let mut pages = buffer_pool.lock_pages_ordered(&page_ids)?;
+                for page in pages.iter_mut() {
+                    page.get_status_mut().used();
+                }
+                thread::yield_now();
+                Ok(())
+            });
+            tx.send(result).unwrap();
+        });
+    }
+    for _ in 0..2 {
+        rx.recv_timeout(Duration::from_secs(30)).expect("deadlocked")?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_and_exclusive_latches() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let rids = (0..4u32)
+        .map(|slot_num| -> Result<RID> {
+            let mut tuple = Tuple::from_data(vec![0; 32]);
+            tuple.set_rid(RID::new(root_id, slot_num));
+            assert!(buffer_pool
+                .fetch_page_exclusive(root_id)?
+                .write()?
+                .insert_tuple(&mut tuple)?);
+            Ok(tuple.get_rid().unwrap().clone())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // shared latches on a page are held together
+    let first = buffer_pool.fetch_page_shared(root_id)?;
+    let second = buffer_pool.fetch_page_shared(root_id)?;
+    let (first_latch, second_latch) = (first.read()?, second.read()?);
+    let tuples =
+        (first_latch.read_tuple(&rids[0])?.unwrap(), second_latch.read_tuple(&rids[0])?.unwrap());
+    assert_eq!(tuples.0.get_data(), tuples.1.get_data());
+    drop((first_latch, second_latch));
+    drop((first, second));
+    assert!(matches!(buffer_pool.fetch_page_shared(100), Err(Error::Value(_))));
+
+    // a writer rewrites every tuple under an exclusive latch, so readers never see a mix. the
+    // buffer pool is only locked to fetch the page, the latches are taken outside of it
+    let buffer_pool = Arc::new(Mutex::new(buffer_pool));
+    let (tx, rx) = mpsc::channel();
+    for reader in 0..5 {
+        let (buffer_pool, rids, tx) = (Arc::clone(&buffer_pool), rids.clone(), tx.clone());
+        thread::spawn(move || {
+            let result = (0..200).try_for_each(|_| -> Result<()> {
+                let page = buffer_pool.lock()?.fetch_page_shared(root_id)?;
+                let latch = page.read()?;
+                let values = rids
+                    .iter()
+                    .map(|rid| Ok(latch.read_tuple(rid)?.unwrap().get_data()[0]))
+                    .collect::<Result<Vec<u8>>>()?;
+                assert!(values.iter().all(|v| *v == values[0]), "reader {}: {:?}", reader, values);
+                drop(latch);
+                drop(page);
+                thread::yield_now();
+                Ok(())
+            });
+            tx.send(result).unwrap();
+        });
+    }
+    {
+        let (buffer_pool, rids, tx) = (Arc::clone(&buffer_pool), rids.clone(), tx.clone());
+        thread::spawn(move || {
+            let result = (1..=200u32).try_for_each(|round| -> Result<()> {
+                let page = buffer_pool.lock()?.fetch_page_exclusive(root_id)?;
+                let mut latch = page.write()?;
+                for rid in &rids {
+                    let mut tuple = Tuple::from_data(vec![round as u8; 32]);
+                    tuple.set_rid(rid.clone());
+                    latch.update_tuple(&tuple)?;
+                    thread::yield_now();
+                }
+                Ok(())
+            });
+            tx.send(result).unwrap();
+        });
+    }
+    for _ in 0..6 {
+        rx.recv_timeout(Duration::from_secs(30)).expect("deadlocked")?;
+    }
+    let page = buffer_pool.lock()?.fetch_page_shared(root_id)?;
+    assert_eq!(200, page.read()?.read_tuple(&rids[3])?.unwrap().get_data()[0]);
+    Ok(())
+}
+
+#[test]
+fn test_page_guards_load_and_pin() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+    buffer_pool.unpin_page(second_id, true)?;
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // a reopened buffer pool caches nothing, so fetching a page reads it from disk
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 1)?;
+    let misses = buffer_pool.stats().misses;
+    let page = buffer_pool.fetch_page_shared(root_id)?;
+    assert_eq!(misses + 1, buffer_pool.stats().misses);
+    assert_eq!(root_id, *page.read()?.get_page_id());
+
+    // the guard pins the only cached page, so nothing else can be loaded until it's dropped
+    assert!(buffer_pool.fetch_page_exclusive(second_id).is_err());
+    drop(page);
+    let page = buffer_pool.fetch_page_exclusive(second_id)?;
+    assert_eq!(second_id, *page.write()?.get_page_id());
+    Ok(())
+}
+
+#[test]
+fn test_heat_map() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let mut page_ids = vec![root_id];
+    for _ in 0..3 {
+        let prev_id = *page_ids.last().unwrap();
+        page_ids.push(*buffer_pool.allocate_page(Some(prev_id))?.write()?.get_page_id());
+    }
+    let base = buffer_pool.heat_map();
+
+    // the last two pages are hot, the first two cold
+    for _ in 0..10 {
+        buffer_pool.fetch_page(page_ids[3])?.unwrap();
+        buffer_pool.fetch_page(page_ids[2])?.unwrap();
+    }
+    buffer_pool.fetch_page(page_ids[0])?.unwrap();
+
+    let heat_map = buffer_pool.heat_map();
+    assert_eq!(4, heat_map.len());
+    let hot = heat_map[..2].iter().map(|(page_id, _)| *page_id).collect::<Vec<_>>();
+    assert!(hot.contains(&page_ids[2]) && hot.contains(&page_ids[3]), "{:?}", heat_map);
+    for (page_id, accesses) in &heat_map {
+        let (_, base_accesses) = base.iter().find(|(id, _)| id == page_id).unwrap();
+        let expect = match page_ids.iter().position(|id| id == page_id) {
+            Some(0) => 1,
+            Some(1) => 0,
+            _ => 10,
+        };
+        assert_eq!(base_accesses + expect, *accesses);
+    }
+    assert!(heat_map.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    Ok(())
+}
+
+#[test]
+fn test_header_page_chain() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let mut tables = Vec::new();
+    for i in 0..200 {
+        let name = format!("table_{}", i);
+        let root_id = buffer_pool.create_table(&name)?.unwrap();
+        tables.push((name, root_id));
+    }
+    let assert_root_ids = |buffer_pool: &BufferPoolManager, tables: &[(String, u32)]| {
+        for (name, root_id) in tables {
+            assert_eq!(Some(*root_id), buffer_pool.get_table_root_id(name)?, "{}", name);
+        }
+        Ok::<_, Error>(())
+    };
+    assert_root_ids(&buffer_pool, &tables)?;
+
+    // the header pages are written on flush, and loaded back on open
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert_root_ids(&buffer_pool, &tables)?;
+    assert!(buffer_pool.create_table("table_199")?.is_none());
+
+    // shrinking moves the last header page, and root pages, to the front of the file
+    for (name, _) in tables.drain(..50) {
+        assert!(buffer_pool.drop_table(&name)?);
+    }
+    assert!(buffer_pool.shrink()? > 0);
+    for (name, root_id) in tables.iter_mut() {
+        *root_id = buffer_pool.get_table_root_id(name)?.unwrap();
+        let page = buffer_pool.fetch_page(*root_id)?.unwrap();
+        assert_eq!(*root_id, page.write()?.get_table_page_id()?);
+        assert!(buffer_pool.unpin_page(*root_id, false)?);
+    }
+    drop(buffer_pool);
+    let buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert_root_ids(&buffer_pool, &tables)?;
+    Ok(())
+}
+
+#[test]
+fn test_stats() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 2)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // the first fetch reads the page from disk, the second is served from the cache
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 1)?;
+    assert_eq!(BufferStats::default(), buffer_pool.stats());
+    buffer_pool.fetch_page(root_id)?.unwrap();
+    buffer_pool.fetch_page(root_id)?.unwrap();
+    assert_eq!(BufferStats { hits: 1, misses: 1, evictions: 0 }, buffer_pool.stats());
+    buffer_pool.unpin_page(root_id, false)?;
+    buffer_pool.unpin_page(root_id, false)?;
+
+    // a full cache evicts a page to make room
+    buffer_pool.fetch_page(second_id)?.unwrap();
+    assert_eq!(BufferStats { hits: 1, misses: 2, evictions: 1 }, buffer_pool.stats());
+    Ok(())
+}
+
+#[test]
+fn test_pinned_pages_are_not_evicted() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 2)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+    let third_id = *buffer_pool.allocate_page(Some(second_id))?.write()?.get_page_id();
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // with every slot pinned, fetching another page errors instead of evicting one
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 2)?;
+    let root_page = buffer_pool.fetch_page(root_id)?.unwrap();
+    let mut tuple = Tuple::from_data(vec![0x01; 64]);
+    tuple.set_rid(RID::new(root_id, 0));
+    assert!(root_page.write()?.insert_tuple(&mut tuple)?);
+    buffer_pool.fetch_page(second_id)?.unwrap();
+    assert!(matches!(buffer_pool.fetch_page(third_id), Err(Error::Value(_))));
+    assert_eq!(1, root_page.write()?.tuples().count());
+
+    // once a page is unpinned, it can be evicted, and its changes are written back
+    assert!(buffer_pool.unpin_page(root_id, true)?);
+    assert!(!buffer_pool.unpin_page(root_id, false)?);
+    assert!(!buffer_pool.unpin_page(third_id, false)?);
+    buffer_pool.fetch_page(third_id)?.unwrap();
+    assert!(root_page.write()?.get_status_mut().get_removed());
+    assert!(buffer_pool.unpin_page(third_id, false)?);
+    let root_page = buffer_pool.fetch_page(root_id)?.unwrap();
+    assert_eq!(1, root_page.write()?.tuples().count());
+    Ok(())
+}
+
+#[test]
+fn test_table_ttl() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert!(buffer_pool.create_table_with_ttl("a", Duration::from_millis(0)).is_err());
+    let root_id = buffer_pool.create_table_with_ttl("a", Duration::from_secs(60))?.unwrap();
+    let other_id = buffer_pool.create_table("b")?.unwrap();
+    assert_eq!(Some(Duration::from_secs(60)), buffer_pool.get_table_ttl("a")?);
+    assert_eq!(None, buffer_pool.get_table_ttl("b")?);
+    assert_eq!(None, buffer_pool.get_table_ttl("c")?);
+
+    // tuples of a table with a ttl are given an expiry time, other tuples aren't
+    let mut rids = Vec::new();
+    for i in 0..3u8 {
+        let mut tuple = Tuple::from_data(vec![i; 4]);
+        tuple.set_rid(RID::new(root_id, 0));
+        assert!(buffer_pool.insert_tuple("a", &mut tuple)?);
+        assert!(tuple.get_expires_at().is_some());
+        rids.push(tuple.get_rid().unwrap().clone());
+    }
+    let mut tuple = Tuple::from_data(vec![9; 4]);
+    tuple.set_rid(RID::new(other_id, 0));
+    assert!(buffer_pool.insert_tuple("b", &mut tuple)?);
+    assert_eq!(None, tuple.get_expires_at());
+
+    // until they expire, tuples are read without their expiry time
+    let now = SystemTime::now();
+    let data = |tuples: Vec<Tuple>| -> Vec<Vec<u8>> {
+        tuples.iter().map(|tuple| tuple.get_data().to_vec()).collect()
+    };
+    assert_eq!(vec![vec![0; 4], vec![1; 4], vec![2; 4]], data(buffer_pool.scan_table("a", now)?));
+    let tuple = buffer_pool.get_tuple("a", &rids[1], now)?.unwrap();
+    assert_eq!(&[1; 4], tuple.get_data());
+    assert_eq!(0, buffer_pool.expire_tuples("a", now)?);
+
+    // once time passes the ttl, reads skip the tuples, which are still stored until expired
+    let later = now + Duration::from_secs(120);
+    assert!(buffer_pool.scan_table("a", later)?.is_empty());
+    assert!(buffer_pool.get_tuple("a", &rids[1], later)?.is_none());
+    let page = buffer_pool.fetch_page(root_id)?.unwrap();
+    assert_eq!(3, page.write()?.tuples().count());
+    assert!(buffer_pool.unpin_page(root_id, false)?);
+
+    // expiring deletes them from the pages, leaving tables without a ttl alone
+    assert_eq!(3, buffer_pool.expire_all(later)?);
+    let page = buffer_pool.fetch_page(root_id)?.unwrap();
+    assert_eq!(0, page.write()?.tuples().count());
+    assert!(buffer_pool.unpin_page(root_id, false)?);
+    assert_eq!(vec![vec![9; 4]], data(buffer_pool.scan_table("b", later)?));
+    assert!(buffer_pool.scan_table("c", later).is_err());
+
+    // the ttl is kept in the header page
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+    let buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert_eq!(Some(Duration::from_secs(60)), buffer_pool.get_table_ttl("a")?);
+    Ok(())
+}
+
+#[test]
+fn test_for_each_page() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+
+    // tables spanning several pages each, one of which is dropped
+    let insert = |buffer_pool: &mut BufferPoolManager, name: &str, count: u8| -> Result<()> {
+        let root_id = buffer_pool.create_table(name)?.unwrap();
+        for i in 0..count {
+            let mut tuple = Tuple::from_data(vec![i; 1000]);
+            tuple.set_rid(RID::new(root_id, 0));
+            assert!(buffer_pool.insert_tuple(name, &mut tuple)?);
+        }
+        Ok(())
+    };
+    insert(&mut buffer_pool, "a", 10)?;
+    insert(&mut buffer_pool, "b", 7)?;
+    insert(&mut buffer_pool, "c", 6)?;
+    assert!(buffer_pool.drop_table("c")?);
+
+    let count_tuples = |buffer_pool: &mut BufferPoolManager| -> Result<usize> {
+        let mut count = 0;
+        buffer_pool.for_each_page(|table_page| {
+            for tuple in table_page.tuples() {
+                tuple?;
+                count += 1;
+            }
+            Ok(())
+        })?;
+        Ok(count)
+    };
+    assert_eq!(17, count_tuples(&mut buffer_pool)?);
+
+    // the first error stops the visit
+    let mut visited = 0;
+    let result = buffer_pool.for_each_page(|_| {
+        visited += 1;
+        Err(Error::Value(String::from("stop")))
+    });
+    assert!(result.is_err());
+    assert_eq!(1, visited);
+    buffer_pool.flush_all()?;
+
+    // once reopened, the dropped pages are skipped as they are on the free list
+    drop(buffer_pool);
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert_eq!(17, count_tuples(&mut buffer_pool)?);
+    Ok(())
+}
+
+#[test]
+fn test_blob_pages() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    assert!(buffer_pool.insert_blob(&[]).is_err());
+
+    // a blob is split into chunks of the largest tuple size, in a chain of pages
+    let blob: Vec<u8> = (0..3 * TablePage::MAX_TUPLE_SIZE + 10).map(|i| i as u8).collect();
+    let read_blob = |buffer_pool: &mut BufferPoolManager, mut page_id| -> Result<_> {
+        let (mut data, mut pages) = (Vec::new(), Vec::new());
+        while page_id != 0 {
+            pages.push(page_id);
+            let (chunk, next_page_id) = buffer_pool.read_blob_chunk(page_id)?;
+            data.extend(chunk);
+            page_id = next_page_id;
+        }
+        pages.sort_unstable();
+        Ok((data, pages))
+    };
+    let first_page_id = buffer_pool.insert_blob(&blob)?;
+    let (data, pages) = read_blob(&mut buffer_pool, first_page_id)?;
+    assert_eq!(4, pages.len());
+    assert!(data == blob);
+
+    // the pages of a deleted blob are handed out again
+    buffer_pool.delete_blob(first_page_id)?;
+    let first_page_id = buffer_pool.insert_blob(&blob)?;
+    let (data, reused_pages) = read_blob(&mut buffer_pool, first_page_id)?;
+    assert_eq!(pages, reused_pages);
+    assert!(data == blob);
+    Ok(())
+}
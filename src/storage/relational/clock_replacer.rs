@@ -1,13 +1,12 @@
-use super::disk_manager::DiskManager;
 use super::page::TablePage;
+use super::replacer::Replacer;
 use crate::error::{Error, Result};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
 
 /// Cache Page, and decide on page replacement behavior
 pub struct ClockReplacer {
     clock_hand: u32,
-    pages: Vec<Arc<Mutex<TablePage>>>,
+    pages: Vec<Arc<RwLock<TablePage>>>,
     capacity: u32,
 }
 
@@ -24,11 +23,21 @@ pub struct ClockStatus {
     edited: bool,
     deleted: bool,
     removed: bool,
+    /// the number of cache hits while the page is cached
+    accesses: u64,
 }
 
 impl ClockStatus {
     pub fn empty() -> ClockStatus {
-        ClockStatus { used: false, edited: false, deleted: false, removed: false }
+        ClockStatus { used: false, edited: false, deleted: false, removed: false, accesses: 0 }
+    }
+
+    pub fn accessed(&mut self) {
+        self.accesses += 1;
+    }
+
+    pub fn get_accesses(&self) -> u64 {
+        self.accesses
     }
 
     pub fn used(&mut self) {
@@ -43,6 +52,10 @@ impl ClockStatus {
         self.used = false;
     }
 
+    pub fn un_edited(&mut self) {
+        self.edited = false;
+    }
+
     pub fn is_edited(&self) -> bool {
         self.edited.clone()
     }
@@ -86,27 +99,36 @@ impl ClockReplacer {
         Ok(ClockReplacer { clock_hand: 0, pages: Vec::new(), capacity })
     }
 
-    pub fn poll(&self, page_id: u32) -> Result<Option<Arc<Mutex<TablePage>>>> {
-        let pages = &self.pages;
-        let mut filter_page = pages
-            .iter()
-            .filter(|p| {
-                let mut lock_page = p.lock().unwrap();
-                lock_page.get_page_id().eq(&page_id) && !lock_page.get_status_mut().get_removed()
-            })
-            .collect::<Vec<_>>();
-        if let Some(page) = filter_page.get_mut(0) {
-            let page_rc = Arc::clone(page);
-            return Ok(Some(page_rc));
-        }
+    /// move the clock hand past the page to be removed, return its index
+    fn advance_hand(&mut self, index: usize) -> usize {
+        self.clock_hand = ((index + 1) % self.pages.len()) as u32;
+        index
+    }
+}
 
-        Ok(None)
+impl Replacer for ClockReplacer {
+    fn pages(&self) -> &[Arc<RwLock<TablePage>>] {
+        &self.pages
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    fn poll(&self, page_id: u32) -> Result<Option<Arc<RwLock<TablePage>>>> {
+        match self.get(page_id)? {
+            Some(page) => {
+                page.write()?.get_status_mut().accessed();
+                Ok(Some(Arc::clone(page)))
+            }
+            None => Ok(None),
+        }
     }
 
     /// push a new page. if a page should be remove, return it
-    pub fn push(&mut self, page: TablePage) -> Result<Option<Arc<Mutex<TablePage>>>> {
-        let push_page = Arc::new(Mutex::new(page));
-        if let Some(index) = self.check_hand()? {
+    fn push(&mut self, page: TablePage) -> Result<Option<Arc<RwLock<TablePage>>>> {
+        let push_page = Arc::new(RwLock::new(page));
+        if let Some(index) = self.victim()? {
             let remove_page = self.pages.remove(index);
             self.pages.insert(index, push_page);
             return Ok(Some(remove_page));
@@ -116,109 +138,57 @@ impl ClockReplacer {
         Ok(None)
     }
 
-    /// flush all page data, where it was edited
-    pub fn flush_all(&self, disk_manager: &mut DiskManager) -> Result<()> {
-        for page in &self.pages {
-            let arc_page = Arc::clone(page);
-            let mut table_page = arc_page.lock().unwrap();
-            if table_page.get_status_mut().is_edited() {
-                let page_id = *table_page.get_page_id();
-                let page_data = table_page.get_data();
-                disk_manager.write_page(page_id, page_data)?;
-            }
-        }
-
-        Ok(())
+    /// drop all cached pages, without flushing them
+    fn clear(&mut self) {
+        self.pages.clear();
+        self.clock_hand = 0;
     }
 
-    /// clockwise!!!
+    /// clockwise!!! sweep from the clock hand, giving used pages a second chance by clearing
+    /// their used tag, and pick the first unused page. an unused page that wasn't edited is
+    /// preferred over an edited one found earlier in the same sweep, since it needs no write.
+    /// the clock hand moves past the picked page. pinned pages are never picked
     /// return:
     ///     None - There is still space, push directly
-    ///     Some - The cache is full, turn the clock hand and find the page to be removed
-    fn check_hand(&mut self) -> Result<Option<usize>> {
+    ///     Some - The cache is full, the index of the page to be removed
+    fn victim(&mut self) -> Result<Option<usize>> {
         if self.capacity as usize > self.pages.len() {
             return Ok(None);
         }
-
-        let mut remove_index: Option<usize> = None;
-        let mut loop_counter = 0;
-        let mut have_err = false;
-
-        loop {
-            if loop_counter >= 4 {
-                have_err = true;
-                break;
-            }
-            let group_map = self.group_by_level();
-            if let Some(high) = group_map.get(&ExpelLevel::HIGH) {
-                if let Some(index) = high.get(0) {
-                    self.clock_hand = *index;
-                    remove_index = Some(*index as usize);
-                    break;
-                }
-            }
-            if let Some(normal) = group_map.get(&ExpelLevel::NORMAL) {
-                if let Some(index) = normal.get(0) {
-                    self.clock_hand = *index;
-                    remove_index = Some(*index as usize);
-                    break;
-                }
-            }
-            if let Some(medium) = group_map.get(&ExpelLevel::MEDIUM) {
-                if let Some(index) = medium.get(0) {
-                    self.clock_hand = *index;
-                    remove_index = Some(*index as usize);
-                    break;
-                }
-            }
-
-            self.clockwise();
-
-            // we should not remove pages of this level
-            // if let Some(low) = group_map.get(&ExpelLevel::LOW) {
-            //     if let Some(index) = low.get(0) {
-            //         self.clock_hand = *index;
-            //         remove_index = Some(*index as usize);
-            //         return Ok(Some(*index as usize));
-            //     }
-            // }
-            loop_counter += 1;
-        }
-
-        if have_err {
+        if self.pages.iter().all(|page| *page.write().unwrap().get_pin_count() > 0) {
             return Err(Error::Value(String::from(
-                "Clock Replacer can not find any page by remove memory",
+                "every cached page is pinned, can't evict a page for a new one",
             )));
         }
-        Ok(remove_index)
-    }
 
-    fn group_by_level(&self) -> HashMap<ExpelLevel, Vec<u32>> {
-        let mut result_map: HashMap<ExpelLevel, Vec<u32>> = HashMap::new();
-        let mut index: u32 = 0;
-        for page in &self.pages {
-            let mut table_page = page.lock().unwrap();
-            let level = table_page.get_status_mut().level();
-
-            if let Some(value) = result_map.get_mut(&level) {
-                value.push(index);
-            } else {
-                let list = vec![index];
-                result_map.insert(level, list);
+        // the first sweep clears every used tag, so the second one always finds a page
+        let len = self.pages.len();
+        for _ in 0..2 {
+            let mut edited_index = None;
+            for step in 0..len {
+                let index = (self.clock_hand as usize + step) % len;
+                let level = {
+                    let mut table_page = self.pages[index].write().unwrap();
+                    if *table_page.get_pin_count() > 0 {
+                        continue;
+                    }
+                    let level = table_page.get_status_mut().level();
+                    if level == ExpelLevel::NORMAL || level == ExpelLevel::LOW {
+                        table_page.get_status_mut().un_used();
+                    }
+                    level
+                };
+                match level {
+                    ExpelLevel::HIGH => return Ok(Some(self.advance_hand(index))),
+                    ExpelLevel::MEDIUM => edited_index = edited_index.or(Some(index)),
+                    ExpelLevel::NORMAL | ExpelLevel::LOW => {}
+                }
+            }
+            if let Some(index) = edited_index {
+                return Ok(Some(self.advance_hand(index)));
             }
-
-            index += 1;
         }
-        result_map
-    }
 
-    /// clockwise to clear used tag
-    fn clockwise(&self) {
-        let pages = &self.pages;
-        for page in pages {
-            let arc_page = Arc::clone(page);
-            let mut mutex_page = arc_page.lock().unwrap();
-            mutex_page.get_status_mut().un_used();
-        }
+        Err(Error::Value(String::from("Clock Replacer can not find any page by remove memory")))
     }
 }
@@ -0,0 +1,77 @@
+use crate::error::Result;
+use crate::storage::relational::clock_replacer::ClockReplacer;
+use crate::storage::relational::page::{TablePage, PAGE_SIZE};
+use crate::storage::relational::replacer::Replacer;
+use crate::storage::relational::tuple::{Tuple, RID};
+
+/// push a page read back from disk, so it's used but not edited. return the removed page id
+fn push(clock_replacer: &mut ClockReplacer, page_id: u32) -> Result<Option<u32>> {
+    let table_page = TablePage::from_data(page_id, [0u8; PAGE_SIZE])?;
+    match clock_replacer.push(table_page)? {
+        Some(page) => Ok(Some(*page.write()?.get_page_id())),
+        None => Ok(None),
+    }
+}
+
+/// clear the used tag of a cached page, as if the clock hand passed it
+fn un_used(clock_replacer: &ClockReplacer, page_id: u32) -> Result<()> {
+    clock_replacer.get(page_id)?.unwrap().write()?.get_status_mut().un_used();
+    Ok(())
+}
+
+#[test]
+fn test_clock_hand() -> Result<()> {
+    let mut clock_replacer = ClockReplacer::new(3)?;
+    for page_id in 1..=3 {
+        assert_eq!(None, push(&mut clock_replacer, page_id)?);
+    }
+
+    // page 1 is used, so it gets a second chance, and page 2 is removed
+    un_used(&clock_replacer, 2)?;
+    un_used(&clock_replacer, 3)?;
+    assert_eq!(Some(2), push(&mut clock_replacer, 4)?);
+
+    // the hand continues after the removed page, instead of starting over
+    assert_eq!(Some(3), push(&mut clock_replacer, 5)?);
+    assert_eq!(Some(1), push(&mut clock_replacer, 6)?);
+
+    // when every page is used, the first sweep clears them all, and the hand's page goes.
+    // the pages are now 6, 4, 5 and the hand is at page 4
+    assert_eq!(Some(4), push(&mut clock_replacer, 7)?);
+
+    // an unused page that wasn't edited goes before an edited one, even if it's further on
+    for page_id in &[6, 7, 5] {
+        un_used(&clock_replacer, *page_id)?;
+    }
+    clock_replacer.get(5)?.unwrap().write()?.get_status_mut().edited();
+    assert_eq!(Some(6), push(&mut clock_replacer, 8)?);
+    Ok(())
+}
+
+#[test]
+fn test_get_need_flush() -> Result<()> {
+    let mut clock_replacer = ClockReplacer::new(3)?;
+    for page_id in 1..=3 {
+        push(&mut clock_replacer, page_id)?;
+    }
+    assert!(clock_replacer.get_need_flush().is_empty());
+
+    for page_id in &[1, 3] {
+        let mut tuple = Tuple::from_data(vec![*page_id as u8; 8]);
+        tuple.set_rid(RID::new(*page_id, 0));
+        let page = clock_replacer.get(*page_id)?.unwrap();
+        let mut table_page = page.write()?;
+        *table_page = TablePage::new(*page_id, None, [0u8; PAGE_SIZE])?;
+        assert!(table_page.insert_tuple(&mut tuple)?);
+    }
+    let need_flush = clock_replacer.get_need_flush();
+    assert_eq!(vec![1, 3], need_flush.iter().map(|(page_id, _)| *page_id).collect::<Vec<_>>());
+    for (page_id, data) in &need_flush {
+        let page = clock_replacer.get(*page_id)?.unwrap();
+        assert_eq!(page.write()?.get_data(), &data[..]);
+    }
+
+    clock_replacer.clear_edited();
+    assert!(clock_replacer.get_need_flush().is_empty());
+    Ok(())
+}
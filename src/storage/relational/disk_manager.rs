@@ -1,51 +1,135 @@
 use crate::error::{Error, Result};
-use crate::storage::relational::page::PAGE_SIZE;
+use crate::storage::relational::page::{Page, TablePage, PAGE_SIZE};
+use std::collections::{BTreeSet, HashMap};
 use std::fs::{create_dir_all, File, OpenOptions};
 use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+/// A manifest of a db file backup
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupManifest {
+    /// the number of pages copied
+    pub page_count: u32,
+    /// CRC32 of all page data at the time of the backup, in page order
+    pub digest: u32,
+    /// the highest page LSN at the time of the backup. an incremental backup on top of
+    /// this one copies the pages with a higher LSN
+    pub lsn: u32,
+}
+
+/// A store of pages by page id, e.g. the db file or a tiered store on top of it
+pub trait PageStore {
+    /// read a page into the given buffer of PAGE_SIZE bytes
+    fn read_page(&mut self, page_id: u32, buf: &mut [u8]) -> Result<()>;
+
+    /// write a page of PAGE_SIZE bytes
+    fn write_page(&mut self, page_id: u32, page_data: &[u8]) -> Result<()>;
+}
+
 pub struct DiskManager {
     // write to log file
     log_file: Arc<Mutex<File>>,
     // write to db file
     db_file: Arc<Mutex<File>>,
+    // the last page written, until it's safely in the db file
+    double_write_file: Arc<Mutex<File>>,
     num_flushes: u32,
     num_writes: u32,
+    // the highest LSN given to a page
+    lsn: u32,
+    // the first page of the free list, or 0 if it's empty
+    free_list_head: u32,
 }
 
 impl DiskManager {
+    /// the head of the free list in the header page (page 0)
+    const OFFSET_FREE_LIST_HEAD: usize = 9;
+    /// a free page is marked deleted, like a dropped table page
+    const OFFSET_DELETED: usize = 4;
+    const OFFSET_LSN: usize = 5;
+    /// the next free page, where table pages keep their next page id
+    const OFFSET_NEXT_FREE_PAGE_ID: usize = 13;
+    /// a double-write record: page id (4), page data, and CRC32 of both (4)
+    const DOUBLE_WRITE_SIZE: usize = 4 + PAGE_SIZE + 4;
+
     /// Creates or opens a new disk db, with files in the given directory.
     pub fn open(db_dir: &Path) -> Result<DiskManager> {
         create_dir_all(db_dir)?;
         let db_file =
             OpenOptions::new().read(true).write(true).create(true).open(db_dir.join("toydb.db"))?;
+        // the log is only appended to, whatever was read last or written before a restart
         let log_file = OpenOptions::new()
             .read(true)
-            .write(true)
+            .append(true)
             .create(true)
             .open(db_dir.join("toydb.log"))?;
+        let double_write_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(db_dir.join("toydb.dwb"))?;
 
-        let disk_manager = DiskManager {
+        let mut disk_manager = DiskManager {
             log_file: Arc::new(Mutex::new(log_file)),
             db_file: Arc::new(Mutex::new(db_file)),
+            double_write_file: Arc::new(Mutex::new(double_write_file)),
             num_flushes: 0,
             num_writes: 0,
+            lsn: 0,
+            free_list_head: 0,
         };
+        // a page torn by a crash is repaired before anything reads it
+        disk_manager.recover_double_write()?;
+
+        // continue the LSNs after the ones already written
+        let mut page_data = [0u8; PAGE_SIZE];
+        for page_id in 0..disk_manager.get_num_pages()? {
+            disk_manager.read_page(page_id, &mut page_data)?;
+            if page_id == 0 {
+                disk_manager.free_list_head =
+                    DiskManager::read_u32(&page_data, DiskManager::OFFSET_FREE_LIST_HEAD);
+            }
+            disk_manager.lsn = disk_manager.lsn.max(DiskManager::page_lsn(page_id, page_data)?);
+        }
 
         Ok(disk_manager)
     }
 
-    /// Write the contents of the specified page into disk file
+    /// return a new LSN, to stamp on a page before writing it
+    pub fn next_lsn(&mut self) -> u32 {
+        self.lsn += 1;
+        self.lsn
+    }
+
+    /// Write the contents of the specified page into disk file.
+    /// the header page always gets the current free list head, whatever the given data holds,
+    /// so a stale copy of the header page can't bring back an old head.
+    /// the page is written to the double-write file first, so a torn write can be repaired
     pub fn write_page(&mut self, page_id: u32, page_data: &[u8]) -> Result<()> {
         if page_data.len() != PAGE_SIZE {
-            return Err(Error::Value("page size was lass than PAGE_SIZE".to_string()));
+            return Err(Error::Value(format!("page data must be {} bytes", PAGE_SIZE)));
         }
+        let header_data;
+        let mut page_data = page_data;
+        let offset = DiskManager::OFFSET_FREE_LIST_HEAD;
+        if page_id == 0 && DiskManager::read_u32(page_data, offset) != self.free_list_head {
+            header_data = self.with_free_list_head(page_data)?;
+            page_data = &header_data;
+        }
+        self.write_double_write(page_id, page_data)?;
+
         let mut db_file = self.db_file.lock()?;
+        let offset = page_id as u64 * PAGE_SIZE as u64;
+        // extend the file with zeroed pages up to the page, so the pages before it are
+        // readable even if they were never written
+        if db_file.metadata()?.len() < offset {
+            db_file.set_len(offset)?;
+        }
         let mut buf_writer = BufWriter::new(&mut *db_file);
-        let offset = page_id * (PAGE_SIZE as u32);
         // set write cursor to offset
-        buf_writer.seek(SeekFrom::Start(offset as u64))?;
+        buf_writer.seek(SeekFrom::Start(offset))?;
         buf_writer.write_all(page_data)?;
         // needs to flush to keep disk file in sync
         buf_writer.flush()?;
@@ -57,6 +141,58 @@ impl DiskManager {
         Ok(())
     }
 
+    /// write a page to the double-write file, and sync it, before the page is written in
+    /// place. the file only holds the last page written
+    fn write_double_write(&mut self, page_id: u32, page_data: &[u8]) -> Result<()> {
+        let mut record = Vec::with_capacity(DiskManager::DOUBLE_WRITE_SIZE);
+        record.extend_from_slice(&page_id.to_le_bytes());
+        record.extend_from_slice(page_data);
+        record.extend_from_slice(&crc32fast::hash(&record).to_le_bytes());
+        let mut double_write_file = self.double_write_file.lock()?;
+        double_write_file.seek(SeekFrom::Start(0))?;
+        double_write_file.write_all(&record)?;
+        double_write_file.sync_data()?;
+        Ok(())
+    }
+
+    /// write a page copied as is, e.g. from the double-write file or a backup. a copy of the
+    /// header page holds the free list head that goes with it, so the head is taken from it
+    fn write_copied_page(&mut self, page_id: u32, page_data: &[u8]) -> Result<()> {
+        if page_id == 0 {
+            self.free_list_head =
+                DiskManager::read_u32(page_data, DiskManager::OFFSET_FREE_LIST_HEAD);
+        }
+        self.write_page(page_id, page_data)
+    }
+
+    /// copy the page in the double-write file, if any, to its place in the db file, in case
+    /// writing it there was cut short. a record that doesn't check out was itself cut short,
+    /// so the page was never written in place
+    fn recover_double_write(&mut self) -> Result<()> {
+        let mut record = Vec::new();
+        let mut double_write_file = self.double_write_file.lock()?;
+        double_write_file.seek(SeekFrom::Start(0))?;
+        double_write_file.read_to_end(&mut record)?;
+        drop(double_write_file);
+
+        if record.len() == DiskManager::DOUBLE_WRITE_SIZE {
+            let (page, checksum) = record.split_at(4 + PAGE_SIZE);
+            if crc32fast::hash(page) == DiskManager::read_u32(checksum, 0) {
+                self.write_copied_page(DiskManager::read_u32(page, 0), &page[4..])?;
+            }
+        }
+        self.clear_double_write()
+    }
+
+    /// empty the double-write file, once the page in it is synced to the db file, or
+    /// before the db file is truncated, so the page is never copied back past the end
+    fn clear_double_write(&mut self) -> Result<()> {
+        let double_write_file = self.double_write_file.lock()?;
+        double_write_file.set_len(0)?;
+        double_write_file.sync_data()?;
+        Ok(())
+    }
+
     /// Read the contents of the specified page into the given memory area
     pub fn read_page(&mut self, page_id: u32, buf: &mut [u8]) -> Result<()> {
         let offset = page_id as u64 * PAGE_SIZE as u64;
@@ -70,6 +206,106 @@ impl DiskManager {
         Ok(())
     }
 
+    /// return a page id for a new page, reusing a free page if there is one, or else
+    /// extending the db file. page 0 is the header page, and is never allocated
+    pub fn allocate_page(&mut self) -> Result<u32> {
+        let page_id = self.free_list_head;
+        if page_id == 0 {
+            let page_id = self.get_num_pages()?.max(1);
+            // claim the page, so the next allocation doesn't return it as well
+            self.write_page(page_id, &[0u8; PAGE_SIZE])?;
+            return Ok(page_id);
+        }
+
+        let next_page_id = self.next_free_page_id(page_id)?;
+        self.set_free_list_head(next_page_id)?;
+        Ok(page_id)
+    }
+
+    /// add a page to the free list, for allocate_page to reuse. the page data is
+    /// overwritten with the free list link, so the page must no longer be in use
+    pub fn deallocate_page(&mut self, page_id: u32) -> Result<()> {
+        if page_id == 0 || !self.have_page(page_id)? {
+            return Err(Error::Value(format!("can't deallocate page {}", page_id)));
+        }
+        if self.get_free_pages()?.contains(&page_id) {
+            return Err(Error::Value(format!("page {} is already free", page_id)));
+        }
+
+        let lsn = self.next_lsn();
+        let mut free_page = Page::new(page_id, [0u8; PAGE_SIZE])?;
+        free_page.write_data(&page_id.to_le_bytes(), 0, 4)?;
+        free_page.write_data(&[1u8], DiskManager::OFFSET_DELETED, 1)?;
+        free_page.write_data(&lsn.to_le_bytes(), DiskManager::OFFSET_LSN, 4)?;
+        free_page.write_data(
+            &self.free_list_head.to_le_bytes(),
+            DiskManager::OFFSET_NEXT_FREE_PAGE_ID,
+            4,
+        )?;
+        let mut page_data = [0u8; PAGE_SIZE];
+        free_page.read_data(&mut page_data, 0, PAGE_SIZE)?;
+        // the page is linked before it becomes the head, so a crash in between only leaks it
+        self.write_page(page_id, &page_data)?;
+        self.set_free_list_head(page_id)
+    }
+
+    /// return the pages on the free list, from the head
+    pub fn get_free_pages(&mut self) -> Result<Vec<u32>> {
+        let num_pages = self.get_num_pages()?;
+        let mut free_pages = Vec::new();
+        let mut page_id = self.free_list_head;
+        while page_id != 0 {
+            if free_pages.len() >= num_pages as usize {
+                return Err(Error::PageCorrupt(format!("page {}: free list has a cycle", page_id)));
+            }
+            free_pages.push(page_id);
+            page_id = self.next_free_page_id(page_id)?;
+        }
+        Ok(free_pages)
+    }
+
+    /// read the link of a page on the free list
+    fn next_free_page_id(&mut self, page_id: u32) -> Result<u32> {
+        let mut page_data = [0u8; PAGE_SIZE];
+        self.read_page(page_id, &mut page_data)?;
+        Page::verify_checksum(page_id, &page_data)?;
+        if page_data[DiskManager::OFFSET_DELETED] != 1 {
+            return Err(Error::PageCorrupt(format!(
+                "page {}: on the free list but in use",
+                page_id
+            )));
+        }
+        Ok(DiskManager::read_u32(&page_data, DiskManager::OFFSET_NEXT_FREE_PAGE_ID))
+    }
+
+    /// set the free list head, and write it to the header page
+    fn set_free_list_head(&mut self, page_id: u32) -> Result<()> {
+        self.free_list_head = page_id;
+        let mut header_data = [0u8; PAGE_SIZE];
+        if self.have_page(0)? {
+            self.read_page(0, &mut header_data)?;
+        }
+        let header_data = self.with_free_list_head(&header_data)?;
+        self.write_page(0, &header_data)
+    }
+
+    /// return a copy of the header page data, with the current free list head
+    fn with_free_list_head(&self, page_data: &[u8]) -> Result<[u8; PAGE_SIZE]> {
+        let mut data = [0u8; PAGE_SIZE];
+        data.copy_from_slice(page_data);
+        let mut header = Page::new(0, data)?;
+        let head = self.free_list_head.to_le_bytes();
+        header.write_data(&head, DiskManager::OFFSET_FREE_LIST_HEAD, 4)?;
+        header.read_data(&mut data, 0, PAGE_SIZE)?;
+        Ok(data)
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        let mut value = [0u8; 4];
+        value.copy_from_slice(&data[offset..offset + 4]);
+        u32::from_le_bytes(value)
+    }
+
     /// check this db have page by page id
     pub fn have_page(&mut self, page_id: u32) -> Result<bool> {
         let file_size = self.get_db_size()?;
@@ -81,7 +317,7 @@ impl DiskManager {
         Ok(true)
     }
 
-    /// Write the contents of the log into disk file
+    /// Append an entry to the log file
     /// Only return when sync is done, and only perform sequence write
     pub fn write_log(&mut self, log_data: &[u8]) -> Result<()> {
         let mut log_file = self.log_file.lock()?;
@@ -97,27 +333,262 @@ impl DiskManager {
         Ok(())
     }
 
-    /// Read the contents of the log into the given memory area
-    //  Always read from the beginning and perform sequence read
-    //  @return: None means already reach the end
-    pub fn read_log(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
-        if offset > self.get_log_size()? || buf.is_empty() {
-            return Err(Error::Value(String::from("offset is out of range or buf size is zero.")));
+    /// Read up to len bytes of the log, starting at the given offset.
+    /// the data is cut short at the end of the log, and None means the offset is at the end
+    pub fn read_log(&mut self, len: usize, offset: u64) -> Result<Option<Vec<u8>>> {
+        if len == 0 {
+            return Err(Error::Value(String::from("log read size is zero.")));
         }
+        let log_size = self.get_log_size()?;
+        if offset >= log_size {
+            return Ok(None);
+        }
+        let mut buf = vec![0u8; len.min((log_size - offset) as usize)];
         let mut log_file = self.log_file.lock()?;
         log_file.seek(SeekFrom::Start(offset))?;
-        log_file.read_exact(buf)?;
-        Ok(())
+        log_file.read_exact(&mut buf)?;
+        Ok(Some(buf))
     }
 
+    /// the number of log writes
     pub fn get_num_flushes(&self) -> &u32 {
         &self.num_flushes
     }
 
+    /// the number of page writes
     pub fn get_num_writes(&self) -> &u32 {
         &self.num_writes
     }
 
+    /// iterate over every whole page in the db file, regardless of what is in use, e.g.
+    /// for offline tools. a trailing partial page is yielded as an error at the end
+    pub fn iter_pages(&mut self) -> impl Iterator<Item = Result<(u32, [u8; PAGE_SIZE])>> {
+        let db_file = Arc::clone(&self.db_file);
+        let db_size = self.get_db_size();
+        let mut next_page_id = 0;
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let db_size = match &db_size {
+                Ok(db_size) => *db_size,
+                Err(err) => {
+                    done = true;
+                    return Some(Err(err.clone()));
+                }
+            };
+            let page_id = next_page_id;
+            if page_id as u64 >= db_size / PAGE_SIZE as u64 {
+                done = true;
+                let partial_size = db_size % PAGE_SIZE as u64;
+                if partial_size > 0 {
+                    return Some(Err(Error::PageCorrupt(format!(
+                        "page {}: trailing partial page of {} bytes",
+                        page_id, partial_size
+                    ))));
+                }
+                return None;
+            }
+            next_page_id += 1;
+
+            let read = || -> Result<[u8; PAGE_SIZE]> {
+                let mut data = [0u8; PAGE_SIZE];
+                let mut db_file = db_file.lock()?;
+                db_file.seek(SeekFrom::Start(page_id as u64 * PAGE_SIZE as u64))?;
+                db_file.read_exact(&mut data)?;
+                Ok(data)
+            };
+            Some(read().map(|data| (page_id, data)))
+        })
+    }
+
+    /// shrink the db file, by moving the pages after the last free page into the free
+    /// pages near the start and truncating the file. page 0 is never moved.
+    /// the pages on the free list are reclaimed as well, and the free list is emptied.
+    /// return the bytes reclaimed, and the new page id of every moved page
+    pub fn shrink(&mut self, free_pages: &[u32]) -> Result<(u64, HashMap<u32, u32>)> {
+        let db_size = self.get_db_size()?;
+        let num_pages = self.get_num_pages()?;
+        let listed_pages = self.get_free_pages()?;
+        // the free pages are overwritten below, so the list is dropped first
+        if !listed_pages.is_empty() {
+            self.set_free_list_head(0)?;
+        }
+        let free_pages = free_pages
+            .iter()
+            .chain(listed_pages.iter())
+            .copied()
+            .filter(|page_id| *page_id != 0 && *page_id < num_pages)
+            .collect::<BTreeSet<_>>();
+        let new_num_pages = num_pages - free_pages.len() as u32;
+
+        // the number of free pages before the new end equals the number of pages in use after it
+        let holes = free_pages.iter().copied().take_while(|page_id| *page_id < new_num_pages);
+        let moved = (new_num_pages..num_pages).filter(|page_id| !free_pages.contains(page_id));
+        let mut remap = HashMap::new();
+        let mut page_data = [0u8; PAGE_SIZE];
+        for (old_page_id, new_page_id) in moved.zip(holes) {
+            self.read_page(old_page_id, &mut page_data)?;
+            self.write_page(new_page_id, &page_data)?;
+            remap.insert(old_page_id, new_page_id);
+        }
+
+        let new_db_size = new_num_pages as u64 * PAGE_SIZE as u64;
+        self.clear_double_write()?;
+        let db_file = self.db_file.lock()?;
+        db_file.set_len(new_db_size)?;
+        db_file.sync_all()?;
+        Ok((db_size - new_db_size, remap))
+    }
+
+    /// copy the db file into the given directory, as a consistent snapshot: page writes
+    /// are blocked while copying. every page's checksum is checked, and every page is
+    /// read back from the copy and checked against the original
+    pub fn backup_to(&mut self, backup_dir: &Path) -> Result<BackupManifest> {
+        create_dir_all(backup_dir)?;
+        let mut backup_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(backup_dir.join("toydb.db"))?;
+
+        let mut db_file = self.db_file.lock()?;
+        let db_size = db_file.metadata()?.len();
+        let page_count = (db_size / PAGE_SIZE as u64) as u32;
+        if db_size % PAGE_SIZE as u64 != 0 {
+            return Err(Error::PageCorrupt(format!(
+                "page {}: trailing partial page of {} bytes",
+                page_count,
+                db_size % PAGE_SIZE as u64
+            )));
+        }
+
+        let mut digest = crc32fast::Hasher::new();
+        let mut lsn = 0;
+        let mut checksums = Vec::with_capacity(page_count as usize);
+        let mut page_data = [0u8; PAGE_SIZE];
+        db_file.seek(SeekFrom::Start(0))?;
+        for page_id in 0..page_count {
+            db_file.read_exact(&mut page_data)?;
+            Page::verify_checksum(page_id, &page_data)?;
+            backup_file.write_all(&page_data)?;
+            digest.update(&page_data);
+            lsn = lsn.max(DiskManager::page_lsn(page_id, page_data)?);
+            checksums.push(crc32fast::hash(&page_data));
+        }
+        drop(db_file);
+        backup_file.sync_all()?;
+
+        backup_file.seek(SeekFrom::Start(0))?;
+        for (page_id, checksum) in checksums.into_iter().enumerate() {
+            backup_file.read_exact(&mut page_data)?;
+            if crc32fast::hash(&page_data) != checksum {
+                return Err(Error::PageCorrupt(format!(
+                    "page {}: backup copy does not match the original",
+                    page_id
+                )));
+            }
+        }
+
+        Ok(BackupManifest { page_count, digest: digest.finalize(), lsn })
+    }
+
+    /// write the pages changed since the given backup into the given directory, as a delta
+    /// which apply_incremental can apply on top of that backup. the header page has no LSN,
+    /// so it is always included. return the manifest of the database state, which later
+    /// incremental backups can build on
+    pub fn backup_incremental(
+        &mut self,
+        base: &BackupManifest,
+        backup_dir: &Path,
+    ) -> Result<BackupManifest> {
+        create_dir_all(backup_dir)?;
+        let backup_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(backup_dir.join("toydb.delta"))?;
+        let mut backup_writer = BufWriter::new(backup_file);
+
+        let mut db_file = self.db_file.lock()?;
+        let db_size = db_file.metadata()?.len();
+        let page_count = (db_size / PAGE_SIZE as u64) as u32;
+        if db_size % PAGE_SIZE as u64 != 0 {
+            return Err(Error::PageCorrupt(format!(
+                "page {}: trailing partial page of {} bytes",
+                page_count,
+                db_size % PAGE_SIZE as u64
+            )));
+        }
+
+        // delta format: page count (4), then page id (4) and page data for each changed page
+        backup_writer.write_all(&page_count.to_le_bytes())?;
+        let mut digest = crc32fast::Hasher::new();
+        let mut lsn = base.lsn;
+        let mut page_data = [0u8; PAGE_SIZE];
+        db_file.seek(SeekFrom::Start(0))?;
+        for page_id in 0..page_count {
+            db_file.read_exact(&mut page_data)?;
+            Page::verify_checksum(page_id, &page_data)?;
+            digest.update(&page_data);
+            let page_lsn = DiskManager::page_lsn(page_id, page_data)?;
+            if page_id == 0 || page_lsn > base.lsn {
+                backup_writer.write_all(&page_id.to_le_bytes())?;
+                backup_writer.write_all(&page_data)?;
+            }
+            lsn = lsn.max(page_lsn);
+        }
+        drop(db_file);
+        backup_writer.flush()?;
+        backup_writer.get_ref().sync_all()?;
+
+        Ok(BackupManifest { page_count, digest: digest.finalize(), lsn })
+    }
+
+    /// apply a delta written by backup_incremental to this db file, which must hold the
+    /// backup the delta was taken on top of
+    pub fn apply_incremental(&mut self, backup_dir: &Path) -> Result<()> {
+        let mut delta = Vec::new();
+        File::open(backup_dir.join("toydb.delta"))?.read_to_end(&mut delta)?;
+        if delta.len() < 4 || (delta.len() - 4) % (4 + PAGE_SIZE) != 0 {
+            return Err(Error::Value(format!("invalid delta of {} bytes", delta.len())));
+        }
+        let mut page_count = [0u8; 4];
+        page_count.copy_from_slice(&delta[..4]);
+        let page_count = u32::from_le_bytes(page_count);
+
+        let mut page_data = [0u8; PAGE_SIZE];
+        for record in delta[4..].chunks(4 + PAGE_SIZE) {
+            let mut page_id = [0u8; 4];
+            page_id.copy_from_slice(&record[..4]);
+            let page_id = u32::from_le_bytes(page_id);
+            page_data.copy_from_slice(&record[4..]);
+            self.write_copied_page(page_id, &page_data)?;
+            self.lsn = self.lsn.max(DiskManager::page_lsn(page_id, page_data)?);
+        }
+
+        self.clear_double_write()?;
+        let db_file = self.db_file.lock()?;
+        db_file.set_len(page_count as u64 * PAGE_SIZE as u64)?;
+        db_file.sync_all()?;
+        Ok(())
+    }
+
+    /// return the LSN of a page. the header page (page 0) has none
+    fn page_lsn(page_id: u32, page_data: [u8; PAGE_SIZE]) -> Result<u32> {
+        if page_id == 0 {
+            return Ok(0);
+        }
+        TablePage::from_data(page_id, page_data)?.get_lsn()
+    }
+
+    /// get the number of whole pages stored in the db file
+    pub fn get_num_pages(&self) -> Result<u32> {
+        Ok((self.get_db_size()? / PAGE_SIZE as u64) as u32)
+    }
+
     /// get the db file size
     fn get_db_size(&self) -> Result<u64> {
         let file = self.db_file.lock()?;
@@ -136,7 +607,19 @@ impl DiskManager {
 
 impl Drop for DiskManager {
     fn drop(&mut self) {
-        self.db_file.lock().unwrap().sync_all().ok();
+        if self.db_file.lock().unwrap().sync_all().is_ok() {
+            self.clear_double_write().ok();
+        }
         self.log_file.lock().unwrap().sync_all().ok();
     }
 }
+
+impl PageStore for DiskManager {
+    fn read_page(&mut self, page_id: u32, buf: &mut [u8]) -> Result<()> {
+        DiskManager::read_page(self, page_id, buf)
+    }
+
+    fn write_page(&mut self, page_id: u32, page_data: &[u8]) -> Result<()> {
+        DiskManager::write_page(self, page_id, page_data)
+    }
+}
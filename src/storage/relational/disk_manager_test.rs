@@ -0,0 +1,341 @@
+use crate::error::{Error, Result};
+use crate::storage::relational::buffer_pool::BufferPoolManager;
+use crate::storage::relational::disk_manager::DiskManager;
+use crate::storage::relational::page::{TablePage, PAGE_SIZE};
+use crate::storage::relational::tuple::{Tuple, RID};
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use tempdir::TempDir;
+
+#[test]
+fn test_write_page_size() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    assert!(disk_manager.write_page(1, &[0x01; PAGE_SIZE - 1]).is_err());
+    assert!(disk_manager.write_page(1, &[0x01; PAGE_SIZE + 1]).is_err());
+
+    // pages are laid out back to back in 4KB blocks
+    disk_manager.write_page(1, &[0x01; PAGE_SIZE])?;
+    assert_eq!(8192, std::fs::metadata(dir.path().join("toydb.db"))?.len());
+    let mut data = [0u8; PAGE_SIZE];
+    disk_manager.read_page(1, &mut data)?;
+    assert!(data.iter().all(|b| *b == 0x01));
+    Ok(())
+}
+
+#[test]
+fn test_write_page_extends_file() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    disk_manager.write_page(4, &[0x04; PAGE_SIZE])?;
+    assert_eq!(5, disk_manager.get_num_pages()?);
+
+    // the pages skipped over are zeroed, not missing
+    let mut data = [0xffu8; PAGE_SIZE];
+    for page_id in 1..4 {
+        assert!(disk_manager.have_page(page_id)?);
+        disk_manager.read_page(page_id, &mut data)?;
+        assert!(data.iter().all(|b| *b == 0), "page {} contents", page_id);
+    }
+    disk_manager.read_page(4, &mut data)?;
+    assert!(data.iter().all(|b| *b == 0x04));
+    assert!(!disk_manager.have_page(5)?);
+    Ok(())
+}
+
+#[test]
+fn test_log() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    assert_eq!(None, disk_manager.read_log(4, 0)?);
+    assert!(disk_manager.read_log(0, 0).is_err());
+
+    disk_manager.write_log(b"abc")?;
+    disk_manager.write_log(b"defg")?;
+    assert_eq!(Some(b"abcd".to_vec()), disk_manager.read_log(4, 0)?);
+    // reads are cut short at the end of the log, and don't move where the log is written
+    assert_eq!(Some(b"efg".to_vec()), disk_manager.read_log(8, 4)?);
+    assert_eq!(None, disk_manager.read_log(1, 7)?);
+    disk_manager.write_log(b"h")?;
+    disk_manager.write_page(1, &[0x01; PAGE_SIZE])?;
+    assert_eq!(3, *disk_manager.get_num_flushes());
+    assert_eq!(1, *disk_manager.get_num_writes());
+
+    // the log is kept apart from the pages, and appended to after a reopen
+    drop(disk_manager);
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    disk_manager.write_log(b"i")?;
+    assert_eq!(Some(b"abcdefghi".to_vec()), disk_manager.read_log(16, 0)?);
+    assert_eq!(8192, std::fs::metadata(dir.path().join("toydb.db"))?.len());
+    assert_eq!(1, *disk_manager.get_num_flushes());
+    Ok(())
+}
+
+#[test]
+fn test_double_write_recovery() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let db_path = dir.path().join("toydb.db");
+    let double_write_path = dir.path().join("toydb.dwb");
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    let mut tuple = Tuple::from_data(vec![0x01; 64]);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    let page_data = table_page.get_data().to_vec();
+
+    // a clean shutdown empties the double-write file
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    disk_manager.write_page(1, &page_data)?;
+    assert_eq!(4 + PAGE_SIZE as u64 + 4, std::fs::metadata(&double_write_path)?.len());
+    drop(disk_manager);
+    assert_eq!(0, std::fs::metadata(&double_write_path)?.len());
+
+    // crash after writing half of the page in place: the page is copied back on open
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    disk_manager.write_page(1, &page_data)?;
+    std::mem::forget(disk_manager);
+    let mut db_file = OpenOptions::new().write(true).open(&db_path)?;
+    db_file.seek(SeekFrom::Start(PAGE_SIZE as u64 + PAGE_SIZE as u64 / 2))?;
+    db_file.write_all(&[0xff; PAGE_SIZE / 2])?;
+    drop(db_file);
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    let mut data = [0u8; PAGE_SIZE];
+    disk_manager.read_page(1, &mut data)?;
+    assert_eq!(page_data, data.to_vec());
+    assert_eq!(0, std::fs::metadata(&double_write_path)?.len());
+
+    // crash while writing the double-write record: the page was never written in place
+    disk_manager.write_page(1, &[0u8; PAGE_SIZE])?;
+    std::mem::forget(disk_manager);
+    let mut record = std::fs::read(&double_write_path)?;
+    let len = record.len();
+    record[len - 1] ^= 0xff;
+    std::fs::write(&double_write_path, &record)?;
+    std::fs::write(&db_path, [&[0u8; PAGE_SIZE][..], &page_data].concat())?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    disk_manager.read_page(1, &mut data)?;
+    assert_eq!(page_data, data.to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_iter_pages() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    assert_eq!(0, disk_manager.iter_pages().count());
+
+    for page_id in 0..5u32 {
+        disk_manager.write_page(page_id, &[page_id as u8 + 1; PAGE_SIZE])?;
+    }
+    let pages = disk_manager.iter_pages().collect::<Result<Vec<_>>>()?;
+    assert_eq!(vec![0, 1, 2, 3, 4], pages.iter().map(|(page_id, _)| *page_id).collect::<Vec<_>>());
+    // the header page gets the free list head written into it, so it is compared as read
+    let mut header_data = [0u8; PAGE_SIZE];
+    disk_manager.read_page(0, &mut header_data)?;
+    assert!(pages[0].1 == header_data);
+    for (page_id, data) in &pages[1..] {
+        assert!(data.iter().all(|b| *b == *page_id as u8 + 1), "page {} contents", page_id);
+    }
+
+    // a trailing partial page is an error after the whole pages
+    OpenOptions::new().append(true).open(dir.path().join("toydb.db"))?.write_all(&[0xff; 100])?;
+    let mut pages = disk_manager.iter_pages();
+    for page_id in 0..5u32 {
+        assert_eq!(page_id, pages.next().unwrap()?.0);
+    }
+    assert!(matches!(pages.next(), Some(Err(Error::PageCorrupt(_)))));
+    assert!(pages.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_shrink() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    for page_id in 0..6u32 {
+        disk_manager.write_page(page_id, &[page_id as u8 + 1; PAGE_SIZE])?;
+    }
+    let db_size = || -> Result<u64> { Ok(std::fs::metadata(dir.path().join("toydb.db"))?.len()) };
+
+    // trailing free pages are truncated
+    let (reclaimed, remap) = disk_manager.shrink(&[4, 5])?;
+    assert_eq!(2 * PAGE_SIZE as u64, reclaimed);
+    assert!(remap.is_empty());
+    assert_eq!(4 * PAGE_SIZE as u64, db_size()?);
+
+    // pages after a free page are moved into it, and page 0 is never freed
+    let (reclaimed, remap) = disk_manager.shrink(&[0, 1, 7])?;
+    assert_eq!(PAGE_SIZE as u64, reclaimed);
+    assert_eq!(vec![(3, 1)], remap.into_iter().collect::<Vec<_>>());
+    assert_eq!(3 * PAGE_SIZE as u64, db_size()?);
+    let pages = disk_manager.iter_pages().collect::<Result<Vec<_>>>()?;
+    let first_bytes = pages.iter().map(|(page_id, data)| (*page_id, data[0])).collect::<Vec<_>>();
+    assert_eq!(vec![(0, 1), (1, 4), (2, 3)], first_bytes);
+
+    Ok(())
+}
+
+#[test]
+fn test_backup_to() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    for i in 0..50u8 {
+        let mut tuple = Tuple::from_data(vec![i; 100]);
+        tuple.set_rid(RID::new(root_id, 0));
+        assert!(buffer_pool.insert_tuple("a", &mut tuple)?);
+    }
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    let backup_dir = TempDir::new("toydb-backup")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    let manifest = disk_manager.backup_to(backup_dir.path())?;
+    let pages = disk_manager.iter_pages().collect::<Result<Vec<_>>>()?;
+    assert_eq!(pages.len() as u32, manifest.page_count);
+    let data = pages.iter().flat_map(|(_, data)| data.iter().copied()).collect::<Vec<_>>();
+    assert_eq!(crc32fast::hash(&data), manifest.digest);
+
+    // the copy matches page for page, and its pages load cleanly
+    let backup_pages =
+        DiskManager::open(backup_dir.path())?.iter_pages().collect::<Result<Vec<_>>>()?;
+    assert!(pages.iter().map(|(_, d)| &d[..]).eq(backup_pages.iter().map(|(_, d)| &d[..])));
+    let mut buffer_pool = BufferPoolManager::open(backup_dir.path(), 8)?;
+    let page = buffer_pool.fetch_page(root_id)?.unwrap();
+    let tuple = page.write()?.get_tuple(&RID::new(root_id, 0))?.unwrap();
+    assert_eq!(&[0; 100][..], tuple.get_data());
+
+    Ok(())
+}
+
+#[test]
+fn test_backup_incremental() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    for i in 0..50u8 {
+        let mut tuple = Tuple::from_data(vec![i; 100]);
+        tuple.set_rid(RID::new(root_id, 0));
+        assert!(buffer_pool.insert_tuple("a", &mut tuple)?);
+    }
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // take a full backup of pages 0-2
+    let backup_dir = TempDir::new("toydb-backup")?;
+    let base = DiskManager::open(dir.path())?.backup_to(backup_dir.path())?;
+    assert_eq!(3, base.page_count);
+
+    // change page 2 and append page 3, leaving page 1 as it was
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let mut tuple = Tuple::from_data(vec![0xff; 100]);
+    tuple.set_rid(RID::new(2, 0));
+    assert!(buffer_pool.fetch_page(2)?.unwrap().write()?.insert_tuple(&mut tuple)?);
+    buffer_pool.allocate_page(Some(2))?;
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // the delta holds the header page and pages 2-3
+    let delta_dir = TempDir::new("toydb-delta")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    let manifest = disk_manager.backup_incremental(&base, delta_dir.path())?;
+    assert_eq!(4, manifest.page_count);
+    assert!(manifest.lsn > base.lsn);
+    let delta_size = std::fs::metadata(delta_dir.path().join("toydb.delta"))?.len();
+    assert_eq!(4 + 3 * (4 + PAGE_SIZE as u64), delta_size);
+
+    // restoring the delta on top of the full backup reproduces the current state
+    let mut backup = DiskManager::open(backup_dir.path())?;
+    backup.apply_incremental(delta_dir.path())?;
+    let pages = disk_manager.iter_pages().collect::<Result<Vec<_>>>()?;
+    let backup_pages = backup.iter_pages().collect::<Result<Vec<_>>>()?;
+    assert_eq!(pages.len(), backup_pages.len());
+    assert!(pages.iter().map(|(_, d)| &d[..]).eq(backup_pages.iter().map(|(_, d)| &d[..])));
+    let restore_dir = TempDir::new("toydb-restore")?;
+    assert_eq!(manifest, backup.backup_to(restore_dir.path())?);
+
+    Ok(())
+}
+
+#[test]
+fn test_allocate_page() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    // page 0 is the header page, so allocation starts at page 1
+    let pages = (0..3).map(|_| disk_manager.allocate_page()).collect::<Result<Vec<_>>>()?;
+    assert_eq!(vec![1, 2, 3], pages);
+    assert_eq!(4, disk_manager.get_num_pages()?);
+
+    // a freed page is reused before the file is extended
+    disk_manager.deallocate_page(2)?;
+    assert!(disk_manager.deallocate_page(2).is_err());
+    assert!(disk_manager.deallocate_page(0).is_err());
+    assert!(disk_manager.deallocate_page(4).is_err());
+    assert_eq!(vec![2], disk_manager.get_free_pages()?);
+    assert_eq!(2, disk_manager.allocate_page()?);
+    assert_eq!(4, disk_manager.allocate_page()?);
+    assert_eq!(5, disk_manager.get_num_pages()?);
+
+    // the free list survives a reopen, and is used last in, first out
+    disk_manager.deallocate_page(1)?;
+    disk_manager.deallocate_page(3)?;
+    drop(disk_manager);
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    assert_eq!(vec![3, 1], disk_manager.get_free_pages()?);
+    assert_eq!(3, disk_manager.allocate_page()?);
+    assert_eq!(1, disk_manager.allocate_page()?);
+    assert_eq!(5, disk_manager.allocate_page()?);
+    assert!(disk_manager.get_free_pages()?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_allocate_page_header() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 10)?;
+    assert_eq!(Some(1), buffer_pool.create_table("table")?);
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // the free list head is kept when the header page is written over it
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    let page_id = disk_manager.allocate_page()?;
+    disk_manager.deallocate_page(page_id)?;
+    let mut header_data = [0u8; PAGE_SIZE];
+    disk_manager.read_page(0, &mut header_data)?;
+    header_data[9..13].copy_from_slice(&[0u8; 4]);
+    disk_manager.write_page(0, &header_data)?;
+    drop(disk_manager);
+
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    assert_eq!(vec![page_id], disk_manager.get_free_pages()?);
+    drop(disk_manager);
+    let buffer_pool = BufferPoolManager::open(dir.path(), 10)?;
+    assert_eq!(Some(1), buffer_pool.get_table_root_id("table")?);
+    Ok(())
+}
+
+#[test]
+fn test_allocate_page_stale_header() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 10)?;
+    assert_eq!(Some(1), buffer_pool.create_table("a")?);
+    assert!(buffer_pool.drop_table("a")?);
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    // the header page is loaded while the freed page heads the free list. once the page is
+    // reused, flushing the header page must not bring back the old head
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 10)?;
+    assert_eq!(Some(1), buffer_pool.create_table("b")?);
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    assert!(disk_manager.get_free_pages()?.is_empty());
+    drop(disk_manager);
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 10)?;
+    assert_eq!(Some(2), buffer_pool.create_table("c")?);
+    assert_eq!(Some(1), buffer_pool.get_table_root_id("b")?);
+    Ok(())
+}
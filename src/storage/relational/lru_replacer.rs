@@ -0,0 +1,93 @@
+use super::page::TablePage;
+use super::replacer::Replacer;
+use crate::error::{Error, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Cache Page, and remove the least recently used page that isn't pinned
+pub struct LruReplacer {
+    pages: Vec<Arc<RwLock<TablePage>>>,
+    /// the time of the last access to each page, by index. the time is a counter of accesses
+    last_access: Vec<AtomicU64>,
+    clock: AtomicU64,
+    capacity: u32,
+}
+
+impl LruReplacer {
+    pub fn new(capacity: u32) -> Result<LruReplacer> {
+        if capacity == 0 {
+            return Err(Error::Value(String::from("capacity can't be zero!")));
+        }
+        Ok(LruReplacer {
+            pages: Vec::new(),
+            last_access: Vec::new(),
+            clock: AtomicU64::new(0),
+            capacity,
+        })
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl Replacer for LruReplacer {
+    fn pages(&self) -> &[Arc<RwLock<TablePage>>] {
+        &self.pages
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    fn poll(&self, page_id: u32) -> Result<Option<Arc<RwLock<TablePage>>>> {
+        for (page, last_access) in self.pages.iter().zip(&self.last_access) {
+            let mut table_page = page.write()?;
+            if *table_page.get_page_id() == page_id && !table_page.get_status_mut().get_removed() {
+                table_page.get_status_mut().accessed();
+                last_access.store(self.tick(), Ordering::Relaxed);
+                return Ok(Some(Arc::clone(page)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn push(&mut self, page: TablePage) -> Result<Option<Arc<RwLock<TablePage>>>> {
+        let push_page = Arc::new(RwLock::new(page));
+        let now = AtomicU64::new(self.tick());
+        if let Some(index) = self.victim()? {
+            self.last_access[index] = now;
+            return Ok(Some(std::mem::replace(&mut self.pages[index], push_page)));
+        }
+        self.pages.push(push_page);
+        self.last_access.push(now);
+        Ok(None)
+    }
+
+    fn victim(&mut self) -> Result<Option<usize>> {
+        if self.capacity as usize > self.pages.len() {
+            return Ok(None);
+        }
+        let mut victim: Option<(usize, u64)> = None;
+        for (index, page) in self.pages.iter().enumerate() {
+            if *page.write().unwrap().get_pin_count() > 0 {
+                continue;
+            }
+            let last_access = self.last_access[index].load(Ordering::Relaxed);
+            if victim.is_none_or(|(_, oldest)| last_access < oldest) {
+                victim = Some((index, last_access));
+            }
+        }
+        match victim {
+            Some((index, _)) => Ok(Some(index)),
+            None => Err(Error::Value(String::from(
+                "every cached page is pinned, can't evict a page for a new one",
+            ))),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pages.clear();
+        self.last_access.clear();
+    }
+}
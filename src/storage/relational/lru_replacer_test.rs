@@ -0,0 +1,87 @@
+use crate::error::{Error, Result};
+use crate::storage::relational::buffer_pool::BufferPoolManager;
+use crate::storage::relational::clock_replacer::ClockReplacer;
+use crate::storage::relational::lru_replacer::LruReplacer;
+use crate::storage::relational::page::{TablePage, PAGE_SIZE};
+use crate::storage::relational::replacer::Replacer;
+use tempdir::TempDir;
+
+/// push a page read back from disk. return the removed page id
+fn push(replacer: &mut dyn Replacer, page_id: u32) -> Result<Option<u32>> {
+    let table_page = TablePage::from_data(page_id, [0u8; PAGE_SIZE])?;
+    match replacer.push(table_page)? {
+        Some(page) => Ok(Some(*page.write()?.get_page_id())),
+        None => Ok(None),
+    }
+}
+
+#[test]
+fn test_lru_replacer() -> Result<()> {
+    assert!(LruReplacer::new(0).is_err());
+    let mut lru_replacer = LruReplacer::new(3)?;
+    for page_id in 1..=3 {
+        // there's room, so nothing is evicted
+        assert_eq!(None, lru_replacer.victim()?);
+        assert_eq!(None, push(&mut lru_replacer, page_id)?);
+    }
+
+    // the least recently polled page goes first
+    lru_replacer.poll(1)?.unwrap();
+    lru_replacer.poll(3)?.unwrap();
+    assert_eq!(Some(2), push(&mut lru_replacer, 4)?);
+    assert_eq!(Some(1), push(&mut lru_replacer, 5)?);
+
+    // pinned pages are skipped, until every page is pinned
+    lru_replacer.get(3)?.unwrap().write()?.pin();
+    assert_eq!(Some(4), push(&mut lru_replacer, 6)?);
+    lru_replacer.get(5)?.unwrap().write()?.pin();
+    lru_replacer.get(6)?.unwrap().write()?.pin();
+    assert!(matches!(lru_replacer.victim(), Err(Error::Value(_))));
+    assert!(lru_replacer.get(3)?.unwrap().write()?.unpin());
+    assert_eq!(Some(3), push(&mut lru_replacer, 7)?);
+    assert_eq!(3, lru_replacer.size());
+    Ok(())
+}
+
+#[test]
+fn test_replacers_honor_capacity() -> Result<()> {
+    let capacity = 4;
+    let replacers: Vec<Box<dyn Replacer>> =
+        vec![Box::new(ClockReplacer::new(capacity)?), Box::new(LruReplacer::new(capacity)?)];
+    for mut replacer in replacers {
+        // a trace mixing hot pages with a scan of cold ones
+        for (step, page_id) in
+            (1..64u32).map(|i| if i % 3 == 0 { i % 2 + 1 } else { i }).enumerate()
+        {
+            if replacer.poll(page_id)?.is_none() {
+                push(replacer.as_mut(), page_id)?;
+            }
+            assert!(replacer.size() <= replacer.capacity(), "step {}", step);
+            assert!(replacer.get(page_id)?.is_some(), "step {}", step);
+        }
+        assert_eq!(capacity as usize, replacer.size());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_buffer_pool_with_lru_replacer() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 4)?;
+    let root_id = buffer_pool.create_table("a")?.unwrap();
+    let second_id = *buffer_pool.allocate_page(Some(root_id))?.write()?.get_page_id();
+    let third_id = *buffer_pool.allocate_page(Some(second_id))?.write()?.get_page_id();
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+
+    let replacer = Box::new(LruReplacer::new(2)?);
+    let mut buffer_pool = BufferPoolManager::open_with_replacer(dir.path(), replacer)?;
+    for page_id in &[root_id, second_id, root_id, third_id] {
+        assert_eq!(*page_id, *buffer_pool.fetch_page(*page_id)?.unwrap().write()?.get_page_id());
+        assert!(buffer_pool.unpin_page(*page_id, false)?);
+    }
+    // root was used more recently than second, so only second was evicted for third
+    let stats = buffer_pool.stats();
+    assert_eq!((1, 3, 1), (stats.hits, stats.misses, stats.evictions));
+    Ok(())
+}
@@ -1,8 +1,33 @@
+pub mod async_disk_manager;
+#[cfg(test)]
+mod async_disk_manager_test;
+pub mod blob;
 pub mod buffer_pool;
+#[cfg(test)]
+mod buffer_pool_test;
 
-mod clock_replacer;
-mod disk_manager;
-mod page;
+pub mod clock_replacer;
+#[cfg(test)]
+mod clock_replacer_test;
+pub mod disk_manager;
+#[cfg(test)]
+mod disk_manager_test;
+pub mod lru_replacer;
+#[cfg(test)]
+mod lru_replacer_test;
+pub mod page;
 #[cfg(test)]
 mod page_test;
+pub mod partition;
+#[cfg(test)]
+mod partition_test;
+pub mod replacer;
+pub mod store;
+#[cfg(test)]
+mod store_test;
+pub mod tiered;
+#[cfg(test)]
+mod tiered_test;
 mod tuple;
+#[cfg(test)]
+mod tuple_test;
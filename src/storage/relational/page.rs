@@ -1,14 +1,14 @@
 use super::clock_replacer::ClockStatus;
+use super::disk_manager::DiskManager;
 use super::tuple::RID;
 use crate::error::{Error, Result};
 use crate::storage::relational::tuple::Tuple;
-use std::mem::size_of;
+use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 use std::option::Option::Some;
-use std::str;
 
 /// Page size: 4KB
-pub const PAGE_SIZE: usize = 4095;
+pub const PAGE_SIZE: usize = 4096;
 
 /// the data page, we have to implement
 pub struct Page {
@@ -20,16 +20,27 @@ pub struct Page {
 
 /// Database use the first page (page_id = 0) as header page to store metadata,
 /// in our case, we will contain information about table/index name (length less than
-/// 32 bytes) and their corresponding root_id
+/// 32 bytes), their corresponding root_id, the fill factor used for inserts and the time to
+/// live of their tuples in milliseconds, 0 if they don't expire.
+/// when the first header page is full, the records continue on a chain of header pages
 ///
-/// Format (size in byte):
+/// Header format (size in bytes), which keeps the fields it shares with table pages at
+/// the same offsets, so disk level tools can read them without knowing the page type:
 ///
-///  /---------------------------------------------------------------<br>
-/// | RecordCount (4) | Entry_1 name (32) | Entry_1 root_id (4) | ... |
-///  /---------------------------------------------------------------
+///  /--------------------------------------------------------------------------
+/// | PageId (4)| Unused (1)| LSN (4)| FreeListHead (4)| NextHeaderPageId (4)| Unused (4) |
+///  /--------------------------------------------------------------------------
 ///
+///  /-------------------------------------------------------------------------------------------------------------------------<br>
+/// | RecordCount (4) | Checksum (4) | Entry_1 name (32) | Entry_1 root_id (4) | Entry_1 fill_factor (8) | Entry_1 ttl (8) | ... |
+///  /-------------------------------------------------------------------------------------------------------------------------
+///
+/// FreeListHead is only used in page 0, by the disk manager: it's the first free page, which
+/// is marked deleted and keeps the next free page id where table pages keep NextPageId.
 pub struct HeaderPage {
     page: Page,
+    /// the next header page of the chain, if any
+    next: Option<Box<HeaderPage>>,
 }
 
 /// Slotted page format:
@@ -45,15 +56,35 @@ pub struct HeaderPage {
 /// | PageId (4)| Deleted (1)| LSN (4)| PrevPageId (4)| NextPageId (4)| FreeSpacePointer(4) |
 ///  /--------------------------------------------------------------------------
 ///
-///  /--------------------------------------------------------------
-/// | TupleCount (4) | Tuple_1 offset (4) | Tuple_1 size (4) | ... |
-///  /--------------------------------------------------------------
+///  /-----------------------------------------------------------------------------
+/// | TupleCount (4) | Checksum (4) | Tuple_1 offset (4) | Tuple_1 size (4) | ... |
+///  /-----------------------------------------------------------------------------
+///
+/// The checksum of both page types is a CRC32 of the page data except the checksum itself,
+/// at offset 25. It is kept up to date by write_data and checked when pages are read from disk.
+///
+/// RIDs are only stable until the next structural change, i.e. one that moves tuple data or
+/// reuses an empty slot. Such changes bump an in-memory mutation epoch, which TupleScan checks
+/// so that a scan errors rather than yield a stale or different tuple.
 pub struct TablePage {
     page: Page,
     status: ClockStatus,
+    epoch: u64,
+}
+
+/// A scan over the live tuples of a table page. It doesn't borrow the page, so the page lock
+/// can be released between steps, but it fails if the page changed structurally in between.
+pub struct TupleScan {
+    page_id: u32,
+    epoch: u64,
+    /// the last RID returned, or None before the first tuple
+    cur_rid: Option<RID>,
 }
 
 impl Page {
+    /// the checksum is at the same offset in header and table pages
+    const OFFSET_CHECKSUM: usize = 25;
+
     pub fn new(page_id: u32, data: [u8; PAGE_SIZE]) -> Result<Page> {
         Ok(Page { data, page_id, pin_count: 0, is_dirty: false })
     }
@@ -63,16 +94,17 @@ impl Page {
         if offset > self.data.len() {
             return Err(Error::Value("offset is out of range".to_string()));
         }
-        let mut end = offset + len;
-        if end > offset + data.len() {
-            end = offset + data.len();
+        let mut end = offset.saturating_add(len);
+        if end > offset.saturating_add(data.len()) {
+            end = offset.saturating_add(data.len());
         }
         if end > self.data.len() {
             end = self.data.len();
         }
 
+        // the read may be cut short by the end of the page
         let read_data = &self.data[offset..end];
-        data.copy_from_slice(read_data);
+        data[..end - offset].copy_from_slice(read_data);
         Ok(end - offset)
     }
 
@@ -81,9 +113,9 @@ impl Page {
         if offset > self.data.len() {
             return Err(Error::Value("offset is out of range".to_string()));
         }
-        let mut end = offset + len;
-        if end > offset + data.len() {
-            end = offset + data.len();
+        let mut end = offset.saturating_add(len);
+        if end > offset.saturating_add(data.len()) {
+            end = offset.saturating_add(data.len());
         }
         if end > self.data.len() {
             end = self.data.len();
@@ -93,10 +125,41 @@ impl Page {
         let write_data = &data[..end - offset];
         let write_splice = &mut self.data[offset..end];
         write_splice.copy_from_slice(write_data);
+        self.update_checksum();
 
         Ok(end - offset)
     }
 
+    /// check the checksum of page data read from disk. an all-zero page was never written,
+    /// e.g. an unflushed header page or a hole in the db file, so it has no checksum to check
+    pub fn verify_checksum(page_id: u32, data: &[u8; PAGE_SIZE]) -> Result<()> {
+        if data.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        let offset = Page::OFFSET_CHECKSUM;
+        let mut checksum = [0u8; 4];
+        checksum.copy_from_slice(&data[offset..offset + 4]);
+        if u32::from_le_bytes(checksum) != Page::compute_checksum(data) {
+            return Err(Error::PageCorrupt(format!("page {}: checksum mismatch", page_id)));
+        }
+        Ok(())
+    }
+
+    /// recompute the checksum, after the page data changed
+    fn update_checksum(&mut self) {
+        let offset = Page::OFFSET_CHECKSUM;
+        let checksum = Page::compute_checksum(&self.data);
+        self.data[offset..offset + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    fn compute_checksum(data: &[u8; PAGE_SIZE]) -> u32 {
+        let offset = Page::OFFSET_CHECKSUM;
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&data[..offset]);
+        hasher.update(&data[offset + 4..]);
+        hasher.finalize()
+    }
+
     pub fn get_page_id(&self) -> &u32 {
         &self.page_id
     }
@@ -105,59 +168,185 @@ impl Page {
         &self.pin_count
     }
 
+    /// pin the page, so the buffer pool doesn't evict it while it's in use
+    pub fn pin(&mut self) {
+        self.pin_count += 1;
+    }
+
+    /// release a pin of the page. return false if it wasn't pinned
+    pub fn unpin(&mut self) -> bool {
+        if self.pin_count == 0 {
+            return false;
+        }
+        self.pin_count -= 1;
+        true
+    }
+
     pub fn is_dirty(&self) -> &bool {
         &self.is_dirty
     }
 }
 
 impl HeaderPage {
+    const OFFSET_LSN: usize = 5;
+    const OFFSET_NEXT_HEADER_PAGE_ID: usize = 13;
+    const OFFSET_RECORD_COUNT: usize = 21;
+    /// records start after the header, which ends with the checksum
+    const OFFSET_RECORDS: usize = 29;
+    /// one record size, include name, root_id, fill_factor and ttl
+    const SIZE_RECORD: usize = 52;
+    const OFFSET_ROOT_ID: usize = 32;
+    const OFFSET_FILL_FACTOR: usize = 36;
+    const OFFSET_TTL: usize = 44;
+
+    /// return the offset of a record, or of the end of the records before it
+    fn record_offset(record_num: usize) -> usize {
+        HeaderPage::OFFSET_RECORDS + record_num * HeaderPage::SIZE_RECORD
+    }
+
     pub fn new(data: [u8; PAGE_SIZE]) -> Result<HeaderPage> {
-        let mut header_page = HeaderPage { page: Page::new(0, data)? };
+        let mut header_page = HeaderPage { page: Page::new(0, data)?, next: None };
         header_page.set_record_count(0)?;
+        header_page.set_next_header_page_id(0)?;
         Ok(header_page)
     }
 
+    /// wrap an existing header page, e.g. read from disk, without initializing it.
+    /// the rest of the chain is loaded with push_page
+    pub fn from_data(data: [u8; PAGE_SIZE]) -> Result<HeaderPage> {
+        Ok(HeaderPage { page: Page::new(0, data)?, next: None })
+    }
+
+    /// chain a new, empty header page after the last one, once all header pages are full
+    pub fn extend(&mut self, page_id: u32) -> Result<()> {
+        if self.get_page_ids().contains(&page_id) {
+            return Err(Error::Value(format!("page {} is already a header page", page_id)));
+        }
+        let mut header_page =
+            HeaderPage { page: Page::new(page_id, [0u8; PAGE_SIZE])?, next: None };
+        header_page.write_data(&page_id.to_le_bytes(), 0, 4)?;
+        header_page.set_record_count(0)?;
+
+        let last = self.last_mut();
+        last.set_next_header_page_id(page_id)?;
+        last.next = Some(Box::new(header_page));
+        Ok(())
+    }
+
+    /// return the page id the last loaded header page links to, if it links to one
+    pub fn get_unloaded_page_id(&self) -> Result<Option<u32>> {
+        match &self.next {
+            Some(next) => next.get_unloaded_page_id(),
+            None => match self.get_next_header_page_id()? {
+                0 => Ok(None),
+                page_id => Ok(Some(page_id)),
+            },
+        }
+    }
+
+    /// append a header page read from disk to the chain. it must be the page
+    /// returned by get_unloaded_page_id
+    pub fn push_page(&mut self, page_id: u32, data: [u8; PAGE_SIZE]) -> Result<()> {
+        if self.get_unloaded_page_id()? != Some(page_id) {
+            return Err(Error::Value(format!("header page {} is not next in the chain", page_id)));
+        }
+        // a corrupt link could loop back into the chain
+        if self.get_page_ids().contains(&page_id) {
+            return Err(Error::PageCorrupt(format!("header page {} is linked twice", page_id)));
+        }
+        let header_page = HeaderPage { page: Page::new(page_id, data)?, next: None };
+        let mut page_id_data = [0u8; 4];
+        header_page.read_data(&mut page_id_data, 0, 4)?;
+        if u32::from_le_bytes(page_id_data) != page_id {
+            return Err(Error::PageCorrupt(format!(
+                "header page {}: stored page id is {}",
+                page_id,
+                u32::from_le_bytes(page_id_data)
+            )));
+        }
+        self.last_mut().next = Some(Box::new(header_page));
+        Ok(())
+    }
+
+    /// return the page ids of the loaded header pages, in chain order
+    pub fn get_page_ids(&self) -> Vec<u32> {
+        let mut page_ids = vec![*self.get_page_id()];
+        if let Some(next) = &self.next {
+            page_ids.extend(next.get_page_ids());
+        }
+        page_ids
+    }
+
+    /// write every header page of the chain to disk, stamped with a new LSN
+    pub fn flush(&mut self, disk_manager: &mut DiskManager) -> Result<()> {
+        let lsn = disk_manager.next_lsn();
+        self.write_data(&lsn.to_le_bytes(), HeaderPage::OFFSET_LSN, 4)?;
+        disk_manager.write_page(*self.get_page_id(), &self.data)?;
+        if let Some(next) = &mut self.next {
+            next.flush(disk_manager)?;
+        }
+        Ok(())
+    }
+
     /// record related
     pub fn insert_record(&mut self, name: &str, root_id: u32) -> Result<bool> {
         if name.len() > 32 {
             return Ok(false);
         }
         // check for duplicate name
-        if self.find_record_num(name)?.is_some() {
+        if self.find_record(name)?.is_some() {
             return Ok(false);
         }
-        let record_count = self.get_record_count()?;
+        self.insert_record_in_chain(name, root_id)
+    }
 
-        // insert name
-        let name_offset = 4 + record_count as usize * 36;
-        let name_data = name.as_bytes();
-        self.write_data(name_data, name_offset, 32)?;
+    /// insert a record into the first header page with room for it.
+    /// return false if every header page is full
+    fn insert_record_in_chain(&mut self, name: &str, root_id: u32) -> Result<bool> {
+        let record_count = self.get_page_record_count()?;
+        if HeaderPage::record_offset(record_count as usize + 1) > PAGE_SIZE {
+            return match &mut self.next {
+                Some(next) => next.insert_record_in_chain(name, root_id),
+                None => Ok(false),
+            };
+        }
+
+        // insert name, padded with zeros
+        let name_offset = HeaderPage::record_offset(record_count as usize);
+        let mut name_data = [0u8; 32];
+        name_data[..name.len()].copy_from_slice(name.as_bytes());
+        self.write_data(&name_data, name_offset, 32)?;
 
         // insert root_id
-        let root_id_offset = name_offset + 32;
+        let root_id_offset = name_offset + HeaderPage::OFFSET_ROOT_ID;
         let root_id_data = root_id.to_le_bytes();
         self.write_data(&root_id_data, root_id_offset, 4)?;
 
+        // pack pages fully by default
+        let fill_factor_offset = name_offset + HeaderPage::OFFSET_FILL_FACTOR;
+        let fill_factor_data = 1.0f64.to_le_bytes();
+        self.write_data(&fill_factor_data, fill_factor_offset, 8)?;
+
+        // tuples don't expire by default
+        let ttl_offset = name_offset + HeaderPage::OFFSET_TTL;
+        self.write_data(&0u64.to_le_bytes(), ttl_offset, 8)?;
+
         // add record
         self.set_record_count(record_count + 1)?;
         Ok(true)
     }
 
     pub fn delete_record(&mut self, name: &str) -> Result<bool> {
-        let record_count = self.get_record_count()?;
-        if record_count == 0 {
-            return Ok(false);
-        }
-
-        if let Some(record_num) = self.find_record_num(name)? {
+        if let Some((header_page, record_num)) = self.find_record_mut(name)? {
+            let record_count = header_page.get_page_record_count()?;
             // the record start offset
-            let offset = record_num as usize * 36 + 4;
+            let offset = HeaderPage::record_offset(record_num as usize);
             // find need move data len
-            let start_pointer = offset + 36;
-            let end_pointer = record_count as usize * 36 + 4;
+            let start_pointer = offset + HeaderPage::SIZE_RECORD;
+            let end_pointer = HeaderPage::record_offset(record_count as usize);
 
-            self.data.copy_within(start_pointer..end_pointer, offset);
-            self.set_record_count(record_count - 1)?;
+            header_page.data.copy_within(start_pointer..end_pointer, offset);
+            header_page.set_record_count(record_count - 1)?;
             Ok(true)
         } else {
             // record not exits
@@ -166,10 +355,11 @@ impl HeaderPage {
     }
 
     pub fn update_record(&mut self, name: &str, root_id: u32) -> Result<bool> {
-        if let Some(record_num) = self.find_record_num(name)? {
-            let offset = record_num as usize * 36 + 4;
+        if let Some((header_page, record_num)) = self.find_record_mut(name)? {
+            let offset =
+                HeaderPage::record_offset(record_num as usize) + HeaderPage::OFFSET_ROOT_ID;
             let root_id_data = root_id.to_le_bytes();
-            self.write_data(&root_id_data, offset, 4)?;
+            header_page.write_data(&root_id_data, offset, 4)?;
             return Ok(true);
         }
         Ok(false)
@@ -177,43 +367,197 @@ impl HeaderPage {
 
     /// return root if success
     pub fn get_root_id(&self, name: &str) -> Result<Option<u32>> {
-        if let Some(record_num) = self.find_record_num(name)? {
-            let offset = record_num as usize * 36 + 4 + 32;
+        if let Some((header_page, record_num)) = self.find_record(name)? {
+            let offset =
+                HeaderPage::record_offset(record_num as usize) + HeaderPage::OFFSET_ROOT_ID;
             let mut root_id_data = [0u8; 4];
-            self.read_data(&mut root_id_data, offset, 4)?;
+            header_page.read_data(&mut root_id_data, offset, 4)?;
             return Ok(Some(u32::from_le_bytes(root_id_data)));
         }
         Ok(None)
     }
 
+    /// set the fraction of a table page's usable space that inserts may fill, in (0.0, 1.0]
+    pub fn set_fill_factor(&mut self, name: &str, fill_factor: f64) -> Result<bool> {
+        if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+            return Err(Error::Value(format!("invalid fill factor {}", fill_factor)));
+        }
+        if let Some((header_page, record_num)) = self.find_record_mut(name)? {
+            let offset =
+                HeaderPage::record_offset(record_num as usize) + HeaderPage::OFFSET_FILL_FACTOR;
+            let fill_factor_data = fill_factor.to_le_bytes();
+            header_page.write_data(&fill_factor_data, offset, 8)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// return fill factor if success
+    pub fn get_fill_factor(&self, name: &str) -> Result<Option<f64>> {
+        if let Some((header_page, record_num)) = self.find_record(name)? {
+            let offset =
+                HeaderPage::record_offset(record_num as usize) + HeaderPage::OFFSET_FILL_FACTOR;
+            let mut fill_factor_data = [0u8; 8];
+            header_page.read_data(&mut fill_factor_data, offset, 8)?;
+            let fill_factor = f64::from_le_bytes(fill_factor_data);
+            if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+                return Err(Error::Value(format!("invalid stored fill factor {}", fill_factor)));
+            }
+            return Ok(Some(fill_factor));
+        }
+        Ok(None)
+    }
+
+    /// set the time to live of a table's tuples in milliseconds, 0 if they don't expire
+    pub fn set_ttl(&mut self, name: &str, ttl: u64) -> Result<bool> {
+        if let Some((header_page, record_num)) = self.find_record_mut(name)? {
+            let offset = HeaderPage::record_offset(record_num as usize) + HeaderPage::OFFSET_TTL;
+            header_page.write_data(&ttl.to_le_bytes(), offset, 8)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// return ttl in milliseconds if success, 0 if the table's tuples don't expire
+    pub fn get_ttl(&self, name: &str) -> Result<Option<u64>> {
+        if let Some((header_page, record_num)) = self.find_record(name)? {
+            let offset = HeaderPage::record_offset(record_num as usize) + HeaderPage::OFFSET_TTL;
+            let mut ttl_data = [0u8; 8];
+            header_page.read_data(&mut ttl_data, offset, 8)?;
+            return Ok(Some(u64::from_le_bytes(ttl_data)));
+        }
+        Ok(None)
+    }
+
+    /// replace the root ids and header page ids found in the map, e.g. after pages were moved
+    pub fn remap_page_ids(&mut self, remap: &HashMap<u32, u32>) -> Result<()> {
+        for record_num in 0..self.get_page_record_count()? as usize {
+            let offset = HeaderPage::record_offset(record_num) + HeaderPage::OFFSET_ROOT_ID;
+            let mut root_id_data = [0u8; 4];
+            self.read_data(&mut root_id_data, offset, 4)?;
+            if let Some(root_id) = remap.get(&u32::from_le_bytes(root_id_data)) {
+                self.write_data(&root_id.to_le_bytes(), offset, 4)?;
+            }
+        }
+
+        let next_page_id = match &self.next {
+            Some(next) => remap.get(next.get_page_id()).copied(),
+            None => None,
+        };
+        if let Some(page_id) = next_page_id {
+            self.set_next_header_page_id(page_id)?;
+        }
+        if let Some(next) = &mut self.next {
+            if let Some(page_id) = next_page_id {
+                next.page_id = page_id;
+                next.write_data(&page_id.to_le_bytes(), 0, 4)?;
+            }
+            next.remap_page_ids(remap)?;
+        }
+        Ok(())
+    }
+
+    /// return the number of records in all header pages
     pub fn get_record_count(&self) -> Result<u32> {
+        let mut record_count = self.get_page_record_count()?;
+        if let Some(next) = &self.next {
+            record_count += next.get_record_count()?;
+        }
+        Ok(record_count)
+    }
+
+    /// return the names of the records in all header pages
+    pub fn get_record_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for record_num in 0..self.get_page_record_count()? as usize {
+            let mut name_data = [0u8; 32];
+            self.read_data(&mut name_data, HeaderPage::record_offset(record_num), 32)?;
+            let len = name_data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            names.push(String::from_utf8(name_data[..len].to_vec()).map_err(|_| {
+                Error::Value(format!("invalid header page record name {:?}", &name_data[..len]))
+            })?);
+        }
+        if let Some(next) = &self.next {
+            names.extend(next.get_record_names()?);
+        }
+        Ok(names)
+    }
+
+    /// return the number of records in this header page
+    fn get_page_record_count(&self) -> Result<u32> {
         let mut record_count_data = [0u8; 4];
-        self.read_data(&mut record_count_data, 0, 4)?;
-        Ok(u32::from_le_bytes(record_count_data))
+        self.read_data(&mut record_count_data, HeaderPage::OFFSET_RECORD_COUNT, 4)?;
+        let record_count = u32::from_le_bytes(record_count_data);
+        // a corrupt record count could point past the end of the page
+        if HeaderPage::record_offset(record_count as usize) > PAGE_SIZE {
+            return Err(Error::Value(format!("invalid header page record count {}", record_count)));
+        }
+        Ok(record_count)
     }
 
     fn set_record_count(&mut self, record_count: u32) -> Result<()> {
         let data = record_count.to_le_bytes();
-        self.write_data(&data, 0, 4)?;
+        self.write_data(&data, HeaderPage::OFFSET_RECORD_COUNT, 4)?;
+        Ok(())
+    }
+
+    fn get_next_header_page_id(&self) -> Result<u32> {
+        let mut page_id_data = [0u8; 4];
+        self.read_data(&mut page_id_data, HeaderPage::OFFSET_NEXT_HEADER_PAGE_ID, 4)?;
+        Ok(u32::from_le_bytes(page_id_data))
+    }
+
+    fn set_next_header_page_id(&mut self, page_id: u32) -> Result<()> {
+        self.write_data(&page_id.to_le_bytes(), HeaderPage::OFFSET_NEXT_HEADER_PAGE_ID, 4)?;
         Ok(())
     }
 
+    /// return the last loaded header page of the chain
+    fn last_mut(&mut self) -> &mut HeaderPage {
+        match self.next {
+            Some(ref mut next) => next.last_mut(),
+            None => self,
+        }
+    }
+
+    /// find the header page holding a record, and the record number in that page
+    fn find_record(&self, name: &str) -> Result<Option<(&HeaderPage, u32)>> {
+        if let Some(record_num) = self.find_record_num(name)? {
+            return Ok(Some((self, record_num)));
+        }
+        match &self.next {
+            Some(next) => next.find_record(name),
+            None => Ok(None),
+        }
+    }
+
+    fn find_record_mut(&mut self, name: &str) -> Result<Option<(&mut HeaderPage, u32)>> {
+        if let Some(record_num) = self.find_record_num(name)? {
+            return Ok(Some((self, record_num)));
+        }
+        match &mut self.next {
+            Some(next) => next.find_record_mut(name),
+            None => Ok(None),
+        }
+    }
+
+    /// find a record in this header page
     fn find_record_num(&self, name: &str) -> Result<Option<u32>> {
         if name.len() > 32 {
             return Ok(None);
         }
-        let record_count = self.get_record_count()?;
+        let record_count = self.get_page_record_count()?;
         if record_count == 0 {
             return Ok(None);
         }
 
         for record_num in 0..record_count as usize {
             let mut read_name = [0u8; 32];
-            let name_offset = record_num * 36 + 4;
+            let name_offset = HeaderPage::record_offset(record_num);
             self.read_data(&mut read_name, name_offset, 32)?;
-            let mut real_name = str::from_utf8(&read_name).unwrap();
-            real_name = real_name.trim_end_matches(|c| c == '\0');
-            if real_name.eq(name) {
+            // compare raw bytes, since a corrupt name may not be valid UTF-8
+            let len = read_name.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            if &read_name[..len] == name.as_bytes() {
                 return Ok(Some(record_num as u32));
             }
         }
@@ -224,12 +568,15 @@ impl HeaderPage {
 impl TablePage {
     /// table page's header end offset
     /// or slot arrays start offset
-    const SIZE_TABLE_PAGE_HEADER: usize = 25;
+    const SIZE_TABLE_PAGE_HEADER: usize = 29;
 
     /// one tuple meta data size in slot array,
     /// include tuple offset and tuple size
     const SIZE_TUPLE: usize = 8;
 
+    /// the size of the largest tuple that fits in an empty page
+    pub const MAX_TUPLE_SIZE: usize = PAGE_SIZE - Self::SIZE_TABLE_PAGE_HEADER - Self::SIZE_TUPLE;
+
     /// Deleted flag offset, this size just one byte
     const OFFSET_DELETED: usize = 4;
     const OFFSET_LSN: usize = 5;
@@ -237,12 +584,12 @@ impl TablePage {
     const OFFSET_NEXT_PAGE_ID: usize = 13;
     const OFFSET_FREE_SPACE: usize = 17;
     const OFFSET_TUPLE_COUNT: usize = 21;
-    const OFFSET_TUPLE_OFFSET: usize = 25;
+    const OFFSET_TUPLE_OFFSET: usize = 29;
     /// naming things is hard
-    const OFFSET_TUPLE_SIZE: usize = 29;
+    const OFFSET_TUPLE_SIZE: usize = 33;
 
     // delete flag, the 32nd bit of tuple_size is the delete flag bit
-    const DELETE_MASK: u32 = 1 << (size_of::<u32>() - 1);
+    const DELETE_MASK: u32 = 1 << (u32::BITS - 1);
 
     /// init the tablePage header.
     /// page_id: the page ID of this table page
@@ -257,7 +604,7 @@ impl TablePage {
             return Err(Error::Value(String::from("table page id can not set 0!")));
         }
         let page = Page::new(page_id, data)?;
-        let mut table_page = TablePage { page, status: ClockStatus::empty() };
+        let mut table_page = TablePage { page, status: ClockStatus::empty(), epoch: 0 };
         // used = true, when page created
         table_page.status.used();
 
@@ -274,12 +621,82 @@ impl TablePage {
         Ok(table_page)
     }
 
+    /// wrap an existing table page, e.g. read from disk, without initializing its header
+    pub fn from_data(page_id: u32, data: [u8; PAGE_SIZE]) -> Result<TablePage> {
+        if page_id == 0 {
+            return Err(Error::Value(String::from("table page id can not set 0!")));
+        }
+        let page = Page::new(page_id, data)?;
+        let mut table_page = TablePage { page, status: ClockStatus::empty(), epoch: 0 };
+        table_page.status.used();
+        Ok(table_page)
+    }
+
+    /// check the structural invariants of a table page, e.g. after reading it from disk
+    pub fn validate(&self) -> Result<()> {
+        let corrupt =
+            |msg: String| Err(Error::PageCorrupt(format!("page {}: {}", self.get_page_id(), msg)));
+
+        let mut page_id_data = [0u8; 4];
+        self.read_data(&mut page_id_data, 0, 4)?;
+        let page_id = u32::from_le_bytes(page_id_data);
+        if page_id != *self.get_page_id() {
+            return corrupt(format!("stored page id is {}", page_id));
+        }
+        let mut deleted = [0u8];
+        self.read_data(&mut deleted, TablePage::OFFSET_DELETED, 1)?;
+        if deleted[0] > 1 {
+            return corrupt(format!("invalid deleted flag {}", deleted[0]));
+        }
+
+        let free_space_pointer = self.get_free_space_pointer()? as usize;
+        let tuple_count = self.get_tuple_count()? as usize;
+        if free_space_pointer > PAGE_SIZE {
+            return corrupt(format!(
+                "free space pointer {} is past the page end",
+                free_space_pointer
+            ));
+        }
+        let slots_end = tuple_count
+            .checked_mul(TablePage::SIZE_TUPLE)
+            .and_then(|size| size.checked_add(TablePage::SIZE_TABLE_PAGE_HEADER));
+        if !matches!(slots_end, Some(end) if end <= free_space_pointer) {
+            return corrupt(format!(
+                "slot array of {} tuples overlaps free space pointer {}",
+                tuple_count, free_space_pointer
+            ));
+        }
+
+        for slot_num in 0..tuple_count as u32 {
+            let tuple_size = TablePage::unset_deleted_flag(self.get_tuple_size(slot_num)?) as usize;
+            if tuple_size == 0 {
+                continue;
+            }
+            let tuple_offset = self.get_tuple_offset_at_slot(slot_num)? as usize;
+            if tuple_offset < free_space_pointer || tuple_offset + tuple_size > PAGE_SIZE {
+                return corrupt(format!(
+                    "tuple at slot {} with offset {} and size {} is outside the tuple data",
+                    slot_num, tuple_offset, tuple_size
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// get lsn from table page
     pub fn get_lsn(&mut self) -> Result<u32> {
         self.status.used();
         let mut lsn_data = [0u8; 4];
         self.read_data(&mut lsn_data, TablePage::OFFSET_LSN, 4)?;
-        Ok(u32::from_ne_bytes(lsn_data))
+        Ok(u32::from_le_bytes(lsn_data))
+    }
+
+    /// set the lsn of the table page, when it is written to disk or a redo record is applied
+    pub fn set_lsn(&mut self, lsn: u32) -> Result<()> {
+        self.status.edited();
+        let lsn_data = lsn.to_le_bytes();
+        self.write_data(&lsn_data, TablePage::OFFSET_LSN, 4)?;
+        Ok(())
     }
 
     /// return the page ID of this table page
@@ -324,13 +741,30 @@ impl TablePage {
 
     /// insert a tuple into the page
     pub fn insert_tuple(&mut self, tuple: &mut Tuple) -> Result<bool> {
+        self.insert_tuple_with_fill_factor(tuple, 1.0)
+    }
+
+    /// insert a tuple into the page, treating the page as full once the given
+    /// fraction of its usable space is used. the rest is left for updates
+    pub fn insert_tuple_with_fill_factor(
+        &mut self,
+        tuple: &mut Tuple,
+        fill_factor: f64,
+    ) -> Result<bool> {
         if tuple.get_length() == 0 {
             return Err(Error::Value(String::from("Can't have empty tuple!")));
         }
         if let None = tuple.get_rid() {
             return Ok(false);
         }
-        if self.get_free_space_remaining()? < (tuple.get_length() + TablePage::SIZE_TUPLE) as u32 {
+        let free_space_remaining = self.get_free_space_remaining()?;
+        let tuple_space = tuple.get_length() + TablePage::SIZE_TUPLE;
+        if free_space_remaining < tuple_space as u32 {
+            return Ok(false);
+        }
+        let usable_space = (PAGE_SIZE - TablePage::SIZE_TABLE_PAGE_HEADER) as f64;
+        let used_space = usable_space - free_space_remaining as f64;
+        if used_space + tuple_space as f64 > fill_factor * usable_space {
             return Ok(false);
         }
 
@@ -350,15 +784,60 @@ impl TablePage {
         self.set_tuple_size(slot_num, save_len as u32)?;
         if slot_num == self.get_tuple_count()? {
             self.set_tuple_count(slot_num + 1)?;
+        } else {
+            // a reused slot may still be referenced by RIDs of the tuple deleted from it
+            self.epoch += 1;
         }
 
         let rid = RID::new(*self.get_page_id(), slot_num);
-        tuple.set_rid(rid);
+        tuple.assign_rid(rid);
         tuple.allocated();
         self.status.edited();
         Ok(true)
     }
 
+    /// insert tuples into new slots at the end of the slot array, in order, until one
+    /// doesn't fit. each stored tuple is assigned its RID. return how many were stored.
+    /// the header is only updated once all of them are written, so an error leaves the
+    /// page as it was
+    pub fn insert_tuples(&mut self, tuples: &mut [Tuple]) -> Result<usize> {
+        if tuples.iter().any(|tuple| tuple.get_length() == 0) {
+            return Err(Error::Value(String::from("Can't have empty tuple!")));
+        }
+        let page_id = *self.get_page_id();
+        let tuple_count = self.get_tuple_count()?;
+        let mut free_space_remaining = self.get_free_space_remaining()? as usize;
+        let mut free_space_pointer = self.get_free_space_pointer()?;
+
+        let mut stored = 0;
+        for tuple in tuples.iter_mut() {
+            let tuple_space = tuple.get_length() + TablePage::SIZE_TUPLE;
+            if free_space_remaining < tuple_space {
+                break;
+            }
+            let slot_num = tuple_count + stored as u32;
+            let save_len = tuple.get_length();
+            free_space_pointer -= save_len as u32;
+            self.write_data(tuple.get_data(), free_space_pointer as usize, save_len)?;
+            self.set_tuple_offset_at_slot(slot_num, free_space_pointer)?;
+            self.set_tuple_size(slot_num, save_len as u32)?;
+            free_space_remaining -= tuple_space;
+            stored += 1;
+        }
+        if stored == 0 {
+            return Ok(0);
+        }
+        self.set_free_space_pointer(free_space_pointer)?;
+        self.set_tuple_count(tuple_count + stored as u32)?;
+
+        for (slot_num, tuple) in (tuple_count..).zip(tuples[..stored].iter_mut()) {
+            tuple.assign_rid(RID::new(page_id, slot_num));
+            tuple.allocated();
+        }
+        self.status.edited();
+        Ok(stored)
+    }
+
     /// mark a tuple as deleted. this does not actually delete the tuple
     /// rid: rid of the tuple to mark as deleted
     pub fn mark_delete(&mut self, rid: &RID) -> Result<bool> {
@@ -384,8 +863,8 @@ impl TablePage {
         if self.page_is_deleted()? {
             return Ok(true);
         }
-        let mut delete_flag = [1u8];
-        self.write_data(&mut delete_flag, TablePage::OFFSET_DELETED, 1)?;
+        self.status.edited();
+        self.write_data(&[1u8], TablePage::OFFSET_DELETED, 1)?;
         Ok(true)
     }
 
@@ -404,11 +883,15 @@ impl TablePage {
         if !TablePage::is_deleted(tuple_size as u32) {
             return Err(Error::Value(String::from("The tuple was not deleted.")));
         }
+        let tuple_size = TablePage::unset_deleted_flag(tuple_size);
         let tuple_offset = self.get_tuple_offset_at_slot(slot_num)?;
         let free_space_pointer = self.get_free_space_pointer()?;
         if tuple_offset < free_space_pointer {
             return Err(Error::Value(String::from("Free space appearss before tuples.")));
         }
+        // the data moves below rely on every tuple lying between the free space pointer
+        // and the page end, which a corrupt page may violate
+        self.validate()?;
 
         // remove slot
         self.set_tuple_size(slot_num, 0)?;
@@ -421,15 +904,20 @@ impl TablePage {
             free_space_pointer as usize..tuple_offset as usize,
             (free_space_pointer + tuple_size) as usize,
         );
+        self.update_checksum();
 
-        // update slot
+        // update slot, skipping empty slots which have no data to move
         for slot_num_i in 0..self.get_tuple_count()? {
+            if self.get_tuple_size(slot_num_i)? == 0 {
+                continue;
+            }
             let tuple_offset_i = self.get_tuple_offset_at_slot(slot_num_i)?;
             if tuple_offset_i < tuple_offset {
                 self.set_tuple_offset_at_slot(slot_num_i, tuple_offset_i + tuple_size)?;
             }
         }
 
+        self.epoch += 1;
         self.status.edited();
         Ok(())
     }
@@ -470,17 +958,42 @@ impl TablePage {
                 "Offset should appear after current free space position.",
             )));
         }
+        // the data moves below rely on every tuple lying between the free space pointer
+        // and the page end, which a corrupt page may violate
+        self.validate()?;
+
+        // a tuple of the same size is updated in place, only writing the changed bytes
+        if new_tuple_size == tuple_size as usize {
+            let old_data = &self.data[tuple_offset..tuple_offset + new_tuple_size];
+            let new_data = tuple.get_data();
+            let changed = |(old, new): (&u8, &u8)| old != new;
+            let first = old_data.iter().zip(new_data).position(changed);
+            let last = old_data.iter().zip(new_data).rposition(changed);
+            if let (Some(first), Some(last)) = (first, last) {
+                self.write_data(&new_data[first..=last], tuple_offset + first, last + 1 - first)?;
+                self.status.edited();
+            }
+            return Ok(());
+        }
+
+        // the new tuple may be larger than the old one, so this can't be computed as
+        // free_space_pointer + (tuple_size - new_tuple_size)
+        let new_free_space_pointer = (free_space_pointer + tuple_size as usize)
+            .checked_sub(new_tuple_size)
+            .ok_or_else(|| {
+                Error::PageCorrupt(format!(
+                    "page {}: no room to grow tuple at slot {} to {} bytes",
+                    self.get_page_id(),
+                    slot_num,
+                    new_tuple_size
+                ))
+            })?;
 
         // move the before data of the tuple_offset,to make room for the new tuple
-        self.data.copy_within(
-            free_space_pointer..tuple_offset,
-            free_space_pointer + tuple_size as usize - new_tuple_size,
-        );
+        self.data.copy_within(free_space_pointer..tuple_offset, new_free_space_pointer);
 
         // update free space pointer
-        self.set_free_space_pointer(
-            (free_space_pointer + tuple_size as usize - new_tuple_size) as u32,
-        )?;
+        self.set_free_space_pointer(new_free_space_pointer as u32)?;
 
         // update tuple data
         let update_data = tuple.get_data();
@@ -490,8 +1003,11 @@ impl TablePage {
             new_tuple_size,
         )?;
 
-        // update all tuple offset
+        // update all tuple offset, skipping empty slots which have no data to move
         for slot_num_i in 0..self.get_tuple_count()? {
+            if self.get_tuple_size(slot_num_i)? == 0 {
+                continue;
+            }
             let slot_offset_i = self.get_tuple_offset_at_slot(slot_num_i)?;
             if slot_offset_i < tuple_offset as u32 {
                 self.set_tuple_offset_at_slot(
@@ -508,10 +1024,57 @@ impl TablePage {
         )?;
         self.set_tuple_size(slot_num, new_tuple_size as u32)?;
 
+        // tuples only move when the size changes
+        if new_tuple_size as u32 != tuple_size {
+            self.epoch += 1;
+        }
         self.status.edited();
         Ok(())
     }
 
+    /// rewrite the tuple data contiguously at the end of the page, and remove empty slots
+    /// from the slot array. the remaining slots keep their order but are renumbered, so
+    /// RIDs of this page change, including those of tuples marked deleted.
+    /// return the number of bytes reclaimed
+    pub fn compact(&mut self) -> Result<u32> {
+        // the tuples are copied out by their slots, which a corrupt page may not cover
+        self.validate()?;
+        let free_space_remaining = self.get_free_space_remaining()?;
+
+        let mut tuples = Vec::new();
+        for slot_num in 0..self.get_tuple_count()? {
+            let tuple_size = self.get_tuple_size(slot_num)?;
+            if tuple_size == 0 {
+                continue;
+            }
+            let tuple_offset = self.get_tuple_offset_at_slot(slot_num)? as usize;
+            let size = TablePage::unset_deleted_flag(tuple_size) as usize;
+            tuples.push((tuple_size, self.data[tuple_offset..tuple_offset + size].to_vec()));
+        }
+
+        let mut free_space_pointer = PAGE_SIZE;
+        for (slot_num, (tuple_size, tuple_data)) in tuples.iter().enumerate() {
+            free_space_pointer -= tuple_data.len();
+            self.data[free_space_pointer..free_space_pointer + tuple_data.len()]
+                .copy_from_slice(tuple_data);
+            self.set_tuple_offset_at_slot(slot_num as u32, free_space_pointer as u32)?;
+            self.set_tuple_size(slot_num as u32, *tuple_size)?;
+        }
+        self.set_tuple_count(tuples.len() as u32)?;
+        self.set_free_space_pointer(free_space_pointer as u32)?;
+
+        // clear the removed slots and stale tuple data, which are now free space
+        let slots_end = TablePage::SIZE_TABLE_PAGE_HEADER + TablePage::SIZE_TUPLE * tuples.len();
+        for byte in &mut self.data[slots_end..free_space_pointer] {
+            *byte = 0;
+        }
+        self.update_checksum();
+
+        self.epoch += 1;
+        self.status.edited();
+        Ok(self.get_free_space_remaining()? - free_space_remaining)
+    }
+
     /// to be called on abort.
     /// Rollback a delete,
     /// i.e. the reverses a MarkDelete
@@ -529,9 +1092,20 @@ impl TablePage {
         Ok(())
     }
 
-    /// read a tuple from a table
+    /// read a tuple from a table, counting it as a use of the page
     /// rid: rid of the tuple to read
     pub fn get_tuple(&mut self, rid: &RID) -> Result<Option<Tuple>> {
+        let tuple = self.read_tuple(rid)?;
+        if tuple.is_some() {
+            self.status.used();
+        }
+        Ok(tuple)
+    }
+
+    /// read a tuple from a table without touching the page status, so it can be called
+    /// under a shared latch
+    /// rid: rid of the tuple to read
+    pub fn read_tuple(&self, rid: &RID) -> Result<Option<Tuple>> {
         let page_id = *self.get_page_id();
         if page_id != *rid.get_page_id() {
             return Err(Error::Value(String::from("the page id is not include this page")));
@@ -546,6 +1120,9 @@ impl TablePage {
         }
 
         let tuple_offset = self.get_tuple_offset_at_slot(slot_num)?;
+        if tuple_offset as usize + tuple_size as usize > PAGE_SIZE {
+            return Err(Error::Value(format!("tuple at slot {} is out of page range", slot_num)));
+        }
         let mut tuple_data = vec![0u8; tuple_size as usize];
         self.read_data(&mut tuple_data, tuple_offset as usize, tuple_size as usize)?;
 
@@ -553,7 +1130,6 @@ impl TablePage {
         let mut tuple = Tuple::from_data(tuple_data);
         tuple.set_rid(tuple_rid);
         tuple.allocated();
-        self.status.used();
         Ok(Some(tuple))
     }
 
@@ -587,7 +1163,7 @@ impl TablePage {
         }
 
         let slot_num = *cur_rid.get_slot_num();
-        for slot_num_i in (slot_num + 1)..self.get_tuple_count()? {
+        for slot_num_i in slot_num.saturating_add(1)..self.get_tuple_count()? {
             let tuple_size = self.get_tuple_size(slot_num_i)?;
             if !TablePage::is_deleted(tuple_size) {
                 let rid = RID::new(page_id, slot_num_i);
@@ -599,6 +1175,52 @@ impl TablePage {
         Ok(None)
     }
 
+    /// start a scan over the live tuples of this page
+    pub fn scan(&self) -> TupleScan {
+        TupleScan { page_id: *self.get_page_id(), epoch: self.epoch, cur_rid: None }
+    }
+
+    /// iterate over the live tuples of this page in slot order, with their RIDs.
+    /// iteration stops after the first error
+    pub fn tuples(&mut self) -> impl Iterator<Item = Result<(RID, Tuple)>> + '_ {
+        let page_id = *self.get_page_id();
+        let (mut slots, mut error) = match self.get_tuple_count() {
+            Ok(tuple_count) => (0..tuple_count, None),
+            Err(err) => (0..0, Some(err)),
+        };
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if let Some(err) = error.take() {
+                return Some(Err(err));
+            }
+            if failed {
+                return None;
+            }
+            for slot_num in slots.by_ref() {
+                let rid = RID::new(page_id, slot_num);
+                match self.get_tuple(&rid) {
+                    Ok(Some(tuple)) => return Some(Ok((rid, tuple))),
+                    Ok(None) => continue,
+                    Err(err) => {
+                        failed = true;
+                        return Some(Err(err));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// the mutation epoch, bumped on every change that can invalidate RIDs
+    pub fn get_epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// get the ClockStatus from the table page, read only
+    pub fn get_status(&self) -> &ClockStatus {
+        &self.status
+    }
+
     /// get the ClockStatus from the table page to edit by ClockReplacer
     pub fn get_status_mut(&mut self) -> &mut ClockStatus {
         &mut self.status
@@ -608,7 +1230,7 @@ impl TablePage {
     pub fn page_is_deleted(&self) -> Result<bool> {
         let mut flag = [0u8];
         self.read_data(&mut flag, TablePage::OFFSET_DELETED, 1)?;
-        Ok(flag[0] == 0)
+        Ok(flag[0] == 1)
     }
 
     /// foreach slot array we haved, if tuple_size equals 0,
@@ -657,9 +1279,17 @@ impl TablePage {
     fn get_free_space_remaining(&self) -> Result<u32> {
         let free_space_pointer = self.get_free_space_pointer()?;
         let tuple_count = self.get_tuple_count()?;
-        Ok(free_space_pointer
-            - TablePage::SIZE_TABLE_PAGE_HEADER as u32
-            - TablePage::SIZE_TUPLE as u32 * tuple_count)
+        // a corrupt page could claim more slots than fit before its free space pointer
+        (TablePage::SIZE_TUPLE as u32)
+            .checked_mul(tuple_count)
+            .and_then(|size| size.checked_add(TablePage::SIZE_TABLE_PAGE_HEADER as u32))
+            .and_then(|size| free_space_pointer.checked_sub(size))
+            .ok_or_else(|| {
+                Error::Value(format!(
+                    "free space pointer {} is before slot array of {} tuples",
+                    free_space_pointer, tuple_count
+                ))
+            })
     }
 
     /// return tuple offset at slot slot_num
@@ -727,6 +1357,29 @@ impl TablePage {
     }
 }
 
+impl TupleScan {
+    /// return the next live tuple of the page, which must be the page the scan was started on
+    pub fn next(&mut self, page: &mut TablePage) -> Result<Option<Tuple>> {
+        if *page.get_page_id() != self.page_id {
+            return Err(Error::Value(String::from("the scan was not started on this page")));
+        }
+        if page.epoch != self.epoch {
+            return Err(Error::Value(String::from("page modified during scan")));
+        }
+        let rid = match &self.cur_rid {
+            None => page.get_first_tuple_rid()?,
+            Some(cur_rid) => page.get_next_tuple_rid(cur_rid)?,
+        };
+        let rid = match rid {
+            Some(rid) => rid,
+            None => return Ok(None),
+        };
+        let tuple = page.get_tuple(&rid)?;
+        self.cur_rid = Some(rid);
+        Ok(tuple)
+    }
+}
+
 /// for the type change, make HeaderPage to Page
 /// and not rewrite code
 impl Deref for HeaderPage {
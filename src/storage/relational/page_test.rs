@@ -1,5 +1,7 @@
-use crate::error::Result;
-use crate::storage::relational::page::{HeaderPage, PAGE_SIZE};
+use crate::error::{Error, Result};
+use crate::storage::relational::page::{HeaderPage, Page, TablePage, PAGE_SIZE};
+use crate::storage::relational::tuple::{Tuple, RID};
+use proptest::prelude::*;
 
 struct Record {
     record_name: &'static str,
@@ -30,3 +32,709 @@ fn test_header_page() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_header_page_fill_factor() -> Result<()> {
+    let mut header_page = HeaderPage::new([0u8; PAGE_SIZE])?;
+    header_page.insert_record("a", 1)?;
+    header_page.insert_record("b", 2)?;
+    assert_eq!(header_page.get_fill_factor("a")?, Some(1.0));
+
+    assert!(header_page.set_fill_factor("a", 0.5)?);
+    assert!(!header_page.set_fill_factor("c", 0.5)?);
+    assert!(header_page.set_fill_factor("a", 0.0).is_err());
+    assert!(header_page.set_fill_factor("a", 1.5).is_err());
+    assert_eq!(header_page.get_fill_factor("a")?, Some(0.5));
+    assert_eq!(header_page.get_fill_factor("b")?, Some(1.0));
+    assert_eq!(header_page.get_fill_factor("c")?, None);
+
+    // deleting a record moves the following records, with their fill factor
+    assert!(header_page.delete_record("a")?);
+    assert!(header_page.set_fill_factor("b", 0.8)?);
+    assert_eq!(header_page.get_root_id("b")?, Some(2));
+    assert_eq!(header_page.get_fill_factor("b")?, Some(0.8));
+    Ok(())
+}
+
+#[test]
+fn test_header_page_ttl() -> Result<()> {
+    let mut header_page = HeaderPage::new([0u8; PAGE_SIZE])?;
+    header_page.insert_record("a", 1)?;
+    header_page.insert_record("b", 2)?;
+    assert_eq!(header_page.get_ttl("a")?, Some(0));
+
+    assert!(header_page.set_ttl("a", 1000)?);
+    assert!(!header_page.set_ttl("c", 1000)?);
+    assert_eq!(header_page.get_ttl("a")?, Some(1000));
+    assert_eq!(header_page.get_ttl("b")?, Some(0));
+    assert_eq!(header_page.get_ttl("c")?, None);
+    assert_eq!(header_page.get_fill_factor("a")?, Some(1.0));
+    assert_eq!(header_page.get_record_names()?, vec!["a".to_string(), "b".to_string()]);
+
+    // deleting a record moves the following records, with their ttl
+    assert!(header_page.set_ttl("b", 500)?);
+    assert!(header_page.delete_record("a")?);
+    assert_eq!(header_page.get_ttl("b")?, Some(500));
+    assert_eq!(header_page.get_record_names()?, vec!["b".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_header_page_chain() -> Result<()> {
+    let mut header_page = HeaderPage::new([0u8; PAGE_SIZE])?;
+    let mut next_page_id = 100;
+    for i in 0..200u32 {
+        let name = format!("table_{}", i);
+        if !header_page.insert_record(&name, i + 1)? {
+            header_page.extend(next_page_id)?;
+            next_page_id += 1;
+            assert!(header_page.insert_record(&name, i + 1)?);
+        }
+    }
+    // 92 records fit in a header page
+    assert_eq!(vec![0, 100, 101], header_page.get_page_ids());
+    assert_eq!(200, header_page.get_record_count()?);
+    for i in 0..200u32 {
+        assert_eq!(Some(i + 1), header_page.get_root_id(&format!("table_{}", i))?);
+    }
+    assert!(!header_page.insert_record("table_150", 1)?);
+    assert!(header_page.extend(100).is_err());
+
+    // records on later pages can be changed and deleted, and freed space is reused first
+    assert!(header_page.set_fill_factor("table_150", 0.5)?);
+    assert_eq!(Some(0.5), header_page.get_fill_factor("table_150")?);
+    assert!(header_page.delete_record("table_150")?);
+    assert!(header_page.delete_record("table_0")?);
+    assert_eq!(None, header_page.get_root_id("table_150")?);
+    assert!(header_page.insert_record("a", 1000)?);
+    assert_eq!(vec![0, 100, 101], header_page.get_page_ids());
+
+    // only the page the chain links to next can be loaded
+    let mut header_page = HeaderPage::new([0u8; PAGE_SIZE])?;
+    assert_eq!(None, header_page.get_unloaded_page_id()?);
+    assert!(header_page.push_page(100, [0u8; PAGE_SIZE]).is_err());
+    Ok(())
+}
+
+#[test]
+// Regression test for corrupt header pages found by the page fuzz target.
+fn test_corrupt_header_page() -> Result<()> {
+    // A record name that isn't valid UTF-8 used to panic.
+    let mut data = [0u8; PAGE_SIZE];
+    data[21] = 1;
+    data[29..31].copy_from_slice(&[0xff, 0xfe]);
+    let header_page = HeaderPage::from_data(data)?;
+    assert_eq!(header_page.get_record_count()?, 1);
+    assert_eq!(header_page.get_root_id("a")?, None);
+
+    // A record count past the end of the page is an error.
+    let mut data = [0u8; PAGE_SIZE];
+    data[21..25].copy_from_slice(&u32::MAX.to_le_bytes());
+    let header_page = HeaderPage::from_data(data)?;
+    assert!(header_page.get_record_count().is_err());
+    assert!(header_page.get_root_id("a").is_err());
+
+    // A link to a page that isn't a header page, or back into the chain, is an error.
+    let mut data = [0u8; PAGE_SIZE];
+    data[13..17].copy_from_slice(&5u32.to_le_bytes());
+    let mut header_page = HeaderPage::from_data(data)?;
+    assert_eq!(Some(5), header_page.get_unloaded_page_id()?);
+    assert!(matches!(header_page.push_page(5, [0u8; PAGE_SIZE]), Err(Error::PageCorrupt(_))));
+    data[0..4].copy_from_slice(&5u32.to_le_bytes());
+    header_page.push_page(5, data)?;
+    assert_eq!(Some(5), header_page.get_unloaded_page_id()?);
+    assert!(matches!(header_page.push_page(5, data), Err(Error::PageCorrupt(_))));
+    Ok(())
+}
+
+#[test]
+// Regression test for corrupt table pages found by the page fuzz target.
+fn test_corrupt_table_page() -> Result<()> {
+    // A tuple size past the end of the page used to panic or allocate unbounded memory.
+    let mut data = [0u8; PAGE_SIZE];
+    data[21..25].copy_from_slice(&1u32.to_le_bytes());
+    data[29..33].copy_from_slice(&4000u32.to_le_bytes());
+    data[33..37].copy_from_slice(&0x7fff_fff0u32.to_le_bytes());
+    let mut table_page = TablePage::from_data(1, data)?;
+    let rid = table_page.get_first_tuple_rid()?.expect("expected a tuple");
+    assert!(table_page.get_tuple(&rid).is_err());
+
+    // A slot array overlapping the free space pointer used to overflow.
+    let mut data = [0u8; PAGE_SIZE];
+    data[17..21].copy_from_slice(&10u32.to_le_bytes());
+    data[21..25].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut table_page = TablePage::from_data(1, data)?;
+    let mut tuple = Tuple::from_data(vec![0x01]);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple).is_err());
+
+    // A slot straddling the end of the page used to panic on the short read.
+    let mut buf = [0u8; 4];
+    let table_page = TablePage::from_data(1, [0u8; PAGE_SIZE])?;
+    assert_eq!(table_page.read_data(&mut buf, PAGE_SIZE - 2, 4)?, 2);
+    Ok(())
+}
+
+#[test]
+fn test_insert_tuples() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    let mut tuple = Tuple::from_data(vec![0xff; 100]);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    assert!(table_page.insert_tuples(&mut [Tuple::from_data(vec![])]).is_err());
+    assert_eq!(0, table_page.insert_tuples(&mut [])?);
+
+    // 40 tuples of 100 bytes and a slot each only fit in part
+    let mut tuples: Vec<Tuple> = (0..40).map(|i| Tuple::from_data(vec![i as u8; 100])).collect();
+    let free_space = PAGE_SIZE - 29 - 108;
+    let stored = table_page.insert_tuples(&mut tuples)?;
+    assert_eq!(free_space / 108, stored);
+    table_page.validate()?;
+    assert_eq!(1 + stored as u32, read_u32(table_page.get_data(), 21));
+    assert_eq!((PAGE_SIZE - 100 * (1 + stored)) as u32, read_u32(table_page.get_data(), 17));
+
+    for (i, tuple) in tuples.iter().enumerate() {
+        if i < stored {
+            let rid = tuple.get_rid().unwrap();
+            assert_eq!(&RID::new(1, 1 + i as u32), rid);
+            assert_eq!(vec![i as u8; 100], table_page.get_tuple(rid)?.unwrap().get_data());
+        } else {
+            assert!(tuple.get_rid().is_none());
+        }
+    }
+
+    // what is left fits a smaller tuple, but not another full one
+    let left = free_space - 108 * stored;
+    let mut tuples = vec![Tuple::from_data(vec![0x01; 100]), Tuple::from_data(vec![0x02; 8])];
+    assert_eq!(0, table_page.insert_tuples(&mut tuples)?);
+    let mut tuples = vec![Tuple::from_data(vec![0x02; left - 8])];
+    assert_eq!(1, table_page.insert_tuples(&mut tuples)?);
+    table_page.validate()?;
+    assert_eq!(read_u32(table_page.get_data(), 17), 29 + 8 * (2 + stored as u32));
+    Ok(())
+}
+
+#[test]
+fn test_delete_page() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    assert!(!table_page.page_is_deleted()?);
+    assert!(table_page.delete_page()?);
+    assert!(table_page.page_is_deleted()?);
+    assert!(table_page.get_status_mut().is_edited());
+    assert_eq!(1, table_page.get_data()[4]);
+    table_page.validate()?;
+
+    // deleting again is a no-op
+    assert!(table_page.delete_page()?);
+    assert!(table_page.page_is_deleted()?);
+    Ok(())
+}
+
+#[test]
+fn test_table_page_tuples() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    assert!(table_page.tuples().next().is_none());
+    for (slot_num, value) in [0x01u8, 0x02, 0x03].iter().enumerate() {
+        let mut tuple = Tuple::from_data(vec![*value; 10]);
+        tuple.set_rid(RID::new(1, slot_num as u32));
+        assert!(table_page.insert_tuple(&mut tuple)?);
+    }
+    table_page.mark_delete(&RID::new(1, 1))?;
+    table_page.apply_delete(&RID::new(1, 1))?;
+
+    let tuples = table_page.tuples().collect::<Result<Vec<_>>>()?;
+    assert_eq!(2, tuples.len());
+    assert_eq!(RID::new(1, 0), tuples[0].0);
+    assert_eq!(vec![0x01; 10], tuples[0].1.get_data());
+    assert_eq!(RID::new(1, 2), tuples[1].0);
+    assert_eq!(vec![0x03; 10], tuples[1].1.get_data());
+
+    // a corrupt slot ends the iteration with an error
+    table_page.write_data(&(PAGE_SIZE as u32).to_le_bytes(), 29 + 2 * 8, 4)?;
+    let mut tuples = table_page.tuples();
+    assert!(tuples.next().unwrap().is_ok());
+    assert!(tuples.next().unwrap().is_err());
+    assert!(tuples.next().is_none());
+    Ok(())
+}
+
+#[test]
+fn test_table_page_lsn() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    assert_eq!(0, table_page.get_lsn()?);
+    assert!(!table_page.get_status_mut().is_edited());
+
+    table_page.set_lsn(0x0102_0304)?;
+    assert!(table_page.get_status_mut().is_edited());
+    assert_eq!(0x0102_0304, table_page.get_lsn()?);
+    // the lsn is stored little endian, whatever the architecture
+    let mut data = [0u8; PAGE_SIZE];
+    data.copy_from_slice(table_page.get_data());
+    assert_eq!([0x04, 0x03, 0x02, 0x01], data[5..9]);
+    Page::verify_checksum(1, &data)?;
+
+    let mut table_page = TablePage::from_data(1, data)?;
+    assert_eq!(0x0102_0304, table_page.get_lsn()?);
+    Ok(())
+}
+
+#[test]
+fn test_full_table_page() -> Result<()> {
+    assert_eq!(4096, PAGE_SIZE);
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+
+    // a single tuple fills everything after the header and its slot
+    let mut data = vec![0x01; PAGE_SIZE - 29 - 8];
+    *data.last_mut().unwrap() = 0xff;
+    let mut tuple = Tuple::from_data(data.clone());
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    assert_eq!(37, read_u32(table_page.get_data(), 29));
+    assert_eq!(37, read_u32(table_page.get_data(), 17));
+    assert_eq!(data, table_page.get_tuple(&RID::new(1, 0))?.unwrap().get_data());
+    table_page.validate()?;
+
+    // the tuple's last byte is the last byte of the page
+    let mut last = [0u8; 2];
+    assert_eq!(1, table_page.read_data(&mut last, 4095, 2)?);
+    assert_eq!(0xff, last[0]);
+    assert_eq!(0, table_page.write_data(&[0x02], PAGE_SIZE, 1)?);
+
+    // the page is full, but shrinking the tuple frees space again
+    let mut tuple = Tuple::from_data(vec![0x03]);
+    tuple.set_rid(RID::new(1, 1));
+    assert!(!table_page.insert_tuple(&mut tuple)?);
+    let mut tuple = Tuple::from_data(vec![0x04; 100]);
+    tuple.set_rid(RID::new(1, 0));
+    table_page.update_tuple(&tuple)?;
+    let mut tuple = Tuple::from_data(vec![0x03]);
+    tuple.set_rid(RID::new(1, 1));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    table_page.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_validate_table_page() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    let mut tuple = Tuple::from_data(vec![0x01, 0x02, 0x03]);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    table_page.validate()?;
+    let mut data = [0u8; PAGE_SIZE];
+    data.copy_from_slice(table_page.get_data());
+
+    let corrupt = |offset: usize, value: u32| -> Result<()> {
+        let mut data = data;
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        TablePage::from_data(1, data)?.validate()
+    };
+    let assert_corrupt = |result: Result<()>| {
+        assert!(matches!(result, Err(Error::PageCorrupt(_))), "{:?}", result);
+    };
+
+    // stored page id does not match
+    assert_corrupt(corrupt(0, 2));
+    // free space pointer past the page end
+    assert_corrupt(corrupt(17, PAGE_SIZE as u32 + 1));
+    // slot array overlapping the tuple data
+    assert_corrupt(corrupt(21, 1000));
+    // tuple offset before the free space pointer
+    assert_corrupt(corrupt(29, 100));
+    // tuple size past the page end
+    assert_corrupt(corrupt(33, 4));
+    Ok(())
+}
+
+#[test]
+// Corrupt slots must make apply_delete and update_tuple fail cleanly, rather than panic in
+// their data moves or offset arithmetic.
+fn test_corrupt_table_page_moves() -> Result<()> {
+    // two 3-byte tuples, at offsets 4093 and 4090
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    for slot_num in 0..2 {
+        let mut tuple = Tuple::from_data(vec![slot_num as u8; 3]);
+        tuple.set_rid(RID::new(1, slot_num));
+        assert!(table_page.insert_tuple(&mut tuple)?);
+    }
+    assert!(table_page.mark_delete(&RID::new(1, 0))?);
+    let mut data = [0u8; PAGE_SIZE];
+    data.copy_from_slice(table_page.get_data());
+    let corrupt = |offset: usize, value: u32| -> Result<TablePage> {
+        let mut data = data;
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        TablePage::from_data(1, data)
+    };
+    let assert_corrupt = |result: Result<()>| {
+        assert!(matches!(result, Err(Error::PageCorrupt(_))), "{:?}", result);
+    };
+
+    // deleted tuple extending past the page end
+    let mut page = corrupt(33, TablePage::set_deleted_flag(100))?;
+    assert_corrupt(page.apply_delete(&RID::new(1, 0)));
+    // other tuple extending past the page end
+    let mut page = corrupt(41, 100)?;
+    assert_corrupt(page.apply_delete(&RID::new(1, 0)));
+
+    // updated tuple extending past the page end
+    let mut page = corrupt(29, 4094)?;
+    page.rollback_delete(&RID::new(1, 0))?;
+    let mut tuple = Tuple::from_data(vec![0x01; 3]);
+    tuple.set_rid(RID::new(1, 0));
+    assert_corrupt(page.update_tuple(&tuple));
+    // growing a tuple while another one lies before the free space pointer
+    let mut page = corrupt(29, 5)?;
+    let mut tuple = Tuple::from_data(vec![0x01; 10]);
+    tuple.set_rid(RID::new(1, 1));
+    assert_corrupt(page.update_tuple(&tuple));
+    Ok(())
+}
+
+#[test]
+fn test_page_checksum() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    let mut tuple = Tuple::from_data(vec![0x01, 0x02, 0x03]);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    let mut data = [0u8; PAGE_SIZE];
+    data.copy_from_slice(table_page.get_data());
+    Page::verify_checksum(1, &data)?;
+
+    // deleting the tuple moves data, and must keep the checksum up to date
+    assert!(table_page.mark_delete(&RID::new(1, 0))?);
+    table_page.apply_delete(&RID::new(1, 0))?;
+    data.copy_from_slice(table_page.get_data());
+    Page::verify_checksum(1, &data)?;
+
+    // a single flipped bit is detected, in table pages and the header page
+    data[PAGE_SIZE - 1] ^= 0x01;
+    assert!(matches!(Page::verify_checksum(1, &data), Err(Error::PageCorrupt(_))));
+    let mut header_page = HeaderPage::new([0u8; PAGE_SIZE])?;
+    header_page.insert_record("a", 1)?;
+    header_page.read_data(&mut data, 0, PAGE_SIZE)?;
+    Page::verify_checksum(0, &data)?;
+    data[29] ^= 0x01;
+    assert!(matches!(Page::verify_checksum(0, &data), Err(Error::PageCorrupt(_))));
+
+    // an all-zero page was never written
+    Page::verify_checksum(1, &[0u8; PAGE_SIZE])?;
+    Ok(())
+}
+
+#[test]
+fn test_scan_page_modified() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    for (i, data) in [vec![0x01], vec![0x02, 0x02], vec![0x03, 0x03, 0x03]].iter().enumerate() {
+        let mut tuple = Tuple::from_data(data.clone());
+        tuple.set_rid(RID::new(1, i as u32));
+        assert!(table_page.insert_tuple(&mut tuple)?);
+    }
+
+    // an unmodified page is scanned in slot order, and marking a delete doesn't move data
+    let mut scan = table_page.scan();
+    assert_eq!(scan.next(&mut table_page)?.unwrap().get_data(), &[0x01]);
+    assert!(table_page.mark_delete(&RID::new(1, 1))?);
+    assert_eq!(scan.next(&mut table_page)?.unwrap().get_data(), &[0x03, 0x03, 0x03]);
+    assert!(scan.next(&mut table_page)?.is_none());
+
+    // applying the delete moves tuple data, so a scan in progress must fail
+    let mut scan = table_page.scan();
+    assert_eq!(scan.next(&mut table_page)?.unwrap().get_data(), &[0x01]);
+    table_page.apply_delete(&RID::new(1, 1))?;
+    assert!(matches!(
+        scan.next(&mut table_page),
+        Err(Error::Value(message)) if message == "page modified during scan"
+    ));
+
+    // as does reusing the emptied slot, and resizing a tuple
+    let mut scan = table_page.scan();
+    let mut tuple = Tuple::from_data(vec![0x04]);
+    tuple.set_rid(RID::new(1, 1));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    assert!(scan.next(&mut table_page).is_err());
+
+    let mut scan = table_page.scan();
+    let mut tuple = Tuple::from_data(vec![0x05, 0x05]);
+    tuple.set_rid(RID::new(1, 0));
+    table_page.update_tuple(&tuple)?;
+    assert!(scan.next(&mut table_page).is_err());
+
+    // a scan started after the changes sees the new tuples
+    let mut scan = table_page.scan();
+    let mut tuples = Vec::new();
+    while let Some(tuple) = scan.next(&mut table_page)? {
+        tuples.push(tuple.get_data().to_vec());
+    }
+    assert_eq!(tuples, vec![vec![0x05, 0x05], vec![0x04], vec![0x03, 0x03, 0x03]]);
+    Ok(())
+}
+
+#[test]
+fn test_compact_table_page() -> Result<()> {
+    // fill the page with 37 tuples of 100 bytes, leaving 71 bytes
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    for slot_num in 0..37u32 {
+        let mut tuple = Tuple::from_data(vec![slot_num as u8; 100]);
+        tuple.set_rid(RID::new(1, slot_num));
+        assert!(table_page.insert_tuple(&mut tuple)?);
+    }
+    for slot_num in (1..37u32).step_by(2) {
+        assert!(table_page.mark_delete(&RID::new(1, slot_num))?);
+        table_page.apply_delete(&RID::new(1, slot_num))?;
+    }
+
+    // the 18 empty slots still take 144 bytes, so a tuple needing 1908 bytes doesn't fit
+    // in the 1871 free bytes
+    let mut tuple = Tuple::from_data(vec![0xff; 1900]);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(!table_page.insert_tuple(&mut tuple)?);
+
+    let epoch = table_page.get_epoch();
+    assert_eq!(144, table_page.compact()?);
+    assert_ne!(epoch, table_page.get_epoch());
+    table_page.validate()?;
+    assert_eq!(19, read_u32(table_page.get_data(), 21));
+    for slot_num in 0..19u32 {
+        let tuple = table_page.get_tuple(&RID::new(1, slot_num))?.unwrap();
+        assert_eq!(vec![2 * slot_num as u8; 100], tuple.get_data());
+    }
+
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    assert_eq!(vec![0xff; 1900], table_page.get_tuple(&RID::new(1, 19))?.unwrap().get_data());
+    assert_eq!(0, table_page.compact()?);
+    Ok(())
+}
+
+#[test]
+fn test_update_tuple_in_place() -> Result<()> {
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    for slot_num in 0..2u32 {
+        let mut tuple = Tuple::from_data(vec![slot_num as u8; 1000]);
+        tuple.set_rid(RID::new(1, slot_num));
+        assert!(table_page.insert_tuple(&mut tuple)?);
+    }
+    let before = table_page.get_data().to_vec();
+    let epoch = table_page.get_epoch();
+
+    // updating a single field of a large tuple only writes that field
+    let mut data = vec![0x00; 1000];
+    data[500..504].copy_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+    let mut tuple = Tuple::from_data(data.clone());
+    tuple.set_rid(RID::new(1, 0));
+    table_page.update_tuple(&tuple)?;
+    assert_eq!(data, table_page.get_tuple(&RID::new(1, 0))?.unwrap().get_data());
+    assert_eq!(vec![0x01; 1000], table_page.get_tuple(&RID::new(1, 1))?.unwrap().get_data());
+    assert_eq!(epoch, table_page.get_epoch());
+    let after = table_page.get_data();
+    let changed = (29..PAGE_SIZE).filter(|i| before[*i] != after[*i]).collect::<Vec<_>>();
+    let tuple_offset = read_u32(after, 29) as usize;
+    assert_eq!((tuple_offset + 500..tuple_offset + 504).collect::<Vec<_>>(), changed);
+
+    // a size change still moves the tuple data
+    let mut tuple = Tuple::from_data(vec![0x05; 10]);
+    tuple.set_rid(RID::new(1, 0));
+    table_page.update_tuple(&tuple)?;
+    assert_eq!(vec![0x05; 10], table_page.get_tuple(&RID::new(1, 0))?.unwrap().get_data());
+    assert_ne!(epoch, table_page.get_epoch());
+    table_page.validate()?;
+    Ok(())
+}
+
+/// An operation on a table page, for property tests.
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(Vec<u8>),
+    Update(u32, Vec<u8>),
+    MarkDelete(u32),
+    ApplyDelete(u32),
+    RollbackDelete(u32),
+    Compact,
+}
+
+/// The expected state of a slot.
+#[derive(Clone, Debug, PartialEq)]
+enum Slot {
+    Live(Vec<u8>),
+    Deleted(Vec<u8>),
+    Empty,
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    let data = || prop::collection::vec(any::<u8>(), 1..300);
+    let slot = || 0..24u32;
+    prop_oneof![
+        3 => data().prop_map(Op::Insert),
+        2 => (slot(), data()).prop_map(|(slot, data)| Op::Update(slot, data)),
+        1 => slot().prop_map(Op::MarkDelete),
+        1 => slot().prop_map(Op::ApplyDelete),
+        1 => slot().prop_map(Op::RollbackDelete),
+        1 => Just(Op::Compact),
+    ]
+}
+
+/// Reads a little-endian u32 from raw page data.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+/// Asserts that the table page matches the expected slots, and that its layout is consistent.
+fn assert_page(page: &mut TablePage, slots: &[Slot]) -> Result<()> {
+    page.validate()?;
+    let data = page.get_data().to_vec();
+    let free_space_pointer = read_u32(&data, 17) as usize;
+    let tuple_count = read_u32(&data, 21) as usize;
+    assert_eq!(tuple_count, slots.len());
+    assert!(free_space_pointer >= 29 + 8 * tuple_count && free_space_pointer <= PAGE_SIZE);
+
+    // Tuple data must lie between the free space pointer and the page end, without overlaps,
+    // and must account for all space used after the free space pointer.
+    let mut ranges = Vec::new();
+    for (slot_num, slot) in slots.iter().enumerate() {
+        let offset = read_u32(&data, 29 + 8 * slot_num) as usize;
+        let size = TablePage::unset_deleted_flag(read_u32(&data, 33 + 8 * slot_num)) as usize;
+        match slot {
+            Slot::Live(expect) | Slot::Deleted(expect) => {
+                assert_eq!(size, expect.len());
+                assert!(offset >= free_space_pointer && offset + size <= PAGE_SIZE);
+                assert_eq!(&data[offset..offset + size], expect.as_slice());
+                ranges.push((offset, offset + size));
+            }
+            Slot::Empty => assert_eq!(size, 0),
+        }
+    }
+    ranges.sort_unstable();
+    for pair in ranges.windows(2) {
+        assert!(pair[0].1 <= pair[1].0, "overlapping tuples {:?} and {:?}", pair[0], pair[1]);
+    }
+    let used: usize = ranges.iter().map(|(start, end)| end - start).sum();
+    assert_eq!(free_space_pointer, PAGE_SIZE - used);
+
+    // Live tuples must be readable and iterable, other slots not.
+    let mut live = Vec::new();
+    for (slot_num, slot) in slots.iter().enumerate() {
+        let tuple = page.get_tuple(&RID::new(1, slot_num as u32))?;
+        match slot {
+            Slot::Live(expect) => {
+                assert_eq!(tuple.map(|t| t.get_data().to_vec()), Some(expect.clone()));
+                live.push(slot_num as u32);
+            }
+            Slot::Deleted(_) | Slot::Empty => assert!(tuple.is_none()),
+        }
+    }
+    let mut iterated = Vec::new();
+    let mut next = page.get_first_tuple_rid()?;
+    while let Some(rid) = next {
+        iterated.push(*rid.get_slot_num());
+        next = page.get_next_tuple_rid(&rid)?;
+    }
+    assert_eq!(iterated, live);
+    Ok(())
+}
+
+/// Applies the operations to a table page, checking the result of each operation against a
+/// model of the expected slots, and the page invariants after each operation.
+fn check_table_page(ops: &[Op]) -> Result<()> {
+    let mut page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    let mut slots: Vec<Slot> = Vec::new();
+
+    for op in ops {
+        let used: usize = slots
+            .iter()
+            .map(|slot| match slot {
+                Slot::Live(data) | Slot::Deleted(data) => data.len(),
+                Slot::Empty => 0,
+            })
+            .sum();
+        let free_space = PAGE_SIZE - used - 29 - 8 * slots.len();
+
+        match op {
+            Op::Insert(data) => {
+                let mut tuple = Tuple::from_data(data.clone());
+                tuple.set_rid(RID::new(1, 0));
+                let fits = free_space >= data.len() + 8;
+                assert_eq!(page.insert_tuple(&mut tuple)?, fits);
+                if fits {
+                    match slots.iter().position(|slot| *slot == Slot::Empty) {
+                        Some(i) => slots[i] = Slot::Live(data.clone()),
+                        None => slots.push(Slot::Live(data.clone())),
+                    }
+                }
+            }
+            Op::Update(slot_num, data) => {
+                let mut tuple = Tuple::from_data(data.clone());
+                tuple.set_rid(RID::new(1, *slot_num));
+                let result = page.update_tuple(&tuple);
+                match slots.get(*slot_num as usize).cloned() {
+                    Some(Slot::Live(old)) if free_space + old.len() >= data.len() => {
+                        result?;
+                        slots[*slot_num as usize] = Slot::Live(data.clone());
+                    }
+                    _ => assert!(result.is_err()),
+                }
+            }
+            Op::MarkDelete(slot_num) => {
+                let result = page.mark_delete(&RID::new(1, *slot_num))?;
+                match slots.get(*slot_num as usize).cloned() {
+                    Some(Slot::Live(data)) => {
+                        assert!(result);
+                        slots[*slot_num as usize] = Slot::Deleted(data);
+                    }
+                    _ => assert!(!result),
+                }
+            }
+            Op::ApplyDelete(slot_num) => {
+                let result = page.apply_delete(&RID::new(1, *slot_num));
+                match slots.get(*slot_num as usize).cloned() {
+                    Some(Slot::Deleted(_)) => {
+                        result?;
+                        slots[*slot_num as usize] = Slot::Empty;
+                    }
+                    _ => assert!(result.is_err()),
+                }
+            }
+            Op::RollbackDelete(slot_num) => {
+                let result = page.rollback_delete(&RID::new(1, *slot_num));
+                match slots.get(*slot_num as usize).cloned() {
+                    Some(Slot::Deleted(data)) => {
+                        result?;
+                        slots[*slot_num as usize] = Slot::Live(data);
+                    }
+                    Some(_) => result?,
+                    None => assert!(result.is_err()),
+                }
+            }
+            Op::Compact => {
+                let empty = slots.iter().filter(|slot| **slot == Slot::Empty).count();
+                assert_eq!(page.compact()? as usize, 8 * empty);
+                slots.retain(|slot| *slot != Slot::Empty);
+            }
+        }
+        assert_page(&mut page, &slots)?;
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    // Applies random operation sequences to a table page, and checks the slotted page invariants.
+    fn test_table_page_invariants(ops in prop::collection::vec(op_strategy(), 1..200)) {
+        check_table_page(&ops).unwrap();
+    }
+}
+
+#[test]
+// Regression test for shrunk failures found by test_table_page_invariants.
+fn test_table_page_delete() -> Result<()> {
+    // A tuple size with bit 3 set (e.g. 8) was considered deleted, and applying a delete used
+    // the flagged size and moved empty slots.
+    check_table_page(&[
+        Op::Insert(vec![1; 8]),
+        Op::Insert(vec![2; 3]),
+        Op::MarkDelete(0),
+        Op::ApplyDelete(0),
+        Op::Update(1, vec![3; 5]),
+        Op::Insert(vec![4; 2]),
+    ])
+}
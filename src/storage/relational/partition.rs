@@ -0,0 +1,159 @@
+use super::buffer_pool::BufferPoolManager;
+use super::tuple::{Tuple, RID};
+use crate::error::{Error, Result};
+use std::ops::{Bound, RangeBounds};
+use std::time::SystemTime;
+
+/// A table split into partitions by ranges of one of its columns, so scans of a range of the
+/// column only read the partitions that can hold it. each partition is a table of the buffer
+/// pool, with its own page chain and header page record, named after the table and its number,
+/// e.g. "orders.0". the table itself keeps a single tuple describing the partitions:
+///
+/// | column (4) | bound_1 | ... | bound_n |
+///
+/// built by Tuple::from_values. partition i holds the tuples whose column value is at least
+/// bound_i and less than bound_i+1, the first partition also holding null values. column values
+/// are compared as bytes, so e.g. integers should be encoded big-endian
+pub struct PartitionedTable {
+    name: String,
+    /// the partitioning column
+    column: usize,
+    /// the lower bound of each partition but the first, in ascending order
+    bounds: Vec<Vec<u8>>,
+}
+
+impl PartitionedTable {
+    /// create a table partitioned by the given column at the given bounds, which must be in
+    /// ascending order. there is one more partition than bounds.
+    /// return None if the table or a partition name is too long or already exists
+    pub fn create(
+        buffer_pool: &mut BufferPoolManager,
+        name: &str,
+        column: usize,
+        bounds: Vec<Vec<u8>>,
+    ) -> Result<Option<PartitionedTable>> {
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(Error::Value(String::from("partition bounds must be ascending")));
+        }
+        let table = PartitionedTable { name: name.to_string(), column, bounds };
+        for partition in (0..table.partition_count()).map(|i| table.partition_name(i)) {
+            if partition.len() > 32 || buffer_pool.get_table_root_id(&partition)?.is_some() {
+                return Ok(None);
+            }
+        }
+        let root_id = match buffer_pool.create_table(name)? {
+            Some(root_id) => root_id,
+            None => return Ok(None),
+        };
+        for i in 0..table.partition_count() {
+            buffer_pool.create_table(&table.partition_name(i))?;
+        }
+
+        let mut values = vec![Some((column as u32).to_le_bytes().to_vec())];
+        values.extend(table.bounds.iter().map(|bound| Some(bound.clone())));
+        let mut tuple = Tuple::from_values(&values);
+        tuple.set_rid(RID::new(root_id, 0));
+        if !buffer_pool.insert_tuple(name, &mut tuple)? {
+            return Err(Error::Internal(format!("can not store partitions of table {}", name)));
+        }
+        Ok(Some(table))
+    }
+
+    /// open a partitioned table, if it exists
+    pub fn open(
+        buffer_pool: &mut BufferPoolManager,
+        name: &str,
+    ) -> Result<Option<PartitionedTable>> {
+        if buffer_pool.get_table_root_id(name)?.is_none() {
+            return Ok(None);
+        }
+        let invalid = || Error::Value(format!("table {} is not partitioned", name));
+        let tuple = buffer_pool.scan_table(name, SystemTime::now())?.into_iter().next();
+        let mut values = tuple.ok_or_else(invalid)?.get_values()?.into_iter();
+        let column = match values.next() {
+            Some(Some(column)) if column.len() == 4 => {
+                u32::from_le_bytes([column[0], column[1], column[2], column[3]]) as usize
+            }
+            _ => return Err(invalid()),
+        };
+        let bounds = values.collect::<Option<Vec<_>>>().ok_or_else(invalid)?;
+        Ok(Some(PartitionedTable { name: name.to_string(), column, bounds }))
+    }
+
+    /// the number of partitions
+    pub fn partition_count(&self) -> usize {
+        self.bounds.len() + 1
+    }
+
+    /// the name of a partition's table
+    pub fn partition_name(&self, partition: usize) -> String {
+        format!("{}.{}", self.name, partition)
+    }
+
+    /// the partition holding a column value, None being null
+    pub fn partition_for(&self, value: Option<&[u8]>) -> usize {
+        match value {
+            Some(value) => self.bounds.iter().take_while(|bound| bound.as_slice() <= value).count(),
+            None => 0,
+        }
+    }
+
+    /// the partitions that can hold column values in the given range, in ascending order.
+    /// the others are pruned from scans of the range
+    pub fn partitions_for(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<usize> {
+        (0..self.partition_count())
+            .filter(|&i| {
+                // partition i holds the values in [bounds[i - 1], bounds[i])
+                let below_start = match (start, self.bounds.get(i)) {
+                    (Bound::Included(start), Some(upper))
+                    | (Bound::Excluded(start), Some(upper)) => start >= upper.as_slice(),
+                    _ => false,
+                };
+                let above_end = match (end, i.checked_sub(1).map(|i| &self.bounds[i])) {
+                    (Bound::Included(end), Some(lower)) => end < lower.as_slice(),
+                    (Bound::Excluded(end), Some(lower)) => end <= lower.as_slice(),
+                    _ => false,
+                };
+                !below_start && !above_end
+            })
+            .collect()
+    }
+
+    /// insert a tuple built by Tuple::from_values into the partition of its column value,
+    /// see BufferPoolManager::insert_tuple
+    pub fn insert(&self, buffer_pool: &mut BufferPoolManager, tuple: &mut Tuple) -> Result<bool> {
+        let values = tuple.get_values()?;
+        let value = values.get(self.column).ok_or_else(|| {
+            Error::Value(format!("tuple has no column {} to partition by", self.column))
+        })?;
+        let partition = self.partition_for(value.as_deref());
+        buffer_pool.insert_tuple(&self.partition_name(partition), tuple)
+    }
+
+    /// read the tuples whose column value is in the given range, only scanning the partitions
+    /// that can hold them. tuples with a null value are never in a range
+    pub fn scan(
+        &self,
+        buffer_pool: &mut BufferPoolManager,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<Tuple>> {
+        let range = (start, end);
+        let mut tuples = Vec::new();
+        for partition in self.partitions_for(start, end) {
+            let name = self.partition_name(partition);
+            for tuple in buffer_pool.scan_table(&name, SystemTime::now())? {
+                let values = tuple.get_values()?;
+                match values.get(self.column) {
+                    Some(Some(value))
+                        if RangeBounds::<[u8]>::contains(&range, value.as_slice()) =>
+                    {
+                        tuples.push(tuple)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(tuples)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::error::{Error, Result};
+use crate::storage::relational::buffer_pool::BufferPoolManager;
+use crate::storage::relational::partition::PartitionedTable;
+use crate::storage::relational::tuple::{Tuple, RID};
+use std::ops::Bound;
+use std::time::SystemTime;
+use tempdir::TempDir;
+
+/// build an (id, value) tuple, the id encoded big-endian so it's ordered as bytes
+fn row(id: u32) -> Tuple {
+    let mut tuple = Tuple::from_values(&[Some(id.to_be_bytes().to_vec()), Some(vec![0x01; 16])]);
+    tuple.set_rid(RID::new(0, 0));
+    tuple
+}
+
+/// return the ids of (id, value) tuples
+fn ids(tuples: Vec<Tuple>) -> Result<Vec<u32>> {
+    tuples
+        .iter()
+        .map(|tuple| match tuple.get_values()?.as_slice() {
+            [Some(id), _] => Ok(u32::from_be_bytes([id[0], id[1], id[2], id[3]])),
+            _ => Err(Error::Value(String::from("not an (id, value) tuple"))),
+        })
+        .collect()
+}
+
+#[test]
+fn test_partition_pruning() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let bounds = vec![10u32.to_be_bytes().to_vec(), 20u32.to_be_bytes().to_vec()];
+    let table = PartitionedTable::create(&mut buffer_pool, "t", 0, bounds.clone())?.unwrap();
+    assert!(PartitionedTable::create(&mut buffer_pool, "t", 0, bounds.clone())?.is_none());
+    assert!(PartitionedTable::create(&mut buffer_pool, "u", 0, vec![vec![2], vec![1]]).is_err());
+    assert_eq!(3, table.partition_count());
+
+    // inserts are routed to the partition of their id, each partition being its own table
+    for id in 0..30 {
+        assert!(table.insert(&mut buffer_pool, &mut row(id))?);
+    }
+    let now = SystemTime::now();
+    assert_eq!((0..10).collect::<Vec<_>>(), ids(buffer_pool.scan_table("t.0", now)?)?);
+    assert_eq!((10..20).collect::<Vec<_>>(), ids(buffer_pool.scan_table("t.1", now)?)?);
+    assert_eq!((20..30).collect::<Vec<_>>(), ids(buffer_pool.scan_table("t.2", now)?)?);
+
+    // a range within a partition only fetches the page of that partition
+    let (start, end) = (12u32.to_be_bytes(), 15u32.to_be_bytes());
+    assert_eq!(vec![1], table.partitions_for(Bound::Included(&start), Bound::Included(&end)));
+    let fetches = |buffer_pool: &BufferPoolManager| {
+        let stats = buffer_pool.stats();
+        stats.hits + stats.misses
+    };
+    let before = fetches(&buffer_pool);
+    let tuples = table.scan(&mut buffer_pool, Bound::Included(&start), Bound::Included(&end))?;
+    assert_eq!(vec![12, 13, 14, 15], ids(tuples)?);
+    assert_eq!(1, fetches(&buffer_pool) - before);
+
+    // bounds are inclusive lower bounds of partitions
+    let bound = 20u32.to_be_bytes();
+    assert_eq!(vec![0, 1], table.partitions_for(Bound::Unbounded, Bound::Excluded(&bound)));
+    assert_eq!(vec![2], table.partitions_for(Bound::Included(&bound), Bound::Unbounded));
+    assert_eq!(vec![0, 1, 2], table.partitions_for(Bound::Unbounded, Bound::Unbounded));
+    let before = fetches(&buffer_pool);
+    let tuples = table.scan(&mut buffer_pool, Bound::Excluded(&start), Bound::Unbounded)?;
+    assert_eq!((13..30).collect::<Vec<_>>(), ids(tuples)?);
+    assert_eq!(2, fetches(&buffer_pool) - before);
+
+    // the partitions are kept in the table, and found again when reopened
+    buffer_pool.flush_all()?;
+    drop(buffer_pool);
+    let mut buffer_pool = BufferPoolManager::open(dir.path(), 8)?;
+    let table = PartitionedTable::open(&mut buffer_pool, "t")?.unwrap();
+    assert_eq!(3, table.partition_count());
+    let tuples = table.scan(&mut buffer_pool, Bound::Included(&bound), Bound::Unbounded)?;
+    assert_eq!((20..30).collect::<Vec<_>>(), ids(tuples)?);
+    assert!(PartitionedTable::open(&mut buffer_pool, "missing")?.is_none());
+    buffer_pool.create_table("plain")?.unwrap();
+    assert!(PartitionedTable::open(&mut buffer_pool, "plain").is_err());
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use super::disk_manager::DiskManager;
+use super::page::TablePage;
+use crate::error::Result;
+use std::sync::{Arc, RwLock};
+
+/// A page cache with an eviction policy, used by the buffer pool. the policies keep the
+/// cached pages in a list, with the removed page's slot reused by the page replacing it.
+/// pinned pages are never evicted
+pub trait Replacer: Send + Sync {
+    /// the cached pages
+    fn pages(&self) -> &[Arc<RwLock<TablePage>>];
+
+    /// the maximum number of cached pages
+    fn capacity(&self) -> usize;
+
+    /// find a cached page and take a ref, counting it as an access
+    fn poll(&self, page_id: u32) -> Result<Option<Arc<RwLock<TablePage>>>>;
+
+    /// push a new page. if a page should be remove, return it
+    fn push(&mut self, page: TablePage) -> Result<Option<Arc<RwLock<TablePage>>>>;
+
+    /// return the index of the page to remove for a new page, or None if there is still
+    /// space. error if every page is pinned
+    fn victim(&mut self) -> Result<Option<usize>>;
+
+    /// drop all cached pages, without flushing them
+    fn clear(&mut self);
+
+    /// the number of cached pages
+    fn size(&self) -> usize {
+        self.pages().len()
+    }
+
+    /// pin a cached page, so it isn't evicted while in use. return false if it isn't cached
+    fn pin(&self, page_id: u32) -> Result<bool> {
+        match self.get(page_id)? {
+            Some(page) => {
+                page.write()?.pin();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// drop a cached page without flushing it, e.g. once the page is freed, so a stale copy is
+    /// never written over it. return false if it isn't cached
+    fn discard(&self, page_id: u32) -> Result<bool> {
+        match self.get(page_id)? {
+            Some(page) => {
+                let mut table_page = page.write()?;
+                while table_page.unpin() {}
+                table_page.get_status_mut().un_edited();
+                table_page.get_status_mut().set_removed(true);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// find a cached page without taking a ref. this briefly takes a shared latch on every
+    /// cached page, so it must not be called while holding an exclusive page latch
+    fn get(&self, page_id: u32) -> Result<Option<&Arc<RwLock<TablePage>>>> {
+        for page in self.pages() {
+            let lock_page = page.read()?;
+            if lock_page.get_page_id().eq(&page_id) && !lock_page.get_status().get_removed() {
+                return Ok(Some(page));
+            }
+        }
+        Ok(None)
+    }
+
+    /// return the ids of the cached pages with their access counts, most accessed first
+    fn heat_map(&self) -> Vec<(u32, u64)> {
+        let mut heat_map = Vec::new();
+        for page in self.pages() {
+            let mut table_page = page.write().unwrap();
+            if !table_page.get_status_mut().get_removed() {
+                heat_map
+                    .push((*table_page.get_page_id(), table_page.get_status_mut().get_accesses()));
+            }
+        }
+        heat_map.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        heat_map
+    }
+
+    /// flush all page data, where it was edited
+    fn flush_all(&self, disk_manager: &mut DiskManager) -> Result<()> {
+        for page in self.pages() {
+            let mut table_page = page.write().unwrap();
+            if table_page.get_status_mut().is_edited() {
+                let page_id = *table_page.get_page_id();
+                table_page.set_lsn(disk_manager.next_lsn())?;
+                let page_data = table_page.get_data();
+                disk_manager.write_page(page_id, page_data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// return the id and a copy of the data of every edited page, e.g. to write them out
+    /// without holding the page locks while writing
+    fn get_need_flush(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut need_flush = Vec::new();
+        for page in self.pages() {
+            let mut table_page = page.write().unwrap();
+            let status = table_page.get_status_mut();
+            if status.is_edited() && !status.get_removed() {
+                need_flush.push((*table_page.get_page_id(), table_page.get_data().to_vec()));
+            }
+        }
+        need_flush
+    }
+
+    /// clear the edited tag of every page, once the pages from get_need_flush are written.
+    /// pages edited in between would lose their tag, so they must not be edited meanwhile
+    fn clear_edited(&mut self) {
+        for page in self.pages() {
+            page.write().unwrap().get_status_mut().un_edited();
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use super::blob::BlobReader;
+use super::buffer_pool::BufferPoolManager;
+use super::page::TablePage;
+use super::tuple::{Tuple, RID};
+use crate::error::{Error, Result};
+use crate::storage::kv::{Range, Scan, Store};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// the table holding the key/value pairs, recorded in the header page
+const TABLE_NAME: &str = "kv";
+
+/// the default maximum size of a value, in bytes
+pub const MAX_BLOB_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A key/value store keeping each pair as a tuple of key and value columns, in the pages of a
+/// table of the buffer pool. the pages are not ordered by key, so the keys are indexed in memory
+/// with the RIDs of their tuples. the index is rebuilt by scanning the table when opened.
+/// a value too large for the tuple to fit in a page is stored as a blob in overflow pages, see
+/// BufferPoolManager::insert_blob. its tuple then has a null value column, followed by a column
+/// referring to the blob:
+///
+/// | FirstPageId (4) | Length (8) |
+pub struct Relational {
+    /// locked for reads too, since fetching a page may read it from disk into the cache
+    buffer_pool: Mutex<BufferPoolManager>,
+    index: BTreeMap<Vec<u8>, RID>,
+    /// the maximum size of a value, in bytes
+    max_blob_size: u64,
+}
+
+/// A value as stored in a tuple
+enum StoredValue {
+    Inline(Vec<u8>),
+    Overflow { first_page_id: u32, len: u64 },
+}
+
+impl Relational {
+    /// open the store in the given directory, caching at most cache_capacity pages
+    pub fn new(dir: &Path, cache_capacity: u32) -> Result<Relational> {
+        Relational::with_max_blob_size(dir, cache_capacity, MAX_BLOB_SIZE)
+    }
+
+    /// open the store in the given directory, caching at most cache_capacity pages, and
+    /// rejecting values larger than max_blob_size bytes
+    pub fn with_max_blob_size(
+        dir: &Path,
+        cache_capacity: u32,
+        max_blob_size: u64,
+    ) -> Result<Relational> {
+        let mut buffer_pool = BufferPoolManager::open(dir, cache_capacity)?;
+        let root_id = match buffer_pool.get_table_root_id(TABLE_NAME)? {
+            Some(root_id) => root_id,
+            None => buffer_pool
+                .create_table(TABLE_NAME)?
+                .ok_or_else(|| Error::Internal(format!("can not create table {}", TABLE_NAME)))?,
+        };
+
+        let mut index = BTreeMap::new();
+        let mut page_id = root_id;
+        loop {
+            let page = buffer_pool.fetch_page(page_id)?.ok_or_else(|| {
+                Error::Value(format!("page {} of table {} can not be found", page_id, TABLE_NAME))
+            })?;
+            let mut table_page = page.write()?;
+            table_page.unpin();
+            for item in table_page.tuples() {
+                let (rid, tuple) = item?;
+                index.insert(Relational::decode(&tuple)?.0, rid);
+            }
+            // page 0 is the header page, so it marks the end of the chain
+            match table_page.get_next_page_id()? {
+                0 => break,
+                next_page_id => page_id = next_page_id,
+            }
+        }
+        Ok(Relational { buffer_pool: Mutex::new(buffer_pool), index, max_blob_size })
+    }
+
+    /// split a tuple into its key and stored value
+    fn decode(tuple: &Tuple) -> Result<(Vec<u8>, StoredValue)> {
+        match tuple.get_values()?.as_mut_slice() {
+            [Some(key), Some(value)] => {
+                Ok((std::mem::take(key), StoredValue::Inline(std::mem::take(value))))
+            }
+            [Some(key), None, Some(blob)] if blob.len() == 12 => {
+                let first_page_id = u32::from_le_bytes([blob[0], blob[1], blob[2], blob[3]]);
+                let mut len = [0u8; 8];
+                len.copy_from_slice(&blob[4..]);
+                let len = u64::from_le_bytes(len);
+                Ok((std::mem::take(key), StoredValue::Overflow { first_page_id, len }))
+            }
+            _ => Err(Error::Value(String::from("tuple is not a key/value pair"))),
+        }
+    }
+
+    /// build the tuple of a pair whose value is stored as a blob in overflow pages
+    fn blob_tuple(key: &[u8], first_page_id: u32, len: u64) -> Tuple {
+        let mut blob = first_page_id.to_le_bytes().to_vec();
+        blob.extend_from_slice(&len.to_le_bytes());
+        Tuple::from_values(&[Some(key.to_vec()), None, Some(blob)])
+    }
+
+    /// read the tuple stored at a RID of the index
+    fn read_tuple(&self, rid: &RID) -> Result<Tuple> {
+        let mut buffer_pool = self.buffer_pool.lock()?;
+        let page = buffer_pool
+            .fetch_page(*rid.get_page_id())?
+            .ok_or_else(|| Error::Value(format!("page {} can not be found", rid.get_page_id())))?;
+        let mut table_page = page.write()?;
+        table_page.unpin();
+        table_page
+            .get_tuple(rid)?
+            .ok_or_else(|| Error::Internal(format!("indexed tuple {:?} does not exist", rid)))
+    }
+
+    /// read the pair stored at a RID of the index
+    fn read(&self, rid: &RID) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (key, stored) = Relational::decode(&self.read_tuple(rid)?)?;
+        let mut value = Vec::new();
+        self.reader(stored).read_to_end(&mut value)?;
+        Ok((key, value))
+    }
+
+    /// a reader streaming a stored value
+    fn reader(&self, stored: StoredValue) -> BlobReader<'_> {
+        match stored {
+            StoredValue::Inline(value) => BlobReader::inline(&self.buffer_pool, value),
+            StoredValue::Overflow { first_page_id, len } => {
+                BlobReader::new(&self.buffer_pool, first_page_id, len)
+            }
+        }
+    }
+
+    /// return the RID of the tuple a key is stored in, if it exists
+    pub fn get_rid(&self, key: &[u8]) -> Option<RID> {
+        self.index.get(key).cloned()
+    }
+
+    /// stream a column of the tuple stored at a RID: 0 for the key, 1 for the value. a value
+    /// stored in overflow pages is read a page at a time, so it's never buffered in full
+    pub fn read_blob(&self, rid: &RID, column: usize) -> Result<impl Read + '_> {
+        let (key, stored) = Relational::decode(&self.read_tuple(rid)?)?;
+        Ok(match column {
+            0 => BlobReader::inline(&self.buffer_pool, key),
+            1 => self.reader(stored),
+            _ => return Err(Error::Value(format!("a key/value pair has no column {}", column))),
+        })
+    }
+
+    /// remove the tuple stored at a RID of the index. the slot is freed, and the other
+    /// tuples of the page keep their RIDs
+    fn remove(&mut self, rid: &RID) -> Result<()> {
+        let buffer_pool = self.buffer_pool.get_mut()?;
+        let page = buffer_pool
+            .fetch_page(*rid.get_page_id())?
+            .ok_or_else(|| Error::Value(format!("page {} can not be found", rid.get_page_id())))?;
+        let mut table_page = page.write()?;
+        table_page.unpin();
+        let tuple = table_page
+            .get_tuple(rid)?
+            .ok_or_else(|| Error::Internal(format!("indexed tuple {:?} does not exist", rid)))?;
+        if !table_page.mark_delete(rid)? {
+            return Err(Error::Internal(format!("indexed tuple {:?} does not exist", rid)));
+        }
+        table_page.apply_delete(rid)?;
+        drop(table_page);
+        if let StoredValue::Overflow { first_page_id, .. } = Relational::decode(&tuple)?.1 {
+            buffer_pool.delete_blob(first_page_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Relational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "relational")
+    }
+}
+
+impl Store for Relational {
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        if let Some(rid) = self.index.remove(key) {
+            self.remove(&rid)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.buffer_pool.get_mut()?.flush_all()
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.index.get(key) {
+            Some(rid) => Ok(Some(self.read(rid)?.1)),
+            None => Ok(None),
+        }
+    }
+
+    fn scan(&self, range: Range) -> Scan {
+        // the pairs are read under the buffer pool lock, which is scoped to this method,
+        // so the result is buffered
+        Box::new(
+            self.index.range(range).map(|(_, rid)| self.read(rid)).collect::<Vec<_>>().into_iter(),
+        )
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        if value.len() as u64 > self.max_blob_size {
+            return Err(Error::Value(format!(
+                "value of {} bytes exceeds the maximum of {} bytes",
+                value.len(),
+                self.max_blob_size
+            )));
+        }
+        // a tuple of two columns takes their lengths, the column count and the null bitmap
+        let mut tuple = if 13 + key.len() + value.len() <= TablePage::MAX_TUPLE_SIZE {
+            Tuple::from_values(&[Some(key.to_vec()), Some(value)])
+        } else {
+            // check the key leaves room for the blob reference before storing the blob
+            if Relational::blob_tuple(key, 0, 0).get_length() > TablePage::MAX_TUPLE_SIZE {
+                return Err(Error::Value(format!("key of {} bytes is too large", key.len())));
+            }
+            let first_page_id = self.buffer_pool.get_mut()?.insert_blob(&value)?;
+            Relational::blob_tuple(key, first_page_id, value.len() as u64)
+        };
+        // a tuple needs a RID to be inserted, it is replaced by the one it is stored at
+        tuple.set_rid(RID::new(0, 0));
+        if !self.buffer_pool.get_mut()?.insert_tuple(TABLE_NAME, &mut tuple)? {
+            return Err(Error::Internal(format!("table {} does not exist", TABLE_NAME)));
+        }
+        let rid = tuple
+            .get_rid()
+            .cloned()
+            .ok_or_else(|| Error::Internal(String::from("inserted tuple has no RID")))?;
+        // the old pair is only removed once the new one is stored
+        if let Some(old_rid) = self.index.insert(key.to_vec(), rid) {
+            self.remove(&old_rid)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Relational {
+    fn drop(&mut self) {
+        if let Ok(buffer_pool) = self.buffer_pool.get_mut() {
+            buffer_pool.flush_all().ok();
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::error::Result;
+use crate::storage::kv::{Range, Store, TestSuite, MVCC};
+use crate::storage::relational::page::PAGE_SIZE;
+use crate::storage::relational::store::Relational;
+use std::io::Read;
+use tempdir::TempDir;
+
+impl TestSuite<Relational> for Relational {
+    fn setup() -> Result<Self> {
+        // a small cache, so the tests also evict pages and read them back
+        let dir = TempDir::new("toydb")?;
+        Relational::new(dir.path(), 8)
+    }
+}
+
+#[test]
+fn test_store() -> Result<()> {
+    Relational::test()
+}
+
+#[test]
+fn test_reopen() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut store = Relational::new(dir.path(), 8)?;
+    for i in 0..500u32 {
+        store.set(&i.to_be_bytes(), vec![0x01; 16])?;
+    }
+    for i in (0..500u32).step_by(2) {
+        store.delete(&i.to_be_bytes())?;
+    }
+    store.set(&1u32.to_be_bytes(), vec![0x02; 64])?;
+    store.set(b"empty", vec![])?;
+    drop(store);
+
+    // the index is rebuilt from the tuples in the table pages
+    let mut store = Relational::new(dir.path(), 8)?;
+    assert_eq!(Some(vec![0x02; 64]), store.get(&1u32.to_be_bytes())?);
+    assert_eq!(Some(vec![0x01; 16]), store.get(&3u32.to_be_bytes())?);
+    assert_eq!(None, store.get(&2u32.to_be_bytes())?);
+    assert_eq!(Some(vec![]), store.get(b"empty")?);
+    let keys = store
+        .scan(Range::from(0u32.to_be_bytes().to_vec()..10u32.to_be_bytes().to_vec()))
+        .map(|item| item.map(|(key, _)| key))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        vec![1u32, 3, 5, 7, 9].into_iter().map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>(),
+        keys
+    );
+
+    // a deleted key can be set again
+    store.set(&0u32.to_be_bytes(), vec![0x03; 16])?;
+    assert_eq!(Some(vec![0x03; 16]), store.get(&0u32.to_be_bytes())?);
+    assert_eq!(252, store.scan(Range::from(..)).count());
+    Ok(())
+}
+
+#[test]
+fn test_mvcc() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mvcc = MVCC::new(Box::new(Relational::new(dir.path(), 8)?));
+    let mut txn = mvcc.begin()?;
+    txn.set(b"a", vec![0x01])?;
+    txn.set(b"b", vec![0x02])?;
+    txn.commit()?;
+    let mut txn = mvcc.begin()?;
+    txn.delete(b"a")?;
+    txn.rollback()?;
+    drop(mvcc);
+
+    // committed versions survive reopening, and are visible to new transactions
+    let mvcc = MVCC::new(Box::new(Relational::new(dir.path(), 8)?));
+    let txn = mvcc.begin()?;
+    assert_eq!(Some(vec![0x01]), txn.get(b"a")?);
+    assert_eq!(
+        vec![(b"a".to_vec(), vec![0x01]), (b"b".to_vec(), vec![0x02])],
+        txn.scan(..)?.collect::<Result<Vec<_>>>()?
+    );
+    txn.commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_blob() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let mut store = Relational::with_max_blob_size(dir.path(), 8, 1 << 20)?;
+    // a value spanning many more pages than are cached
+    let blob: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    store.set(b"blob", blob.clone())?;
+    store.set(b"small", vec![0x01; 16])?;
+    assert!(store.set(b"huge", vec![0x02; (1 << 20) + 1]).is_err());
+    assert_eq!(None, store.get(b"huge")?);
+
+    // the value is streamed a page at a time
+    let rid = store.get_rid(b"blob").unwrap();
+    let mut reader = store.read_blob(&rid, 1)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut streamed = Vec::new();
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        assert!(len <= PAGE_SIZE);
+        streamed.extend_from_slice(&buf[..len]);
+    }
+    drop(reader);
+    assert!(streamed == blob);
+    let mut key = Vec::new();
+    store.read_blob(&rid, 0)?.read_to_end(&mut key)?;
+    assert_eq!(b"blob".to_vec(), key);
+    assert!(store.read_blob(&rid, 2).is_err());
+    assert!(store.get(b"blob")? == Some(blob.clone()));
+    drop(store);
+
+    // the blob is found again once reopened, and replacing it frees its pages for reuse
+    let mut store = Relational::with_max_blob_size(dir.path(), 8, 1 << 20)?;
+    assert!(store.get(b"blob")? == Some(blob.clone()));
+    store.set(b"blob", vec![0x03; 16])?;
+    store.set(b"other", blob.clone())?;
+    assert_eq!(Some(vec![0x03; 16]), store.get(b"blob")?);
+    assert!(store.get(b"other")? == Some(blob));
+    assert_eq!(Some(vec![0x01; 16]), store.get(b"small")?);
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use crate::error::{Error, Result};
+use crate::storage::relational::disk_manager::{DiskManager, PageStore};
+use crate::storage::relational::page::{Page, PAGE_SIZE};
+use std::collections::BTreeMap;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// An object store for cold pages, e.g. an S3-compatible bucket
+pub trait CloudBackend {
+    /// store an object, replacing any object with the same key
+    fn put(&mut self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// fetch an object, if it exists
+    fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// delete an object, if it exists
+    fn delete(&mut self, key: &str) -> Result<()>;
+}
+
+/// Where a page is stored
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tier {
+    /// in the local db file
+    Local,
+    /// in the cloud backend, under the given key
+    Cold(String),
+}
+
+/// A page store keeping hot pages in the local db file, and cold pages in a cloud backend.
+/// pages are demoted explicitly, e.g. by picking pages missing from the buffer pool heat map,
+/// and promoted back to the local db file when they are read or written.
+///
+/// the locations of demoted pages are kept in toydb.tiers in the db directory, so they
+/// survive a restart. a demoted page is zeroed in the db file, so tools reading the db file
+/// directly, e.g. backups, see it as an unwritten page
+pub struct TieredPageStore<B: CloudBackend> {
+    disk_manager: DiskManager,
+    backend: B,
+    /// the object key of each demoted page
+    cold_pages: BTreeMap<u32, String>,
+    tiers_path: PathBuf,
+}
+
+impl<B: CloudBackend> TieredPageStore<B> {
+    /// open the db in the given directory, with its demoted pages in the given backend
+    pub fn open(db_dir: &Path, backend: B) -> Result<TieredPageStore<B>> {
+        let disk_manager = DiskManager::open(db_dir)?;
+        let tiers_path = db_dir.join("toydb.tiers");
+        let mut cold_pages = BTreeMap::new();
+        if tiers_path.exists() {
+            let mut tiers = Vec::new();
+            File::open(&tiers_path)?.read_to_end(&mut tiers)?;
+            cold_pages = TieredPageStore::<B>::decode_tiers(&tiers)?;
+        }
+        Ok(TieredPageStore { disk_manager, backend, cold_pages, tiers_path })
+    }
+
+    /// return where a page is stored
+    pub fn get_tier(&self, page_id: u32) -> Tier {
+        match self.cold_pages.get(&page_id) {
+            Some(key) => Tier::Cold(key.clone()),
+            None => Tier::Local,
+        }
+    }
+
+    /// move a page from the db file to the cloud backend.
+    /// return false if the page is not in the db file, or already demoted
+    pub fn demote(&mut self, page_id: u32) -> Result<bool> {
+        if self.cold_pages.contains_key(&page_id) || !self.disk_manager.have_page(page_id)? {
+            return Ok(false);
+        }
+        let mut page_data = [0u8; PAGE_SIZE];
+        self.disk_manager.read_page(page_id, &mut page_data)?;
+        Page::verify_checksum(page_id, &page_data)?;
+
+        let key = format!("pages/{}", page_id);
+        self.backend.put(&key, &page_data)?;
+        self.cold_pages.insert(page_id, key);
+        self.save_tiers()?;
+        // the local copy is only dropped once the page is recorded as demoted
+        self.disk_manager.write_page(page_id, &[0u8; PAGE_SIZE])?;
+        Ok(true)
+    }
+
+    /// move a demoted page back to the db file, unless it is overwritten by the given data
+    fn promote(&mut self, page_id: u32, page_data: Option<&[u8]>) -> Result<()> {
+        let key = match self.cold_pages.get(&page_id) {
+            Some(key) => key.clone(),
+            None => return Ok(()),
+        };
+        match page_data {
+            Some(page_data) => self.disk_manager.write_page(page_id, page_data)?,
+            None => {
+                let cold_data = self.backend.get(&key)?.ok_or_else(|| {
+                    Error::Internal(format!("page {} is missing from the backend", page_id))
+                })?;
+                if cold_data.len() != PAGE_SIZE {
+                    return Err(Error::PageCorrupt(format!(
+                        "page {}: backend object of {} bytes",
+                        page_id,
+                        cold_data.len()
+                    )));
+                }
+                let mut cold_page = [0u8; PAGE_SIZE];
+                cold_page.copy_from_slice(&cold_data);
+                Page::verify_checksum(page_id, &cold_page)?;
+                self.disk_manager.write_page(page_id, &cold_page)?;
+            }
+        }
+        self.cold_pages.remove(&page_id);
+        self.save_tiers()?;
+        // the backend copy is only dropped once the page is recorded as local
+        self.backend.delete(&key)
+    }
+
+    /// write the locations of the demoted pages, replacing the tiers file atomically.
+    /// format: page count (4), then page id (4), key length (4) and key for each page
+    fn save_tiers(&self) -> Result<()> {
+        let mut tiers = Vec::new();
+        tiers.extend_from_slice(&(self.cold_pages.len() as u32).to_le_bytes());
+        for (page_id, key) in &self.cold_pages {
+            tiers.extend_from_slice(&page_id.to_le_bytes());
+            tiers.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            tiers.extend_from_slice(key.as_bytes());
+        }
+        let tmp_path = self.tiers_path.with_extension("tiers.tmp");
+        let mut tmp_file =
+            OpenOptions::new().write(true).create(true).truncate(true).open(&tmp_path)?;
+        tmp_file.write_all(&tiers)?;
+        tmp_file.sync_all()?;
+        rename(&tmp_path, &self.tiers_path)?;
+        Ok(())
+    }
+
+    fn decode_tiers(mut tiers: &[u8]) -> Result<BTreeMap<u32, String>> {
+        let mut cold_pages = BTreeMap::new();
+        for _ in 0..take_u32(&mut tiers)? {
+            let page_id = take_u32(&mut tiers)?;
+            let key_len = take_u32(&mut tiers)? as usize;
+            let key = String::from_utf8(take(&mut tiers, key_len)?.to_vec())
+                .map_err(|_| Error::Value(String::from("invalid key in tiers file")))?;
+            cold_pages.insert(page_id, key);
+        }
+        if !tiers.is_empty() {
+            return Err(Error::Value(String::from("trailing data in tiers file")));
+        }
+        Ok(cold_pages)
+    }
+}
+
+impl<B: CloudBackend> PageStore for TieredPageStore<B> {
+    fn read_page(&mut self, page_id: u32, buf: &mut [u8]) -> Result<()> {
+        self.promote(page_id, None)?;
+        self.disk_manager.read_page(page_id, buf)
+    }
+
+    fn write_page(&mut self, page_id: u32, page_data: &[u8]) -> Result<()> {
+        if self.cold_pages.contains_key(&page_id) {
+            return self.promote(page_id, Some(page_data));
+        }
+        self.disk_manager.write_page(page_id, page_data)
+    }
+}
+
+/// split the given number of bytes off the front of the data
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(Error::Value(String::from("truncated tiers file")));
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_u32(data: &mut &[u8]) -> Result<u32> {
+    let mut value = [0u8; 4];
+    value.copy_from_slice(take(data, 4)?);
+    Ok(u32::from_le_bytes(value))
+}
@@ -0,0 +1,123 @@
+use crate::error::Result;
+use crate::storage::relational::disk_manager::{DiskManager, PageStore};
+use crate::storage::relational::page::{TablePage, PAGE_SIZE};
+use crate::storage::relational::tiered::{CloudBackend, Tier, TieredPageStore};
+use crate::storage::relational::tuple::{Tuple, RID};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempdir::TempDir;
+
+/// An in-memory cloud backend. clones share their objects, so tests can inspect them
+#[derive(Clone, Default)]
+struct MockBackend {
+    objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl MockBackend {
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.objects.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+}
+
+impl CloudBackend for MockBackend {
+    fn put(&mut self, key: &str, data: &[u8]) -> Result<()> {
+        self.objects.lock()?.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.objects.lock()?.get(key).cloned())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        self.objects.lock()?.remove(key);
+        Ok(())
+    }
+}
+
+fn table_page_data(page_id: u32, value: u8) -> Result<Vec<u8>> {
+    let mut table_page = TablePage::new(page_id, None, [0u8; PAGE_SIZE])?;
+    let mut tuple = Tuple::from_data(vec![value; 64]);
+    tuple.set_rid(RID::new(page_id, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+    Ok(table_page.get_data().to_vec())
+}
+
+#[test]
+fn test_tiered_page_store() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let backend = MockBackend::default();
+    let page1 = table_page_data(1, 0x01)?;
+    let page2 = table_page_data(2, 0x02)?;
+    let mut store = TieredPageStore::open(dir.path(), backend.clone())?;
+    store.write_page(1, &page1)?;
+    store.write_page(2, &page2)?;
+    assert_eq!(Tier::Local, store.get_tier(1));
+
+    // demoting moves the page to the backend, and zeroes the local copy
+    assert!(store.demote(1)?);
+    assert!(!store.demote(1)?);
+    assert!(!store.demote(5)?);
+    assert_eq!(Tier::Cold(String::from("pages/1")), store.get_tier(1));
+    assert_eq!(vec![String::from("pages/1")], backend.keys());
+    assert_eq!(page1, backend.objects.lock()?["pages/1"]);
+    drop(store);
+    let mut disk_manager = DiskManager::open(dir.path())?;
+    let mut data = [0xffu8; PAGE_SIZE];
+    disk_manager.read_page(1, &mut data)?;
+    assert!(data.iter().all(|&b| b == 0));
+    drop(disk_manager);
+
+    // the tiers survive a reopen, and reading the page promotes it
+    let mut store = TieredPageStore::open(dir.path(), backend.clone())?;
+    assert_eq!(Tier::Cold(String::from("pages/1")), store.get_tier(1));
+    store.read_page(1, &mut data)?;
+    assert_eq!(page1, data.to_vec());
+    assert_eq!(Tier::Local, store.get_tier(1));
+    assert!(backend.keys().is_empty());
+    store.read_page(2, &mut data)?;
+    assert_eq!(page2, data.to_vec());
+
+    // writing a cold page promotes it without fetching the old data
+    assert!(store.demote(2)?);
+    backend.objects.lock()?.clear();
+    let page2 = table_page_data(2, 0x03)?;
+    store.write_page(2, &page2)?;
+    assert_eq!(Tier::Local, store.get_tier(2));
+    drop(store);
+
+    let mut store = TieredPageStore::open(dir.path(), backend)?;
+    assert_eq!(Tier::Local, store.get_tier(1));
+    assert_eq!(Tier::Local, store.get_tier(2));
+    store.read_page(1, &mut data)?;
+    assert_eq!(page1, data.to_vec());
+    store.read_page(2, &mut data)?;
+    assert_eq!(page2, data.to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_tiered_page_store_corrupt() -> Result<()> {
+    let dir = TempDir::new("toydb")?;
+    let backend = MockBackend::default();
+    let mut store = TieredPageStore::open(dir.path(), backend.clone())?;
+    store.write_page(1, &table_page_data(1, 0x01)?)?;
+    assert!(store.demote(1)?);
+
+    // a damaged object is rejected, and the page stays cold
+    backend.objects.lock()?.get_mut("pages/1").unwrap()[100] ^= 0xff;
+    let mut data = [0u8; PAGE_SIZE];
+    assert!(store.read_page(1, &mut data).is_err());
+    assert_eq!(Tier::Cold(String::from("pages/1")), store.get_tier(1));
+    backend.objects.lock()?.clear();
+    assert!(store.read_page(1, &mut data).is_err());
+    drop(store);
+
+    // a truncated tiers file is rejected
+    let tiers = std::fs::read(dir.path().join("toydb.tiers"))?;
+    std::fs::write(dir.path().join("toydb.tiers"), &tiers[..tiers.len() - 1])?;
+    assert!(TieredPageStore::open(dir.path(), backend).is_err());
+    Ok(())
+}
@@ -1,9 +1,27 @@
+use crate::error::{Error, Result};
+
+/// A tuple is stored as raw bytes. Tuples built from column values use this encoding:
+///
+/// | ColumnCount (4)| NullBitmap (ColumnCount / 8, rounded up)| Columns |
+///
+/// bit i % 8 of bitmap byte i / 8 is set when column i is null. each non-null column is
+/// stored as its length (4) followed by its bytes, null columns take no space
+///
+/// Tuples of a table with a time to live are stored prefixed with their expiry time:
+///
+/// | ExpiresAt (8) | Data |
+///
+/// in milliseconds since the UNIX epoch, see to_expiring and from_expiring
 pub struct Tuple {
     data: Vec<u8>,
     rid: Option<RID>,
     allocated: bool,
+    /// the expiry time in milliseconds since the UNIX epoch, if the tuple expires
+    expires_at: Option<u64>,
 }
 
+/// A record id, addressing a tuple by its page and slot
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RID {
     page_id: u32,
     slot_num: u32,
@@ -11,11 +29,80 @@ pub struct RID {
 
 impl Tuple {
     pub fn empty() -> Tuple {
-        Tuple { data: Vec::new(), rid: None, allocated: false }
+        Tuple { data: Vec::new(), rid: None, allocated: false, expires_at: None }
     }
 
     pub fn from_data(data: Vec<u8>) -> Tuple {
-        Tuple { data, rid: None, allocated: false }
+        Tuple { data, rid: None, allocated: false, expires_at: None }
+    }
+
+    /// encode column values, None being null, into a tuple
+    pub fn from_values(values: &[Option<Vec<u8>>]) -> Tuple {
+        let bitmap_len = values.len().div_ceil(8);
+        let mut data = Vec::with_capacity(4 + bitmap_len);
+        data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        data.resize(4 + bitmap_len, 0);
+        for (column, value) in values.iter().enumerate() {
+            match value {
+                Some(value) => {
+                    data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    data.extend_from_slice(value);
+                }
+                None => data[4 + column / 8] |= 1 << (column % 8),
+            }
+        }
+        Tuple::from_data(data)
+    }
+
+    /// decode the column values of a tuple built by from_values
+    pub fn get_values(&self) -> Result<Vec<Option<Vec<u8>>>> {
+        let invalid = |msg: &str| Error::Value(format!("invalid tuple encoding: {}", msg));
+        let column_count = self.get_column_count().ok_or_else(|| invalid("no column count"))?;
+        let mut offset = 4 + column_count.div_ceil(8);
+        if self.data.len() < offset {
+            return Err(invalid("null bitmap is truncated"));
+        }
+
+        let mut values = Vec::with_capacity(column_count);
+        for column in 0..column_count {
+            if self.is_null(column) {
+                values.push(None);
+                continue;
+            }
+            let len = self
+                .data
+                .get(offset..offset + 4)
+                .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+                .ok_or_else(|| invalid("column length is truncated"))?;
+            offset += 4;
+            let value = offset
+                .checked_add(len)
+                .and_then(|end| self.data.get(offset..end))
+                .ok_or_else(|| invalid("column is truncated"))?;
+            values.push(Some(value.to_vec()));
+            offset += len;
+        }
+        if offset != self.data.len() {
+            return Err(invalid("trailing data"));
+        }
+        Ok(values)
+    }
+
+    /// check the null bitmap of a tuple built by from_values.
+    /// columns past the column count are not null
+    pub fn is_null(&self, column: usize) -> bool {
+        match self.get_column_count() {
+            Some(column_count) if column < column_count => {
+                self.data.get(4 + column / 8).is_some_and(|b| b & (1 << (column % 8)) != 0)
+            }
+            _ => false,
+        }
+    }
+
+    /// the column count of a tuple built by from_values
+    fn get_column_count(&self) -> Option<usize> {
+        let count = self.data.get(0..4)?;
+        Some(u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
     }
 
     pub fn get_data(&self) -> &[u8] {
@@ -42,6 +129,11 @@ impl Tuple {
         true
     }
 
+    /// set the RID the tuple was stored at, replacing any previous RID
+    pub fn assign_rid(&mut self, rid: RID) {
+        self.rid = Some(rid);
+    }
+
     /// Get length of the tuple
     pub fn get_length(&self) -> usize {
         self.data.len()
@@ -50,6 +142,40 @@ impl Tuple {
     pub fn allocated(&mut self) {
         self.allocated = true;
     }
+
+    /// the expiry time in milliseconds since the UNIX epoch, if the tuple expires
+    pub fn get_expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    /// return true if the tuple expires at or before the given time, in milliseconds since
+    /// the UNIX epoch
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// encode the tuple as stored in a table with a time to live, expiring at the given time
+    pub fn to_expiring(&self, expires_at: u64) -> Tuple {
+        let mut data = Vec::with_capacity(8 + self.data.len());
+        data.extend_from_slice(&expires_at.to_le_bytes());
+        data.extend_from_slice(&self.data);
+        Tuple { data, rid: self.rid.clone(), allocated: false, expires_at: None }
+    }
+
+    /// decode a tuple stored in a table with a time to live, keeping its RID
+    pub fn from_expiring(stored: Tuple) -> Result<Tuple> {
+        if stored.data.len() < 8 {
+            return Err(Error::Value(String::from("invalid tuple encoding: no expiry time")));
+        }
+        let mut expires_at = [0u8; 8];
+        expires_at.copy_from_slice(&stored.data[..8]);
+        Ok(Tuple {
+            data: stored.data[8..].to_vec(),
+            rid: stored.rid,
+            allocated: stored.allocated,
+            expires_at: Some(u64::from_le_bytes(expires_at)),
+        })
+    }
 }
 
 impl RID {
@@ -0,0 +1,72 @@
+use crate::error::Result;
+use crate::storage::relational::page::{TablePage, PAGE_SIZE};
+use crate::storage::relational::tuple::{Tuple, RID};
+use std::collections::HashMap;
+
+#[test]
+fn test_tuple_null_bitmap() -> Result<()> {
+    let values = vec![Some(b"abc".to_vec()), None, Some(vec![])];
+    let tuple = Tuple::from_values(&values);
+    // column count, a single bitmap byte, and the two non-null columns
+    assert_eq!(4 + 1 + (4 + 3) + 4, tuple.get_length());
+    assert!(!tuple.is_null(0));
+    assert!(tuple.is_null(1));
+    assert!(!tuple.is_null(2));
+    assert!(!tuple.is_null(3));
+    assert_eq!(values, tuple.get_values()?);
+
+    // an empty value is not null, and the bitmap grows a byte per 8 columns
+    let values: Vec<Option<Vec<u8>>> =
+        (0..9).map(|i| if i % 4 == 0 { None } else { Some(vec![]) }).collect();
+    let tuple = Tuple::from_values(&values);
+    assert_eq!(4 + 2 + 6 * 4, tuple.get_length());
+    assert_eq!([0x11, 0x01], tuple.get_data()[4..6]);
+    assert!(tuple.is_null(8));
+    assert!(!tuple.is_null(9));
+    assert_eq!(values, tuple.get_values()?);
+    assert_eq!(Vec::<Option<Vec<u8>>>::new(), Tuple::from_values(&[]).get_values()?);
+    Ok(())
+}
+
+#[test]
+fn test_tuple_values_stored() -> Result<()> {
+    let values = vec![Some(b"a".to_vec()), None, Some(b"c".to_vec())];
+    let mut table_page = TablePage::new(1, None, [0u8; PAGE_SIZE])?;
+    let mut tuple = Tuple::from_values(&values);
+    tuple.set_rid(RID::new(1, 0));
+    assert!(table_page.insert_tuple(&mut tuple)?);
+
+    let tuple = table_page.get_tuple(&RID::new(1, 0))?.unwrap();
+    assert!(tuple.is_null(1));
+    assert_eq!(values, tuple.get_values()?);
+    Ok(())
+}
+
+#[test]
+fn test_tuple_invalid_values() {
+    let data = Tuple::from_values(&[Some(b"abc".to_vec()), None]).get_data().to_vec();
+    for len in 0..data.len() {
+        assert!(Tuple::from_data(data[..len].to_vec()).get_values().is_err());
+    }
+    let mut trailing = data.clone();
+    trailing.push(0);
+    assert!(Tuple::from_data(trailing).get_values().is_err());
+
+    // a huge column length must not overflow
+    let mut data = data;
+    data[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(Tuple::from_data(data).get_values().is_err());
+}
+
+#[test]
+fn test_rid_map_key() {
+    let mut tuples = HashMap::new();
+    tuples.insert(RID::new(1, 0), "a");
+    tuples.insert(RID::new(1, 1), "b");
+    tuples.insert(RID::new(2, 0), "c");
+    let rid = RID::new(1, 0);
+    tuples.insert(rid.clone(), "d");
+    assert_eq!(3, tuples.len());
+    assert_eq!(Some(&"d"), tuples.get(&rid));
+    assert_ne!(RID::new(1, 0), RID::new(0, 1));
+}
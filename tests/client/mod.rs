@@ -4,15 +4,21 @@ use super::{assert_row, assert_rows, setup};
 
 use toydb::error::{Error, Result};
 use toydb::raft;
-use toydb::sql::engine::{Mode, Status};
+use toydb::server::{Request, Response};
+use toydb::sql::engine::{Mode, Status, MAX_PREPARED_STATEMENTS};
 use toydb::sql::execution::ResultSet;
 use toydb::sql::schema;
 use toydb::sql::types::{Column, DataType, Value};
 use toydb::storage::kv;
 use toydb::Client;
 
+use bincode::Options as _;
 use pretty_assertions::assert_eq;
 use serial_test::serial;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::TcpStream;
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
@@ -113,6 +119,63 @@ async fn list_tables() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn list_tables_after_drop() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    assert_eq!(
+        c.execute("DROP TABLE movies").await?,
+        ResultSet::DropTable { name: "movies".into() }
+    );
+    assert_eq!(c.list_tables().await?, vec!["countries", "genres", "studios"]);
+    assert_eq!(
+        c.execute("DROP TABLE movies").await,
+        Err(Error::Value("Table movies does not exist".into()))
+    );
+    assert_eq!(
+        c.execute("DROP TABLE IF EXISTS movies").await?,
+        ResultSet::DropTable { name: "movies".into() }
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn list_tables_with() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(vec![
+        "CREATE TABLE a (id INTEGER PRIMARY KEY)",
+        "CREATE TABLE log_2 (id INTEGER PRIMARY KEY)",
+        "CREATE TABLE log_1 (id INTEGER PRIMARY KEY)",
+        "CREATE TABLE log_3 (id INTEGER PRIMARY KEY)",
+        "CREATE TABLE logs (id INTEGER PRIMARY KEY)",
+        "CREATE TABLE z (id INTEGER PRIMARY KEY)",
+    ])
+    .await?;
+
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        c.list_tables_with(None, None).await?,
+        (names(&["a", "log_1", "log_2", "log_3", "logs", "z"]), false)
+    );
+    assert_eq!(
+        c.list_tables_with(Some("log_"), None).await?,
+        (names(&["log_1", "log_2", "log_3"]), false)
+    );
+    assert_eq!(
+        c.list_tables_with(Some("log_"), Some(2)).await?,
+        (names(&["log_1", "log_2"]), true)
+    );
+    assert_eq!(
+        c.list_tables_with(Some("log_"), Some(3)).await?,
+        (names(&["log_1", "log_2", "log_3"]), false)
+    );
+    assert_eq!(c.list_tables_with(None, Some(1)).await?, (names(&["a"]), true));
+    assert_eq!(c.list_tables_with(None, Some(0)).await?, (names(&[]), true));
+    assert_eq!(c.list_tables_with(Some("x"), Some(1)).await?, (names(&[]), false));
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn status() -> Result<()> {
@@ -126,10 +189,15 @@ async fn status() -> Result<()> {
                 leader: "test".into(),
                 term: 0,
                 node_last_index: vec![("test".to_string(), 26)].into_iter().collect(),
+                node_next_index: HashMap::new(),
+                node_last_contact: HashMap::new(),
+                last_index: 26,
                 commit_index: 26,
                 apply_index: 26,
+                pending_notifications: 0,
+                pending_queries: 0,
                 storage: "hybrid".into(),
-                storage_size: 3239,
+                storage_size: 3265,
             },
             mvcc: kv::mvcc::Status { txns: 1, txns_active: 0, storage: "memory".into() },
         }
@@ -137,6 +205,31 @@ async fn status() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn admin_requests_disabled() -> Result<()> {
+    let _teardown = setup::server_with_options(
+        "test",
+        "127.0.0.1:9605",
+        "127.0.0.1:9705",
+        HashMap::new(),
+        raft::RaftConfig::default(),
+        false,
+    )
+    .await?;
+    let c = Client::new("127.0.0.1:9605").await?;
+
+    // Cluster administration requests are rejected by default, without affecting the session.
+    let disabled = Err(Error::Value("Cluster administration requests are disabled".into()));
+    assert_eq!(c.transfer_leadership("test").await, disabled);
+    assert_eq!(c.add_server("other", "127.0.0.1:9706").await, disabled);
+    assert_eq!(c.add_learner("other", "127.0.0.1:9706").await, disabled);
+    assert_eq!(c.promote_learner("other").await, disabled);
+    assert_eq!(c.remove_server("test").await, disabled);
+    assert_row(c.execute("SELECT 1").await?, vec![Value::Integer(1)]);
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn execute() -> Result<()> {
@@ -220,6 +313,241 @@ async fn execute() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// A table created through the client should round-trip rows, with query results streamed back
+// row by row and reassembled by the client.
+async fn execute_roundtrip() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(Vec::new()).await?;
+    assert_eq!(c.list_tables().await?, Vec::<String>::new());
+
+    assert_eq!(
+        c.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)").await?,
+        ResultSet::CreateTable { name: "test".into() }
+    );
+    assert_eq!(c.list_tables().await?, vec!["test"]);
+    assert_eq!(c.get_table("test").await?.name, "test");
+
+    assert_eq!(
+        c.execute("INSERT INTO test VALUES (1, 'a'), (2, NULL), (3, 'c')").await?,
+        ResultSet::Create { count: 3 }
+    );
+    assert_rows(
+        c.execute("SELECT * FROM test ORDER BY id DESC").await?,
+        vec![
+            vec![Value::Integer(3), Value::String("c".into())],
+            vec![Value::Integer(2), Value::Null],
+            vec![Value::Integer(1), Value::String("a".into())],
+        ],
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn execute_params() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    // INSERT
+    assert_eq!(
+        c.execute_with_params(
+            "INSERT INTO genres VALUES (?, ?)",
+            vec![Value::Integer(9), Value::String("Western's".into())]
+        )
+        .await,
+        Ok(ResultSet::Create { count: 1 }),
+    );
+    assert_eq!(
+        c.execute_with_params(
+            "INSERT INTO genres (name, id) VALUES (?, ?)",
+            vec![Value::Integer(10), Value::String("Horror".into())]
+        )
+        .await,
+        Err(Error::Value("Invalid datatype INTEGER for parameter 1, expected STRING".into())),
+    );
+    assert_eq!(
+        c.execute_with_params("INSERT INTO genres VALUES (?, ?)", vec![Value::Integer(10)]).await,
+        Err(Error::Value("No value given for parameter 2".into())),
+    );
+
+    // SELECT
+    assert_row(
+        c.execute_with_params("SELECT * FROM genres WHERE id = ?", vec![Value::Integer(9)]).await?,
+        vec![Value::Integer(9), Value::String("Western's".into())],
+    );
+    assert_row(
+        c.execute_with_params(
+            "SELECT id FROM genres WHERE name = ? OR id = ?",
+            vec![Value::String("Action".into()), Value::Integer(-1)],
+        )
+        .await?,
+        vec![Value::Integer(2)],
+    );
+    assert_eq!(
+        c.execute_with_params("SELECT * FROM genres WHERE ? = id", vec![Value::Boolean(true)])
+            .await,
+        Err(Error::Value("Invalid datatype BOOLEAN for parameter 1, expected INTEGER".into())),
+    );
+
+    // UPDATE and DELETE
+    assert_eq!(
+        c.execute_with_params(
+            "UPDATE genres SET name = ? WHERE id = ?",
+            vec![Value::String("Horror".into()), Value::Integer(9)]
+        )
+        .await,
+        Ok(ResultSet::Update { count: 1 }),
+    );
+    assert_eq!(
+        c.execute_with_params("UPDATE genres SET name = ? WHERE id = 9", vec![Value::Float(1.0)])
+            .await,
+        Err(Error::Value("Invalid datatype FLOAT for parameter 1, expected STRING".into())),
+    );
+    assert_eq!(
+        c.execute_with_params("DELETE FROM genres WHERE name = ?", vec![Value::Null]).await,
+        Ok(ResultSet::Delete { count: 0 }),
+    );
+    assert_eq!(
+        c.execute_with_params("DELETE FROM genres WHERE name = ?", vec!["Horror".into()]).await,
+        Ok(ResultSet::Delete { count: 1 }),
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn blob() -> Result<()> {
+    let (c, _teardown) =
+        setup::server_with_client(vec!["CREATE TABLE files (id INTEGER PRIMARY KEY, data BLOB)"])
+            .await?;
+    assert_eq!(c.get_table("files").await?.columns[1].datatype, DataType::Bytes);
+
+    // Byte strings have no literals, so they're given as parameters.
+    let data: Vec<u8> = (0..=255).collect();
+    c.execute_with_params("INSERT INTO files VALUES (1, ?)", vec![Value::Bytes(data.clone())])
+        .await?;
+    assert!(c
+        .execute_with_params("INSERT INTO files VALUES (2, ?)", vec![Value::String("a".into())])
+        .await
+        .is_err());
+    assert_row(
+        c.execute_with_params(
+            "SELECT data FROM files WHERE data = ?",
+            vec![Value::Bytes(data.clone())],
+        )
+        .await?,
+        vec![Value::Bytes(data)],
+    );
+    assert_rows(
+        c.execute_with_params("SELECT id FROM files WHERE data = ?", vec![Value::Bytes(vec![1])])
+            .await?,
+        vec![],
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn execute_prepared() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    // A prepared INSERT can be executed repeatedly with different parameters.
+    let insert = c.prepare("INSERT INTO genres VALUES (?, ?)").await?;
+    assert_eq!(
+        c.execute_prepared(insert, vec![Value::Integer(9), Value::String("Western".into())]).await,
+        Ok(ResultSet::Create { count: 1 }),
+    );
+    assert_eq!(
+        c.execute_prepared(insert, vec![Value::Integer(10), Value::String("Horror".into())]).await,
+        Ok(ResultSet::Create { count: 1 }),
+    );
+    assert_eq!(
+        c.execute_prepared(insert, vec![Value::Integer(11)]).await,
+        Err(Error::Value("No value given for parameter 2".into())),
+    );
+
+    let select = c.prepare("SELECT name FROM genres WHERE id = ?").await?;
+    assert_ne!(insert, select);
+    assert_row(
+        c.execute_prepared(select, vec![Value::Integer(9)]).await?,
+        vec![Value::String("Western".into())],
+    );
+    assert_row(
+        c.execute_prepared(select, vec![Value::Integer(10)]).await?,
+        vec![Value::String("Horror".into())],
+    );
+
+    assert_eq!(
+        c.execute_prepared(select, vec![Value::String("Western".into())]).await,
+        Err(Error::Value("Invalid datatype STRING for parameter 1, expected INTEGER".into())),
+    );
+
+    // The plan is rebuilt when the schema of its tables changes.
+    c.execute("CREATE TABLE extra (id INTEGER PRIMARY KEY)").await?;
+    c.execute("DROP TABLE extra").await?;
+    assert_row(
+        c.execute_prepared(select, vec![Value::Integer(9)]).await?,
+        vec![Value::String("Western".into())],
+    );
+
+    // Parameters needed while planning are bound on every execution.
+    let limit = c.prepare("SELECT name FROM genres ORDER BY id LIMIT ?").await?;
+    assert_row(
+        c.execute_prepared(limit, vec![Value::Integer(1)]).await?,
+        vec![Value::String("Science Fiction".into())],
+    );
+
+    // Unknown handles and invalid queries are rejected.
+    assert_eq!(
+        c.execute_prepared(99, vec![]).await,
+        Err(Error::Value("Prepared statement 99 does not exist".into())),
+    );
+    assert!(c.prepare("SELEKT 1").await.is_err());
+
+    // Prepared statements belong to the connection.
+    let other = Client::new("127.0.0.1:9605").await?;
+    assert_eq!(
+        other.execute_prepared(insert, vec![]).await,
+        Err(Error::Value(format!("Prepared statement {} does not exist", insert))),
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn deallocate_prepared() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    let select = c.prepare("SELECT name FROM genres WHERE id = ?").await?;
+    c.deallocate(select).await?;
+    assert_eq!(
+        c.execute_prepared(select, vec![Value::Integer(1)]).await,
+        Err(Error::Value(format!("Prepared statement {} does not exist", select))),
+    );
+    assert_eq!(
+        c.deallocate(select).await,
+        Err(Error::Value(format!("Prepared statement {} does not exist", select))),
+    );
+
+    // A session can only hold a limited number of prepared statements, until some are deallocated.
+    let mut handles = Vec::new();
+    for _ in 0..MAX_PREPARED_STATEMENTS {
+        handles.push(c.prepare("SELECT 1").await?);
+    }
+    assert_eq!(
+        c.prepare("SELECT 1").await,
+        Err(Error::Value(format!(
+            "Too many prepared statements, at most {} are allowed per session",
+            MAX_PREPARED_STATEMENTS
+        ))),
+    );
+    c.deallocate(handles[0]).await?;
+    let handle = c.prepare("SELECT 1").await?;
+    assert_row(c.execute_prepared(handle, vec![]).await?, vec![Value::Integer(1)]);
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn execute_txn() -> Result<()> {
@@ -348,3 +676,527 @@ async fn execute_txn_concurrent() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn activity() -> Result<()> {
+    let (a, _teardown) = setup::server_with_client(setup::movies()).await?;
+    let b = Client::new("127.0.0.1:9605").await?;
+
+    a.execute("BEGIN").await?;
+    a.execute("DELETE FROM movies WHERE id = 1").await?;
+
+    // Other sessions show up with their last statement, and the querying session with the
+    // statement it's running.
+    let query = "SELECT state, statement FROM toydb_activity WHERE state != 'idle' ORDER BY id";
+    assert_rows(
+        b.execute(query).await?,
+        vec![
+            vec![
+                Value::String("idle in transaction".into()),
+                Value::String("DELETE FROM movies WHERE id = 1".into()),
+            ],
+            vec![Value::String("active".into()), Value::String(query.into())],
+        ],
+    );
+
+    a.execute("ROLLBACK").await?;
+    assert_rows(
+        b.execute(query).await?,
+        vec![vec![Value::String("active".into()), Value::String(query.into())]],
+    );
+
+    // The table can't be written to, or replaced.
+    assert_eq!(
+        b.execute("DELETE FROM toydb_activity").await,
+        Err(Error::Value("Table toydb_activity is read-only".into()))
+    );
+    assert_eq!(
+        b.execute("CREATE TABLE toydb_activity (id INTEGER PRIMARY KEY)").await,
+        Err(Error::Value("Table name toydb_activity is reserved".into()))
+    );
+    assert!(!b.list_tables().await?.contains(&"toydb_activity".to_string()));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn transaction_monitor_abort() -> Result<()> {
+    let (a, _teardown) = setup::server_with_transaction_monitor(
+        setup::movies(),
+        Some(Duration::from_millis(100)),
+        Some(Duration::from_millis(300)),
+    )
+    .await?;
+    let b = Client::new("127.0.0.1:9605").await?;
+
+    // A transaction left idle past the abort threshold is rolled back.
+    a.execute("BEGIN").await?;
+    a.execute("DELETE FROM movies WHERE id = 1").await?;
+    let (id, _) = a.txn().unwrap();
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_rows(
+        b.execute("SELECT state FROM toydb_activity ORDER BY id").await?,
+        vec![vec![Value::String("idle".into())], vec![Value::String("active".into())]],
+    );
+    assert_row(b.execute("SELECT COUNT(*) FROM movies").await?, vec![Value::Integer(10)]);
+
+    // The session errors until the client ends the transaction, which rolls it back.
+    assert_eq!(
+        a.execute("SELECT * FROM genres").await,
+        Err(Error::Value(format!(
+            "Transaction {} was aborted, statements are ignored until it is rolled back",
+            id
+        )))
+    );
+    assert_eq!(a.execute("COMMIT").await?, ResultSet::Rollback { id });
+    assert_eq!(a.txn(), None);
+    assert_row(a.execute("SELECT COUNT(*) FROM movies").await?, vec![Value::Integer(10)]);
+
+    // Transactions that are ended in time are left alone.
+    a.execute("BEGIN").await?;
+    a.execute("DELETE FROM movies WHERE id = 1").await?;
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    a.execute("COMMIT").await?;
+    assert_row(b.execute("SELECT COUNT(*) FROM movies").await?, vec![Value::Integer(9)]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn stat_statements() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+    c.reset_statement_stats().await?;
+
+    // Executions are aggregated by normalized statement.
+    c.execute("SELECT * FROM genres").await?;
+    c.execute("select *\n  from genres").await?;
+    c.execute("SELECT * FROM genres").await?;
+    c.execute("UPDATE genres SET name = 'x' WHERE id = 1").await?;
+    assert!(c.execute("SELECT * FROM missing").await.is_err());
+
+    let query = "SELECT statement, calls, rows, total_ms > 0.0, mean_ms = total_ms / calls \
+                 FROM toydb_stat_statements";
+    assert_rows(
+        c.execute(query).await?,
+        vec![
+            vec![
+                Value::String("SELECT * FROM genres".into()),
+                Value::Integer(3),
+                Value::Integer(9),
+                Value::Boolean(true),
+                Value::Boolean(true),
+            ],
+            vec![
+                Value::String("UPDATE genres SET name = 'x' WHERE id = 1".into()),
+                Value::Integer(1),
+                Value::Integer(1),
+                Value::Boolean(true),
+                Value::Boolean(true),
+            ],
+        ],
+    );
+
+    // Resetting clears the statistics. Queries of the table are recorded too, once they complete.
+    c.reset_statement_stats().await?;
+    assert_rows(
+        c.execute("SELECT COUNT(*) FROM toydb_stat_statements").await?,
+        vec![vec![Value::Integer(0)]],
+    );
+    assert_rows(
+        c.execute("SELECT COUNT(*) FROM toydb_stat_statements").await?,
+        vec![vec![Value::Integer(1)]],
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn pipeline() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::simple()).await?;
+
+    // Results come back in statement order, with failures in place.
+    let mut statements: Vec<String> =
+        (1..=50).map(|id| format!("INSERT INTO test VALUES ({}, 'v{}')", id, id)).collect();
+    statements.insert(25, "INSERT INTO test VALUES (1, 'duplicate')".into());
+    statements.push("SELECT COUNT(*) FROM test".into());
+    let mut results = c.pipeline(statements).await;
+    assert_eq!(results.len(), 52);
+    assert_rows(results.pop().unwrap()?, vec![vec![Value::Integer(50)]]);
+    assert!(results.remove(25).is_err());
+    for result in results {
+        assert_eq!(result?, ResultSet::Create { count: 1 });
+    }
+
+    // Transaction state follows the pipelined statements.
+    let results = c.pipeline(vec!["BEGIN".into(), "DELETE FROM test".into()]).await;
+    assert!(results.iter().all(|r| r.is_ok()));
+    assert!(matches!(c.txn(), Some((_, Mode::ReadWrite))));
+    c.execute("ROLLBACK").await?;
+    assert_rows(c.execute("SELECT COUNT(*) FROM test").await?, vec![vec![Value::Integer(50)]]);
+
+    assert!(c.pipeline(Vec::new()).await.is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn validate() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::simple()).await?;
+
+    assert_eq!(
+        c.validate("SELECT * FROM").await,
+        Err(Error::Parse("Unexpected end of input".into()))
+    );
+    assert_eq!(
+        c.validate("SELECT * FROM x").await,
+        Err(Error::Value("Table x does not exist".into()))
+    );
+    assert_eq!(
+        c.validate("INSERT INTO test VALUES (?, ?)").await,
+        Err(Error::Value("No value given for parameter 1".into()))
+    );
+
+    // Valid statements are planned, but not executed.
+    c.validate("INSERT INTO test VALUES (1, 'a')").await?;
+    c.validate("CREATE TABLE other (id INTEGER PRIMARY KEY)").await?;
+    c.validate("EXPLAIN DELETE FROM test").await?;
+    c.validate("BEGIN").await?;
+    assert_eq!(c.txn(), None);
+    assert_rows(c.execute("SELECT COUNT(*) FROM test").await?, vec![vec![Value::Integer(0)]]);
+    assert_eq!(c.list_tables().await?, vec!["test".to_string()]);
+
+    // The session transaction is left alone.
+    c.execute("BEGIN").await?;
+    c.validate("UPDATE test SET value = 'b'").await?;
+    c.validate("COMMIT").await?;
+    assert!(matches!(c.txn(), Some((_, Mode::ReadWrite))));
+    c.execute("ROLLBACK").await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn max_frame_size() -> Result<()> {
+    let (c, _teardown) = setup::server_with_max_frame_size(1024).await?;
+    c.execute("CREATE TABLE big (id INTEGER PRIMARY KEY, a STRING, b STRING)").await?;
+    let value = "x".repeat(600);
+    c.execute(&format!("INSERT INTO big VALUES (1, '{}', '')", value)).await?;
+    c.execute(&format!("UPDATE big SET b = '{}'", value)).await?;
+
+    // An oversized row is replaced by an error, and the connection stays usable.
+    match c.execute("SELECT * FROM big").await {
+        Err(Error::Value(msg)) => {
+            assert!(msg.contains("exceeds the maximum frame size"), "{}", msg)
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_rows(c.execute("SELECT id FROM big").await?, vec![vec![Value::Integer(1)]]);
+
+    // An oversized request is rejected, and closes the connection.
+    let query = format!("INSERT INTO big VALUES (2, '{}', '{}')", value, value);
+    match c.execute(&query).await {
+        Err(Error::Value(msg)) => assert!(msg.starts_with("Request rejected: "), "{}", msg),
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert!(c.execute("SELECT id FROM big").await.is_err());
+
+    // The server keeps serving other clients, and didn't apply the request.
+    let c = Client::new("127.0.0.1:9605").await?;
+    assert_rows(c.execute("SELECT id FROM big").await?, vec![vec![Value::Integer(1)]]);
+    Ok(())
+}
+
+/// Sends a length-prefixed request frame with the given payload.
+async fn send_frame(socket: &mut TcpStream, payload: &[u8]) -> Result<()> {
+    socket.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+    socket.write_all(payload).await?;
+    Ok(())
+}
+
+/// Receives a response frame's payload, or None if the server closed the connection.
+async fn receive_payload(socket: &mut TcpStream) -> Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    if socket.read(&mut len[..1]).await? == 0 {
+        return Ok(None);
+    }
+    socket.read_exact(&mut len[1..]).await?;
+    let mut payload = vec![0; u32::from_be_bytes(len) as usize];
+    socket.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+/// Receives a response frame, or None if the server closed the connection.
+async fn receive_frame(socket: &mut TcpStream) -> Result<Option<Result<Response>>> {
+    match receive_payload(socket).await? {
+        Some(payload) => Ok(Some(bincode::DefaultOptions::new().deserialize(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+/// Receives a JSON response frame, or None if the server closed the connection.
+async fn receive_json_frame(socket: &mut TcpStream) -> Result<Option<Result<Response>>> {
+    match receive_payload(socket).await? {
+        Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn malformed_request() -> Result<()> {
+    let (_client, _teardown) = setup::server_with_client(setup::simple()).await?;
+    let mut socket = TcpStream::connect("127.0.0.1:9605").await?;
+    let garbage = [0xfa, 0xff, 0x00, 0x13];
+    let ping = bincode::DefaultOptions::new().serialize(&Request::Ping)?;
+
+    // A malformed request gets an error, and the connection carries on.
+    send_frame(&mut socket, &garbage).await?;
+    match receive_frame(&mut socket).await? {
+        Some(Err(Error::Value(msg))) => assert_eq!(msg, "malformed request"),
+        response => panic!("Unexpected response {:?}", response),
+    }
+    send_frame(&mut socket, &ping).await?;
+    assert!(matches!(receive_frame(&mut socket).await?, Some(Ok(Response::Ping))));
+
+    // Repeated malformed requests close the connection.
+    for _ in 0..3 {
+        send_frame(&mut socket, &garbage).await?;
+        assert!(matches!(receive_frame(&mut socket).await?, Some(Err(Error::Value(_)))));
+    }
+    assert!(receive_frame(&mut socket).await?.is_none());
+
+    // The server keeps serving clients.
+    let c = Client::new("127.0.0.1:9605").await?;
+    c.execute("INSERT INTO test VALUES (1, 'a')").await?;
+    assert_rows(c.execute("SELECT id FROM test").await?, vec![vec![Value::Integer(1)]]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn json_format() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::simple()).await?;
+    c.execute("INSERT INTO test VALUES (1, 'a'), (2, NULL)").await?;
+
+    // A client whose first request is JSON gets JSON responses, with query rows streamed as for
+    // bincode clients.
+    let mut socket = TcpStream::connect("127.0.0.1:9605").await?;
+    let query = Request::Execute { sql: "SELECT * FROM test".into(), params: vec![] };
+    send_frame(&mut socket, &serde_json::to_vec(&query)?).await?;
+    match receive_json_frame(&mut socket).await? {
+        Some(Ok(Response::Execute(ResultSet::Query { columns, .. }))) => assert_eq!(
+            columns,
+            vec![Column { name: Some("id".into()) }, Column { name: Some("value".into()) }]
+        ),
+        response => panic!("Unexpected response {:?}", response),
+    }
+    let mut rows = Vec::new();
+    loop {
+        match receive_json_frame(&mut socket).await? {
+            Some(Ok(Response::Row(Some(row)))) => rows.push(row),
+            Some(Ok(Response::Row(None))) => break,
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Integer(1), Value::String("a".into())],
+            vec![Value::Integer(2), Value::Null],
+        ]
+    );
+
+    // Errors and malformed requests are also reported as JSON.
+    let query = Request::Execute { sql: "SELECT * FROM missing".into(), params: vec![] };
+    send_frame(&mut socket, &serde_json::to_vec(&query)?).await?;
+    assert!(matches!(receive_json_frame(&mut socket).await?, Some(Err(Error::Value(_)))));
+    send_frame(&mut socket, b"{\"Execute\":").await?;
+    match receive_json_frame(&mut socket).await? {
+        Some(Err(Error::Value(msg))) => assert_eq!(msg, "malformed request"),
+        response => panic!("Unexpected response {:?}", response),
+    }
+    send_frame(&mut socket, &serde_json::to_vec(&Request::Ping)?).await?;
+    assert!(matches!(receive_json_frame(&mut socket).await?, Some(Ok(Response::Ping))));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn cancel() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::simple()).await?;
+    let value = "x".repeat(1000);
+    let values: Vec<_> = (1..=100).map(|id| format!("({}, '{}')", id, value)).collect();
+    c.execute(&format!("INSERT INTO test VALUES {}", values.join(", "))).await?;
+
+    // A query of 10000 rows, far more than fit in the socket buffers, is cancelled while its
+    // rows are streamed, and they end early.
+    let mut socket = TcpStream::connect("127.0.0.1:9605").await?;
+    let bincode = bincode::DefaultOptions::new();
+    let query =
+        Request::Execute { sql: "SELECT * FROM test a CROSS JOIN test b".into(), params: vec![] };
+    send_frame(&mut socket, &bincode.serialize(&query)?).await?;
+    match receive_frame(&mut socket).await? {
+        Some(Ok(Response::Execute(ResultSet::Query { .. }))) => {}
+        response => panic!("Unexpected response {:?}", response),
+    }
+    send_frame(&mut socket, &bincode.serialize(&Request::Cancel)?).await?;
+    let mut count = 0;
+    loop {
+        match receive_frame(&mut socket).await? {
+            Some(Ok(Response::Row(Some(_)))) => count += 1,
+            Some(Ok(Response::Row(None))) => break,
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+    assert!(count < 10000, "{} rows streamed", count);
+
+    // Later queries on the connection aren't cancelled.
+    let query = Request::Execute { sql: "SELECT COUNT(*) FROM test".into(), params: vec![] };
+    send_frame(&mut socket, &bincode.serialize(&query)?).await?;
+    assert!(matches!(
+        receive_frame(&mut socket).await?,
+        Some(Ok(Response::Execute(ResultSet::Query { .. })))
+    ));
+    match receive_frame(&mut socket).await? {
+        Some(Ok(Response::Row(Some(row)))) => assert_eq!(row, vec![Value::Integer(100)]),
+        response => panic!("Unexpected response {:?}", response),
+    }
+    assert!(matches!(receive_frame(&mut socket).await?, Some(Ok(Response::Row(None)))));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn scan_batches() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::simple()).await?;
+    let values: Vec<_> = (1..=2500).map(|id| format!("({}, 'x')", id)).collect();
+    c.execute(&format!("INSERT INTO test VALUES {}", values.join(", "))).await?;
+
+    // Read-only scans fetch their rows in several batches, in and outside of transactions.
+    assert_eq!(c.execute("SELECT COUNT(*) FROM test").await?.into_value()?, Value::Integer(2500));
+    let ids = |result: ResultSet| -> Result<Vec<Value>> {
+        match result {
+            ResultSet::Query { rows, .. } => rows.map(|row| Ok(row?[0].clone())).collect(),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    };
+    let expect: Vec<_> = (1996..=2005).map(Value::Integer).collect();
+    assert_eq!(
+        ids(c.execute("SELECT id FROM test WHERE id > 1995 AND id <= 2005").await?)?,
+        expect
+    );
+    c.execute("BEGIN READ ONLY").await?;
+    assert_eq!(
+        ids(c.execute("SELECT id FROM test WHERE id > 1995 AND id <= 2005").await?)?,
+        expect
+    );
+    c.execute("COMMIT").await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn cancel_statement() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::simple()).await?;
+    let values: Vec<_> = (1..=100).map(|id| format!("({}, 'x')", id)).collect();
+    c.execute(&format!("INSERT INTO test VALUES {}", values.join(", "))).await?;
+
+    // Counting 100^4 joined rows would take far too long, but the query is cancelled while the
+    // rows are joined, before it returns a result.
+    let query = "SELECT COUNT(*) FROM test a CROSS JOIN test b CROSS JOIN test c CROSS JOIN test d";
+    let cancel = c.cancel();
+    let (result, cancelled) = tokio::time::timeout(Duration::from_secs(30), async {
+        tokio::join!(c.execute(query), async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.await
+        })
+    })
+    .await
+    .expect("statement wasn't cancelled");
+    cancelled?;
+    assert_eq!(result, Err(Error::Value("Statement was cancelled".into())));
+
+    // Later statements aren't cancelled.
+    assert_eq!(c.execute("SELECT COUNT(*) FROM test").await?.into_value()?, Value::Integer(100));
+    Ok(())
+}
+
+/// Reads the entries of an audit log as (peer, outcome, sql) fields, checking their timestamps.
+fn read_audit_log(path: &std::path::Path) -> Result<Vec<(String, String, String)>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            assert_eq!(fields.len(), 4, "{}", line);
+            assert!(fields[0].parse::<u128>().is_ok(), "{}", line);
+            Ok((fields[1].to_string(), fields[2].to_string(), fields[3].to_string()))
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn audit_log() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let path = dir.path().join("audit.log");
+    let (c, _teardown) = setup::server_with_audit_log(&path, true).await?;
+
+    c.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)").await?;
+    c.execute("INSERT INTO test VALUES (1, 'a\tb')").await?;
+    c.execute("SELECT * FROM test").await?;
+    assert!(c.execute("INSERT INTO test VALUES (1, 'c')").await.is_err());
+    c.get_table("test").await?;
+
+    // Every executed statement is recorded with the client address and outcome, and other
+    // requests aren't.
+    let entries = read_audit_log(&path)?;
+    assert_eq!(
+        entries
+            .iter()
+            .map(|(_, outcome, sql)| (outcome.as_str(), sql.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("ok", r#""CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)""#),
+            ("ok", r#""INSERT INTO test VALUES (1, 'a\tb')""#),
+            ("ok", r#""SELECT * FROM test""#),
+            (
+                r#"error "Primary key 1 already exists for table test""#,
+                r#""INSERT INTO test VALUES (1, 'c')""#
+            ),
+        ]
+    );
+    for (peer, _, _) in entries {
+        assert!(peer.starts_with("127.0.0.1:"), "{}", peer);
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn audit_log_without_reads() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let path = dir.path().join("audit.log");
+    let (c, _teardown) = setup::server_with_audit_log(&path, false).await?;
+
+    c.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)").await?;
+    c.execute("SELECT * FROM test").await?;
+    c.execute("EXPLAIN SELECT * FROM test").await?;
+    assert!(c.execute("SELECT * FROM missing").await.is_err());
+    c.execute("DELETE FROM test").await?;
+
+    // Successful reads are left out, but failures are recorded since they may have been writes.
+    let entries = read_audit_log(&path)?;
+    assert_eq!(
+        entries
+            .iter()
+            .map(|(_, outcome, sql)| (outcome.as_str(), sql.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("ok", r#""CREATE TABLE test (id INTEGER PRIMARY KEY)""#),
+            (r#"error "Table missing does not exist""#, r#""SELECT * FROM missing""#),
+            ("ok", r#""DELETE FROM test""#),
+        ]
+    );
+    Ok(())
+}
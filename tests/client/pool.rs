@@ -1,13 +1,55 @@
 use super::super::{assert_rows, setup};
 
+use toydb::client::Pool;
 use toydb::error::Result;
+use toydb::server::{Request, Response};
 use toydb::sql::types::Value;
 
 use futures::future::FutureExt as _;
+use futures::sink::SinkExt as _;
+use futures::stream::TryStreamExt as _;
 use pretty_assertions::assert_eq;
 use serial_test::serial;
 use std::collections::HashSet;
 use std::iter::FromIterator as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_serde::formats::Bincode;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+/// Starts a mock server which answers pings, and closes each connection after the given
+/// number of requests. Returns its address and a count of the connections it accepted.
+async fn mock_server(max_requests: usize) -> Result<(SocketAddr, Arc<AtomicUsize>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let connects = Arc::new(AtomicUsize::new(0));
+    let counter = connects.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut stream: tokio_serde::Framed<_, Request, Result<Response>, _> =
+                    tokio_serde::Framed::new(
+                        Framed::new(socket, LengthDelimitedCodec::new()),
+                        Bincode::<Request, Result<Response>>::default(),
+                    );
+                for _ in 0..max_requests {
+                    match stream.try_next().await {
+                        Ok(Some(Request::Ping)) => {
+                            if stream.send(Ok(Response::Ping)).await.is_err() {
+                                return;
+                            }
+                        }
+                        _ => return,
+                    }
+                }
+            });
+        }
+    });
+    Ok((addr, connects))
+}
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
 #[serial]
@@ -68,3 +110,37 @@ async fn drop_rollback() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reuse() -> Result<()> {
+    let (addr, connects) = mock_server(usize::MAX).await?;
+    let pool = Pool::new(vec![addr], 3).await?;
+
+    // Fetching every client makes sure the server has accepted all connections.
+    let clients = vec![pool.get().await, pool.get().await, pool.get().await];
+    assert_eq!(connects.load(Ordering::SeqCst), 3);
+    std::mem::drop(clients);
+
+    // Many concurrent operations share the pooled connections.
+    futures::future::try_join_all((0..100).map(|_| async { pool.get().await.ping().await }))
+        .await?;
+    assert_eq!(connects.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reconnect() -> Result<()> {
+    // Each connection serves the health check ping and one operation, then closes.
+    let (addr, connects) = mock_server(2).await?;
+    let pool = Pool::new(vec![addr], 1).await?;
+
+    pool.get().await.ping().await?;
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+    // The health check finds the connection closed, and reconnects.
+    pool.get().await.ping().await?;
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use super::super::{assert_row, setup};
+
+use toydb::client::Client;
+use toydb::error::Result;
+use toydb::raft;
+use toydb::sql::types::Value;
+
+use serial_test::serial;
+use std::collections::HashMap;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// Large entries should replicate across a cluster where only some nodes compress, and apply the
+// same state on every node as without compression.
+async fn compression_mixed() -> Result<()> {
+    let mut teardowns = Vec::new();
+    for id in 0..3 {
+        let (sql, raft) = (format!("127.0.0.1:{}", 9605 + id), format!("127.0.0.1:{}", 9705 + id));
+        let config = raft::RaftConfig {
+            compression_threshold: if id < 2 { Some(64) } else { None },
+            ..raft::RaftConfig::default()
+        };
+        teardowns.push(
+            setup::server_with_config(
+                &format!("toydb{}", id),
+                &sql,
+                &raft,
+                setup::peers(id, &[0, 1, 2]),
+                config,
+            )
+            .await?,
+        );
+    }
+    let mut clients = HashMap::new();
+    for id in 0..3 {
+        clients.insert(id, Client::new(format!("127.0.0.1:{}", 9605 + id)).await?);
+    }
+
+    let value = "abcdefgh".repeat(100);
+    clients[&0].execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)").await?;
+    for id in 1..=10 {
+        clients[&(id % 3)]
+            .execute(&format!("INSERT INTO test VALUES ({}, '{}')", id, value))
+            .await?;
+    }
+    for id in 0..3 {
+        assert_row(
+            clients[&id].execute("SELECT COUNT(*) FROM test").await?,
+            vec![Value::Integer(10)],
+        );
+        assert_row(
+            clients[&id].execute("SELECT value FROM test WHERE id = 7").await?,
+            vec![Value::String(value.clone())],
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use super::super::{assert_row, setup};
+
+use toydb::client::Client;
+use toydb::error::Result;
+use toydb::sql::types::Value;
+
+use serial_test::serial;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Waits for the given node's log to catch up with the leader's, as reported by the leader.
+async fn wait_caught_up(client: &Client, id: &str) -> Result<bool> {
+    for _ in 0..50 {
+        let status = client.status().await?.raft;
+        if status.node_last_index.get(id) == status.node_last_index.get(&status.leader) {
+            return Ok(true);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Ok(false)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// Adding a node to a 3-node cluster should replicate the log to it, and make it part of the quorum.
+async fn add_server() -> Result<()> {
+    let mut teardowns = HashMap::new();
+    for id in 0..3 {
+        let (sql, raft) = (format!("127.0.0.1:{}", 9605 + id), format!("127.0.0.1:{}", 9705 + id));
+        teardowns.insert(
+            id,
+            setup::server(&format!("toydb{}", id), &sql, &raft, setup::peers(id, &[0, 1, 2]))
+                .await?,
+        );
+    }
+    let mut clients = HashMap::new();
+    for id in 0..3 {
+        clients.insert(id, Client::new(format!("127.0.0.1:{}", 9605 + id)).await?);
+    }
+    let client = &clients[&0];
+    client.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)").await?;
+    client.execute("INSERT INTO test VALUES (1, 'a')").await?;
+
+    // Start a fourth node, knowing about the existing nodes, and add it to the cluster.
+    teardowns.insert(
+        3,
+        setup::server("toydb3", "127.0.0.1:9608", "127.0.0.1:9708", setup::peers(3, &[0, 1, 2, 3]))
+            .await?,
+    );
+    client.add_server("toydb3", "127.0.0.1:9708").await?;
+
+    // The new node should catch up with the leader's log.
+    assert!(wait_caught_up(client, "toydb3").await?, "New node did not catch up with the leader");
+    let leader = client.status().await?.raft.leader;
+    assert_eq!(client.status().await?.raft.node_last_index.len(), 4);
+
+    // Stopping one of the original followers leaves a quorum of 3 out of 4 nodes, which
+    // requires the new node to acknowledge writes.
+    let follower = (0..3).find(|id| format!("toydb{}", id) != leader).unwrap();
+    std::mem::drop(teardowns.remove(&follower));
+    let client = (0..3).find(|id| *id != follower).map(|id| &clients[&id]).unwrap();
+    client.execute("INSERT INTO test VALUES (2, 'b')").await?;
+    assert_row(
+        client.execute("SELECT * FROM test WHERE id = 2").await?,
+        vec![Value::Integer(2), Value::String("b".into())],
+    );
+
+    // Adding the same node again should error.
+    assert!(client.add_server("toydb3", "127.0.0.1:9708").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// A learner should receive the log, and can be promoted to a voter once it has caught up.
+async fn add_learner() -> Result<()> {
+    let (mut clients, _teardown) = setup::cluster_with_clients(3, setup::simple()).await?;
+    let client = clients.remove(0);
+    client.execute("INSERT INTO test VALUES (1, 'a')").await?;
+
+    let _learner =
+        setup::server("toydb3", "127.0.0.1:9608", "127.0.0.1:9708", setup::peers(3, &[0, 1, 2, 3]))
+            .await?;
+    assert!(client.promote_learner("toydb3").await.is_err());
+    client.add_learner("toydb3", "127.0.0.1:9708").await?;
+    assert!(wait_caught_up(&client, "toydb3").await?, "Learner did not catch up with the leader");
+
+    client.promote_learner("toydb3").await?;
+    assert!(client.promote_learner("toydb3").await.is_err());
+    client.execute("INSERT INTO test VALUES (2, 'b')").await?;
+    assert!(wait_caught_up(&client, "toydb3").await?, "Voter did not catch up with the leader");
+    assert_row(
+        client.execute("SELECT * FROM test WHERE id = 2").await?,
+        vec![Value::Integer(2), Value::String("b".into())],
+    );
+
+    Ok(())
+}
@@ -1,2 +1,7 @@
+mod compression;
 mod isolation;
+mod membership;
+mod quorum;
 mod recovery;
+mod status;
+mod witness;
@@ -0,0 +1,52 @@
+use super::super::setup;
+
+use toydb::client::Client;
+use toydb::error::{Error, Result};
+use toydb::raft;
+
+use serial_test::serial;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// A leader partitioned from its followers should fail writes fast once it detects quorum loss,
+// rather than hang.
+async fn quorum_loss() -> Result<()> {
+    let mut teardowns = HashMap::new();
+    for id in 0..3 {
+        let (sql, raft) = (format!("127.0.0.1:{}", 9605 + id), format!("127.0.0.1:{}", 9705 + id));
+        let config = raft::RaftConfig { quorum_loss_timeout: 5, ..raft::RaftConfig::default() };
+        teardowns.insert(
+            id,
+            setup::server_with_config(
+                &format!("toydb{}", id),
+                &sql,
+                &raft,
+                setup::peers(id, &[0, 1, 2]),
+                config,
+            )
+            .await?,
+        );
+    }
+    let client = Client::new("127.0.0.1:9605").await?;
+    client.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)").await?;
+    client.execute("INSERT INTO test VALUES (1, 'a')").await?;
+
+    // Stop the followers, and wait for the leader to detect the quorum loss.
+    let leader = client.status().await?.raft.leader;
+    let leader: u64 = leader.trim_start_matches("toydb").parse().unwrap();
+    teardowns.retain(|id, _| *id == leader);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let client = Client::new(format!("127.0.0.1:{}", 9605 + leader)).await?;
+    let result = tokio::time::timeout(
+        Duration::from_secs(1),
+        client.execute("INSERT INTO test VALUES (2, 'b')"),
+    )
+    .await
+    .expect("Write didn't fail fast");
+    assert_eq!(result, Err(Error::NoQuorum));
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use super::super::setup;
+
+use toydb::error::Result;
+
+use serial_test::serial;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// The leader's status should report each follower's replication progress.
+async fn replication_progress() -> Result<()> {
+    let (mut clients, _teardown) = setup::cluster_with_clients(3, setup::simple()).await?;
+    let client = clients.remove(0);
+
+    let status = client.status().await?.raft;
+    let followers: Vec<String> =
+        status.node_last_index.keys().filter(|id| **id != status.leader).cloned().collect();
+    assert_eq!(followers.len(), 2);
+    for id in &followers {
+        assert!(status.node_next_index.contains_key(id), "Missing next index for {}", id);
+        assert!(status.node_last_contact.contains_key(id), "Missing last contact for {}", id);
+    }
+
+    // Writing should advance each follower's match index to the leader's last index.
+    client.execute("INSERT INTO test VALUES (1, 'a')").await?;
+    let mut caught_up = false;
+    for _ in 0..50 {
+        let current = client.status().await?.raft;
+        assert!(current.last_index > status.last_index);
+        caught_up = followers.iter().all(|id| {
+            current.node_last_index[id] > status.node_last_index[id]
+                && current.node_last_index[id] == current.last_index
+                && current.node_next_index[id] == current.last_index + 1
+        });
+        if caught_up {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(caught_up, "Followers did not catch up with the leader");
+
+    Ok(())
+}
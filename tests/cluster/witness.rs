@@ -0,0 +1,53 @@
+use super::super::{assert_row, setup};
+
+use toydb::client::Client;
+use toydb::error::Result;
+use toydb::raft;
+use toydb::sql::types::Value;
+
+use serial_test::serial;
+use std::collections::HashMap;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// A cluster of two full nodes and a witness should remain available when a full node fails, using
+// the witness for quorum. The witness should never become leader.
+async fn witness_quorum() -> Result<()> {
+    let mut teardowns = HashMap::new();
+    for id in 0..3 {
+        let (sql, raft) = (format!("127.0.0.1:{}", 9605 + id), format!("127.0.0.1:{}", 9705 + id));
+        let config = raft::RaftConfig { witness: id == 2, ..raft::RaftConfig::default() };
+        teardowns.insert(
+            id,
+            setup::server_with_config(
+                &format!("toydb{}", id),
+                &sql,
+                &raft,
+                setup::peers(id, &[0, 1, 2]),
+                config,
+            )
+            .await?,
+        );
+    }
+    let mut clients = HashMap::new();
+    for id in 0..2 {
+        clients.insert(id, Client::new(format!("127.0.0.1:{}", 9605 + id)).await?);
+    }
+    clients[&0].execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)").await?;
+    clients[&0].execute("INSERT INTO test VALUES (1, 'a')").await?;
+
+    let leader = clients[&0].status().await?.raft.leader;
+    assert_ne!(leader, "toydb2", "Witness became leader");
+
+    // Stop the full node that isn't the leader, leaving the leader and witness as the quorum.
+    let follower = (0..2).find(|id| format!("toydb{}", id) != leader).unwrap();
+    std::mem::drop(teardowns.remove(&follower));
+    let client = &clients[&(1 - follower)];
+    client.execute("INSERT INTO test VALUES (2, 'b')").await?;
+    assert_row(
+        client.execute("SELECT * FROM test WHERE id = 2").await?,
+        vec![Value::Integer(2), Value::String("b".into())],
+    );
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use super::setup;
+
+use toydb::error::Result;
+
+use pretty_assertions::assert_eq;
+use serial_test::serial;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::TcpStream;
+
+const METRICS_ADDR: &str = "127.0.0.1:9805";
+
+/// Sends an HTTP GET request to the metrics server, returning the status line and body.
+async fn get(path: &str) -> Result<(String, String)> {
+    let mut socket = TcpStream::connect(METRICS_ADDR).await?;
+    socket.write_all(format!("GET {} HTTP/1.1\r\nHost: toydb\r\n\r\n", path).as_bytes()).await?;
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await?;
+    let (head, body) = response.split_at(response.find("\r\n\r\n").unwrap() + 4);
+    Ok((head.lines().next().unwrap().to_string(), body.to_string()))
+}
+
+/// Returns the value of an unlabeled metric sample.
+fn sample(metrics: &str, name: &str) -> u64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{} ", name)))
+        .unwrap_or_else(|| panic!("Missing metric {}", name))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+// The metrics endpoint should export node status, and track activity.
+async fn metrics() -> Result<()> {
+    let (client, _teardown) = setup::server_with_metrics(setup::simple(), METRICS_ADDR).await?;
+
+    let (status, before) = get("/metrics").await?;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    for name in &[
+        "toydb_raft_leader",
+        "toydb_raft_term",
+        "toydb_raft_last_index",
+        "toydb_raft_commit_index",
+        "toydb_raft_apply_index",
+        "toydb_raft_pending_notifications",
+        "toydb_raft_pending_queries",
+        "toydb_raft_storage_bytes",
+        "toydb_mvcc_txns_total",
+        "toydb_mvcc_txns_active",
+    ] {
+        assert!(before.contains(&format!("# TYPE {} ", name)), "Missing metric {}", name);
+    }
+    assert_eq!(sample(&before, "toydb_raft_leader"), 1);
+
+    client.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')").await?;
+    client.execute("SELECT * FROM test").await?;
+    let (_, after) = get("/metrics").await?;
+    for name in &["toydb_raft_last_index", "toydb_raft_commit_index", "toydb_mvcc_txns_total"] {
+        assert!(sample(&after, name) > sample(&before, name), "{} did not increase", name);
+    }
+    assert_eq!(sample(&after, "toydb_mvcc_txns_active"), 0);
+
+    assert_eq!(get("/unknown").await?.0, "HTTP/1.1 404 Not Found");
+    Ok(())
+}
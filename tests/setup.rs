@@ -1,15 +1,21 @@
 #![allow(clippy::implicit_hasher)]
 
+use toydb::audit::AuditLog;
 use toydb::client::{Client, Pool};
 use toydb::error::Result;
-use toydb::server::Server;
+use toydb::raft;
+use toydb::server::{DurabilityPreset, Server};
 use toydb::storage;
 
 use futures_util::future::FutureExt as _;
 use pretty_assertions::assert_eq;
 use std::collections::HashMap;
+use std::time::Duration;
 use tempdir::TempDir;
 
+/// The maximum request and response frame size of test servers
+pub const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
 // Movie data
 pub fn movies() -> Vec<&'static str> {
     vec![
@@ -67,23 +73,60 @@ pub fn simple() -> Vec<&'static str> {
     vec!["CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)"]
 }
 
+/// Returns the Raft peers of the given node, out of the given node IDs.
+pub fn peers(id: u64, nodes: &[u64]) -> HashMap<String, String> {
+    nodes
+        .iter()
+        .filter(|n| **n != id)
+        .map(|n| (format!("toydb{}", n), format!("127.0.0.1:{}", 9705 + n)))
+        .collect()
+}
+
 /// Sets up a test server
 pub async fn server(
     id: &str,
     addr_sql: &str,
     addr_raft: &str,
     peers: HashMap<String, String>,
+) -> Result<Teardown> {
+    server_with_config(id, addr_sql, addr_raft, peers, raft::RaftConfig::default()).await
+}
+
+/// Sets up a test server with the given Raft configuration
+pub async fn server_with_config(
+    id: &str,
+    addr_sql: &str,
+    addr_raft: &str,
+    peers: HashMap<String, String>,
+    config: raft::RaftConfig,
+) -> Result<Teardown> {
+    // Test clients administer the cluster, e.g. to change its membership.
+    server_with_options(id, addr_sql, addr_raft, peers, config, true).await
+}
+
+/// Sets up a test server with the given Raft configuration, which allows clients to administer
+/// the cluster if allow_admin is true
+pub async fn server_with_options(
+    id: &str,
+    addr_sql: &str,
+    addr_raft: &str,
+    peers: HashMap<String, String>,
+    config: raft::RaftConfig,
+    allow_admin: bool,
 ) -> Result<Teardown> {
     let dir = TempDir::new("toydb")?;
     let mut srv = Server::new(
         id,
         peers,
         Box::new(storage::log::Hybrid::new(dir.path(), false)?),
+        DurabilityPreset::Fast,
         Box::new(storage::kv::Memory::new()),
+        config,
+        MAX_FRAME_SIZE,
     )
     .await?;
 
-    srv = srv.listen(addr_sql, addr_raft).await?;
+    srv = srv.listen(addr_sql, addr_raft).await?.allow_admin_requests(allow_admin);
     let (task, abort) = srv.serve().remote_handle();
     tokio::spawn(task);
 
@@ -107,6 +150,136 @@ pub async fn server_with_client(queries: Vec<&str>) -> Result<(Client, Teardown)
     Ok((client, teardown))
 }
 
+/// Sets up a server with a client, limiting request and response frames to the given size
+pub async fn server_with_max_frame_size(max_frame_size: usize) -> Result<(Client, Teardown)> {
+    let dir = TempDir::new("toydb")?;
+    let srv = Server::new(
+        "test",
+        HashMap::new(),
+        Box::new(storage::log::Hybrid::new(dir.path(), false)?),
+        DurabilityPreset::Fast,
+        Box::new(storage::kv::Memory::new()),
+        raft::RaftConfig::default(),
+        max_frame_size,
+    )
+    .await?
+    .listen("127.0.0.1:9605", "127.0.0.1:9705")
+    .await?;
+    let (task, abort) = srv.serve().remote_handle();
+    tokio::spawn(task);
+    let teardown = Teardown::new(move || {
+        std::mem::drop(abort);
+        std::mem::drop(dir);
+    });
+    Ok((Client::new("127.0.0.1:9605").await?, teardown))
+}
+
+/// Sets up a server with a client, recording executed statements in an audit log at the given
+/// path
+pub async fn server_with_audit_log(
+    path: &std::path::Path,
+    include_reads: bool,
+) -> Result<(Client, Teardown)> {
+    let dir = TempDir::new("toydb")?;
+    let srv = Server::new(
+        "test",
+        HashMap::new(),
+        Box::new(storage::log::Hybrid::new(dir.path(), false)?),
+        DurabilityPreset::Fast,
+        Box::new(storage::kv::Memory::new()),
+        raft::RaftConfig::default(),
+        MAX_FRAME_SIZE,
+    )
+    .await?
+    .listen("127.0.0.1:9605", "127.0.0.1:9705")
+    .await?
+    .audit(AuditLog::new(path, include_reads)?);
+    let (task, abort) = srv.serve().remote_handle();
+    tokio::spawn(task);
+    let teardown = Teardown::new(move || {
+        std::mem::drop(abort);
+        std::mem::drop(dir);
+    });
+    Ok((Client::new("127.0.0.1:9605").await?, teardown))
+}
+
+/// Sets up a server with a client, monitoring transactions with the given warning and abort
+/// thresholds
+pub async fn server_with_transaction_monitor(
+    queries: Vec<&str>,
+    warn_after: Option<Duration>,
+    abort_after: Option<Duration>,
+) -> Result<(Client, Teardown)> {
+    let dir = TempDir::new("toydb")?;
+    let srv = Server::new(
+        "test",
+        HashMap::new(),
+        Box::new(storage::log::Hybrid::new(dir.path(), false)?),
+        DurabilityPreset::Fast,
+        Box::new(storage::kv::Memory::new()),
+        raft::RaftConfig::default(),
+        MAX_FRAME_SIZE,
+    )
+    .await?
+    .listen("127.0.0.1:9605", "127.0.0.1:9705")
+    .await?
+    .monitor_transactions(warn_after, abort_after);
+    let (task, abort) = srv.serve().remote_handle();
+    tokio::spawn(task);
+    let teardown = Teardown::new(move || {
+        std::mem::drop(abort);
+        std::mem::drop(dir);
+    });
+    let client = Client::new("127.0.0.1:9605").await?;
+    if !queries.is_empty() {
+        client.execute("BEGIN").await?;
+        for query in queries {
+            client.execute(query).await?;
+        }
+        client.execute("COMMIT").await?;
+    }
+    Ok((client, teardown))
+}
+
+/// Sets up a server with a client, also serving metrics on the given address
+#[cfg(feature = "metrics")]
+pub async fn server_with_metrics(
+    queries: Vec<&str>,
+    addr_metrics: &str,
+) -> Result<(Client, Teardown)> {
+    let dir = TempDir::new("toydb")?;
+    let srv = Server::new(
+        "test",
+        HashMap::new(),
+        Box::new(storage::log::Hybrid::new(dir.path(), false)?),
+        DurabilityPreset::Fast,
+        Box::new(storage::kv::Memory::new()),
+        raft::RaftConfig::default(),
+        MAX_FRAME_SIZE,
+    )
+    .await?
+    .listen("127.0.0.1:9605", "127.0.0.1:9705")
+    .await?
+    .listen_metrics(addr_metrics)
+    .await?;
+    let (task, abort) = srv.serve().remote_handle();
+    tokio::spawn(task);
+    let teardown = Teardown::new(move || {
+        std::mem::drop(abort);
+        std::mem::drop(dir);
+    });
+
+    let client = Client::new("127.0.0.1:9605").await?;
+    if !queries.is_empty() {
+        client.execute("BEGIN").await?;
+        for query in queries {
+            client.execute(query).await?;
+        }
+        client.execute("COMMIT").await?;
+    }
+    Ok((client, teardown))
+}
+
 /// Sets up a server cluster
 pub async fn cluster(nodes: HashMap<String, (String, String)>) -> Result<Teardown> {
     let mut teardown = Teardown::empty();
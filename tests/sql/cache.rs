@@ -0,0 +1,98 @@
+//! Plan cache tests, using an in-memory database
+use toydb::error::Result;
+use toydb::sql::engine::Engine as _;
+use toydb::sql::plan::{Node, Plan, PlanCache, PlanCacheStats};
+
+#[test]
+fn plan_cache_reuse() -> Result<()> {
+    let engine = super::setup(vec![
+        "CREATE TABLE movies (id INTEGER PRIMARY KEY, title STRING NOT NULL)",
+        "INSERT INTO movies VALUES (1, 'Stalker'), (2, 'Sicario')",
+    ])?;
+    let mut session = engine.session()?;
+    let cache = engine.plan_cache();
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 1, hits: 0, misses: 1 });
+
+    // Running the same query twice should only plan it once, regardless of formatting.
+    let first = session.execute("SELECT * FROM movies WHERE id = 1")?.into_row()?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 0, misses: 2 });
+    let second = session.execute("select *\n  FROM movies   WHERE id=1")?.into_row()?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 1, misses: 2 });
+    assert_eq!(first, second);
+
+    // Different literals are planned separately.
+    session.execute("SELECT * FROM movies WHERE id = 2")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 3, hits: 1, misses: 3 });
+
+    // Cached plans are also used within explicit transactions.
+    session.execute("BEGIN")?;
+    session.execute("SELECT * FROM movies WHERE id = 1")?;
+    session.execute("COMMIT")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 3, hits: 2, misses: 3 });
+
+    Ok(())
+}
+
+#[test]
+fn plan_cache_invalidate() -> Result<()> {
+    let engine = super::setup(vec![
+        "CREATE TABLE movies (id INTEGER PRIMARY KEY, title STRING NOT NULL)",
+        "INSERT INTO movies VALUES (1, 'Stalker'), (2, 'Sicario')",
+    ])?;
+    let mut session = engine.session()?;
+    let cache = engine.plan_cache();
+
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 1, misses: 2 });
+
+    // A schema change must invalidate the cached plan, which is then replanned to use the index.
+    session.execute("CREATE INDEX movies_title ON movies (title)")?;
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 1, misses: 3 });
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 2, misses: 3 });
+
+    // A rolled back schema change must not leave a valid cached plan behind.
+    session.execute("BEGIN")?;
+    session.execute("DROP INDEX movies_title")?;
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    session.execute("ROLLBACK")?;
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 2, misses: 5 });
+
+    // Schema changes to other tables must not invalidate the cached plan.
+    session.execute("CREATE TABLE genres (id INTEGER PRIMARY KEY)")?;
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 3, misses: 5 });
+
+    // New table statistics must invalidate the cached plan, such that it is costed again.
+    session.execute("ANALYZE movies")?;
+    session.execute("SELECT * FROM movies WHERE title = 'Sicario'")?;
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 2, hits: 3, misses: 6 });
+
+    Ok(())
+}
+
+#[test]
+fn plan_cache_lru() -> Result<()> {
+    let cache = PlanCache::new(2);
+    let tables = vec![("t".to_string(), 1)];
+    let v1 = |_: &str| Ok(1);
+    cache.put("a".into(), tables.clone(), Plan(Node::Nothing))?;
+    cache.put("b".into(), tables.clone(), Plan(Node::Nothing))?;
+    assert!(cache.get("a", v1)?.is_some());
+
+    // Adding a plan to a full cache should evict the least recently used plan.
+    cache.put("c".into(), tables, Plan(Node::Nothing))?;
+    assert!(cache.get("a", v1)?.is_some());
+    assert!(cache.get("b", v1)?.is_none());
+    assert!(cache.get("c", v1)?.is_some());
+
+    // Plans for other table versions are evicted.
+    assert!(cache.get("c", |_| Ok(2))?.is_none());
+    assert!(cache.get("c", v1)?.is_none());
+    assert_eq!(cache.stats()?, PlanCacheStats { size: 1, hits: 3, misses: 3 });
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+mod cache;
 mod expression;
 mod mutation;
 mod query;
@@ -147,6 +147,8 @@ test_schema! { with [
     ];
     drop_table: "DROP TABLE a",
     drop_table_bare: "DROP TABLE",
+    drop_table_if_exists: "DROP TABLE IF EXISTS a",
+    drop_table_if_exists_missing: "DROP TABLE IF EXISTS name",
     drop_table_missing: "DROP TABLE name",
     drop_table_multiple: "DROP TABLE a, c",
 }
@@ -160,6 +162,50 @@ test_schema! { with [
     drop_table_ref_self: "DROP TABLE self",
 }
 
+test_schema! { with [
+        "CREATE TABLE a (id INTEGER PRIMARY KEY, value STRING, code STRING, other STRING INDEX)",
+        "INSERT INTO a VALUES (1, 'x', 'a', 'a'), (2, 'y', 'b', 'b'), (3, 'x', 'c', 'a'), (4, NULL, NULL, 'b')",
+    ];
+    create_index: "CREATE INDEX a_value ON a (value)",
+    create_index_bare: "CREATE INDEX",
+    create_index_column_missing: "CREATE INDEX a_missing ON a (missing)",
+    create_index_indexed: "CREATE INDEX a_other ON a (other)",
+    create_index_multiple: "CREATE INDEX a_value ON a (value, other)",
+    create_index_pk: "CREATE INDEX a_id ON a (id)",
+    create_index_table_missing: "CREATE INDEX a_value ON missing (value)",
+    create_index_unique: "CREATE UNIQUE INDEX a_code ON a (code)",
+    create_index_unique_conflict: "CREATE UNIQUE INDEX a_value ON a (value)",
+    drop_index_missing: "DROP INDEX a_value",
+}
+test_schema! { with [
+        "CREATE TABLE a (id INTEGER PRIMARY KEY, value STRING)",
+        "INSERT INTO a VALUES (1, 'x'), (2, 'y'), (3, 'x')",
+        "CREATE INDEX a_value ON a (value)",
+    ];
+    create_index_exists: "CREATE INDEX a_value ON a (id)",
+    create_index_explain: "EXPLAIN SELECT * FROM a WHERE value = 'x'",
+    drop_index: "DROP INDEX a_value",
+    drop_index_bare: "DROP INDEX",
+}
+
+test_schema! { with [
+        "CREATE TABLE a (id INTEGER PRIMARY KEY, code INTEGER INDEX, country STRING INDEX)",
+        "INSERT INTO a VALUES
+            (1, 1, 'us'), (2, 2, 'us'), (3, 3, 'us'), (4, 4, 'us'), (5, 5, 'us'), (6, 6, 'us'),
+            (7, 7, 'us'), (8, 8, 'us'), (9, 9, 'us'), (10, 10, 'us'), (11, 11, 'us'), (12, 12, 'us'),
+            (13, 13, 'us'), (14, 14, 'us'), (15, 15, 'us'), (16, 16, 'us'), (17, 17, 'us'), (18, 18, 'us'),
+            (19, 19, 'us'), (20, 20, 'us'), (21, 21, 'us'), (22, 22, 'us'), (23, 23, 'us'), (24, 24, 'us'),
+            (25, 25, 'us'), (26, 26, 'us'), (27, 27, 'us'), (28, 28, 'us'), (29, 29, 'us'), (30, 30, 'us'),
+            (31, 31, 'us'), (32, 32, 'us')",
+        "ANALYZE a",
+    ];
+    index_cost_selective: "EXPLAIN SELECT * FROM a WHERE code = 7",
+    index_cost_unselective: "EXPLAIN SELECT * FROM a WHERE country = 'us'",
+    analyze: "ANALYZE a",
+    analyze_missing: "ANALYZE b",
+    analyze_bare: "ANALYZE",
+}
+
 test_schema! { with [
         r#"CREATE TABLE types (
             id INTEGER PRIMARY KEY,
@@ -2,8 +2,11 @@
 
 mod client;
 mod cluster;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod setup;
 mod sql;
+mod toysql;
 
 use toydb::sql::execution::ResultSet;
 use toydb::sql::types::Row;
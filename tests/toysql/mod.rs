@@ -0,0 +1,109 @@
+use super::setup;
+
+use toydb::error::Result;
+
+use pretty_assertions::assert_eq;
+use serial_test::serial;
+use std::io::Write as _;
+use std::process::{Command, Stdio};
+use tempdir::TempDir;
+
+/// Runs toysql against the test server with the given arguments, piping in the given script,
+/// and returns its non-empty output lines without any prompts.
+fn toysql(args: &[&str], script: &str) -> Result<Vec<String>> {
+    let home = TempDir::new("toysql")?;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_toysql"))
+        .args(args)
+        .env("HOME", home.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(script.as_bytes())?;
+    let output = child.wait_with_output()?;
+    assert!(output.status.success(), "toysql failed: {:?}", output);
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim_start_matches("toydb> ").to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// A piped script can span statements over several lines, and query results are printed in an
+// aligned table. Errors are reported inline, without stopping the script.
+async fn script() -> Result<()> {
+    let (_client, _teardown) = setup::server_with_client(setup::simple()).await?;
+    let script = "INSERT INTO test VALUES (1, 'a'), (2, NULL), (3, 'hello');
+SELECT *
+  FROM test
+  ORDER BY id;
+SELECT * FROM missing;
+!tables
+SELECT value FROM test WHERE id = 1;
+";
+    let output = tokio::task::block_in_place(|| toysql(&["-a", "-H"], script))?;
+    assert_eq!(output[0], "Connected to toyDB node \"test\". Enter !help for instructions.");
+    assert_eq!(
+        output[1..].iter().filter(|line| !line.starts_with("Error: ")).collect::<Vec<_>>(),
+        vec![
+            "Created 3 rows",
+            "id | value",
+            "---+------",
+            "1  | a",
+            "2  | NULL",
+            "3  | hello",
+            "(3 rows)",
+            "test",
+            "value",
+            "-----",
+            "a",
+            "(1 row)",
+        ]
+    );
+    assert_eq!(output.iter().filter(|line| line.starts_with("Error: ")).count(), 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+// !copy imports CSV files through the header's columns, and exports tables as CSV. A failed
+// import inserts no rows.
+async fn copy() -> Result<()> {
+    let (_client, _teardown) = setup::server_with_client(setup::simple()).await?;
+    let dir = TempDir::new("toysql")?;
+    let (import, invalid, export) = (
+        dir.path().join("import.csv"),
+        dir.path().join("invalid.csv"),
+        dir.path().join("export.csv"),
+    );
+    std::fs::write(&import, "value,id\n\"a, \"\"b\"\"\nc\",1\r\n,2\n\"\",3\n")?;
+    std::fs::write(&invalid, "id,value\n4,d\nfive,e\n")?;
+
+    let script = format!(
+        "!copy test FROM '{}'
+!copy test FROM '{}'
+SELECT id FROM test WHERE value IS NULL;
+SELECT id FROM test ORDER BY id;
+!copy test TO '{}'
+",
+        import.display(),
+        invalid.display(),
+        export.display()
+    );
+    let output = tokio::task::block_in_place(|| toysql(&[], &script))?;
+    assert_eq!(
+        output[1..],
+        [
+            format!("Copied 3 rows from {}", import.display()),
+            "Error: Invalid INTEGER five for column id".to_string(),
+            "2".to_string(),
+            "1".to_string(),
+            "2".to_string(),
+            "3".to_string(),
+            format!("Copied 3 rows to {}", export.display()),
+        ]
+    );
+    assert_eq!(std::fs::read_to_string(&export)?, "id,value\n1,\"a, \"\"b\"\"\nc\"\n2,\n3,\"\"\n");
+    Ok(())
+}